@@ -32,6 +32,7 @@ export class MotionDetection {
 	private timeOfFrameSecond: number = 0;
 	// WebAssembly
 	private MotionDetector: any = null;
+	private MotionOptions: any = null;
 	private motionDetector: any = null;
 	
 	// State
@@ -87,6 +88,7 @@ export class MotionDetection {
 			const MotionDetector = getMotionDetector();
 			if (MotionDetector) {
 				this.MotionDetector = MotionDetector;
+				this.MotionOptions = (window as any).WasmMotionOptions;
 				clearInterval(interval);
 			} else if (attempts >= maxAttempts) {
 				clearInterval(interval);
@@ -244,24 +246,43 @@ export class MotionDetection {
 			const outputImageData = ctx.createImageData(this.canvasElement.width, this.canvasElement.height);
 			if (this.motionDetector) {
 				try {
-					// Use the new method with internal frame caching (50% less data transfer!)
-					this.motionDetector.process_motion_with_cache(
-						currentImageData.data,
-						outputImageData.data,
-						{
-							move_type: this._options.moveType,
-							decay_rate: this._options.motionDecayRate,
-							angle_radians: this._options.movementAngle * (Math.PI / 180),
-							speed: this._options.movementSpeed,
-							threshold: this._options.motionThreshold,
-							sensitivity: this._options.motionSensitivity,
-							rotation_speed: this._options.rotationSpeed,
-							amplitude: this._options.waveAmplitude,
-							frequency: this._options.waveFrequency,
-							phase_increment: this._options.wavePhase,
-							direction: this._options.waveDirection
+					// Validated in Rust; unknown or mistyped options throw instead of being ignored
+					const options = this.MotionOptions.fromObject({
+						move_type: this._options.moveType,
+						decay_rate: this._options.motionDecayRate,
+						angle_radians: this._options.movementAngle * (Math.PI / 180),
+						speed: this._options.movementSpeed,
+						threshold: this._options.motionThreshold,
+						sensitivity: this._options.motionSensitivity,
+						rotation_speed: this._options.rotationSpeed,
+						amplitude: this._options.waveAmplitude,
+						frequency: this._options.waveFrequency,
+						phase_increment: this._options.wavePhase,
+						direction: this._options.waveDirection
+					});
+					try {
+						const memory: WebAssembly.Memory | undefined = (window as any).WasmMemory;
+						if (memory && this.motionDetector.process_in_place) {
+							// Write the frame straight into WASM memory and read the result from there;
+							// the pointer calls may grow memory, so `memory.buffer` is read after them
+							const inputPtr = this.motionDetector.input_ptr();
+							const inputLen = this.motionDetector.buffer_len();
+							new Uint8ClampedArray(memory.buffer, inputPtr, inputLen).set(currentImageData.data);
+							this.motionDetector.process_in_place(options);
+							const outputPtr = this.motionDetector.output_ptr();
+							const outputLen = this.motionDetector.output_buffer_len();
+							outputImageData.data.set(new Uint8ClampedArray(memory.buffer, outputPtr, outputLen));
+						} else {
+							// Use the new method with internal frame caching (50% less data transfer!)
+							this.motionDetector.process_motion_with_cache(
+								currentImageData.data,
+								outputImageData.data,
+								options
+							);
 						}
-					);
+					} finally {
+						options.free();
+					}
 				} catch (error) {
 					console.error('WASM motion detection error:', error);
 					this._state.hasError = true;
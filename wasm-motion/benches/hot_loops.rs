@@ -0,0 +1,159 @@
+// Hot loops of the detector over the native API: the per-pixel kernels, whole frames through
+// the detection pipeline and one pass of every move mode, at a few resolutions.
+//
+//   cargo bench --no-default-features --bench hot_loops [-- <filter>]
+use std::hint::black_box;
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use motion_detection::testing::moving_square;
+use motion_detection::{simd, Attractor, MotionDetector, MotionOptions, MoveType};
+
+const RESOLUTIONS: [(u32, u32); 3] = [(320, 240), (640, 360), (1280, 720)];
+
+const MODES: [MoveType; 15] = [
+    MoveType::Direction,
+    MoveType::Radial,
+    MoveType::Spiral,
+    MoveType::Wave,
+    MoveType::Zoom,
+    MoveType::Rotate,
+    MoveType::Turbulence,
+    MoveType::Flow,
+    MoveType::Kaleidoscope,
+    MoveType::Shake,
+    MoveType::Attract,
+    MoveType::Perspective,
+    MoveType::Smear,
+    MoveType::Feedback,
+    MoveType::Diffuse,
+];
+
+fn kernels(c: &mut Criterion) {
+    let (width, height) = RESOLUTIONS[2];
+    let row = moving_square(width, height, 0);
+    let row = &row[..width as usize * 4];
+    let mut gray = vec![0.0; width as usize];
+    let reference: Vec<f32> = (0..width).map(|x| (x % 251) as f32).collect();
+    let radial = vec![1.0; width as usize];
+    let mut diff = vec![0.0; width as usize];
+
+    let mut group = c.benchmark_group("kernels");
+    group.throughput(Throughput::Elements(width as u64));
+    group.bench_function("luma_row", |b| {
+        b.iter(|| simd::luma_row(black_box(row), (0, 1, 2), &mut gray))
+    });
+    simd::luma_row(row, (0, 1, 2), &mut gray);
+    group.bench_function("abs_diff_row", |b| {
+        b.iter(|| simd::abs_diff_row(black_box(&gray), &reference, &mut diff))
+    });
+    simd::abs_diff_row(&gray, &reference, &mut diff);
+    let offsets = vec![0.0; width as usize];
+    let mut enhanced = vec![0.0; width as usize];
+    group.bench_function("enhance_row", |b| {
+        b.iter(|| {
+            simd::enhance_row(
+                black_box(&diff),
+                &offsets,
+                &radial,
+                1,
+                30.0,
+                0.0,
+                1.0,
+                &mut enhanced,
+            )
+        })
+    });
+    group.finish();
+}
+
+fn detection(c: &mut Criterion) {
+    let mut group = c.benchmark_group("detection");
+    for (width, height) in RESOLUTIONS {
+        let frames = [
+            moving_square(width, height, 0),
+            moving_square(width, height, 1),
+        ];
+        let mut detector = MotionDetector::try_new(width, height).unwrap();
+        let mut output = vec![0; detector.output_len()];
+        let options = MotionOptions::default();
+
+        group.throughput(Throughput::Elements(width as u64 * height as u64));
+        group.bench_function(
+            BenchmarkId::from_parameter(format!("{width}x{height}")),
+            |b| {
+                let mut frame_index = 0;
+                b.iter(|| {
+                    frame_index ^= 1;
+                    detector
+                        .process(&frames[frame_index], &mut output, &options)
+                        .unwrap();
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+// One pass of `options.move_type` through the detector's public per-mode entry points
+fn move_once(detector: &mut MotionDetector, options: &MotionOptions) {
+    match options.move_type {
+        Some(MoveType::Direction) => detector.move_in_direction(options),
+        Some(MoveType::Radial) => detector.move_radially(options),
+        Some(MoveType::Spiral) => detector.move_spiral(options),
+        Some(MoveType::Wave) => detector.move_wave(options),
+        Some(MoveType::Zoom) => detector.move_zoom(options),
+        Some(MoveType::Rotate) => detector.move_rotate(options),
+        Some(MoveType::Turbulence) => detector.move_turbulence(options),
+        Some(MoveType::Flow) => detector.move_flow(options),
+        Some(MoveType::Kaleidoscope) => detector.move_kaleidoscope(options),
+        Some(MoveType::Shake) => detector.move_shake(options),
+        Some(MoveType::Attract) => detector.move_attract(options),
+        Some(MoveType::Perspective) => detector.move_perspective(options),
+        Some(MoveType::Smear) => detector.move_smear(options),
+        Some(MoveType::Feedback) => detector.move_feedback(options),
+        Some(MoveType::Diffuse) => detector.move_diffuse(options),
+        None => {}
+    }
+}
+
+fn movement(c: &mut Criterion) {
+    for move_type in MODES {
+        let mut group = c.benchmark_group(format!("move_{}", move_type.name()));
+        group
+            .sample_size(20)
+            .measurement_time(Duration::from_secs(2));
+        for (width, height) in RESOLUTIONS {
+            let mut detector = MotionDetector::try_new(width, height).unwrap();
+            let mut options = MotionOptions::default();
+            options.move_type = Some(move_type);
+            options.speed = 3.0;
+            // Trails to move, and the inputs of the flow, attract and perspective modes
+            let mut output = vec![0; detector.output_len()];
+            for frame_index in 0..4 {
+                let frame = moving_square(width, height, frame_index);
+                detector.process(&frame, &mut output, &options).unwrap();
+            }
+            detector.set_flow_field(&[3.0, 1.0], 1).unwrap();
+            detector.set_attractor_points(vec![Attractor {
+                x: 0.5,
+                y: 0.5,
+                strength: 2.0,
+                radius: 64.0,
+            }]);
+            detector
+                .set_perspective_corners(&[0.02, 0.02, -0.02, 0.02, 0.0, 0.0, 0.0, 0.0])
+                .unwrap();
+
+            group.throughput(Throughput::Elements(width as u64 * height as u64));
+            group.bench_function(
+                BenchmarkId::from_parameter(format!("{width}x{height}")),
+                |b| b.iter(|| move_once(&mut detector, black_box(&options))),
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, kernels, detection, movement);
+criterion_main!(benches);
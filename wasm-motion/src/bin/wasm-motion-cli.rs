@@ -0,0 +1,295 @@
+// Native front-end for tuning and regression testing: runs the detector over an image
+// sequence or raw RGBA frames piped from ffmpeg, e.g.
+//
+//   ffmpeg -i in.mp4 -f rawvideo -pix_fmt rgba - \
+//     | wasm-motion-cli --raw 1280x720 --config trails.json --raw-output --stats stats.json \
+//     | ffmpeg -f rawvideo -pix_fmt rgba -s 1280x720 -i - out.mp4
+use std::fs;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use motion_detection::testing::fnv1a;
+use motion_detection::{MotionDetector, MotionOptions, OptionValue, PixelFormat};
+use serde_json::{json, Value};
+
+const USAGE: &str = "usage: wasm-motion-cli (--input <dir> | --raw <WIDTHxHEIGHT>) \
+(--output <dir> | --raw-output) [--config <options.json>] [--stats <stats.json>]";
+
+enum Input {
+    // Image files processed in file name order
+    Images(Vec<PathBuf>),
+    // Raw RGBA frames read from stdin
+    Raw { width: u32, height: u32 },
+}
+
+enum Output {
+    Images(PathBuf),
+    Raw,
+}
+
+struct Args {
+    input: Input,
+    output: Output,
+    config: Option<PathBuf>,
+    stats: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    match parse_args().and_then(run) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("wasm-motion-cli: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut input = None;
+    let mut output = None;
+    let mut config = None;
+    let mut stats = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--input" => input = Some(Input::Images(list_images(Path::new(&value()?))?)),
+            "--raw" => {
+                let size = value()?;
+                let (width, height) = size
+                    .split_once('x')
+                    .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+                    .ok_or_else(|| format!("invalid frame size: {}", size))?;
+                input = Some(Input::Raw { width, height });
+            }
+            "--output" => output = Some(Output::Images(PathBuf::from(value()?))),
+            "--raw-output" => output = Some(Output::Raw),
+            "--config" => config = Some(PathBuf::from(value()?)),
+            "--stats" => stats = Some(PathBuf::from(value()?)),
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ => return Err(format!("unexpected argument: {}\n{}", arg, USAGE)),
+        }
+    }
+
+    Ok(Args {
+        input: input.ok_or(USAGE)?,
+        output: output.ok_or(USAGE)?,
+        config,
+        stats,
+    })
+}
+
+fn list_images(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| format!("{}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            matches!(
+                path.extension().and_then(|ext| ext.to_str()),
+                Some("png" | "jpg" | "jpeg")
+            )
+        })
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
+fn load_params(config: Option<&Path>) -> Result<MotionOptions, String> {
+    let mut params = MotionOptions::default();
+    let Some(path) = config else {
+        return Ok(params);
+    };
+
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let value: Value =
+        serde_json::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let Value::Object(options) = value else {
+        return Err(format!("{}: expected a JSON object", path.display()));
+    };
+
+    for (key, value) in &options {
+        set_json_option(&mut params, key, value)?;
+    }
+    Ok(params)
+}
+
+fn set_json_option(params: &mut MotionOptions, key: &str, value: &Value) -> Result<(), String> {
+    let value = match value {
+        Value::Number(number) => OptionValue::Number(number.as_f64().unwrap_or(0.0)),
+        Value::String(text) => OptionValue::Text(text),
+        Value::Bool(flag) => OptionValue::Bool(*flag),
+        // Grouped options such as `temporal_filter: {type, window}`
+        Value::Object(group) if MotionOptions::is_option_group(key) => {
+            for (field, value) in group {
+                set_json_option(params, MotionOptions::grouped_option(key, field)?, value)?;
+            }
+            return Ok(());
+        }
+        // `{mod, base, depth}` routes a modulation signal to the option
+        Value::Object(route) => {
+            let field = |name: &str| route.get(name).and_then(Value::as_f64);
+            let signal =
+                field("mod").ok_or_else(|| format!("option {} needs a mod signal index", key))?;
+            if let Some(base) = field("base") {
+                params.set_option(key, OptionValue::Number(base))?;
+            }
+            return params.modulate(key, signal as usize, field("depth").unwrap_or(1.0) as f32);
+        }
+        _ => {
+            return Err(format!(
+                "option {} must be a number, string, boolean or object",
+                key
+            ))
+        }
+    };
+    params.set_option(key, value)
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let params = load_params(args.config.as_deref())?;
+    // Raw frames arrive in the configured `input_format`; decoded images are always RGBA
+    let input_format = params.input_format.unwrap_or(PixelFormat::Rgba);
+    if matches!(args.input, Input::Images(_)) && input_format != PixelFormat::Rgba {
+        return Err(format!(
+            "input_format {} needs --raw input",
+            input_format.name()
+        ));
+    }
+    let mut frame_stats = Vec::new();
+    let mut detector: Option<MotionDetector> = None;
+    let mut output_frame = Vec::new();
+    let stdout = io::stdout();
+    let mut raw_out = BufWriter::new(stdout.lock());
+
+    let mut frames = FrameSource::new(args.input, input_format.bytes_per_pixel());
+    while let Some((width, height, frame)) = frames.next_frame()? {
+        let detector = match &mut detector {
+            Some(detector) => detector,
+            None => detector.insert(MotionDetector::try_new(width, height)?),
+        };
+        if (detector.width(), detector.height()) != (width, height) {
+            return Err(format!(
+                "frame {} is {}x{}, expected {}x{}",
+                frame_stats.len(),
+                width,
+                height,
+                detector.width(),
+                detector.height()
+            ));
+        }
+
+        output_frame.resize(detector.output_len(), 0);
+        detector.process(&frame, &mut output_frame, &params)?;
+        frame_stats.push(frame_stats_json(
+            frame_stats.len(),
+            detector.persistence(),
+            &output_frame,
+        ));
+
+        match &args.output {
+            Output::Raw => raw_out
+                .write_all(&output_frame)
+                .map_err(|e| e.to_string())?,
+            Output::Images(dir) => {
+                let path = dir.join(format!("frame_{:06}.png", frame_stats.len() - 1));
+                image::save_buffer(&path, &output_frame, width, height, image::ColorType::Rgba8)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+    }
+    raw_out.flush().map_err(|e| e.to_string())?;
+
+    if let Some(path) = args.stats {
+        let report = json!({
+            "frames": frame_stats.len(),
+            "width": detector.as_ref().map_or(0, |d| d.width()),
+            "height": detector.as_ref().map_or(0, |d| d.height()),
+            "per_frame": frame_stats,
+        });
+        let text = serde_json::to_string_pretty(&report).map_err(|e| e.to_string())?;
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
+
+fn frame_stats_json(index: usize, persistence: &[f32], output: &[u8]) -> Value {
+    let total: f64 = persistence.iter().map(|&value| value as f64).sum();
+    let active = persistence.iter().filter(|&&value| value >= 1.0).count();
+    let pixels = persistence.len().max(1) as f64;
+    json!({
+        "frame": index,
+        "mean_intensity": total / pixels,
+        "active_fraction": active as f64 / pixels,
+        // Hex, as JSON numbers lose precision beyond 53 bits
+        "output_hash": format!("{:016x}", fnv1a(output)),
+    })
+}
+
+struct FrameSource {
+    input: Input,
+    // Bytes per pixel of raw frames
+    bytes_per_pixel: usize,
+    next_image: usize,
+}
+
+impl FrameSource {
+    fn new(input: Input, bytes_per_pixel: usize) -> FrameSource {
+        FrameSource {
+            input,
+            bytes_per_pixel,
+            next_image: 0,
+        }
+    }
+
+    fn next_frame(&mut self) -> Result<Option<(u32, u32, Vec<u8>)>, String> {
+        match &self.input {
+            Input::Images(paths) => {
+                let Some(path) = paths.get(self.next_image) else {
+                    return Ok(None);
+                };
+                self.next_image += 1;
+                let image = image::open(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?
+                    .into_rgba8();
+                Ok(Some((image.width(), image.height(), image.into_raw())))
+            }
+            &Input::Raw { width, height } => {
+                let mut frame = vec![0; width as usize * height as usize * self.bytes_per_pixel];
+                match io::stdin().lock().read_exact(&mut frame) {
+                    Ok(()) => Ok(Some((width, height, frame))),
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+                    Err(e) => Err(e.to_string()),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use motion_detection::TemporalFilter;
+
+    #[test]
+    fn config_takes_grouped_options() {
+        let path =
+            std::env::temp_dir().join(format!("wasm-motion-cli-{}.json", std::process::id()));
+        fs::write(
+            &path,
+            r#"{"temporal_filter": {"type": "median", "window": 5}, "morphology": {"erode": 2}}"#,
+        )
+        .unwrap();
+        let params = load_params(Some(&path));
+        fs::remove_file(&path).unwrap();
+        let params = params.unwrap();
+        assert_eq!(params.temporal_filter, TemporalFilter::Median);
+        assert_eq!(params.temporal_window, 5);
+        assert_eq!(params.erode, 2);
+
+        let mut params = MotionOptions::default();
+        let group = json!({"kind": "median"});
+        assert!(set_json_option(&mut params, "temporal_filter", &group).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+// Motion-triggered snapshots of `set_frame_capture`: the last few analysed frames are kept
+// in a small ring, and when a frame's motion energy rises past the trigger it is captured
+// together with the frames before it, so a wildlife or security camera gets its snapshots
+// without copying every frame out to JS.
+use std::collections::VecDeque;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// A captured input frame as RGBA at the frame resolution, see `take_captures`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedFrame {
+    // Stream time of the frame, see `stream_time_ms`
+    pub timestamp_ms: f64,
+    // `energy` of the frame's motion stats
+    pub energy: f32,
+    // Kept from before the trigger rather than the frame that set it off
+    pub pre_trigger: bool,
+    pub width: u32,
+    pub height: u32,
+    pixels: Vec<u8>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl CapturedFrame {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn pixels(&self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+// The capture settings, the ring of recent frames and the captures waiting to be taken
+pub(crate) struct FrameCapture {
+    trigger_energy: f32,
+    pre_trigger_frames: usize,
+    max_captures: usize,
+    // The last analysed frames, oldest first: up to `pre_trigger_frames` and the current one
+    recent: VecDeque<CapturedFrame>,
+    captures: VecDeque<CapturedFrame>,
+    // The last analysed frame was above the trigger; the energy has to drop below it again
+    // before the next capture, so ongoing motion is captured once
+    triggered: bool,
+}
+
+impl FrameCapture {
+    pub(crate) fn new(
+        trigger_energy: f32,
+        pre_trigger_frames: usize,
+        max_captures: usize,
+    ) -> FrameCapture {
+        FrameCapture {
+            trigger_energy: trigger_energy.max(0.0),
+            pre_trigger_frames,
+            max_captures: max_captures.max(1),
+            recent: VecDeque::with_capacity(pre_trigger_frames + 1),
+            captures: VecDeque::new(),
+            triggered: false,
+        }
+    }
+
+    // Take an analysed frame of `width` x `height` with motion `energy`; `fill` writes its
+    // RGBA pixels into the (cleared) buffer
+    pub(crate) fn update(
+        &mut self,
+        energy: f32,
+        timestamp_ms: f64,
+        (width, height): (u32, u32),
+        fill: impl FnOnce(&mut Vec<u8>),
+    ) {
+        // The oldest frame's buffer is reused for this one
+        let mut pixels = if self.recent.len() > self.pre_trigger_frames {
+            self.recent
+                .pop_front()
+                .map_or_else(Vec::new, |frame| frame.pixels)
+        } else {
+            Vec::new()
+        };
+        pixels.clear();
+        fill(&mut pixels);
+        self.recent.push_back(CapturedFrame {
+            timestamp_ms,
+            energy,
+            pre_trigger: true,
+            width,
+            height,
+            pixels,
+        });
+
+        let above = energy > self.trigger_energy;
+        if above && !self.triggered {
+            if let Some(frame) = self.recent.back_mut() {
+                frame.pre_trigger = false;
+            }
+            for frame in self.recent.drain(..) {
+                if self.captures.len() == self.max_captures {
+                    self.captures.pop_front();
+                }
+                self.captures.push_back(frame);
+            }
+        }
+        self.triggered = above;
+    }
+
+    pub(crate) fn drain(&mut self) -> Vec<CapturedFrame> {
+        self.captures.drain(..).collect()
+    }
+
+    pub(crate) fn pending(&self) -> usize {
+        self.captures.len()
+    }
+
+    // Forget the recent frames and the trigger state, keeping the captures
+    pub(crate) fn restart(&mut self) {
+        self.recent.clear();
+        self.triggered = false;
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.recent
+            .iter()
+            .chain(&self.captures)
+            .map(|frame| frame.pixels.capacity())
+            .sum()
+    }
+}
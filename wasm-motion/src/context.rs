@@ -0,0 +1,364 @@
+// Per-pixel lookup tables that only depend on the resolution, the picture area and the
+// radial profile, and the `DetectorContext` through which detectors of the same
+// configuration share them instead of each holding megabytes of identical tables.
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+use std::sync::OnceLock;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::RadialProfile;
+
+// What a set of tables is built for; `area` (x, y, width, height) is the picture area the
+// tables are centered on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LutKey {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) area: [usize; 4],
+    pub(crate) profile: RadialProfile,
+    pub(crate) lens: Option<LensModel>,
+    // Polar tables as 16-bit fixed point, see `set_low_memory`
+    pub(crate) packed: bool,
+}
+
+// Radial lens distortion of `set_lens_model`: a pixel `r` from the optical center (at `cx`,
+// `cy` in fractions of the picture area) is truly `r (1 + k1 p^2 + k2 p^4)` away, with `p`
+// its distance in half-diagonals of the area
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LensModel {
+    pub(crate) k1: f32,
+    pub(crate) k2: f32,
+    pub(crate) cx: f32,
+    pub(crate) cy: f32,
+}
+
+// A lens model at the size of a picture area, in both directions: the forward model and a
+// table of picture distances at whole-pixel true distances for going back
+pub(crate) struct LensProjection {
+    model: LensModel,
+    inv_half_diagonal: f32,
+    picture_distance: Vec<f32>,
+}
+
+impl LensProjection {
+    fn new(model: LensModel, half_diagonal: f32, farthest: f32) -> LensProjection {
+        let mut projection = LensProjection {
+            model,
+            inv_half_diagonal: 1.0 / half_diagonal.max(1.0),
+            picture_distance: Vec::new(),
+        };
+        // Bisection on the forward model, which is monotonic for sensible coefficients;
+        // true distances reach a bit past the farthest corner for sources outside the area
+        let largest = projection.true_distance(farthest * 2.0);
+        projection.picture_distance = (0..=largest.max(0.0).ceil() as usize + 1)
+            .map(|distance| {
+                let (mut low, mut high) = (0.0, farthest * 2.0);
+                for _ in 0..24 {
+                    let middle = (low + high) * 0.5;
+                    if projection.true_distance(middle) < distance as f32 {
+                        low = middle;
+                    } else {
+                        high = middle;
+                    }
+                }
+                (low + high) * 0.5
+            })
+            .collect();
+        projection
+    }
+
+    // True distance of a pixel `distance` from the optical center in the picture
+    pub(crate) fn true_distance(&self, distance: f32) -> f32 {
+        let p2 = (distance * self.inv_half_diagonal).powi(2);
+        distance * (1.0 + self.model.k1 * p2 + self.model.k2 * p2 * p2)
+    }
+
+    // Where in the picture a true distance from the optical center lands
+    pub(crate) fn picture_distance(&self, distance: f32) -> f32 {
+        let last = self.picture_distance.len() - 1;
+        let position = distance.clamp(0.0, last as f32);
+        let index = (position as usize).min(last - 1);
+        let fraction = position - index as f32;
+        let (near, far) = (
+            self.picture_distance[index],
+            self.picture_distance[index + 1],
+        );
+        near + (far - near) * fraction
+    }
+}
+
+// A polar table at full precision or, for low-memory detectors, as 16-bit steps of `scale`
+// above `offset`, which stay within a fifth of a pixel even at the corners of a 4K frame
+pub(crate) enum Lut {
+    Full(Vec<f32>),
+    Packed {
+        values: Vec<u16>,
+        offset: f32,
+        scale: f32,
+    },
+}
+
+impl Lut {
+    fn new(values: Vec<f32>, packed: bool) -> Lut {
+        if !packed {
+            return Lut::Full(values);
+        }
+        let (low, high) = values
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(low, high), &value| {
+                (low.min(value), high.max(value))
+            });
+        if low > high {
+            return Lut::Full(Vec::new());
+        }
+        let scale = (high - low) / u16::MAX as f32;
+        let inv_scale = if scale > 0.0 { 1.0 / scale } else { 0.0 };
+        Lut::Packed {
+            values: values
+                .iter()
+                .map(|&value| ((value - low) * inv_scale).round() as u16)
+                .collect(),
+            offset: low,
+            scale,
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn get(&self, index: usize) -> f32 {
+        match self {
+            Lut::Full(values) => values[index],
+            Lut::Packed {
+                values,
+                offset,
+                scale,
+            } => offset + values[index] as f32 * scale,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Lut::Full(values) => values.len(),
+            Lut::Packed { values, .. } => values.len(),
+        }
+    }
+
+    pub(crate) fn bytes(&self) -> usize {
+        match self {
+            Lut::Full(values) => values.capacity() * std::mem::size_of::<f32>(),
+            Lut::Packed { values, .. } => values.capacity() * std::mem::size_of::<u16>(),
+        }
+    }
+
+    pub(crate) fn non_finite(&self) -> usize {
+        match self {
+            Lut::Full(values) => values.iter().filter(|value| !value.is_finite()).count(),
+            Lut::Packed { offset, scale, .. } => {
+                usize::from(!offset.is_finite() || !scale.is_finite())
+            }
+        }
+    }
+}
+
+// Optimization #1: Pre-computed lookup tables. `distance` holds the radial profile curve,
+// which scales the threshold rise, and is read by every detection along with the
+// sensitivity; the polar coordinates only serve the modes that move around the center and
+// are built the first time one of them runs (see `free_unused_luts`).
+pub(crate) struct DetectorLuts {
+    pub(crate) key: LutKey,
+    pub(crate) distance: Vec<f32>,
+    pub(crate) radial_sensitivity: Vec<f32>,
+    polar: OnceLock<PolarLuts>,
+    // Center of the area and the distance of its corners
+    pub(crate) center_x: f32,
+    pub(crate) center_y: f32,
+    pub(crate) max_radius: f32,
+    // With a lens model the polar tables and the radial profile hold true distances around
+    // the optical center
+    pub(crate) lens: Option<LensProjection>,
+}
+
+// Angle and distance of every pixel from the center, with squared distances for cheap
+// comparisons. Low-memory tables leave those out and square on the fly.
+pub(crate) struct PolarLuts {
+    pub(crate) angle: Lut,
+    pub(crate) distance: Lut,
+    pub(crate) distance_squared: Vec<f32>,
+}
+
+impl PolarLuts {
+    #[inline(always)]
+    pub(crate) fn distance_squared(&self, pixel_index: usize) -> f32 {
+        match self.distance_squared.get(pixel_index) {
+            Some(&distance_squared) => distance_squared,
+            None => self.distance.get(pixel_index).powi(2),
+        }
+    }
+
+    pub(crate) fn non_finite(&self) -> usize {
+        self.angle.non_finite()
+            + self.distance.non_finite()
+            + self
+                .distance_squared
+                .iter()
+                .filter(|value| !value.is_finite())
+                .count()
+    }
+}
+
+impl DetectorLuts {
+    pub(crate) fn build(key: LutKey) -> DetectorLuts {
+        let [area_x, area_y, area_width, area_height] = key.area;
+        let (center_x, center_y) = match key.lens {
+            Some(lens) => (
+                area_x as f32 + area_width as f32 * lens.cx,
+                area_y as f32 + area_height as f32 * lens.cy,
+            ),
+            None => (
+                area_x as f32 + area_width as f32 / 2.0,
+                area_y as f32 + area_height as f32 / 2.0,
+            ),
+        };
+        let half_width = area_width as f32 / 2.0;
+        let half_height = area_height as f32 / 2.0;
+        let max_radius = ((half_width * half_width) + (half_height * half_height)).sqrt();
+        let lens = key.lens.map(|lens| {
+            // Distance of the area's farthest corner from the optical center
+            let reach_x =
+                (center_x - area_x as f32).max(area_x as f32 + area_width as f32 - center_x);
+            let reach_y =
+                (center_y - area_y as f32).max(area_y as f32 + area_height as f32 - center_y);
+            LensProjection::new(lens, max_radius, reach_x.hypot(reach_y))
+        });
+        let mut luts = DetectorLuts {
+            key,
+            distance: Vec::new(),
+            radial_sensitivity: Vec::new(),
+            polar: OnceLock::new(),
+            center_x,
+            center_y,
+            max_radius,
+            lens,
+        };
+        let inv_max_radius = 1.0
+            / luts
+                .lens
+                .as_ref()
+                .map_or(max_radius, |lens| lens.true_distance(max_radius));
+        let profile = key.profile;
+        let buffer_size = key.width as usize * key.height as usize;
+
+        // Pre-allocate all vectors with exact capacity to avoid reallocations
+        let mut distance = Vec::with_capacity(buffer_size);
+        let mut radial_sensitivity = Vec::with_capacity(buffer_size);
+
+        // Cache-friendly initialization: Process row by row to improve spatial locality
+        for y in 0..key.height {
+            for x in 0..key.width {
+                let curve = profile.curve(luts.true_distance(x, y) * inv_max_radius);
+                distance.push(curve);
+                radial_sensitivity
+                    .push((1.0 - curve * profile.falloff).max(profile.min_sensitivity));
+            }
+        }
+        luts.distance = distance;
+        luts.radial_sensitivity = radial_sensitivity;
+        luts
+    }
+
+    // The polar tables, built on first use and then shared like the others
+    pub(crate) fn polar(&self) -> &PolarLuts {
+        self.polar.get_or_init(|| {
+            let buffer_size = self.key.width as usize * self.key.height as usize;
+            let mut angle = Vec::with_capacity(buffer_size);
+            let mut distance = Vec::with_capacity(buffer_size);
+            let mut distance_squared =
+                Vec::with_capacity(if self.key.packed { 0 } else { buffer_size });
+            for y in 0..self.key.height {
+                let dy = y as f32 - self.center_y;
+                for x in 0..self.key.width {
+                    let dx = x as f32 - self.center_x;
+                    let pixel_distance = self.true_distance(x, y);
+                    angle.push(dy.atan2(dx));
+                    distance.push(pixel_distance);
+                    if !self.key.packed {
+                        distance_squared.push(match self.lens {
+                            Some(_) => pixel_distance * pixel_distance,
+                            None => dx * dx + dy * dy,
+                        });
+                    }
+                }
+            }
+            PolarLuts {
+                angle: Lut::new(angle, self.key.packed),
+                distance: Lut::new(distance, self.key.packed),
+                distance_squared,
+            }
+        })
+    }
+
+    // The polar tables if some mode has built them
+    pub(crate) fn built_polar(&self) -> Option<&PolarLuts> {
+        self.polar.get()
+    }
+
+    // Drop the polar tables until a mode needs them again
+    pub(crate) fn free_polar(&mut self) {
+        self.polar.take();
+    }
+
+    // Distance of pixel (x, y) from the center, through the lens model if there is one
+    fn true_distance(&self, x: u32, y: u32) -> f32 {
+        let (dx, dy) = (x as f32 - self.center_x, y as f32 - self.center_y);
+        let distance = (dx * dx + dy * dy).sqrt();
+        self.lens
+            .as_ref()
+            .map_or(distance, |lens| lens.true_distance(distance))
+    }
+}
+
+// Cache of the lookup tables of the detectors created with `MotionDetector::with_context`,
+// e.g. for several cameras or picture-in-picture at one resolution. The detectors own their
+// tables; the context only remembers them, so tables no detector uses any more are freed.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Default)]
+pub struct DetectorContext {
+    luts: Rc<RefCell<Vec<Weak<DetectorLuts>>>>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl DetectorContext {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> DetectorContext {
+        DetectorContext::default()
+    }
+
+    // Distinct sets of tables in use by the context's detectors
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn shared_table_count(&self) -> usize {
+        self.luts
+            .borrow()
+            .iter()
+            .filter(|luts| luts.strong_count() > 0)
+            .count()
+    }
+}
+
+impl DetectorContext {
+    // The tables for `key`, built only when no detector of the context holds them yet
+    pub(crate) fn luts(&self, key: LutKey) -> Rc<DetectorLuts> {
+        let mut cache = self.luts.borrow_mut();
+        cache.retain(|luts| luts.strong_count() > 0);
+        if let Some(shared) = cache
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|luts| luts.key == key)
+        {
+            return shared;
+        }
+        let built = Rc::new(DetectorLuts::build(key));
+        cache.push(Rc::downgrade(&built));
+        built
+    }
+}
@@ -0,0 +1,7347 @@
+// The motion detector: per-frame decode, diff, detection and trail persistence, plus the
+// state it keeps between frames. Move modes live in `movement`, output colouring in `render`.
+use std::borrow::Cow;
+use std::cell::OnceCell;
+use std::collections::VecDeque;
+use std::f32::consts::TAU;
+use std::ops::Range;
+use std::rc::Rc;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::{Clamped, JsCast, JsValue};
+#[cfg(feature = "wasm")]
+use web_sys::{
+    CanvasRenderingContext2d, HtmlVideoElement, ImageBitmap, ImageData, OffscreenCanvas,
+    OffscreenCanvasRenderingContext2d, WebGl2RenderingContext, WebGlTexture,
+};
+
+use crate::capture::{CapturedFrame, FrameCapture};
+use crate::context::{DetectorContext, DetectorLuts, LensModel, LutKey, PolarLuts};
+use crate::diagnostics::{Diagnostics, LogLevel};
+use crate::fluid::{Fluid, FluidSettings};
+#[cfg(feature = "threads")]
+use crate::movement::parallel_band_rows;
+use crate::movement::{
+    self, Attractor, FlowField, FrameContext, MoveTransition, Movement, ParameterTransition,
+    Perspective, Prng, PyramidLevel, RowBand, DEFAULT_MOVE_TRANSITION_FRAMES, MAX_ATTRACTORS,
+    PYRAMID_MAX_LEVELS,
+};
+#[cfg(feature = "wasm")]
+use crate::movement::{ATTRACTOR_DEFAULT_RADIUS, ATTRACTOR_DEFAULT_STRENGTH};
+use crate::particles::ParticleField;
+use crate::registration::FramePair;
+use crate::render::{
+    build_palette, build_ramp, hue_to_rgb, BlendMode, LevelCurve, PixelFormat, Silhouette,
+    ToneMapping, TrailFade, Vignette,
+};
+#[cfg(feature = "wasm")]
+use crate::timeline::{Easing, KeyframeValue};
+use crate::timeline::{Keyframe, Timeline};
+use crate::trigger::{DebounceUnit, MotionEvent, MotionTrigger};
+use crate::zones::Zones;
+#[cfg(feature = "wasm")]
+use crate::zones::MAX_ZONES;
+use crate::{
+    check_memory, check_resolution, estimated_memory_bytes, js_error, now_ms, simd, testing,
+    AccumulationMode, AlphaMode, AutoThreshold, BackgroundModel, Blob, ChannelMode,
+    ComparisonLayout, CompositeMode, DetectionMode, ExportError, GlobalMotion, MotionOptions,
+    MotionProjections, MotionStats, MoveType, OutputMode, PowerTier, RadialProfile,
+    RecordingSource, TemporalFilter, Track, Zone, ZoneEvent, DETECT_CHUNK, MAX_DIFFUSION,
+    MAX_FLUID_CELL_SIZE, MAX_GRID_STEP, MAX_MORPHOLOGY_ITERATIONS, MAX_PROCESS_EVERY_N,
+    MAX_TEMPORAL_WINDOW, NOISE_INITIAL_SIGMA, NOISE_LEARNING_RATE, NOISE_MIN_SIGMA,
+    REFERENCE_FRAME_MS,
+};
+#[cfg(feature = "wasm")]
+use crate::{js_exception, yield_to_event_loop, WorkerFrame, DEFAULT_ASYNC_CHUNK_ROWS};
+
+// Rectangle in pixel coordinates, clamped to the frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Rect {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+impl Rect {
+    fn full(width: u32, height: u32) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: width as usize,
+            height: height as usize,
+        }
+    }
+
+    fn rows(&self) -> Range<usize> {
+        self.y..self.y + self.height
+    }
+
+    fn cols(&self) -> Range<usize> {
+        self.x..self.x + self.width
+    }
+
+    fn contains(&self, x: usize, y: usize) -> bool {
+        self.cols().contains(&x) && self.rows().contains(&y)
+    }
+
+    fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect {
+            x,
+            y,
+            width: (self.x + self.width)
+                .min(other.x + other.width)
+                .saturating_sub(x),
+            height: (self.y + self.height)
+                .min(other.y + other.height)
+                .saturating_sub(y),
+        }
+    }
+}
+
+// Channels at or below this level count as black when looking for letterbox bars
+const LETTERBOX_BLACK_LEVEL: u8 = 16;
+// Frames between letterbox scans; a new layout must be seen on two scans in a row
+const LETTERBOX_CHECK_INTERVAL: u32 = 30;
+
+// Borrowed input frame in one of the supported sample layouts
+#[derive(Clone, Copy)]
+enum FrameInput<'a> {
+    // 8-bit interleaved pixels in the detector's configured input format, rows `row_stride`
+    // bytes apart (0 = no padding between rows)
+    Packed {
+        data: &'a [u8],
+        row_stride: usize,
+    },
+    // 16-bit per channel RGBA (RGBA64)
+    Rgba64(&'a [u16]),
+    // 16-bit single-channel luminance
+    Gray16(&'a [u16]),
+    // 8-bit luminance converted by the caller, used without the luma weighting
+    Gray8(&'a [u8]),
+    // Linear floating-point RGBA, e.g. WebGPU HDR readbacks
+    RgbaF32(&'a [f32]),
+    // Separate 8-bit R, G and B planes as produced by some decoders and ML pipelines
+    Planar {
+        r: &'a [u8],
+        g: &'a [u8],
+        b: &'a [u8],
+    },
+    // 4:2:0 YUV (I420, or NV12 with interleaved UV in `u_plane` and no `v_plane`) as found
+    // in `VideoFrame`s; rows are `y_stride` / `uv_stride` bytes apart
+    Yuv420 {
+        y_plane: &'a [u8],
+        u_plane: &'a [u8],
+        v_plane: &'a [u8],
+        y_stride: usize,
+        uv_stride: usize,
+    },
+}
+
+impl<'a> FrameInput<'a> {
+    fn packed(data: &'a [u8]) -> FrameInput<'a> {
+        FrameInput::Packed {
+            data,
+            row_stride: 0,
+        }
+    }
+
+    // Whether the input holds a whole `width` x `height` frame; packed input has
+    // `bytes_per_pixel` bytes per pixel
+    fn check_len(&self, width: usize, height: usize, bytes_per_pixel: usize) -> Result<(), String> {
+        let pixels = width * height;
+        let needs = |name: &str, len: usize, needed: usize| {
+            if len < needed {
+                return Err(format!(
+                    "{} holds {} elements, a {}x{} frame needs {}",
+                    name, len, width, height, needed
+                ));
+            }
+            Ok(())
+        };
+        match *self {
+            FrameInput::Packed { data, row_stride } => {
+                let row_len = width * bytes_per_pixel;
+                if row_stride != 0 && row_stride < row_len {
+                    return Err(format!(
+                        "row stride {} is narrower than a {} pixel wide frame",
+                        row_stride, width
+                    ));
+                }
+                let needed = match row_stride {
+                    0 => pixels * bytes_per_pixel,
+                    _ => height.saturating_sub(1) * row_stride + row_len,
+                };
+                needs("input", data.len(), needed)
+            }
+            FrameInput::Rgba64(data) => needs("input", data.len(), pixels * 4),
+            FrameInput::Gray16(data) => needs("input", data.len(), pixels),
+            FrameInput::Gray8(data) => needs("input", data.len(), pixels),
+            FrameInput::RgbaF32(data) => needs("input", data.len(), pixels * 4),
+            FrameInput::Planar { r, g, b } => {
+                needs("R plane", r.len(), pixels)?;
+                needs("G plane", g.len(), pixels)?;
+                needs("B plane", b.len(), pixels)
+            }
+            FrameInput::Yuv420 {
+                y_plane,
+                u_plane,
+                v_plane,
+                y_stride,
+                uv_stride,
+            } => {
+                // NV12 interleaves U and V in one plane of twice the width
+                let chroma_width = width.div_ceil(2) * if v_plane.is_empty() { 2 } else { 1 };
+                if y_stride < width || uv_stride < chroma_width {
+                    return Err(format!(
+                        "strides {} and {} are narrower than a {} pixel wide frame",
+                        y_stride, uv_stride, width
+                    ));
+                }
+                let chroma_len = height.div_ceil(2).saturating_sub(1) * uv_stride + chroma_width;
+                needs(
+                    "Y plane",
+                    y_plane.len(),
+                    height.saturating_sub(1) * y_stride + width,
+                )?;
+                needs("U plane", u_plane.len(), chroma_len)?;
+                if v_plane.is_empty() {
+                    return Ok(());
+                }
+                needs("V plane", v_plane.len(), chroma_len)
+            }
+        }
+    }
+}
+
+// Ring buffer of the last frames for `drain_recording`; frames pushed out of the ring are
+// reused for the next ones
+struct Recording {
+    source: RecordingSource,
+    capacity: usize,
+    frames: VecDeque<Vec<u8>>,
+}
+
+// Fraction of changed pixels below which a frame counts as quiet
+const POWER_QUIET_ACTIVITY: f32 = 0.001;
+// Consecutive quiet frames before dropping to `Reduced` and to `Idle`
+const POWER_REDUCED_AFTER: u32 = 30;
+const POWER_IDLE_AFTER: u32 = 120;
+const POWER_IDLE_INTERVAL: u32 = 4;
+
+// Largest gain either way of `exposure_compensation`, so a frame going (nearly) black isn't
+// blown up into noise
+const EXPOSURE_MAX_GAIN: f32 = 4.0;
+
+// Largest camera shift `stabilize` cancels, in processing pixels, and how clearly the frames
+// must line up for it to shift at all
+const STABILIZE_MAX_SHIFT: u32 = 16;
+const STABILIZE_MIN_CONFIDENCE: f32 = 0.3;
+
+// Particles per detector, whatever `set_particles` asks for
+const MAX_PARTICLES: usize = 1 << 16;
+
+// Taps of `set_echo`, and frames the longest of them may reach back (a trail snapshot per
+// frame is kept that far)
+const MAX_ECHO_TAPS: usize = 8;
+const MAX_ECHO_DELAY: u32 = 64;
+
+// `auto_threshold`: share of the way to each frame's Otsu threshold, and the range it is
+// kept in so a still frame doesn't pull it down into the sensor noise
+const AUTO_THRESHOLD_SMOOTHING: f32 = 0.1;
+const AUTO_THRESHOLD_RANGE: (f32, f32) = (8.0, 128.0);
+
+// `process_frame_at`: longest frame interval the movement is timed with, so a stalled
+// decoder or a paused stream doesn't fling the trails, and the smoothing of `effective_fps`
+const MAX_TIMESTAMP_DELTA_MS: f32 = 250.0;
+const FPS_SMOOTHING: f32 = 0.1;
+
+// Most frames `set_frame_capture` keeps from before a trigger, and captures it holds
+const MAX_PRE_TRIGGER_FRAMES: u32 = 30;
+const MAX_CAPTURES: u32 = 256;
+
+// Longest ring of `record_frames`, ten seconds at 60 fps
+const MAX_RECORDED_FRAMES: u32 = 600;
+
+// Ramp of the `dwell` output mode until `set_dwell_colors`: embers glowing through red and
+// yellow to white, and the most colors it takes
+const DEFAULT_DWELL_COLORS: [u32; 4] = [0x400000, 0xff0000, 0xffff00, 0xffffff];
+const MAX_DWELL_COLORS: usize = 16;
+
+// Widest kernel of `set_detection_kernel`
+const MAX_DETECTION_KERNEL_SIZE: u32 = 9;
+
+// Bits of `last_frame_flags`: the frame was taken as a scene change
+pub const FRAME_SCENE_CHANGE: u32 = 1;
+
+// Frame time controller of `set_target_frame_time_ms`: over budget it first shrinks the
+// high and medium quality radii, then processes at a coarser scale; with time to spare it
+// walks back the same way
+#[derive(Clone, Copy, Debug)]
+struct FrameBudget {
+    target_ms: f32,
+    // Smoothed processing time of recent frames (None before the first one)
+    average_ms: Option<f32>,
+    // Share of the quality radii kept, 0..1
+    radius_scale: f32,
+    // Processing downscale on top of the `processing_scale` option
+    extra_factor: u32,
+    // Set once the caller reports timings; the detector's own measurement is then unused
+    external: bool,
+}
+
+// Weight of each new frame time in the average, the over/under budget margins and the
+// steps of the controller
+const BUDGET_SMOOTHING: f32 = 0.2;
+const BUDGET_OVER: f32 = 1.1;
+const BUDGET_UNDER: f32 = 0.6;
+const BUDGET_RADIUS_DOWN: f32 = 0.1;
+const BUDGET_RADIUS_UP: f32 = 0.05;
+const BUDGET_MAX_EXTRA_FACTOR: u32 = 4;
+
+impl FrameBudget {
+    fn new(target_ms: f32) -> FrameBudget {
+        FrameBudget {
+            target_ms,
+            average_ms: None,
+            radius_scale: 1.0,
+            extra_factor: 1,
+            external: false,
+        }
+    }
+
+    fn update(&mut self, frame_ms: f32) {
+        let average = self.average_ms.map_or(frame_ms, |average| {
+            average + (frame_ms - average) * BUDGET_SMOOTHING
+        });
+        self.average_ms = Some(average);
+
+        if average > self.target_ms * BUDGET_OVER {
+            if self.radius_scale > 0.0 {
+                self.radius_scale = (self.radius_scale - BUDGET_RADIUS_DOWN).max(0.0);
+            } else if self.extra_factor < BUDGET_MAX_EXTRA_FACTOR {
+                self.extra_factor += 1;
+                self.radius_scale = 1.0;
+                // Judge the new scale by its own frames
+                self.average_ms = None;
+            }
+        } else if average < self.target_ms * BUDGET_UNDER {
+            if self.radius_scale < 1.0 {
+                self.radius_scale = (self.radius_scale + BUDGET_RADIUS_UP).min(1.0);
+            } else if self.extra_factor > 1 {
+                self.extra_factor -= 1;
+                self.radius_scale = 0.0;
+                self.average_ms = None;
+            }
+        }
+    }
+}
+
+// Separable Gaussian blur of the `rect` area of a frame `width` pixels wide, reading
+// clamped to the rect edges; `scratch` holds the horizontal pass
+fn gaussian_blur(
+    source: &[f32],
+    target: &mut [f32],
+    scratch: &mut [f32],
+    width: usize,
+    rect: Rect,
+    sigma: f32,
+) {
+    let radius = (sigma * 3.0).ceil().max(1.0) as usize;
+    let mut kernel: Vec<f32> = (0..=radius * 2)
+        .map(|i| {
+            let offset = i as f32 - radius as f32;
+            (-(offset * offset) / (2.0 * sigma * sigma)).exp()
+        })
+        .collect();
+    let total: f32 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|weight| *weight /= total);
+
+    let clamp_x = |x: isize| x.clamp(rect.x as isize, (rect.x + rect.width) as isize - 1) as usize;
+    let clamp_y = |y: isize| y.clamp(rect.y as isize, (rect.y + rect.height) as isize - 1) as usize;
+
+    for y in rect.rows() {
+        for x in rect.cols() {
+            let mut sum = 0.0;
+            for (i, weight) in kernel.iter().enumerate() {
+                let sample_x = clamp_x(x as isize + i as isize - radius as isize);
+                sum += source[y * width + sample_x] * weight;
+            }
+            scratch[y * width + x] = sum;
+        }
+    }
+    for y in rect.rows() {
+        for x in rect.cols() {
+            let mut sum = 0.0;
+            for (i, weight) in kernel.iter().enumerate() {
+                let sample_y = clamp_y(y as isize + i as isize - radius as isize);
+                sum += scratch[sample_y * width + x] * weight;
+            }
+            target[y * width + x] = sum;
+        }
+    }
+}
+
+// 0-255 values as 16-bit fixed point with 8 fractional bits, for the state low-memory
+// detectors keep between frames; values past the range are clamped to it, NaN to 0
+fn pack_fixed(values: &[f32]) -> Vec<u16> {
+    values
+        .iter()
+        .map(|&value| (value.clamp(0.0, 255.0) * 256.0).round() as u16)
+        .collect()
+}
+
+fn unpack_fixed(values: &[u16]) -> Vec<f32> {
+    values.iter().map(|&value| value as f32 / 256.0).collect()
+}
+
+// Bilinear rescale of a single-channel buffer, sampling pixel centers with clamped edges
+fn resample_bilinear(
+    source: &[f32],
+    source_width: usize,
+    source_height: usize,
+    target_width: usize,
+    target_height: usize,
+) -> Vec<f32> {
+    let mut target = Vec::with_capacity(target_width * target_height);
+    if source_width == 0 || source_height == 0 {
+        target.resize(target_width * target_height, 0.0);
+        return target;
+    }
+
+    let scale_x = source_width as f32 / target_width.max(1) as f32;
+    let scale_y = source_height as f32 / target_height.max(1) as f32;
+    for y in 0..target_height {
+        let source_y = ((y as f32 + 0.5) * scale_y - 0.5).clamp(0.0, (source_height - 1) as f32);
+        let y0 = source_y as usize;
+        let y1 = (y0 + 1).min(source_height - 1);
+        let fy = source_y - y0 as f32;
+        for x in 0..target_width {
+            let source_x = ((x as f32 + 0.5) * scale_x - 0.5).clamp(0.0, (source_width - 1) as f32);
+            let x0 = source_x as usize;
+            let x1 = (x0 + 1).min(source_width - 1);
+            let fx = source_x - x0 as f32;
+
+            let top = source[y0 * source_width + x0]
+                + (source[y0 * source_width + x1] - source[y0 * source_width + x0]) * fx;
+            let bottom = source[y1 * source_width + x0]
+                + (source[y1 * source_width + x1] - source[y1 * source_width + x0]) * fx;
+            target.push(top + (bottom - top) * fy);
+        }
+    }
+    target
+}
+
+// Region of the frame `set_output_viewport` writes, in frame pixels, and the size of the
+// output it is fitted into
+#[derive(Clone, Copy, Debug)]
+struct OutputViewport {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    target_width: u32,
+    target_height: u32,
+}
+
+// Weights of `set_detection_kernel`, row-major, `size` x `size` with `size` odd
+#[derive(Clone, Debug)]
+struct DetectionKernel {
+    weights: Vec<f32>,
+    size: usize,
+}
+
+// Difference-of-Gaussians band-pass applied to the frame difference
+#[derive(Clone, Copy, Debug)]
+struct DogFilter {
+    inner_sigma: f32,
+    outer_sigma: f32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct MotionDetector {
+    // Processing resolution; the frames read and written are `frame_width` x `frame_height`,
+    // `processing_factor` times larger (rounded up)
+    width: u32,
+    height: u32,
+    frame_width: u32,
+    frame_height: u32,
+    processing_factor: u32,
+    // Footprint limit of `with_memory_limit`, which `resize` keeps to as well
+    memory_limit: Option<u64>,
+    // Output at processing resolution before upscaling (empty at full resolution)
+    scaled_output: Vec<u8>,
+    persistence_buffer: Vec<f32>,
+    // Between frames of a low-memory detector the trails and the cached previous frame as
+    // `pack_fixed` values instead, and the trails unpacked again for the readers of
+    // `trails`; all empty while a frame runs and with the mode off
+    packed_persistence: Vec<u16>,
+    packed_gray: Vec<u16>,
+    unpacked_persistence: OnceCell<Vec<f32>>,
+    // Lookup tables for the radial profile and the area they are centered on, shared with
+    // the other detectors of `lut_context` if there is one
+    radial_profile: RadialProfile,
+    lens_model: Option<LensModel>,
+    // Polar tables, trails and frame cache as 16-bit fixed point, see `set_low_memory`
+    low_memory: bool,
+    luts: Rc<DetectorLuts>,
+    lut_context: Option<DetectorContext>,
+    // Optimization #2: Reusable buffer to avoid allocations
+    temp_buffer: Vec<f32>,
+    // Downsampled copies of the persistence buffer (level 1 first), rebuilt each frame when
+    // enabled, so large displacements sample pre-filtered trails instead of aliasing
+    pyramid_levels: u32,
+    persistence_pyramid: Vec<PyramidLevel>,
+    // Move mode switching: options of the previous frame, the running cross-fade, its
+    // length, and where the outgoing movement is rendered during it
+    last_move_params: Option<MotionOptions>,
+    move_transition: Option<MoveTransition>,
+    move_transition_frames: u32,
+    transition_buffer: Vec<f32>,
+    // Options of the previous frame as used (before timing), and the running
+    // `transition_to` easing of the movement parameters
+    last_options: Option<MotionOptions>,
+    parameter_transition: Option<ParameterTransition>,
+    // Optimization #6: Cache previous frame in Rust (50% less data transfer), stored as
+    // luma so the cache is independent of the input pixel format
+    previous_gray: Vec<f32>,
+    // Luma of the frame being processed; swapped with `previous_gray` after each frame
+    current_gray: Vec<f32>,
+    // Scratch for the shifted previous frame of `stabilize` (empty until first used)
+    stabilized_gray: Vec<f32>,
+    // Unfiltered luma of the last frames for `temporal_filter` (None while it's off)
+    temporal_history: Option<TemporalHistory>,
+    // Background estimate for the `background_sub` detection mode (empty until first used)
+    // and how far the temporal median moves towards each new frame
+    background_model: BackgroundModel,
+    background_gray: Vec<f32>,
+    background_step: f32,
+    // Absolute frame difference of the frame being processed, filled before detection so
+    // whole-frame filters can run on it
+    diff_buffer: Vec<f32>,
+    // Blob tracks carried between `update_tracks` calls
+    tracker: Tracker,
+    // Motion-spawned particles of `set_particles`, in processing pixels
+    particles: Option<ParticleField>,
+    // Vector field of the flow move mode
+    flow_field: Option<FlowField>,
+    // Per-frame warp of the perspective move mode
+    perspective: Option<Perspective>,
+    // Points of the attract move mode
+    attractors: Vec<Attractor>,
+    // Shake mode: the random sequence, the seed it was started from and this frame's offset
+    shake_rng: Prng,
+    shake_seed: Option<u32>,
+    shake_offset: (f32, f32),
+    // Latest modulation signals (e.g. FFT bands) for modulated options
+    modulation: Vec<f32>,
+    // Optional DoG saliency filter and its working buffers (empty while it's off)
+    dog_filter: Option<DogFilter>,
+    dog_buffers: [Vec<f32>; 3],
+    // Cropped and scaled output of `set_output_viewport` (None = the whole frame), and the
+    // full-frame output it is taken from
+    output_viewport: Option<OutputViewport>,
+    viewport_frame: Vec<u8>,
+    // Optional neighbourhood kernel over the frame difference and its output buffer
+    detection_kernel: Option<DetectionKernel>,
+    kernel_buffer: Vec<f32>,
+    // Horizontal pass of the erode / dilate filters (empty while they're off)
+    morphology_buffer: Vec<f32>,
+    // Hue of the motion direction last seen at each pixel for `output_mode: "direction"`
+    // (empty in the other modes)
+    direction_hue: Vec<f32>,
+    // Trail level at each sample of `grid_mode`, row-major and `motion_grid_width` samples
+    // wide (empty while it's off)
+    motion_grid: Vec<f32>,
+    motion_grid_width: usize,
+    // Smoothed threshold of `auto_threshold` (None until a frame was analysed with it)
+    auto_threshold: Option<f32>,
+    // Trigger zones of `define_zones` and their queued events
+    zones: Zones,
+    // Debounced started / stopped signals of `set_motion_trigger`
+    motion_trigger: Option<MotionTrigger>,
+    // Motion-triggered snapshots of `set_frame_capture`
+    frame_capture: Option<FrameCapture>,
+    // Keyframed options of `load_timeline`
+    timeline: Option<Timeline>,
+    // Projector-to-camera warp of `suppress_self_feedback` (None = the camera sees the output
+    // unwarped) and the previous output's luma in camera space at processing resolution
+    // (empty while it's off)
+    feedback_homography: Option<Perspective>,
+    feedback_gray: Vec<f32>,
+    // Key color and RGB distance of `set_ignore_color`
+    ignore_color: Option<([u8; 3], f32)>,
+    // Running mean and standard deviation of each pixel's luminance for
+    // `adaptive_threshold` (empty while it's off)
+    noise_mean: Vec<f32>,
+    noise_sigma: Vec<f32>,
+    // Blurred trails and the horizontal pass of `diffusion` (empty while it's off)
+    diffusion_buffers: [Vec<f32>; 2],
+    // R, G and B of every pixel of the current and previous frame for the per-channel
+    // difference (empty while it's off; the previous frame is missing for the first frame)
+    channel_frames: [Vec<f32>; 2],
+    // One bit per pixel: whether it was moving in the last frame, for the threshold
+    // hysteresis (empty while it's off)
+    hysteresis_state: Vec<u64>,
+    // Motion summary of `accumulation_mode`, the mode it was built with and the analysed
+    // frames in it (empty until the mode is first used)
+    accumulation_buffer: Vec<f32>,
+    accumulation_source: AccumulationMode,
+    accumulated_frames: u32,
+    // Extra trail layers blended over the main trails, bottom first
+    persistence_layers: Vec<PersistenceLayer>,
+    echo: Option<Echo>,
+    input_format: PixelFormat,
+    output_format: PixelFormat,
+    // Output colors per persistence level, and the output mode / tint / level curve they
+    // were built for
+    palette: [[u8; 3]; 256],
+    palette_key: (OutputMode, u32, LevelCurve),
+    // Brightness factor per output pixel of `output_vignette_*`, and the vignette it was built
+    // for (empty while it's off)
+    vignette_lut: Vec<f32>,
+    vignette_key: Option<Vignette>,
+    // Exposure mapping for floating-point input: linear scale (2^EV) and tone curve
+    hdr_exposure_scale: f32,
+    hdr_tone_mapping: ToneMapping,
+    // Output owned by WASM so it can be blitted without a JS-side copy
+    output_buffer: Vec<u8>,
+    // Last frames kept by `record_frames`
+    recording: Option<Recording>,
+    // Input frame written by JS through `input_ptr` for `process_in_place`
+    input_buffer: Vec<u8>,
+    // Offscreen canvas used to read pixels for `process_from_video`, created on first use
+    #[cfg(feature = "wasm")]
+    capture_context: Option<OffscreenCanvasRenderingContext2d>,
+    // Region of interest: detection, movement and output only run inside it
+    roi: Option<Rect>,
+    // Outside the ROI: show the input frame (true) or render black (false)
+    roi_passthrough: bool,
+    // Trails left outside a new ROI are still decaying towards zero
+    outside_roi_fading: bool,
+    // Per-pixel weight (0..1) of detected motion; empty without a mask
+    mask: Vec<f32>,
+    // Per-pixel decay rates replacing `decay_rate`; empty without a decay map
+    decay_map: Vec<f32>,
+    // Filled motion mask of the `silhouette` output mode; empty in the other modes
+    silhouette: Silhouette,
+    // Velocity and dye of the `fluid` output mode; empty in the other modes
+    fluid: Fluid,
+    // Milliseconds of motion per pixel of the `dwell` output mode (empty in the other modes)
+    // and the colors of its ramp
+    dwell_ms: Vec<f32>,
+    dwell_ramp: [[u8; 3]; 256],
+    // Log level, messages and stage timings of `debug_info`
+    diagnostics: Diagnostics,
+    // Set by `dispose`: the buffers are gone and frames are refused
+    disposed: bool,
+    // Movement steps applied in order instead of the frame's move type (empty: off)
+    movement_pipeline: Vec<MotionOptions>,
+    // Letterbox detection: picture area without black bars (None = whole frame), the
+    // layout seen on the last scan, and frames until the next scan
+    auto_crop: bool,
+    content_rect: Option<Rect>,
+    // Clockwise quarter turns of the picture inside the buffer (see `set_orientation`)
+    orientation_quarter_turns: u32,
+    letterbox_candidate: Option<Rect>,
+    frames_until_letterbox_check: u32,
+    // Automatic power saving: current tier, quiet frames so far and frames since the last
+    // analysed frame
+    power_saving: bool,
+    power_tier: PowerTier,
+    quiet_frames: u32,
+    frames_since_analysis: u32,
+    // Movement-only frames left before the next detection with `process_every_n`
+    frames_until_detection: u32,
+    // Automatic quality for a target frame time (None = off)
+    frame_budget: Option<FrameBudget>,
+    // Wall-clock time of the last frame and the time since the one before, for fades that
+    // are timed rather than per frame
+    last_frame_ms: Option<f64>,
+    frame_elapsed_ms: f32,
+    // Sum of the frame times so far, the clock of motion events
+    stream_time_ms: f64,
+    // Timestamp of the last frame `process_frame_at` took, the frames it dropped and its
+    // smoothed frame rate
+    last_timestamp_us: Option<f64>,
+    dropped_frames: u32,
+    effective_fps: f32,
+    // Reference frames per frame with `delta_time_ms`, applied to every movement step
+    frame_time_scale: f32,
+    // Threshold of the last detection pass, to re-threshold `diff_buffer` for blobs, and
+    // its `adaptive_k` when it was adaptive
+    motion_threshold: f32,
+    adaptive_k: Option<f32>,
+    // Motion totals of the frame being processed, and the summary of the last analysed one
+    frame_totals: FrameTotals,
+    last_stats: MotionStats,
+    // FRAME_* bits of the last processed frame
+    frame_flags: u32,
+    is_first_frame: bool,
+    phase: f32,
+    // Optimization #6: Distance-based processing thresholds for approximation
+    center_x: f32,
+    center_y: f32,
+    // Distance thresholds for different quality levels, and the distance of the corners
+    // they are fractions of
+    high_quality_radius: f32,
+    medium_quality_radius: f32,
+    max_radius: f32,
+}
+
+// Native Rust API, used by the CLI, the Python bindings and headless builds without the
+// `wasm` feature
+impl MotionDetector {
+    // JS-free processing core shared by the wasm entry points and the native bindings;
+    // `current_data` and `output_data` are frames at the detector resolution in the
+    // configured input and output pixel formats (RGBA by default)
+    pub fn process(
+        &mut self,
+        current_data: &[u8],
+        output_data: &mut [u8],
+        params: &MotionOptions,
+    ) -> Result<(), String> {
+        self.process_input(FrameInput::packed(current_data), output_data, params)
+    }
+
+    fn process_input(
+        &mut self,
+        input: FrameInput,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+    ) -> Result<(), String> {
+        let input_format = self.input_format_for(params);
+        self.diagnostics.begin_frame(params);
+        if let Err(message) = self.check_frame(input, input_format, output_data.len()) {
+            self.diagnostics.log(LogLevel::Error, message.clone());
+            return Err(message);
+        }
+        self.input_format = input_format;
+        self.unpack_state();
+        let start = now_ms();
+        // With a viewport the frame is rendered whole first and cropped into the output
+        let viewport = self.output_viewport;
+        let mut viewport_frame = std::mem::take(&mut self.viewport_frame);
+        let frame_output = if viewport.is_some() {
+            viewport_frame.resize(self.frame_output_len(), 0);
+            &mut viewport_frame[..]
+        } else {
+            &mut *output_data
+        };
+        self.update_processing_scale(params);
+        if self.processing_factor == 1 {
+            self.process_scaled(input, frame_output, params);
+        } else {
+            let mut scaled = std::mem::take(&mut self.scaled_output);
+            scaled.resize(self.scaled_output_len(), 0);
+            self.process_scaled(input, &mut scaled, params);
+            self.upscale_output(&scaled, frame_output);
+            self.scaled_output = scaled;
+        }
+        let params = &self.animated(params);
+        self.render_silhouette(frame_output, params);
+        self.render_fluid(frame_output, params);
+        self.render_dwell(frame_output, params);
+        self.apply_vignette(frame_output, params);
+        self.composite_over_input(input, frame_output, params);
+        self.encode_alpha(frame_output, params);
+        self.capture_feedback(frame_output, params);
+        if let Some(viewport) = viewport {
+            self.write_viewport(viewport, &viewport_frame, output_data);
+        }
+        self.viewport_frame = viewport_frame;
+        self.record_frame(output_data);
+        self.diagnostics.end_stage("output");
+        self.diagnostics.end_frame();
+        // Frame times differ from run to run
+        if self.frame_budget.is_some_and(|budget| !budget.external)
+            && !cfg!(feature = "deterministic")
+        {
+            self.update_frame_budget((now_ms() - start) as f32);
+        }
+        self.pack_state();
+        Ok(())
+    }
+
+    // Layout of frames processed with `params`: the `input_format` option's if set. The
+    // detector only switches to it once a frame in it has passed `check_frame`.
+    fn input_format_for(&self, params: &MotionOptions) -> PixelFormat {
+        params.input_format.unwrap_or(self.input_format)
+    }
+
+    // Reject frames and output buffers too short for the frame resolution before anything
+    // reads or writes them
+    fn check_frame(
+        &self,
+        input: FrameInput,
+        input_format: PixelFormat,
+        output_len: usize,
+    ) -> Result<(), String> {
+        self.check_disposed()?;
+        let (width, height) = (self.frame_width, self.frame_height);
+        input.check_len(
+            width as usize,
+            height as usize,
+            input_format.bytes_per_pixel(),
+        )?;
+        if output_len < self.output_len() {
+            return Err(format!(
+                "output holds {} bytes, a {}x{} frame needs {}",
+                output_len,
+                width,
+                height,
+                self.output_len()
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_disposed(&self) -> Result<(), String> {
+        if self.disposed {
+            return Err("detector has been disposed".to_string());
+        }
+        Ok(())
+    }
+
+    // Darken the finished trails towards the frame edges for `output_vignette_strength`; with
+    // `roi_passthrough` the frame outside the ROI is left alone
+    fn apply_vignette(&mut self, output_data: &mut [u8], params: &MotionOptions) {
+        let vignette = Vignette {
+            strength: params.output_vignette_strength,
+            radius: params.output_vignette_radius,
+            softness: params.output_vignette_softness,
+        };
+        if vignette.is_off() {
+            if self.vignette_key.is_some() {
+                self.vignette_lut = Vec::new();
+                self.vignette_key = None;
+            }
+            return;
+        }
+        let frame_width = self.frame_width as usize;
+        let frame_height = self.frame_height as usize;
+        if self.vignette_key != Some(vignette)
+            || self.vignette_lut.len() != frame_width * frame_height
+        {
+            let (half_width, half_height) = (frame_width as f32 / 2.0, frame_height as f32 / 2.0);
+            let inv_max_radius = 1.0 / half_width.hypot(half_height).max(1.0);
+            self.vignette_lut.clear();
+            for y in 0..frame_height {
+                let dy = y as f32 + 0.5 - half_height;
+                self.vignette_lut.extend((0..frame_width).map(|x| {
+                    let dx = x as f32 + 0.5 - half_width;
+                    vignette.gain(dx.hypot(dy) * inv_max_radius)
+                }));
+            }
+            self.vignette_key = Some(vignette);
+        }
+
+        let factor = self.processing_factor as usize;
+        let active = self.active_rect();
+        for (pixel_index, &gain) in self.vignette_lut.iter().enumerate() {
+            let (x, y) = (pixel_index % frame_width, pixel_index / frame_width);
+            if self.roi_passthrough && !active.contains(x / factor, y / factor) {
+                continue;
+            }
+            let rgb = self
+                .output_format
+                .read(output_data, pixel_index)
+                .map(|channel| (channel as f32 * gain).round() as u8);
+            self.output_format.write(output_data, pixel_index, rgb, 255);
+        }
+    }
+
+    // Replace the whole output with the filled silhouette for the `silhouette` output mode,
+    // the area outside the ROI included, so no part of the picture gets through
+    fn render_silhouette(&self, output_data: &mut [u8], params: &MotionOptions) {
+        if params.output_mode != OutputMode::Silhouette {
+            return;
+        }
+        let color = [16, 8, 0].map(|shift| (params.tint_color >> shift) as u8);
+        let (width, height) = (self.width as usize, self.height as usize);
+        let frame_width = self.frame_width as usize;
+        let factor = self.processing_factor as usize;
+        for y in 0..self.frame_height as usize {
+            let row = (y / factor).min(height - 1) * width;
+            for x in 0..frame_width {
+                let filled = self.silhouette.is_filled(row + (x / factor).min(width - 1));
+                let rgb = if filled { color } else { [0; 3] };
+                self.output_format
+                    .write(output_data, y * frame_width + x, rgb, 255);
+            }
+        }
+    }
+
+    // Replace the whole output with the fluid's dye for the `fluid` output mode, sampled
+    // between the cells so the coarse grid doesn't show as blocks
+    fn render_fluid(&self, output_data: &mut [u8], params: &MotionOptions) {
+        if params.output_mode != OutputMode::Fluid {
+            return;
+        }
+        let frame_width = self.frame_width as usize;
+        // Cells cover `fluid_cell_size` pixels of the processed frame
+        let cell_pixels = (fluid_cell_size(params) * self.processing_factor) as f32;
+        for y in 0..self.frame_height as usize {
+            let cell_y = (y as f32 + 0.5) / cell_pixels;
+            for x in 0..frame_width {
+                let dye = self
+                    .fluid
+                    .density_at((x as f32 + 0.5) / cell_pixels, cell_y);
+                let rgb = self.palette[dye.clamp(0.0, 255.0) as usize];
+                self.output_format
+                    .write(output_data, y * frame_width + x, rgb, 255);
+            }
+        }
+    }
+
+    // Replace the whole output with the dwell ramp for the `dwell` output mode; pixels that
+    // have cooled down completely stay black
+    fn render_dwell(&self, output_data: &mut [u8], params: &MotionOptions) {
+        if params.output_mode != OutputMode::Dwell || self.dwell_ms.is_empty() {
+            return;
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        let frame_width = self.frame_width as usize;
+        let factor = self.processing_factor as usize;
+        for y in 0..self.frame_height as usize {
+            let row = (y / factor).min(height - 1) * width;
+            for x in 0..frame_width {
+                let dwell = self.dwell_ms[row + (x / factor).min(width - 1)];
+                let rgb = if dwell > 0.0 {
+                    let level = (dwell / params.dwell_max_ms * 255.0).ceil();
+                    self.dwell_ramp[level.clamp(0.0, 255.0) as usize]
+                } else {
+                    [0; 3]
+                };
+                self.output_format
+                    .write(output_data, y * frame_width + x, rgb, 255);
+            }
+        }
+    }
+
+    // Blend the finished output over the input frame for `composite_mode`. The frame is
+    // mirrored like the trails so the two line up; with `roi_passthrough` the frame outside
+    // the ROI is already there. The `silhouette` output mode never shows the frame.
+    fn composite_over_input(
+        &self,
+        input: FrameInput,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+    ) {
+        if params.output_mode == OutputMode::Silhouette {
+            return;
+        }
+        let (blend_mode, opacity) = match params.composite_mode {
+            CompositeMode::Replace => return,
+            CompositeMode::OverlayAdd => (BlendMode::Add, 1.0),
+            CompositeMode::OverlayScreen => (BlendMode::Screen, 1.0),
+            CompositeMode::AlphaBlend => {
+                (BlendMode::Normal, params.composite_alpha.clamp(0.0, 1.0))
+            }
+        };
+        let frame_width = self.frame_width as usize;
+        let frame_height = self.frame_height as usize;
+        let factor = self.processing_factor as usize;
+        let active = self.active_rect();
+        for y in 0..frame_height {
+            let source_y = if params.flip_vertical {
+                frame_height - 1 - y
+            } else {
+                y
+            };
+            for x in 0..frame_width {
+                if self.roi_passthrough && !active.contains(x / factor, y / factor) {
+                    continue;
+                }
+                let source_x = if params.flip_horizontal {
+                    frame_width - 1 - x
+                } else {
+                    x
+                };
+                let pixel_index = y * frame_width + x;
+                let frame = self.input_rgb(input, source_y * frame_width + source_x);
+                let trail = self.output_format.read(output_data, pixel_index);
+                let rgb = [0, 1, 2].map(|channel| {
+                    let blended = blend_mode.apply(
+                        frame[channel] as f32 / 255.0,
+                        trail[channel] as f32 / 255.0,
+                        opacity,
+                    );
+                    (blended * 255.0).round() as u8
+                });
+                self.output_format.write(output_data, pixel_index, rgb, 255);
+            }
+        }
+    }
+
+    // Write the `alpha_mode` channel over the finished output. Outside the ROI there is no
+    // new motion, and with `roi_passthrough` the frame there stays opaque.
+    fn encode_alpha(&self, output_data: &mut [u8], params: &MotionOptions) {
+        if params.alpha_mode == AlphaMode::Opaque
+            || !matches!(self.output_format, PixelFormat::Rgba | PixelFormat::Bgra)
+        {
+            return;
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        let frame_width = self.frame_width as usize;
+        let factor = self.processing_factor as usize;
+        let active = self.active_rect();
+        let ln_255 = 255f32.ln();
+        for y in 0..self.frame_height as usize {
+            let source_y = (y / factor).min(height - 1);
+            for x in 0..frame_width {
+                let source_x = (x / factor).min(width - 1);
+                let pixel_index = source_y * width + source_x;
+                let inside = active.contains(source_x, source_y);
+                let alpha = if !inside && self.roi_passthrough {
+                    1.0
+                } else {
+                    match params.alpha_mode {
+                        AlphaMode::Opaque => 1.0,
+                        AlphaMode::Freshness => {
+                            let level = self.persistence_buffer[pixel_index];
+                            if level < 1.0 {
+                                0.0
+                            } else {
+                                level.min(255.0).ln() / ln_255
+                            }
+                        }
+                        AlphaMode::Confidence if inside => self.motion_confidence(pixel_index),
+                        AlphaMode::Confidence => 0.0,
+                    }
+                };
+                output_data[(y * frame_width + x) * 4 + 3] = (alpha * 255.0).round() as u8;
+            }
+        }
+    }
+
+    // The output's luma as the camera will see it in the next frame, for
+    // `suppress_self_feedback`
+    fn capture_feedback(&mut self, output_data: &[u8], params: &MotionOptions) {
+        if !params.suppress_self_feedback {
+            self.feedback_gray = Vec::new();
+            return;
+        }
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (frame_width, frame_height) = (self.frame_width as usize, self.frame_height as usize);
+        let mut feedback = std::mem::take(&mut self.feedback_gray);
+        feedback.clear();
+        for y in 0..height {
+            for x in 0..width {
+                let source = self.source_pixel(params, x, y);
+                let u = (source % frame_width) as f32 + 0.5;
+                let v = (source / frame_width) as f32 + 0.5;
+                let projected = match &self.feedback_homography {
+                    Some(homography) => {
+                        homography.map_back(u / frame_width as f32, v / frame_height as f32)
+                    }
+                    None => Some((u / frame_width as f32, v / frame_height as f32)),
+                };
+                let level = projected
+                    .filter(|&(px, py)| (0.0..1.0).contains(&px) && (0.0..1.0).contains(&py))
+                    .map_or(0.0, |(px, py)| {
+                        let output_x = (px * frame_width as f32) as usize;
+                        let output_y = (py * frame_height as f32) as usize;
+                        let [r, g, b] = self
+                            .output_format
+                            .read(output_data, output_y * frame_width + output_x);
+                        r as f32 * 0.299 + g as f32 * 0.587 + b as f32 * 0.114
+                    });
+                feedback.push(level);
+            }
+        }
+        self.feedback_gray = feedback;
+    }
+
+    // Keep this frame's output or trails in the `record_frames` ring
+    fn record_frame(&mut self, output_data: &[u8]) {
+        let output_len = self.output_len();
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        let mut frame = if recording.frames.len() == recording.capacity {
+            recording.frames.pop_front().unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+        frame.clear();
+        match recording.source {
+            RecordingSource::Output => frame.extend_from_slice(&output_data[..output_len]),
+            RecordingSource::Persistence => frame.extend(
+                self.persistence_buffer
+                    .iter()
+                    .map(|&value| value.clamp(0.0, 255.0) as u8),
+            ),
+        }
+        recording.frames.push_back(frame);
+    }
+
+    // One frame at processing resolution; `output_data` is at processing resolution too
+    fn process_scaled(
+        &mut self,
+        input: FrameInput,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+    ) {
+        self.advance_clock(params);
+        self.frame_flags = 0;
+        let params = self.animated(params);
+        let params = self.transitioned(&params);
+        let params = &self.timed(&params);
+        self.update_letterbox(input, params);
+        let rows = self.active_rows();
+
+        // First frame: just cache and return
+        if self.is_first_frame {
+            self.cache_first_frame(input, output_data, params);
+            self.diagnostics.end_stage("decode");
+            return;
+        }
+
+        if self.skip_for_power_saving() {
+            self.fade_rows(output_data, params, rows);
+            self.composite_layers(output_data, params, LayerFrame::Idle);
+            self.render_outside_roi(input, output_data, params);
+            self.diagnostics.end_stage("composite");
+            return;
+        }
+
+        // Perform motion based on type
+        self.begin_frame_movement(params);
+        self.move_rows(params, rows.clone());
+        self.diffuse_trails(params);
+        self.diagnostics.end_stage("move");
+
+        if self.skip_detection(params) {
+            self.fade_moved_rows(output_data, params, rows);
+            self.composite_layers(output_data, params, LayerFrame::Moved);
+            self.render_outside_roi(input, output_data, params);
+            self.diagnostics.end_stage("composite");
+            return;
+        }
+
+        self.decode_rows(input, params, rows.clone());
+        self.stabilize(params);
+        self.compensate_exposure(params);
+        self.diagnostics.end_stage("decode");
+        self.diff_rows(params, rows.clone());
+        self.ignore_key_color(input, params, rows.clone());
+        self.diagnostics.end_stage("diff");
+        if self.detect_scene_change(params) {
+            self.fade_moved_rows(output_data, params, rows);
+            self.composite_layers(output_data, params, LayerFrame::Moved);
+            self.render_outside_roi(input, output_data, params);
+            self.diagnostics.end_stage("composite");
+            self.finish_frame();
+            return;
+        }
+        self.filter_diff();
+        self.morph_diff(params);
+        self.hold_threshold(params, rows.clone());
+        self.detect_rows(output_data, params, rows);
+        self.interpolate_grid(output_data, params);
+        self.diagnostics.end_stage("detect");
+        self.update_zones();
+        self.update_motion_trigger();
+        self.update_capture(input);
+        self.update_silhouette(params);
+        self.update_fluid(params);
+        self.update_dwell(params);
+        self.update_auto_threshold(params);
+        self.accumulate_motion(params);
+        self.diagnostics.end_stage("analyse");
+        self.composite_layers(output_data, params, LayerFrame::Analysed);
+        self.render_outside_roi(input, output_data, params);
+        self.diagnostics.end_stage("composite");
+
+        // Current frame becomes the cached previous frame for the next iteration
+        self.finish_frame();
+    }
+
+    fn advance_clock(&mut self, params: &MotionOptions) {
+        let now = now_ms();
+        // The first frame after construction or a reset takes no time
+        self.frame_elapsed_ms = self.last_frame_ms.map_or(0.0, |last| {
+            if cfg!(feature = "deterministic") {
+                REFERENCE_FRAME_MS
+            } else {
+                (now - last).max(0.0) as f32
+            }
+        });
+        self.last_frame_ms = Some(now);
+        self.frame_time_scale = 1.0;
+        if params.delta_time_ms > 0.0 {
+            self.frame_elapsed_ms = params.delta_time_ms;
+            self.frame_time_scale = params.delta_time_ms / REFERENCE_FRAME_MS;
+        }
+        self.stream_time_ms += self.frame_elapsed_ms as f64;
+        if let Some(timeline) = &mut self.timeline {
+            timeline.advance(self.frame_elapsed_ms);
+        }
+    }
+
+    // `params` with the options of the `load_timeline` keyframes at the current position
+    fn animated(&self, params: &MotionOptions) -> MotionOptions {
+        self.timeline
+            .as_ref()
+            .map_or(*params, |timeline| timeline.apply(params))
+    }
+
+    // `params` with the movement parameters of a running `transition_to`, remembered as the
+    // starting point of the next transition
+    fn transitioned(&mut self, params: &MotionOptions) -> MotionOptions {
+        let stepped = self
+            .parameter_transition
+            .as_mut()
+            .and_then(|transition| transition.step(params));
+        if stepped.is_none() {
+            self.parameter_transition = None;
+        }
+        let params = stepped.unwrap_or(*params);
+        self.last_options = Some(params);
+        params
+    }
+
+    // Options (or a movement step) modulated by the current signals, with the per-frame rates
+    // scaled to this frame's length
+    fn timed(&self, params: &MotionOptions) -> MotionOptions {
+        let mut timed = params
+            .modulated(&self.modulation)
+            .scaled_in_time(self.frame_time_scale);
+        if timed.auto_threshold == AutoThreshold::Otsu {
+            timed.threshold = self.auto_threshold.unwrap_or(timed.threshold);
+        }
+        timed
+    }
+
+    fn trail_fade(&self, params: &MotionOptions) -> TrailFade {
+        if params.history_duration > 0.0 {
+            TrailFade::History(255.0 * self.frame_elapsed_ms / params.history_duration)
+        } else {
+            TrailFade::Decay(params.decay_rate)
+        }
+    }
+
+    // Whether a pixel passed the threshold (and the mask) in the last analysed frame
+    fn is_moving(&self, pixel_index: usize) -> bool {
+        let weighted = self.diff_buffer[pixel_index] * self.luts.radial_sensitivity[pixel_index];
+        let (threshold, offsets, offset_scale) = self.threshold_terms();
+        weighted > threshold + offsets[pixel_index] * offset_scale
+            && self
+                .mask
+                .get(pixel_index)
+                .is_none_or(|weight| *weight > 0.0)
+    }
+
+    // How clearly a pixel cleared its threshold in the last analysed frame: 0 at or below it,
+    // 1 from twice the threshold up, times the mask weight
+    fn motion_confidence(&self, pixel_index: usize) -> f32 {
+        let weighted = self.diff_buffer[pixel_index] * self.luts.radial_sensitivity[pixel_index];
+        let (threshold, offsets, offset_scale) = self.threshold_terms();
+        let limit = (threshold + offsets[pixel_index] * offset_scale).max(1.0);
+        let weight = self.mask.get(pixel_index).copied().unwrap_or(1.0);
+        ((weighted - limit) / limit).clamp(0.0, 1.0) * weight
+    }
+
+    // Whether this frame is skipped by the idle tier; otherwise starts activity counting
+    fn skip_for_power_saving(&mut self) -> bool {
+        self.frame_totals = FrameTotals::default();
+        if self.power_tier != PowerTier::Idle {
+            return false;
+        }
+        self.frames_since_analysis += 1;
+        if self.frames_since_analysis < POWER_IDLE_INTERVAL {
+            return true;
+        }
+        self.frames_since_analysis = 0;
+        false
+    }
+
+    // Whether this frame only moves the trails because of `process_every_n`; the previous
+    // frame cache then stays at the last detection frame
+    fn skip_detection(&mut self, params: &MotionOptions) -> bool {
+        if self.frames_until_detection > 0 {
+            self.frames_until_detection -= 1;
+            return true;
+        }
+        self.frames_until_detection = params.process_every_n.clamp(1, MAX_PROCESS_EVERY_N) - 1;
+        false
+    }
+
+    // Settle the moved trails in `temp_buffer` as the new trails with this frame's fade and
+    // no new motion, writing the output for them
+    fn fade_moved_rows(
+        &mut self,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+        rows: Range<usize>,
+    ) {
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let fade = self.trail_fade(params);
+        self.update_palette(params);
+        let decay_map = (!self.decay_map.is_empty()).then_some(&self.decay_map[..]);
+        for y in rows.clone() {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                let faded = fade
+                    .at(decay_map, self.frame_time_scale, pixel_index)
+                    .apply(self.temp_buffer[pixel_index]);
+                self.persistence_buffer[pixel_index] = faded;
+                let color = self.palette[faded.min(255.0) as usize];
+                self.output_format
+                    .write(output_data, pixel_index, color, 255);
+            }
+        }
+        self.color_by_direction(output_data, params, rows, false);
+    }
+
+    // Pick the tier for the next frame from the activity of the one just analysed: any
+    // motion returns to full quality at once, quiet periods step down gradually
+    fn update_power_tier(&mut self) {
+        if !self.power_saving {
+            return;
+        }
+        let totals = self.frame_totals;
+        let activity = totals.active_pixels as f32 / totals.sampled_pixels.max(1) as f32;
+        if activity > POWER_QUIET_ACTIVITY {
+            self.quiet_frames = 0;
+            self.power_tier = PowerTier::Full;
+            return;
+        }
+
+        // Idle only analyses every few frames, so count the skipped ones too
+        self.quiet_frames += match self.power_tier {
+            PowerTier::Idle => POWER_IDLE_INTERVAL,
+            _ => 1,
+        };
+        self.power_tier = if self.quiet_frames >= POWER_IDLE_AFTER {
+            PowerTier::Idle
+        } else if self.quiet_frames >= POWER_REDUCED_AFTER {
+            PowerTier::Reduced
+        } else {
+            PowerTier::Full
+        };
+    }
+
+    // Frames skipped in the idle tier: trails keep decaying, nothing else is computed
+    fn fade_rows(&mut self, output_data: &mut [u8], params: &MotionOptions, rows: Range<usize>) {
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let fade = self.trail_fade(params);
+        self.update_palette(params);
+        let decay_map = (!self.decay_map.is_empty()).then_some(&self.decay_map[..]);
+        for y in rows.clone() {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                let faded = fade
+                    .at(decay_map, self.frame_time_scale, pixel_index)
+                    .apply(self.persistence_buffer[pixel_index]);
+                self.persistence_buffer[pixel_index] = faded;
+                let color = self.palette[faded.min(255.0) as usize];
+                self.output_format
+                    .write(output_data, pixel_index, color, 255);
+            }
+        }
+        self.color_by_direction(output_data, params, rows, false);
+    }
+
+    // Thresholded, amplified and masked motion of the active columns of row `y` of the last
+    // analysed frame, at full resolution whatever the power tier
+    fn motion_row(&self, params: &MotionOptions, y: usize, motion: &mut [f32]) {
+        let first = y * self.width as usize + self.active_cols().start;
+        let (threshold, offsets, offset_scale) = self.threshold_terms();
+        simd::enhance_row(
+            &self.diff_buffer[first..],
+            &offsets[first..],
+            &self.luts.radial_sensitivity[first..],
+            1,
+            threshold,
+            offset_scale,
+            params.sensitivity,
+            motion,
+        );
+        if !self.mask.is_empty() {
+            for (value, mask) in motion.iter_mut().zip(&self.mask[first..]) {
+                *value *= mask;
+            }
+        }
+    }
+
+    // Fold this frame's motion into the `accumulation_mode` buffer
+    fn accumulate_motion(&mut self, params: &MotionOptions) {
+        if params.accumulation_mode == AccumulationMode::Off {
+            return;
+        }
+        // Switching modes starts over
+        if self.accumulation_buffer.len() != self.persistence_buffer.len()
+            || self.accumulation_source != params.accumulation_mode
+        {
+            self.accumulation_buffer = vec![0.0; self.persistence_buffer.len()];
+            self.accumulation_source = params.accumulation_mode;
+            self.accumulated_frames = 0;
+        }
+        self.accumulated_frames += 1;
+
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let weight = 1.0 / self.accumulated_frames as f32;
+        let mut accumulation = std::mem::take(&mut self.accumulation_buffer);
+        let mut motion = vec![0.0; cols.len()];
+        for y in self.active_rows() {
+            self.motion_row(params, y, &mut motion);
+            let span = &mut accumulation[y * width + cols.start..y * width + cols.end];
+            for (value, &motion) in span.iter_mut().zip(&motion) {
+                *value = match params.accumulation_mode {
+                    AccumulationMode::MaxHold => value.max(motion),
+                    _ => *value + (motion - *value) * weight,
+                };
+            }
+        }
+        self.accumulation_buffer = accumulation;
+    }
+
+    // Move, fade and feed the extra persistence layers like the main trails were this frame,
+    // then re-render the active area with the echoes added to the main trails and the layers
+    // blended over them
+    fn composite_layers(
+        &mut self,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+        frame: LayerFrame,
+    ) {
+        if self.persistence_layers.is_empty() && self.echo.is_none() {
+            return;
+        }
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let rows = self.active_rows();
+        let pixels = self.persistence_buffer.len();
+        let mut layers = std::mem::take(&mut self.persistence_layers);
+        let mut motion = vec![0.0; cols.len()];
+
+        for layer in &mut layers {
+            let layer_params = self.timed(&layer.params);
+            let fade = self.trail_fade(&layer_params);
+            layer.persistence.resize(pixels, 0.0);
+            if frame == LayerFrame::Idle {
+                for y in rows.clone() {
+                    for value in
+                        &mut layer.persistence[y * width + cols.start..y * width + cols.end]
+                    {
+                        *value = fade.apply(*value);
+                    }
+                }
+                continue;
+            }
+
+            layer.moved.clear();
+            layer.moved.resize(pixels, 0.0);
+            let context = FrameContext {
+                source: &layer.persistence,
+                persistence_pyramid: &[],
+                move_transition: None,
+                ..self.frame_context()
+            };
+            context.move_rows_with(
+                &layer_params,
+                rows.clone(),
+                &mut RowBand::new(&mut layer.moved, 0),
+            );
+
+            for y in rows.clone() {
+                let first = y * width + cols.start;
+                if frame == LayerFrame::Analysed {
+                    self.motion_row(params, y, &mut motion);
+                }
+                for (x, &motion) in motion.iter().enumerate() {
+                    let pixel_index = first + x;
+                    layer.persistence[pixel_index] =
+                        fade.persist(&layer_params, motion, layer.moved[pixel_index]);
+                }
+            }
+        }
+
+        let echo = self.echo.take();
+        // Taps reaching further back than the frames seen so far stay dark
+        let taps: Vec<(&[u8], f32)> = echo.as_ref().map_or(Vec::new(), |echo| {
+            echo.taps
+                .iter()
+                .filter(|&&(delay, _)| delay <= echo.history.len())
+                .map(|&(delay, gain)| (&echo.history[echo.history.len() - delay][..], gain))
+                .filter(|(trails, _)| trails.len() == pixels)
+                .collect()
+        });
+        for y in rows {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                let mut value = self.persistence_buffer[pixel_index].min(255.0) / 255.0;
+                for &(trails, gain) in &taps {
+                    value = BlendMode::Add.apply(value, trails[pixel_index] as f32 / 255.0, gain);
+                }
+                for layer in &layers {
+                    let trail = layer.persistence[pixel_index].min(255.0) / 255.0;
+                    value = layer.blend_mode.apply(value, trail, layer.opacity);
+                }
+                let color = self.palette[(value.clamp(0.0, 1.0) * 255.0) as usize];
+                self.output_format
+                    .write(output_data, pixel_index, color, 255);
+            }
+        }
+        self.persistence_layers = layers;
+        self.echo = echo.map(|mut echo| {
+            let longest = echo.taps.iter().map(|&(delay, _)| delay).max().unwrap_or(0);
+            let mut trails = if echo.history.len() >= longest {
+                echo.history.pop_front().unwrap_or_default()
+            } else {
+                Vec::new()
+            };
+            trails.clear();
+            trails.extend(
+                self.persistence_buffer
+                    .iter()
+                    .map(|&value| value.clamp(0.0, 255.0) as u8),
+            );
+            echo.history.push_back(trails);
+            echo
+        });
+    }
+
+    // Size in bytes of one output frame in the configured output format, at the viewport's
+    // target size with `set_output_viewport`
+    pub fn output_len(&self) -> usize {
+        let (width, height) = self.output_size();
+        width as usize * height as usize * self.output_format.bytes_per_pixel()
+    }
+
+    // Size in bytes of the whole frame rendered before any viewport crop
+    fn frame_output_len(&self) -> usize {
+        self.frame_pixels() * self.output_format.bytes_per_pixel()
+    }
+
+    fn output_size(&self) -> (u32, u32) {
+        self.output_viewport
+            .map_or((self.frame_width, self.frame_height), |viewport| {
+                (viewport.target_width, viewport.target_height)
+            })
+    }
+
+    fn frame_pixels(&self) -> usize {
+        self.frame_width as usize * self.frame_height as usize
+    }
+
+    // Pixels at the processing resolution, one per trail value however they are stored
+    fn processing_pixels(&self) -> usize {
+        self.width as usize * self.height as usize
+    }
+
+    fn scaled_output_len(&self) -> usize {
+        self.processing_pixels() * self.output_format.bytes_per_pixel()
+    }
+
+    // Follow a change of `processing_scale` by rescaling the buffers like `resize` does
+    fn update_processing_scale(&mut self, params: &MotionOptions) {
+        let scale = if params.processing_scale.is_finite() {
+            params.processing_scale.clamp(0.125, 1.0)
+        } else {
+            1.0
+        };
+        let factor = (1.0 / scale).round() as u32;
+        let extra_factor = self.frame_budget.map_or(1, |budget| budget.extra_factor);
+        let factor = (factor * extra_factor).clamp(1, 8);
+        if factor == self.processing_factor {
+            return;
+        }
+        self.processing_factor = factor;
+        self.scaled_output = Vec::new();
+        self.resize_processing(
+            self.frame_width.div_ceil(factor),
+            self.frame_height.div_ceil(factor),
+        );
+    }
+
+    // Bilinear upscale of an output frame at processing resolution to the frame resolution
+    fn upscale_output(&self, scaled: &[u8], output_data: &mut [u8]) {
+        let bytes_per_pixel = self.output_format.bytes_per_pixel();
+        let (width, height) = (self.width as usize, self.height as usize);
+        let frame_width = self.frame_width as usize;
+        let factor = self.processing_factor as f32;
+        // Processing pixels on either side of a frame pixel's center, and the weight of the
+        // second one
+        let taps = |position: usize, size: usize| {
+            let source = ((position as f32 + 0.5) / factor - 0.5).clamp(0.0, (size - 1) as f32);
+            let first = source as usize;
+            (first, (first + 1).min(size - 1), source - first as f32)
+        };
+        let columns: Vec<_> = (0..frame_width).map(|x| taps(x, width)).collect();
+
+        for frame_y in 0..self.frame_height as usize {
+            let (y0, y1, ty) = taps(frame_y, height);
+            for (frame_x, &(x0, x1, tx)) in columns.iter().enumerate() {
+                let target = (frame_y * frame_width + frame_x) * bytes_per_pixel;
+                for channel in 0..bytes_per_pixel {
+                    let at = |x: usize, y: usize| {
+                        scaled[(y * width + x) * bytes_per_pixel + channel] as f32
+                    };
+                    let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+                    let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+                    output_data[target + channel] = (top + (bottom - top) * ty + 0.5) as u8;
+                }
+            }
+        }
+    }
+
+    // Fit the viewport region of a whole-frame output into `output_data` at the target size,
+    // scaled bilinearly with its aspect ratio kept and centered between black bars. A
+    // region reaching past a since resized frame is cut at its edge.
+    fn write_viewport(&self, viewport: OutputViewport, frame: &[u8], output_data: &mut [u8]) {
+        let bytes_per_pixel = self.output_format.bytes_per_pixel();
+        let frame_width = self.frame_width as usize;
+        let region_x = viewport.x.min(self.frame_width - 1) as usize;
+        let region_y = viewport.y.min(self.frame_height - 1) as usize;
+        let region_width = (viewport.width as usize).min(frame_width - region_x);
+        let region_height = (viewport.height as usize).min(self.frame_height as usize - region_y);
+        let (target_width, target_height) = (
+            viewport.target_width as usize,
+            viewport.target_height as usize,
+        );
+        let scale = (target_width as f32 / region_width as f32)
+            .min(target_height as f32 / region_height as f32);
+        let fitted_width = ((region_width as f32 * scale).round() as usize).clamp(1, target_width);
+        let fitted_height =
+            ((region_height as f32 * scale).round() as usize).clamp(1, target_height);
+        let left = (target_width - fitted_width) / 2;
+        let top = (target_height - fitted_height) / 2;
+        // Region pixels on either side of an output pixel's center, and the weight of the
+        // second one
+        let taps = |position: usize, offset: usize, size: usize| {
+            let source = ((position as f32 + 0.5) / scale - 0.5).clamp(0.0, (size - 1) as f32);
+            let first = source as usize;
+            (
+                offset + first,
+                offset + (first + 1).min(size - 1),
+                source - first as f32,
+            )
+        };
+        let columns: Vec<_> = (0..fitted_width)
+            .map(|x| taps(x, region_x, region_width))
+            .collect();
+
+        for y in 0..target_height {
+            let Some(fitted_y) = y.checked_sub(top).filter(|&row| row < fitted_height) else {
+                for x in 0..target_width {
+                    self.output_format
+                        .write(output_data, y * target_width + x, [0; 3], 255);
+                }
+                continue;
+            };
+            let (y0, y1, ty) = taps(fitted_y, region_y, region_height);
+            for x in 0..target_width {
+                let Some(&(x0, x1, tx)) = x.checked_sub(left).and_then(|x| columns.get(x)) else {
+                    self.output_format
+                        .write(output_data, y * target_width + x, [0; 3], 255);
+                    continue;
+                };
+                let target = (y * target_width + x) * bytes_per_pixel;
+                for channel in 0..bytes_per_pixel {
+                    let at = |x: usize, y: usize| {
+                        frame[(y * frame_width + x) * bytes_per_pixel + channel] as f32
+                    };
+                    let top = at(x0, y0) + (at(x1, y0) - at(x0, y0)) * tx;
+                    let bottom = at(x0, y1) + (at(x1, y1) - at(x0, y1)) * tx;
+                    output_data[target + channel] = (top + (bottom - top) * ty + 0.5) as u8;
+                }
+            }
+        }
+    }
+
+    // Time the pipeline stages on a scratch detector of the same resolution (this detector's
+    // state is untouched) and return the report as a JSON value
+    pub fn benchmark_report(&self, frames: u32, params: &MotionOptions) -> serde_json::Value {
+        let frames = frames.max(1);
+        let mut scratch = MotionDetector::new_in(self.width, self.height, None);
+        let height = self.height as usize;
+        let mut output = vec![0; scratch.output_len()];
+
+        // Warm-up frame only fills the frame cache
+        let first_frame = testing::moving_square(self.width, self.height, 0);
+        scratch
+            .process(&first_frame, &mut output, params)
+            .expect("synthetic frames match the scratch detector");
+
+        let mut movement_ms = 0.0;
+        let mut detection_ms = 0.0;
+        for frame_index in 1..=frames {
+            let frame = testing::moving_square(self.width, self.height, frame_index);
+
+            let start = now_ms();
+            scratch.begin_frame_movement(params);
+            scratch.move_rows(params, 0..height);
+            let moved = now_ms();
+            scratch.decode_rows(FrameInput::packed(&frame), params, 0..height);
+            scratch.diff_rows(params, 0..height);
+            scratch.filter_diff();
+            scratch.detect_rows(&mut output, params, 0..height);
+            scratch.finish_frame();
+            let detected = now_ms();
+
+            movement_ms += moved - start;
+            detection_ms += detected - moved;
+        }
+
+        let frames_f64 = frames as f64;
+        let total_ms = (movement_ms + detection_ms) / frames_f64;
+        let megapixels = (self.width as f64 * self.height as f64) / 1_000_000.0;
+        serde_json::json!({
+            "width": self.width,
+            "height": self.height,
+            "frames": frames,
+            "move_type": params.move_type.map_or("none", MoveType::name),
+            "stages_ms": {
+                "movement": movement_ms / frames_f64,
+                "detection": detection_ms / frames_f64,
+                "total": total_ms,
+            },
+            "megapixels_per_second": if total_ms > 0.0 { megapixels * 1000.0 / total_ms } else { 0.0 },
+            "memory_bytes": scratch.memory_bytes(),
+        })
+    }
+
+    // Reproducible performance report of `run_selftest`: a detector of `width` x `height`
+    // runs the whole pipeline with the default options over `frames` synthetic frames, each
+    // timed as one reference frame so the output only depends on the build. Reports the
+    // average milliseconds per stage and FNV-1a checksums of the last output and of all of
+    // them in order (hex, as JS numbers can't hold 64 bits).
+    pub fn run_selftest_report(
+        width: u32,
+        height: u32,
+        frames: u32,
+    ) -> Result<serde_json::Value, String> {
+        let frames = frames.max(1);
+        let mut detector = MotionDetector::try_new(width, height)?;
+        detector.set_log_level(LogLevel::Off);
+        let options = MotionOptions {
+            delta_time_ms: REFERENCE_FRAME_MS,
+            ..MotionOptions::default()
+        };
+        let mut output = vec![0; detector.output_len()];
+
+        // Warm-up frame only fills the frame cache
+        detector.process(
+            &testing::moving_square(width, height, 0),
+            &mut output,
+            &options,
+        )?;
+        let mut stages: Vec<(&'static str, f64)> = Vec::new();
+        let mut outputs_hash = testing::fnv1a(&[]);
+        for frame_index in 1..=frames {
+            let frame = testing::moving_square(width, height, frame_index);
+            detector.process(&frame, &mut output, &options)?;
+            for &(stage, ms) in detector.diagnostics.stages() {
+                match stages.iter_mut().find(|(name, _)| *name == stage) {
+                    Some((_, total)) => *total += ms,
+                    None => stages.push((stage, ms)),
+                }
+            }
+            let hashes = [outputs_hash, testing::fnv1a(&output)].map(u64::to_le_bytes);
+            outputs_hash = testing::fnv1a(&hashes.concat());
+        }
+
+        let frames_f64 = frames as f64;
+        let total_ms = stages.iter().map(|(_, ms)| ms).sum::<f64>() / frames_f64;
+        let stages_ms: Vec<_> = stages
+            .iter()
+            .map(|(stage, ms)| serde_json::json!({ "stage": stage, "ms": ms / frames_f64 }))
+            .collect();
+        Ok(serde_json::json!({
+            "width": width,
+            "height": height,
+            "frames": frames,
+            "simd": simd::ENABLED,
+            "deterministic": cfg!(feature = "deterministic"),
+            "stages_ms": stages_ms,
+            "total_ms": total_ms,
+            "frames_per_second": if total_ms > 0.0 { 1000.0 / total_ms } else { 0.0 },
+            "last_output_checksum": format!("{:016x}", testing::fnv1a(&output)),
+            "outputs_checksum": format!("{:016x}", outputs_hash),
+        }))
+    }
+
+    // Health report for field diagnostics: buffer invariants of this detector, plus every
+    // move mode run over synthetic frames on a scratch detector of the same size (so the
+    // live state is untouched), with a reference timing
+    pub fn self_test_report(&self) -> serde_json::Value {
+        use serde_json::json;
+
+        let pixels = self.processing_pixels();
+        let mut checks = Vec::new();
+        let mut check = |name: &str, passed: bool, detail: String| {
+            checks.push(json!({ "name": name, "passed": passed, "detail": detail }));
+        };
+
+        // The polar tables only exist once a mode has used them
+        let polar = self.luts.built_polar();
+        let mut wrong_sizes = Vec::new();
+        for (name, len, allowed_empty) in [
+            ("persistence_buffer", self.trails().len(), false),
+            ("distance_lut", self.luts.distance.len(), false),
+            (
+                "radial_sensitivity_lut",
+                self.luts.radial_sensitivity.len(),
+                false,
+            ),
+            (
+                "polar_angle_lut",
+                polar.map_or(0, |polar| polar.angle.len()),
+                true,
+            ),
+            (
+                "polar_distance_lut",
+                polar.map_or(0, |polar| polar.distance.len()),
+                true,
+            ),
+            (
+                "polar_distance_squared_lut",
+                polar.map_or(0, |polar| polar.distance_squared.len()),
+                true,
+            ),
+            ("previous_gray", self.previous_frame().len(), false),
+            ("current_gray", self.current_gray.len(), false),
+            ("stabilized_gray", self.stabilized_gray.len(), true),
+            ("diff_buffer", self.diff_buffer.len(), false),
+            ("temp_buffer", self.temp_buffer.len(), true),
+            ("background_gray", self.background_gray.len(), true),
+            ("mask", self.mask.len(), true),
+            ("decay_map", self.decay_map.len(), true),
+            ("noise_mean", self.noise_mean.len(), true),
+            ("noise_sigma", self.noise_sigma.len(), true),
+            ("accumulation_buffer", self.accumulation_buffer.len(), true),
+        ] {
+            if len != pixels && !(allowed_empty && len == 0) {
+                wrong_sizes.push(format!("{} has {} values", name, len));
+            }
+        }
+        if !self.output_buffer.is_empty() && self.output_buffer.len() != self.output_len() {
+            wrong_sizes.push(format!(
+                "output_buffer has {} bytes",
+                self.output_buffer.len()
+            ));
+        }
+        check(
+            "buffer_sizes",
+            pixels == (self.width * self.height) as usize && wrong_sizes.is_empty(),
+            format!(
+                "{}x{} = {} pixels; {}",
+                self.width,
+                self.height,
+                pixels,
+                if wrong_sizes.is_empty() {
+                    "all consistent".to_string()
+                } else {
+                    wrong_sizes.join(", ")
+                }
+            ),
+        );
+
+        let non_finite = [&self.luts.distance, &self.luts.radial_sensitivity]
+            .iter()
+            .map(|lut| lut.iter().filter(|value| !value.is_finite()).count())
+            .sum::<usize>()
+            + polar.map_or(0, PolarLuts::non_finite);
+        check(
+            "luts_finite",
+            non_finite == 0,
+            format!("{} non-finite LUT entries", non_finite),
+        );
+
+        let invalid_persistence = self
+            .trails()
+            .iter()
+            .filter(|value| !value.is_finite() || **value < 0.0)
+            .count();
+        check(
+            "persistence_valid",
+            invalid_persistence == 0,
+            format!("{} NaN, infinite or negative values", invalid_persistence),
+        );
+
+        // The synthetic square moves every frame, so every mode must light up some pixels
+        const SELF_TEST_FRAMES: u32 = 8;
+        let modes = [
+            MoveType::Direction,
+            MoveType::Radial,
+            MoveType::Spiral,
+            MoveType::Wave,
+            MoveType::Zoom,
+            MoveType::Rotate,
+            MoveType::Turbulence,
+            MoveType::Flow,
+            MoveType::Kaleidoscope,
+            MoveType::Shake,
+            MoveType::Attract,
+            MoveType::Perspective,
+            MoveType::Smear,
+            MoveType::Feedback,
+            MoveType::Diffuse,
+        ];
+        let mut total_ms = 0.0;
+        for move_type in modes {
+            let params = MotionOptions {
+                move_type: Some(move_type),
+                speed: 2.0,
+                ..MotionOptions::default()
+            };
+            let mut scratch = MotionDetector::new_in(self.width, self.height, None);
+            let mut output = vec![0; scratch.output_len()];
+            let start = now_ms();
+            for frame_index in 0..SELF_TEST_FRAMES {
+                let frame = testing::moving_square(self.width, self.height, frame_index);
+                scratch
+                    .process(&frame, &mut output, &params)
+                    .expect("synthetic frames match the scratch detector");
+            }
+            total_ms += now_ms() - start;
+
+            let lit_pixels = output.chunks_exact(4).filter(|pixel| pixel[0] > 0).count();
+            let finite = scratch.persistence().iter().all(|value| value.is_finite());
+            check(
+                &format!("pipeline_{}", move_type.name()),
+                finite && lit_pixels > 0,
+                format!(
+                    "{} lit output pixels, persistence {}",
+                    lit_pixels,
+                    if finite {
+                        "finite"
+                    } else {
+                        "contains NaN/infinity"
+                    }
+                ),
+            );
+        }
+
+        let passed = checks.iter().all(|check| check["passed"] == true);
+        json!({
+            "ok": passed,
+            "width": self.width,
+            "height": self.height,
+            "checks": checks,
+            "reference_ms_per_frame": total_ms / (modes.len() as u32 * SELF_TEST_FRAMES) as f64,
+        })
+    }
+
+    // Allocated bytes of every internal buffer and LUT, by field name
+    pub fn memory_breakdown(&self) -> Vec<(&'static str, usize)> {
+        let f32_bytes = |buffer: &Vec<f32>| buffer.capacity() * std::mem::size_of::<f32>();
+        let shared = |bytes: usize| bytes / Rc::strong_count(&self.luts);
+        let lut_bytes = |lut: &Vec<f32>| shared(f32_bytes(lut));
+        let polar = self.luts.built_polar();
+        vec![
+            ("persistence_buffer", f32_bytes(&self.persistence_buffer)),
+            (
+                "packed_persistence",
+                self.packed_persistence.capacity() * std::mem::size_of::<u16>(),
+            ),
+            (
+                "unpacked_persistence",
+                self.unpacked_persistence.get().map_or(0, f32_bytes),
+            ),
+            ("temp_buffer", f32_bytes(&self.temp_buffer)),
+            ("transition_buffer", f32_bytes(&self.transition_buffer)),
+            // Tables shared through a `DetectorContext` count in equal parts for each
+            // detector using them, so the sum over all detectors is what is allocated
+            ("distance_lut", lut_bytes(&self.luts.distance)),
+            (
+                "radial_sensitivity_lut",
+                lut_bytes(&self.luts.radial_sensitivity),
+            ),
+            // The polar tables count once a mode has built them
+            (
+                "polar_angle_lut",
+                polar.map_or(0, |polar| shared(polar.angle.bytes())),
+            ),
+            (
+                "polar_distance_lut",
+                polar.map_or(0, |polar| shared(polar.distance.bytes())),
+            ),
+            (
+                "polar_distance_squared_lut",
+                polar.map_or(0, |polar| lut_bytes(&polar.distance_squared)),
+            ),
+            ("previous_gray", f32_bytes(&self.previous_gray)),
+            (
+                "packed_gray",
+                self.packed_gray.capacity() * std::mem::size_of::<u16>(),
+            ),
+            ("current_gray", f32_bytes(&self.current_gray)),
+            ("stabilized_gray", f32_bytes(&self.stabilized_gray)),
+            ("background_gray", f32_bytes(&self.background_gray)),
+            (
+                "temporal_history",
+                self.temporal_history
+                    .as_ref()
+                    .map_or(0, |history| history.frames.iter().map(f32_bytes).sum()),
+            ),
+            ("diff_buffer", f32_bytes(&self.diff_buffer)),
+            ("mask", f32_bytes(&self.mask)),
+            ("decay_map", f32_bytes(&self.decay_map)),
+            ("silhouette", self.silhouette.memory_bytes()),
+            ("fluid", self.fluid.memory_bytes()),
+            (
+                "dwell",
+                self.dwell_ms.capacity() * std::mem::size_of::<f32>(),
+            ),
+            (
+                "frame_capture",
+                self.frame_capture
+                    .as_ref()
+                    .map_or(0, FrameCapture::memory_bytes),
+            ),
+            ("feedback_gray", f32_bytes(&self.feedback_gray)),
+            ("dog_buffers", self.dog_buffers.iter().map(f32_bytes).sum()),
+            ("kernel_buffer", f32_bytes(&self.kernel_buffer)),
+            ("viewport_frame", self.viewport_frame.capacity()),
+            ("morphology_buffer", f32_bytes(&self.morphology_buffer)),
+            ("motion_grid", f32_bytes(&self.motion_grid)),
+            ("zones", self.zones.memory_bytes()),
+            ("noise_mean", f32_bytes(&self.noise_mean)),
+            ("noise_sigma", f32_bytes(&self.noise_sigma)),
+            (
+                "diffusion_buffers",
+                self.diffusion_buffers.iter().map(f32_bytes).sum(),
+            ),
+            (
+                "channel_frames",
+                self.channel_frames.iter().map(f32_bytes).sum(),
+            ),
+            (
+                "hysteresis_state",
+                self.hysteresis_state.len() * std::mem::size_of::<u64>(),
+            ),
+            ("accumulation_buffer", f32_bytes(&self.accumulation_buffer)),
+            (
+                "persistence_layers",
+                self.persistence_layers
+                    .iter()
+                    .map(|layer| f32_bytes(&layer.persistence) + f32_bytes(&layer.moved))
+                    .sum(),
+            ),
+            (
+                "echo",
+                self.echo
+                    .as_ref()
+                    .map_or(0, |echo| echo.history.iter().map(Vec::len).sum()),
+            ),
+            ("output_buffer", self.output_buffer.capacity()),
+            (
+                "recording",
+                self.recording
+                    .as_ref()
+                    .map_or(0, |recording| recording.frames.iter().map(Vec::len).sum()),
+            ),
+            ("input_buffer", self.input_buffer.capacity()),
+            (
+                "particles",
+                self.particles
+                    .as_ref()
+                    .map_or(0, ParticleField::memory_bytes),
+            ),
+            ("scaled_output", self.scaled_output.capacity()),
+            ("vignette_lut", f32_bytes(&self.vignette_lut)),
+            (
+                "persistence_pyramid",
+                self.persistence_pyramid
+                    .iter()
+                    .map(|level| f32_bytes(&level.data))
+                    .sum(),
+            ),
+        ]
+    }
+
+    pub fn memory_bytes(&self) -> usize {
+        self.memory_breakdown().iter().map(|(_, bytes)| bytes).sum()
+    }
+
+    // Native counterpart of `new`: an error instead of a panic or an allocation failure
+    pub fn try_new(width: u32, height: u32) -> Result<MotionDetector, String> {
+        check_resolution(width, height)?;
+        Ok(MotionDetector::new_in(width, height, None))
+    }
+
+    // `try_new` that also refuses resolutions whose buffers would take more than
+    // `max_bytes`, e.g. on low-memory phones
+    pub fn try_new_with_memory_limit(
+        width: u32,
+        height: u32,
+        max_bytes: u64,
+    ) -> Result<MotionDetector, String> {
+        check_memory(width, height, max_bytes)?;
+        let mut detector = MotionDetector::new_in(width, height, None);
+        detector.memory_limit = Some(max_bytes);
+        Ok(detector)
+    }
+
+    pub(crate) fn new_in(
+        width: u32,
+        height: u32,
+        context: Option<DetectorContext>,
+    ) -> MotionDetector {
+        let buffer_size = width as usize * height as usize;
+
+        let mut detector = MotionDetector {
+            width,
+            height,
+            frame_width: width,
+            frame_height: height,
+            processing_factor: 1,
+            memory_limit: None,
+            scaled_output: Vec::new(),
+            // Initialize persistence buffer with zero for better cache locality
+            persistence_buffer: vec![0.0; buffer_size],
+            packed_persistence: Vec::new(),
+            packed_gray: Vec::new(),
+            unpacked_persistence: OnceCell::new(),
+            radial_profile: RadialProfile::default(),
+            lens_model: None,
+            low_memory: false,
+            luts: shared_luts(
+                context.as_ref(),
+                LutKey {
+                    width,
+                    height,
+                    area: [0, 0, width as usize, height as usize],
+                    profile: RadialProfile::default(),
+                    lens: None,
+                    packed: false,
+                },
+            ),
+            lut_context: context,
+            // Pre-allocate temp buffer with exact capacity
+            temp_buffer: Vec::with_capacity(buffer_size),
+            pyramid_levels: 0,
+            persistence_pyramid: Vec::new(),
+            last_move_params: None,
+            move_transition: None,
+            move_transition_frames: DEFAULT_MOVE_TRANSITION_FRAMES,
+            last_options: None,
+            parameter_transition: None,
+            transition_buffer: Vec::new(),
+            // Pre-allocate frame caches with exact capacity (1 luma value per pixel)
+            previous_gray: vec![0.0; buffer_size],
+            current_gray: vec![0.0; buffer_size],
+            stabilized_gray: Vec::new(),
+            background_model: BackgroundModel::RunningAverage,
+            background_gray: Vec::new(),
+            background_step: 0.0,
+            diff_buffer: vec![0.0; buffer_size],
+            tracker: Tracker::default(),
+            particles: None,
+            flow_field: None,
+            perspective: None,
+            attractors: Vec::new(),
+            shake_rng: Prng::new(0),
+            shake_seed: None,
+            shake_offset: (0.0, 0.0),
+            modulation: Vec::new(),
+            dog_filter: None,
+            dog_buffers: Default::default(),
+            output_viewport: None,
+            viewport_frame: Vec::new(),
+            detection_kernel: None,
+            kernel_buffer: Vec::new(),
+            morphology_buffer: Vec::new(),
+            direction_hue: Vec::new(),
+            motion_grid: Vec::new(),
+            motion_grid_width: 0,
+            zones: Zones::default(),
+            motion_trigger: None,
+            frame_capture: None,
+            timeline: None,
+            feedback_homography: None,
+            feedback_gray: Vec::new(),
+            ignore_color: None,
+            auto_threshold: None,
+            noise_mean: Vec::new(),
+            noise_sigma: Vec::new(),
+            diffusion_buffers: Default::default(),
+            channel_frames: Default::default(),
+            hysteresis_state: Vec::new(),
+            accumulation_buffer: Vec::new(),
+            accumulation_source: AccumulationMode::Off,
+            accumulated_frames: 0,
+            persistence_layers: Vec::new(),
+            echo: None,
+            temporal_history: None,
+            input_format: PixelFormat::Rgba,
+            output_format: PixelFormat::Rgba,
+            palette: build_palette(OutputMode::Grayscale, 0, LevelCurve::IDENTITY),
+            palette_key: (OutputMode::Grayscale, 0, LevelCurve::IDENTITY),
+            vignette_lut: Vec::new(),
+            vignette_key: None,
+            hdr_exposure_scale: 1.0,
+            hdr_tone_mapping: ToneMapping::Clamp,
+            // Only allocated once `process_motion` is used
+            output_buffer: Vec::new(),
+            recording: None,
+            input_buffer: Vec::new(),
+            #[cfg(feature = "wasm")]
+            capture_context: None,
+            roi: None,
+            roi_passthrough: false,
+            outside_roi_fading: false,
+            mask: Vec::new(),
+            decay_map: Vec::new(),
+            silhouette: Silhouette::default(),
+            fluid: Fluid::default(),
+            dwell_ms: Vec::new(),
+            dwell_ramp: build_ramp(&DEFAULT_DWELL_COLORS),
+            diagnostics: Diagnostics::new(),
+            disposed: false,
+            movement_pipeline: Vec::new(),
+            auto_crop: false,
+            content_rect: None,
+            orientation_quarter_turns: 0,
+            letterbox_candidate: None,
+            frames_until_letterbox_check: 0,
+            power_saving: false,
+            power_tier: PowerTier::Full,
+            quiet_frames: 0,
+            frames_since_analysis: 0,
+            frames_until_detection: 0,
+            frame_budget: None,
+            last_frame_ms: None,
+            frame_elapsed_ms: 0.0,
+            stream_time_ms: 0.0,
+            last_timestamp_us: None,
+            dropped_frames: 0,
+            effective_fps: 0.0,
+            frame_time_scale: 1.0,
+            motion_threshold: 0.0,
+            adaptive_k: None,
+            frame_totals: FrameTotals::default(),
+            last_stats: MotionStats::default(),
+            frame_flags: 0,
+            is_first_frame: true,
+            phase: 0.0,
+            center_x: 0.0,
+            center_y: 0.0,
+            high_quality_radius: 0.0,
+            medium_quality_radius: 0.0,
+            max_radius: 0.0,
+        };
+        detector.build_luts(Rect::full(width, height));
+        detector
+    }
+
+    // Resolution of the frames read and written; the `width` and `height` fields hold the
+    // processing resolution
+    #[allow(clippy::misnamed_getters)]
+    pub fn width(&self) -> u32 {
+        self.frame_width
+    }
+
+    #[allow(clippy::misnamed_getters)]
+    pub fn height(&self) -> u32 {
+        self.frame_height
+    }
+
+    // Resolution of detection, movement and the persistence buffer (see `processing_scale`)
+    pub fn processing_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn persistence(&self) -> &[f32] {
+        self.trails()
+    }
+
+    // Milliseconds of motion per pixel of the `dwell` output mode at the processing size;
+    // empty in the other modes
+    pub fn dwell_times(&self) -> &[f32] {
+        &self.dwell_ms
+    }
+
+    // Movement steps applied one after another each frame instead of the frame options'
+    // single move type; only the movement options of each step are used. Empty turns the
+    // pipeline off.
+    pub fn set_movement_steps(&mut self, steps: Vec<MotionOptions>) {
+        self.movement_pipeline = steps;
+    }
+
+    // Native counterpart of `set_attractors`; points beyond MAX_ATTRACTORS are dropped
+    pub fn set_attractor_points(&mut self, mut attractors: Vec<Attractor>) {
+        attractors.truncate(MAX_ATTRACTORS);
+        self.attractors = attractors;
+    }
+
+    // Native counterpart of `define_zones`; zones beyond MAX_ZONES are dropped
+    pub fn set_zones(&mut self, zones: Vec<Zone>) {
+        self.zones.set(zones);
+    }
+
+    // Native counterpart of `set_decay_map`
+    pub fn try_set_decay_map(&mut self, rates: &[f32]) -> Result<(), String> {
+        self.check_disposed()?;
+        if rates.len() != self.processing_pixels() {
+            return Err(format!(
+                "decay map has {} values, expected {}",
+                rates.len(),
+                self.processing_pixels()
+            ));
+        }
+        if let Some(index) = rates.iter().position(|rate| !rate.is_finite()) {
+            return Err(format!("decay map value {} is not a finite number", index));
+        }
+        self.decay_map = rates.iter().map(|&rate| rate.clamp(0.0, 1.0)).collect();
+        Ok(())
+    }
+
+    // Native counterpart of `load_timeline`; fails on keyframes `set_option` would reject
+    pub fn set_timeline(&mut self, keyframes: Vec<Keyframe>, looping: bool) -> Result<(), String> {
+        match Timeline::new(keyframes, looping) {
+            Ok(timeline) => self.timeline = Some(timeline),
+            Err(message) => {
+                self.diagnostics
+                    .log(LogLevel::Error, format!("timeline rejected: {}", message));
+                return Err(message);
+            }
+        }
+        Ok(())
+    }
+
+    // Native counterpart of `resize`
+    pub fn try_resize(&mut self, width: u32, height: u32) -> Result<(), String> {
+        self.check_disposed()?;
+        if width == self.frame_width && height == self.frame_height {
+            return Ok(());
+        }
+        match self.memory_limit {
+            Some(max_bytes) => check_memory(width, height, max_bytes)?,
+            None => check_resolution(width, height)?,
+        }
+        self.frame_width = width;
+        self.frame_height = height;
+        // The vignette is laid out at frame resolution
+        self.vignette_key = None;
+        let factor = self.processing_factor;
+        self.resize_processing(width.div_ceil(factor), height.div_ceil(factor));
+        // The capture canvas has the old size
+        #[cfg(feature = "wasm")]
+        {
+            self.capture_context = None;
+        }
+        Ok(())
+    }
+
+    // Native counterparts of `input_ptr` and `output_ptr`
+    pub fn try_input_ptr(&mut self) -> Result<*mut u8, String> {
+        self.check_disposed()?;
+        self.input_buffer.resize(self.buffer_len(), 0);
+        Ok(self.input_buffer.as_mut_ptr())
+    }
+
+    pub fn try_output_ptr(&mut self) -> Result<*const u8, String> {
+        self.check_disposed()?;
+        self.output_buffer.resize(self.output_len(), 0);
+        Ok(self.output_buffer.as_ptr())
+    }
+
+    // Native counterpart of `set_modulation`
+    pub fn set_modulation_signals(&mut self, signals: &[f32]) {
+        self.modulation.clear();
+        self.modulation.extend_from_slice(signals);
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MotionDetector {
+    // Throws for resolutions without pixels or too large to allocate (see `try_new`)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(width: u32, height: u32) -> Result<MotionDetector, ExportError> {
+        MotionDetector::try_new(width, height).map_err(js_error)
+    }
+
+    // A detector whose buffers at this resolution take at most `max_bytes` (see
+    // `estimate_memory_bytes`); larger resolutions throw instead of running out of memory
+    // while allocating
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn with_memory_limit(
+        width: u32,
+        height: u32,
+        max_bytes: f64,
+    ) -> Result<MotionDetector, ExportError> {
+        MotionDetector::try_new_with_memory_limit(width, height, max_bytes.max(0.0) as u64)
+            .map_err(js_error)
+    }
+
+    // Bytes a detector of this resolution allocates with the default options, before
+    // creating it; features like background subtraction, layers and recording add to it
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn estimate_memory_bytes(width: u32, height: u32) -> f64 {
+        estimated_memory_bytes(width, height) as f64
+    }
+
+    // A detector sharing its lookup tables with the other detectors of `context` while they
+    // have the same resolution, picture area and radial profile
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn with_context(
+        width: u32,
+        height: u32,
+        context: &DetectorContext,
+    ) -> Result<MotionDetector, ExportError> {
+        check_resolution(width, height).map_err(js_error)?;
+        Ok(MotionDetector::new_in(width, height, Some(context.clone())))
+    }
+
+    // `row_stride_bytes` is the distance between input rows when they are padded, as
+    // `VideoFrame.copyTo` often leaves them, so the frame needs no repacking first
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion_with_cache(
+        &mut self,
+        current_data: &[u8],    // Only current frame - 50% less data transfer!
+        output_data: &mut [u8], // RGBA output for display
+        options: &MotionOptions,
+        row_stride_bytes: Option<u32>,
+    ) -> Result<(), ExportError> {
+        let input = FrameInput::Packed {
+            data: current_data,
+            row_stride: row_stride_bytes.unwrap_or(0) as usize,
+        };
+        self.process_input(input, output_data, options)
+            .map_err(js_error)
+    }
+
+    // For decoder callbacks such as WebCodecs' `VideoDecoder`: a frame stamped with its
+    // presentation time in microseconds, timed from the previous one (`delta_time_ms` is
+    // derived, capped at 250 ms). Frames no newer than the last one taken are dropped and
+    // leave the output alone; returns whether the frame was processed. After a seek
+    // backwards call `reset_all_state`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_frame_at(
+        &mut self,
+        timestamp_us: f64,
+        current_data: &[u8],
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<bool, ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if !timestamp_us.is_finite() {
+            return Err(js_error(format!(
+                "frame timestamp {} is not finite",
+                timestamp_us
+            )));
+        }
+        let mut params = *options;
+        let elapsed_ms = match self.last_timestamp_us {
+            Some(last) if timestamp_us <= last => {
+                self.dropped_frames += 1;
+                self.diagnostics.log(
+                    LogLevel::Info,
+                    format!(
+                        "frame at {} us dropped, not after the last one at {} us",
+                        timestamp_us, last
+                    ),
+                );
+                return Ok(false);
+            }
+            Some(last) => Some(((timestamp_us - last) / 1000.0) as f32),
+            None => None,
+        };
+        if let Some(elapsed_ms) = elapsed_ms {
+            params.delta_time_ms = elapsed_ms.min(MAX_TIMESTAMP_DELTA_MS);
+        }
+        self.process_input(FrameInput::packed(current_data), output_data, &params)
+            .map_err(js_error)?;
+
+        self.last_timestamp_us = Some(timestamp_us);
+        if let Some(elapsed_ms) = elapsed_ms {
+            let fps = 1000.0 / elapsed_ms;
+            self.effective_fps = if self.effective_fps > 0.0 {
+                self.effective_fps + (fps - self.effective_fps) * FPS_SMOOTHING
+            } else {
+                fps
+            };
+        }
+        Ok(true)
+    }
+
+    // Smoothed rate of the frames `process_frame_at` processed, from their timestamps (0
+    // before the second frame)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn effective_fps(&self) -> f32 {
+        self.effective_fps
+    }
+
+    // Frames `process_frame_at` dropped for arriving out of order since creation or
+    // `reset_all_state`
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn dropped_frames(&self) -> u32 {
+        self.dropped_frames
+    }
+
+    // Same as `process_motion`, but split into bands of `chunk_rows` rows with a yield to the
+    // event loop between bands, so large frames on slow devices don't stall the main thread.
+    // The detector must not be used from JS until the returned promise settles, and the
+    // options are consumed (pass `options.copy()` to keep using them).
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub async fn process_async(
+        &mut self,
+        current_data: Vec<u8>,
+        options: MotionOptions,
+        chunk_rows: Option<u32>,
+    ) -> Result<(), ExportError> {
+        let input_format = self.input_format_for(&options);
+        self.diagnostics.begin_frame(&options);
+        let input = FrameInput::packed(&current_data);
+        if let Err(message) = self.check_frame(input, input_format, self.output_len()) {
+            self.diagnostics.log(LogLevel::Error, message.clone());
+            return Err(js_error(message));
+        }
+        self.input_format = input_format;
+        self.unpack_state();
+        let chunk_rows = chunk_rows.unwrap_or(DEFAULT_ASYNC_CHUNK_ROWS).max(1) as usize;
+        let mut output = std::mem::take(&mut self.output_buffer);
+        output.resize(self.frame_output_len(), 0);
+        self.update_processing_scale(&options);
+
+        if self.processing_factor == 1 {
+            self.process_bands(&current_data, &options, chunk_rows, &mut output)
+                .await;
+        } else {
+            let mut scaled = std::mem::take(&mut self.scaled_output);
+            scaled.resize(self.scaled_output_len(), 0);
+            self.process_bands(&current_data, &options, chunk_rows, &mut scaled)
+                .await;
+            self.upscale_output(&scaled, &mut output);
+            self.scaled_output = scaled;
+        }
+        let options = self.animated(&options);
+        self.render_silhouette(&mut output, &options);
+        self.render_fluid(&mut output, &options);
+        self.render_dwell(&mut output, &options);
+        self.apply_vignette(&mut output, &options);
+        self.composite_over_input(FrameInput::packed(&current_data), &mut output, &options);
+        self.encode_alpha(&mut output, &options);
+        self.capture_feedback(&output, &options);
+        if let Some(viewport) = self.output_viewport {
+            let mut cropped = std::mem::take(&mut self.viewport_frame);
+            cropped.resize(self.output_len(), 0);
+            self.write_viewport(viewport, &output, &mut cropped);
+            self.viewport_frame = std::mem::replace(&mut output, cropped);
+        }
+        self.record_frame(&output);
+        self.diagnostics.end_stage("output");
+        self.diagnostics.end_frame();
+        self.output_buffer = output;
+        self.pack_state();
+        Ok(())
+    }
+
+    // `process_scaled` in bands of `chunk_rows` rows with yields in between
+    #[cfg(feature = "wasm")]
+    async fn process_bands(
+        &mut self,
+        current_data: &[u8],
+        options: &MotionOptions,
+        chunk_rows: usize,
+        output: &mut [u8],
+    ) {
+        self.advance_clock(options);
+        self.frame_flags = 0;
+        let params = self.animated(options);
+        let params = self.transitioned(&params);
+        let params = self.timed(&params);
+        self.update_letterbox(FrameInput::packed(current_data), &params);
+        let active_rows = self.active_rows();
+
+        if self.is_first_frame {
+            self.cache_first_frame(FrameInput::packed(current_data), output, &params);
+            self.diagnostics.end_stage("decode");
+            return;
+        }
+
+        if self.skip_for_power_saving() {
+            self.fade_rows(output, &params, active_rows);
+            self.composite_layers(output, &params, LayerFrame::Idle);
+            self.render_outside_roi(FrameInput::packed(current_data), output, &params);
+            self.diagnostics.end_stage("composite");
+            return;
+        }
+
+        self.begin_frame_movement(&params);
+        for start in active_rows.clone().step_by(chunk_rows) {
+            self.move_rows(&params, start..(start + chunk_rows).min(active_rows.end));
+            yield_to_event_loop().await;
+        }
+        self.diffuse_trails(&params);
+        self.diagnostics.end_stage("move");
+
+        if self.skip_detection(&params) {
+            self.fade_moved_rows(output, &params, active_rows);
+            self.composite_layers(output, &params, LayerFrame::Moved);
+            self.render_outside_roi(FrameInput::packed(current_data), output, &params);
+            self.diagnostics.end_stage("composite");
+            return;
+        }
+
+        // Whole-frame diff filters and scene-change detection need every row differenced
+        // before detection can start
+        let filtered = self.dog_filter.is_some()
+            || self.detection_kernel.is_some()
+            || params.erode > 0
+            || params.dilate > 0
+            || params.scene_change_threshold > 0.0;
+        // Stabilization and exposure compensation need the whole frame decoded before it is
+        // differenced
+        let decoded_first = params.stabilize || params.exposure_compensation;
+        if decoded_first {
+            for start in active_rows.clone().step_by(chunk_rows) {
+                let rows = start..(start + chunk_rows).min(active_rows.end);
+                self.decode_rows(FrameInput::packed(current_data), &params, rows);
+                yield_to_event_loop().await;
+            }
+            self.stabilize(&params);
+            self.compensate_exposure(&params);
+        }
+        for start in active_rows.clone().step_by(chunk_rows) {
+            let rows = start..(start + chunk_rows).min(active_rows.end);
+            if !decoded_first {
+                self.decode_rows(FrameInput::packed(current_data), &params, rows.clone());
+            }
+            self.diff_rows(&params, rows.clone());
+            self.ignore_key_color(FrameInput::packed(current_data), &params, rows.clone());
+            if !filtered {
+                self.hold_threshold(&params, rows.clone());
+                self.detect_rows(output, &params, rows);
+            }
+            yield_to_event_loop().await;
+        }
+
+        if filtered {
+            if self.detect_scene_change(&params) {
+                self.fade_moved_rows(output, &params, active_rows);
+                self.composite_layers(output, &params, LayerFrame::Moved);
+                self.render_outside_roi(FrameInput::packed(current_data), output, &params);
+                self.diagnostics.end_stage("composite");
+                self.finish_frame();
+                return;
+            }
+            self.filter_diff();
+            self.morph_diff(&params);
+            self.hold_threshold(&params, active_rows.clone());
+            yield_to_event_loop().await;
+            for start in active_rows.clone().step_by(chunk_rows) {
+                let rows = start..(start + chunk_rows).min(active_rows.end);
+                self.detect_rows(output, &params, rows);
+                yield_to_event_loop().await;
+            }
+        }
+
+        self.interpolate_grid(output, &params);
+        // Decoding and differencing run in the same bands as detection here
+        self.diagnostics.end_stage("detect");
+        self.update_zones();
+        self.update_motion_trigger();
+        self.update_capture(FrameInput::packed(current_data));
+        self.update_silhouette(&params);
+        self.update_fluid(&params);
+        self.update_dwell(&params);
+        self.update_auto_threshold(&params);
+        self.accumulate_motion(&params);
+        self.diagnostics.end_stage("analyse");
+        self.composite_layers(output, &params, LayerFrame::Analysed);
+        self.render_outside_roi(FrameInput::packed(current_data), output, &params);
+        self.diagnostics.end_stage("composite");
+        self.finish_frame();
+    }
+
+    fn cache_first_frame(
+        &mut self,
+        input: FrameInput,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+    ) {
+        self.decode_rows(input, params, self.active_rows());
+        self.finish_frame();
+        // Re-seeded from the first frame compared against it
+        self.background_gray.clear();
+        self.is_first_frame = false;
+
+        // Output black frame for first frame (alpha 255, RGB 0)
+        for pixel_index in 0..self.persistence_buffer.len() {
+            self.output_format
+                .write(output_data, pixel_index, [0; 3], 255);
+        }
+        self.render_outside_roi(input, output_data, params);
+    }
+
+    // Area that is processed: the ROI minus any letterbox bars
+    fn active_rect(&self) -> Rect {
+        let full = Rect::full(self.width, self.height);
+        let roi = self.roi.unwrap_or(full);
+        roi.intersect(&self.content_rect.unwrap_or(full))
+    }
+
+    fn active_rows(&self) -> Range<usize> {
+        self.active_rect().rows()
+    }
+
+    fn active_cols(&self) -> Range<usize> {
+        self.active_rect().cols()
+    }
+
+    // Fill everything outside the active area with the input frame, the trails left there by
+    // an ROI change while they decay, or black
+    fn render_outside_roi(
+        &mut self,
+        input: FrameInput,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+    ) {
+        let active = self.active_rect();
+        if active == Rect::full(self.width, self.height) {
+            return;
+        }
+        let width = self.width as usize;
+        let output_format = self.output_format;
+        let fading = self.outside_roi_fading && !self.roi_passthrough;
+        let fade = self.trail_fade(params);
+        if fading {
+            self.update_palette(params);
+        }
+        let mut remaining = false;
+
+        for y in 0..self.height as usize {
+            for x in 0..width {
+                if active.contains(x, y) {
+                    continue;
+                }
+                let pixel_index = y * width + x;
+                let rgb = if self.roi_passthrough {
+                    self.input_rgb(input, self.source_pixel(params, x, y))
+                } else if fading {
+                    // Below one gray level the trail is invisible, so end the fade there
+                    let faded = fade.apply(self.persistence_buffer[pixel_index]);
+                    let faded = if faded < 1.0 { 0.0 } else { faded };
+                    self.persistence_buffer[pixel_index] = faded;
+                    remaining |= faded > 0.0;
+                    self.palette[faded.min(255.0) as usize]
+                } else {
+                    [0; 3]
+                };
+                output_format.write(output_data, pixel_index, rgb, 255);
+            }
+        }
+        self.outside_roi_fading = remaining;
+    }
+
+    // Re-prime the frame cache after the ROI or letterbox layout changed. Trails outside the
+    // active area are cleared, or left to decay with `fade_outside` (unless the outside
+    // shows the input frame).
+    fn active_area_changed(&mut self, fade_outside: bool) {
+        self.unpack_state();
+        if fade_outside && !self.roi_passthrough {
+            self.outside_roi_fading = true;
+        } else {
+            let active = self.active_rect();
+            let width = self.width as usize;
+            for (pixel_index, value) in self.persistence_buffer.iter_mut().enumerate() {
+                if !active.contains(pixel_index % width, pixel_index / width) {
+                    *value = 0.0;
+                }
+            }
+        }
+        // The cached frame was only decoded inside the old area
+        self.is_first_frame = true;
+    }
+
+    // Pre-compute the radial lookup tables around the center of `area`, so letterbox bars
+    // don't pull the center-weighting off the picture
+    fn build_luts(&mut self, area: Rect) {
+        let key = LutKey {
+            width: self.width,
+            height: self.height,
+            area: [area.x, area.y, area.width, area.height],
+            profile: self.radial_profile,
+            lens: self.lens_model,
+            packed: self.low_memory,
+        };
+        if self.luts.key != key {
+            self.luts = shared_luts(self.lut_context.as_ref(), key);
+        }
+        // Optimization #6: Store center and radius for distance-based approximation
+        self.center_x = self.luts.center_x;
+        self.center_y = self.luts.center_y;
+        self.max_radius = self.luts.max_radius;
+        self.update_quality_radii();
+    }
+
+    fn update_quality_radii(&mut self) {
+        if cfg!(feature = "deterministic") {
+            self.high_quality_radius = f32::INFINITY;
+            self.medium_quality_radius = f32::INFINITY;
+            return;
+        }
+        // Define quality levels: high quality for center 30%, medium for next 40%, low for outer 30%,
+        // all shrinking towards the center when the frame budget is tight
+        let scale = self.frame_budget.map_or(1.0, |budget| budget.radius_scale);
+        self.high_quality_radius = self.max_radius * 0.3 * scale;
+        self.medium_quality_radius = self.max_radius * 0.7 * scale;
+    }
+
+    // Feed one frame time to the frame budget controller and apply its decision to the
+    // quality radii (the processing scale follows on the next frame)
+    fn update_frame_budget(&mut self, frame_ms: f32) {
+        let Some(budget) = &mut self.frame_budget else {
+            return;
+        };
+        budget.update(frame_ms);
+        self.update_quality_radii();
+    }
+
+    // Periodically look for black bars and switch to the new picture area once the same
+    // layout has been seen on two consecutive scans
+    fn update_letterbox(&mut self, input: FrameInput, params: &MotionOptions) {
+        if !self.auto_crop {
+            return;
+        }
+        if self.frames_until_letterbox_check > 0 {
+            self.frames_until_letterbox_check -= 1;
+            return;
+        }
+        self.frames_until_letterbox_check = LETTERBOX_CHECK_INTERVAL;
+
+        let detected = self.scan_letterbox(input, params);
+        if self.letterbox_candidate != Some(detected) {
+            self.letterbox_candidate = Some(detected);
+            return;
+        }
+
+        let full = Rect::full(self.width, self.height);
+        let content = (detected != full).then_some(detected);
+        if content != self.content_rect {
+            self.set_content_rect(content);
+        }
+    }
+
+    fn set_content_rect(&mut self, content: Option<Rect>) {
+        self.content_rect = content;
+        self.build_luts(content.unwrap_or(Rect::full(self.width, self.height)));
+        self.active_area_changed(false);
+    }
+
+    // Picture area of `input` with constant black borders removed. A (nearly) black frame
+    // keeps the whole frame, so dark scenes aren't mistaken for bars.
+    fn scan_letterbox(&self, input: FrameInput, params: &MotionOptions) -> Rect {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let is_black = |x: usize, y: usize| {
+            let rgb = self.input_rgb(input, self.source_pixel(params, x, y));
+            rgb.iter().all(|&channel| channel <= LETTERBOX_BLACK_LEVEL)
+        };
+        let row_black = |y: usize| (0..width).all(|x| is_black(x, y));
+
+        let Some(top) = (0..height).find(|&y| !row_black(y)) else {
+            return Rect::full(self.width, self.height);
+        };
+        let bottom = (top..height).rev().find(|&y| !row_black(y)).unwrap_or(top) + 1;
+        let col_black = |x: usize| (top..bottom).all(|y| is_black(x, y));
+        let left = (0..width).find(|&x| !col_black(x)).unwrap_or(0);
+        let right = (left..width).rev().find(|&x| !col_black(x)).unwrap_or(left) + 1;
+
+        let content = Rect {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        };
+        // Bars covering more than half the frame are more likely a dark scene
+        if content.width * 2 < width || content.height * 2 < height {
+            return Rect::full(self.width, self.height);
+        }
+        content
+    }
+
+    // Input pixel shown at (x, y) once the flip options are applied; the center of the
+    // pixel's block when processing at a reduced scale
+    fn source_pixel(&self, params: &MotionOptions, x: usize, y: usize) -> usize {
+        let factor = self.processing_factor as usize;
+        let frame_width = self.frame_width as usize;
+        let frame_height = self.frame_height as usize;
+        let x = (x * factor + factor / 2).min(frame_width - 1);
+        let y = (y * factor + factor / 2).min(frame_height - 1);
+        let source_x = if params.flip_horizontal {
+            frame_width - 1 - x
+        } else {
+            x
+        };
+        let source_y = if params.flip_vertical {
+            frame_height - 1 - y
+        } else {
+            y
+        };
+        source_y * frame_width + source_x
+    }
+
+    // Byte offset of a pixel of packed input with rows `row_stride` bytes apart (0 = no
+    // padding between rows)
+    #[inline]
+    fn packed_offset(&self, pixel_index: usize, row_stride: usize) -> usize {
+        let bytes_per_pixel = self.input_format.bytes_per_pixel();
+        if row_stride == 0 {
+            return pixel_index * bytes_per_pixel;
+        }
+        let width = self.frame_width as usize;
+        pixel_index / width * row_stride + pixel_index % width * bytes_per_pixel
+    }
+
+    // Display color of one input pixel, whatever the input layout
+    fn input_rgb(&self, input: FrameInput, pixel_index: usize) -> [u8; 3] {
+        match input {
+            FrameInput::Packed { data, row_stride } => {
+                let base = self.packed_offset(pixel_index, row_stride);
+                let (r, g, b) = self.input_format.rgb_offsets();
+                [data[base + r], data[base + g], data[base + b]]
+            }
+            FrameInput::Rgba64(data) => {
+                let base = pixel_index * 4;
+                [
+                    (data[base] >> 8) as u8,
+                    (data[base + 1] >> 8) as u8,
+                    (data[base + 2] >> 8) as u8,
+                ]
+            }
+            FrameInput::Gray16(data) => [(data[pixel_index] >> 8) as u8; 3],
+            FrameInput::Gray8(data) => [data[pixel_index]; 3],
+            FrameInput::Planar { r, g, b } => [r[pixel_index], g[pixel_index], b[pixel_index]],
+            FrameInput::Yuv420 {
+                y_plane,
+                u_plane,
+                v_plane,
+                y_stride,
+                uv_stride,
+            } => {
+                let width = self.frame_width as usize;
+                let (x, y) = (pixel_index % width, pixel_index / width);
+                let chroma = (y / 2) * uv_stride;
+                let (u, v) = if v_plane.is_empty() {
+                    (u_plane[chroma + x / 2 * 2], u_plane[chroma + x / 2 * 2 + 1])
+                } else {
+                    (u_plane[chroma + x / 2], v_plane[chroma + x / 2])
+                };
+                // BT.601 video range
+                let luma = (y_plane[y * y_stride + x] as f32 - 16.0) * 1.164;
+                let (u, v) = (u as f32 - 128.0, v as f32 - 128.0);
+                [
+                    luma + 1.596 * v,
+                    luma - 0.392 * u - 0.813 * v,
+                    luma + 2.017 * u,
+                ]
+                .map(|channel| channel.clamp(0.0, 255.0) as u8)
+            }
+            FrameInput::RgbaF32(data) => {
+                let base = pixel_index * 4;
+                let channel = |value: f32| {
+                    let mapped = self.hdr_tone_mapping.apply(value * self.hdr_exposure_scale);
+                    (mapped.powf(1.0 / 2.2) * 255.0) as u8
+                };
+                [
+                    channel(data[base]),
+                    channel(data[base + 1]),
+                    channel(data[base + 2]),
+                ]
+            }
+        }
+    }
+
+    // Convert a band of input rows (within the ROI) to luma (0..255, fractional for high bit
+    // depth input) in `current_gray`
+    fn decode_rows(&mut self, input: FrameInput, params: &MotionOptions, rows: Range<usize>) {
+        if self.processing_factor > 1 || params.channel_mode != ChannelMode::Luma {
+            self.decode_rows_downscaled(input, params, rows.clone());
+        } else {
+            self.decode_rows_full(input, params, rows.clone());
+        }
+        self.filter_temporal(params, rows.clone());
+        self.subtract_feedback(params, rows);
+    }
+
+    // Take the light of the previous output off a band of decoded rows, see
+    // `suppress_self_feedback`
+    fn subtract_feedback(&mut self, params: &MotionOptions, rows: Range<usize>) {
+        if !params.suppress_self_feedback || self.feedback_gray.len() != self.current_gray.len() {
+            return;
+        }
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let gain = params.self_feedback_gain;
+        for y in rows {
+            let row = y * width + cols.start..y * width + cols.end;
+            for (value, &feedback) in self.current_gray[row.clone()]
+                .iter_mut()
+                .zip(&self.feedback_gray[row])
+            {
+                *value = (*value - feedback * gain).max(0.0);
+            }
+        }
+    }
+
+    // Replace a band of decoded rows with the `temporal_filter` of them and the rows of the
+    // previous frames; the unfiltered rows are kept for the next frames
+    fn filter_temporal(&mut self, params: &MotionOptions, rows: Range<usize>) {
+        if params.temporal_filter == TemporalFilter::Off {
+            self.temporal_history = None;
+            return;
+        }
+        let window = (params.temporal_window as usize).clamp(2, MAX_TEMPORAL_WINDOW);
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let size = self.current_gray.len();
+        let history = self
+            .temporal_history
+            .get_or_insert_with(TemporalHistory::default);
+        // A new window or resolution starts over
+        if history.frames.len() != window || history.frames[0].len() != size {
+            *history = TemporalHistory {
+                frames: vec![vec![0.0; size]; window],
+                next: 0,
+                filled: 0,
+            };
+        }
+
+        let count = history.filled + 1;
+        let mut values = [0.0f32; MAX_TEMPORAL_WINDOW];
+        for y in rows {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                history.frames[history.next][pixel_index] = self.current_gray[pixel_index];
+                for (slot, value) in values[..count].iter_mut().enumerate() {
+                    *value = history.frames[(history.next + window - slot) % window][pixel_index];
+                }
+                let values = &mut values[..count];
+                self.current_gray[pixel_index] = match params.temporal_filter {
+                    TemporalFilter::Min => values.iter().copied().fold(f32::INFINITY, f32::min),
+                    _ => {
+                        values.sort_unstable_by(f32::total_cmp);
+                        // Even counts (while the window fills up) take the mean of the middle two
+                        (values[(count - 1) / 2] + values[count / 2]) * 0.5
+                    }
+                };
+            }
+        }
+    }
+
+    fn decode_rows_full(&mut self, input: FrameInput, params: &MotionOptions, rows: Range<usize>) {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let cols = self.active_cols();
+        let (flip_horizontal, flip_vertical) = (params.flip_horizontal, params.flip_vertical);
+        let band = rows.clone();
+        let band_cols = cols.clone();
+        // (destination, source) pixel pairs; mirroring happens here so it costs no extra pass
+        let pixels = rows.flat_map(move |y| {
+            let source_y = if flip_vertical { height - 1 - y } else { y };
+            cols.clone().map(move |x| {
+                let source_x = if flip_horizontal { width - 1 - x } else { x };
+                (y * width + x, source_y * width + source_x)
+            })
+        });
+
+        match input {
+            // Unmirrored 4-byte rows are contiguous in the source, so they go through the
+            // row kernel (vectorized with the `simd` feature)
+            FrameInput::Packed {
+                data: current_data,
+                row_stride,
+            } if self.input_format.bytes_per_pixel() == 4 && !flip_horizontal => {
+                let offsets = self.input_format.rgb_offsets();
+                let row_stride = if row_stride == 0 {
+                    width * 4
+                } else {
+                    row_stride
+                };
+                for y in band {
+                    let source_y = if flip_vertical { height - 1 - y } else { y };
+                    let source = source_y * row_stride + band_cols.start * 4
+                        ..source_y * row_stride + band_cols.end * 4;
+                    simd::luma_row(
+                        &current_data[source],
+                        offsets,
+                        &mut self.current_gray
+                            [y * width + band_cols.start..y * width + band_cols.end],
+                    );
+                }
+            }
+            FrameInput::Packed {
+                data: current_data,
+                row_stride,
+            } => {
+                let (r, g, b) = self.input_format.rgb_offsets();
+
+                for (pixel_index, source_index) in pixels {
+                    let base = self.packed_offset(source_index, row_stride);
+
+                    // Fast grayscale conversion using integer arithmetic
+                    let gray = ((current_data[base + r] as u32 * 77)
+                        + (current_data[base + g] as u32 * 150)
+                        + (current_data[base + b] as u32 * 29))
+                        >> 8;
+                    self.current_gray[pixel_index] = gray as f32;
+                }
+            }
+            FrameInput::Rgba64(current_data) => {
+                for (pixel_index, source_index) in pixels {
+                    let base = source_index * 4;
+
+                    // Same weights as the 8-bit path, but the fractional part is kept so
+                    // motion below 8-bit quantization still produces a difference
+                    let gray = (current_data[base] as u32 * 77
+                        + current_data[base + 1] as u32 * 150
+                        + current_data[base + 2] as u32 * 29) as f32;
+                    self.current_gray[pixel_index] = gray * (1.0 / (256.0 * 257.0));
+                }
+            }
+            FrameInput::Gray16(current_data) => {
+                for (pixel_index, source_index) in pixels {
+                    self.current_gray[pixel_index] = current_data[source_index] as f32 / 257.0;
+                }
+            }
+            FrameInput::Gray8(current_data) => {
+                for (pixel_index, source_index) in pixels {
+                    self.current_gray[pixel_index] = current_data[source_index] as f32;
+                }
+            }
+            // The luma plane already is the grayscale frame
+            FrameInput::Yuv420 {
+                y_plane, y_stride, ..
+            } => {
+                for y in band {
+                    let source_y = if flip_vertical { height - 1 - y } else { y };
+                    for x in band_cols.clone() {
+                        let source_x = if flip_horizontal { width - 1 - x } else { x };
+                        self.current_gray[y * width + x] =
+                            y_plane[source_y * y_stride + source_x] as f32;
+                    }
+                }
+            }
+            FrameInput::Planar { r, g, b } => {
+                for (pixel_index, source_index) in pixels {
+                    let gray = ((r[source_index] as u32 * 77)
+                        + (g[source_index] as u32 * 150)
+                        + (b[source_index] as u32 * 29))
+                        >> 8;
+                    self.current_gray[pixel_index] = gray as f32;
+                }
+            }
+            FrameInput::RgbaF32(current_data) => {
+                let exposure = self.hdr_exposure_scale;
+                let tone_mapping = self.hdr_tone_mapping;
+
+                for (pixel_index, source_index) in pixels {
+                    let base = source_index * 4;
+                    let luminance = current_data[base] * 0.299
+                        + current_data[base + 1] * 0.587
+                        + current_data[base + 2] * 0.114;
+
+                    // Exposure, tone curve, then display gamma so thresholds behave like
+                    // they do for 8-bit sRGB input
+                    let mapped = tone_mapping.apply(luminance * exposure);
+                    self.current_gray[pixel_index] = mapped.powf(1.0 / 2.2) * 255.0;
+                }
+            }
+        }
+    }
+
+    // `decode_rows` at a reduced processing scale or in a channel mode other than luma: each
+    // processing pixel gets the mean gray value of its block of input pixels (2x2 at half
+    // scale), and for the per-channel difference also its mean R, G and B
+    fn decode_rows_downscaled(
+        &mut self,
+        input: FrameInput,
+        params: &MotionOptions,
+        rows: Range<usize>,
+    ) {
+        let width = self.width as usize;
+        let factor = self.processing_factor as usize;
+        let frame_width = self.frame_width as usize;
+        let frame_height = self.frame_height as usize;
+        let source_x = |x: usize| {
+            if params.flip_horizontal {
+                frame_width - 1 - x
+            } else {
+                x
+            }
+        };
+        let source_y = |y: usize| {
+            if params.flip_vertical {
+                frame_height - 1 - y
+            } else {
+                y
+            }
+        };
+
+        let mode = params.channel_mode;
+        // Gray inputs have no channels to choose from
+        let color = mode != ChannelMode::Luma
+            && match input {
+                FrameInput::Packed { .. } => self.input_format != PixelFormat::Gray,
+                FrameInput::Gray16(_) | FrameInput::Gray8(_) => false,
+                _ => true,
+            };
+        let mut channels = std::mem::take(&mut self.channel_frames[0]);
+        let per_channel = mode == ChannelMode::PerChannel;
+        if !per_channel {
+            if !channels.is_empty() || !self.channel_frames[1].is_empty() {
+                channels = Vec::new();
+                self.channel_frames[1] = Vec::new();
+            }
+        } else if channels.len() != self.current_gray.len() * 3 {
+            channels = vec![0.0; self.current_gray.len() * 3];
+        }
+
+        for y in rows {
+            let block_rows = y * factor..((y + 1) * factor).min(frame_height);
+            for x in self.active_cols() {
+                let block_cols = x * factor..((x + 1) * factor).min(frame_width);
+                let mut sum = 0.0;
+                let mut channel_sum = [0.0; 3];
+                for block_y in block_rows.clone() {
+                    let row = source_y(block_y) * frame_width;
+                    for block_x in block_cols.clone() {
+                        let source = row + source_x(block_x);
+                        if color {
+                            let rgb = self.input_rgb(input, source).map(f32::from);
+                            sum += mode.gray(rgb);
+                            for (channel_sum, value) in channel_sum.iter_mut().zip(rgb) {
+                                *channel_sum += value;
+                            }
+                        } else {
+                            sum += self.input_luma(input, source);
+                        }
+                    }
+                }
+                let area = (block_rows.len() * block_cols.len()) as f32;
+                let pixel_index = y * width + x;
+                self.current_gray[pixel_index] = sum / area;
+                if per_channel {
+                    for (channel, value) in channel_sum.into_iter().enumerate() {
+                        channels[pixel_index * 3 + channel] = value / area;
+                    }
+                }
+            }
+        }
+        self.channel_frames[0] = channels;
+    }
+
+    // Luma of one input pixel on the same scale as `decode_rows`
+    fn input_luma(&self, input: FrameInput, pixel_index: usize) -> f32 {
+        match input {
+            FrameInput::Packed { data, row_stride } => {
+                let base = self.packed_offset(pixel_index, row_stride);
+                let (r, g, b) = self.input_format.rgb_offsets();
+                (((data[base + r] as u32 * 77)
+                    + (data[base + g] as u32 * 150)
+                    + (data[base + b] as u32 * 29))
+                    >> 8) as f32
+            }
+            FrameInput::Rgba64(data) => {
+                let base = pixel_index * 4;
+                (data[base] as u32 * 77 + data[base + 1] as u32 * 150 + data[base + 2] as u32 * 29)
+                    as f32
+                    * (1.0 / (256.0 * 257.0))
+            }
+            FrameInput::Gray16(data) => data[pixel_index] as f32 / 257.0,
+            FrameInput::Gray8(data) => data[pixel_index] as f32,
+            FrameInput::Yuv420 {
+                y_plane, y_stride, ..
+            } => {
+                let width = self.frame_width as usize;
+                y_plane[pixel_index / width * y_stride + pixel_index % width] as f32
+            }
+            FrameInput::Planar { r, g, b } => {
+                (((r[pixel_index] as u32 * 77)
+                    + (g[pixel_index] as u32 * 150)
+                    + (b[pixel_index] as u32 * 29))
+                    >> 8) as f32
+            }
+            FrameInput::RgbaF32(data) => {
+                let base = pixel_index * 4;
+                let luminance =
+                    data[base] * 0.299 + data[base + 1] * 0.587 + data[base + 2] * 0.114;
+                let mapped = self
+                    .hdr_tone_mapping
+                    .apply(luminance * self.hdr_exposure_scale);
+                mapped.powf(1.0 / 2.2) * 255.0
+            }
+        }
+    }
+
+    // The decoded frame becomes the cached previous frame
+    fn finish_frame(&mut self) {
+        std::mem::swap(&mut self.current_gray, &mut self.previous_gray);
+        if let Some(history) = &mut self.temporal_history {
+            history.filled = (history.filled + 1).min(history.frames.len() - 1);
+            history.next = (history.next + 1) % history.frames.len();
+        }
+        self.channel_frames.swap(0, 1);
+        self.last_stats = self.frame_totals.stats();
+        self.update_power_tier();
+    }
+
+    // Low-memory mode: keep the trails and the cached previous frame as fixed point until
+    // the next frame, and free the movement scratch, which the next frame fills again
+    fn pack_state(&mut self) {
+        if !self.low_memory || !self.packed_persistence.is_empty() {
+            return;
+        }
+        self.packed_persistence = pack_fixed(&self.persistence_buffer);
+        self.packed_gray = pack_fixed(&self.previous_gray);
+        self.persistence_buffer = Vec::new();
+        self.previous_gray = Vec::new();
+        self.temp_buffer = Vec::new();
+        self.unpacked_persistence = OnceCell::new();
+    }
+
+    // Back to f32 trails and frame cache, before a frame or anything else changes them
+    fn unpack_state(&mut self) {
+        if self.packed_persistence.is_empty() {
+            return;
+        }
+        self.persistence_buffer = match self.unpacked_persistence.take() {
+            Some(trails) => trails,
+            None => unpack_fixed(&self.packed_persistence),
+        };
+        self.previous_gray = unpack_fixed(&self.packed_gray);
+        self.packed_persistence = Vec::new();
+        self.packed_gray = Vec::new();
+    }
+
+    // The trails for readers between frames; packed trails are unpacked on the first read
+    // and the copy is kept until the next frame
+    fn trails(&self) -> &[f32] {
+        if self.packed_persistence.is_empty() {
+            return &self.persistence_buffer;
+        }
+        self.unpacked_persistence
+            .get_or_init(|| unpack_fixed(&self.packed_persistence))
+    }
+
+    // The cached previous frame for readers between frames, unpacked if it is packed
+    fn previous_frame(&self) -> Cow<'_, [f32]> {
+        if self.packed_gray.is_empty() {
+            Cow::Borrowed(&self.previous_gray)
+        } else {
+            Cow::Owned(unpack_fixed(&self.packed_gray))
+        }
+    }
+
+    // Shift the cached previous frame onto the decoded one by the camera translation between
+    // them, for `stabilize`. Pixels the shift brings in from outside the active area repeat
+    // its edge; unclear matches (flat scenes, large subjects) leave the frame as it is.
+    fn stabilize(&mut self, params: &MotionOptions) {
+        if !params.stabilize || params.detection_mode != DetectionMode::FrameDiff {
+            return;
+        }
+        let width = self.width as usize;
+        let rect = self.active_rect();
+        let motion = FramePair {
+            newer: &self.current_gray,
+            older: &self.previous_gray,
+            width,
+            rows: rect.rows(),
+            cols: rect.cols(),
+        }
+        .estimate(STABILIZE_MAX_SHIFT, false);
+        if motion.confidence < STABILIZE_MIN_CONFIDENCE
+            || (motion.dx.abs() < 0.05 && motion.dy.abs() < 0.05)
+        {
+            return;
+        }
+
+        let mut stabilized = std::mem::take(&mut self.stabilized_gray);
+        stabilized.clone_from(&self.previous_gray);
+        let clamp_x = |x: f32| x.clamp(rect.x as f32, (rect.x + rect.width - 1) as f32);
+        let clamp_y = |y: f32| y.clamp(rect.y as f32, (rect.y + rect.height - 1) as f32);
+        for y in rect.rows() {
+            let source_y = clamp_y(y as f32 - motion.dy);
+            let top = source_y as usize;
+            let bottom = (top + 1).min(rect.y + rect.height - 1);
+            let fy = source_y - top as f32;
+            for x in rect.cols() {
+                let source_x = clamp_x(x as f32 - motion.dx);
+                let left = source_x as usize;
+                let right = (left + 1).min(rect.x + rect.width - 1);
+                let fx = source_x - left as f32;
+                let sample = |row: usize, column: usize| self.previous_gray[row * width + column];
+                let upper = sample(top, left) + (sample(top, right) - sample(top, left)) * fx;
+                let lower =
+                    sample(bottom, left) + (sample(bottom, right) - sample(bottom, left)) * fx;
+                stabilized[y * width + x] = upper + (lower - upper) * fy;
+            }
+        }
+        std::mem::swap(&mut self.previous_gray, &mut stabilized);
+        self.stabilized_gray = stabilized;
+    }
+
+    // Scale the decoded frame so its mean luminance over the active area matches the frame or
+    // background it is differenced against, for `exposure_compensation`. The scaled frame
+    // is cached, so the next frame is matched to it in turn.
+    fn compensate_exposure(&mut self, params: &MotionOptions) {
+        if !params.exposure_compensation {
+            return;
+        }
+        let reference = match params.detection_mode {
+            DetectionMode::BackgroundSub
+                if self.background_gray.len() == self.current_gray.len() =>
+            {
+                &self.background_gray
+            }
+            _ => &self.previous_gray,
+        };
+        let width = self.width as usize;
+        let rect = self.active_rect();
+        let (mut current_sum, mut reference_sum) = (0.0f64, 0.0f64);
+        for y in rect.rows() {
+            let row = y * width + rect.x..y * width + rect.x + rect.width;
+            current_sum += self.current_gray[row.clone()]
+                .iter()
+                .map(|&value| value as f64)
+                .sum::<f64>();
+            reference_sum += reference[row]
+                .iter()
+                .map(|&value| value as f64)
+                .sum::<f64>();
+        }
+        // A black frame on either side says nothing about the exposure
+        if current_sum <= 0.0 || reference_sum <= 0.0 {
+            return;
+        }
+
+        let gain = ((reference_sum / current_sum) as f32)
+            .clamp(1.0 / EXPOSURE_MAX_GAIN, EXPOSURE_MAX_GAIN);
+        let [current_channels, _] = &mut self.channel_frames;
+        let per_channel = current_channels.len() == self.current_gray.len() * 3;
+        for y in rect.rows() {
+            let row = y * width + rect.x..y * width + rect.x + rect.width;
+            for value in &mut self.current_gray[row.clone()] {
+                *value = (*value * gain).min(255.0);
+            }
+            if per_channel {
+                for value in &mut current_channels[row.start * 3..row.end * 3] {
+                    *value = (*value * gain).min(255.0);
+                }
+            }
+        }
+    }
+
+    // Clear the difference of pixels whose current color is the key of `set_ignore_color`
+    fn ignore_key_color(&mut self, input: FrameInput, params: &MotionOptions, rows: Range<usize>) {
+        let Some((key, tolerance)) = self.ignore_color else {
+            return;
+        };
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        for y in rows {
+            for x in cols.clone() {
+                let [r, g, b] = self.input_rgb(input, self.source_pixel(params, x, y));
+                let distance_squared = [r, g, b]
+                    .iter()
+                    .zip(&key)
+                    .map(|(&value, &key)| (value as f32 - key as f32).powi(2))
+                    .sum::<f32>();
+                if distance_squared <= tolerance * tolerance {
+                    self.diff_buffer[y * width + x] = 0.0;
+                }
+            }
+        }
+    }
+
+    // Difference of a band of decoded rows against the previous frame or the background
+    // model into `diff_buffer`
+    fn diff_rows(&mut self, params: &MotionOptions, rows: Range<usize>) {
+        self.update_noise(params, rows.clone());
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let background_step = self.background_step;
+        let learning_rate = params.learning_rate.clamp(0.0, 1.0);
+
+        // Per-channel difference, once there is a previous frame to compare the channels with
+        let [current, previous] = &self.channel_frames;
+        if params.detection_mode == DetectionMode::FrameDiff
+            && params.channel_mode == ChannelMode::PerChannel
+            && previous.len() == current.len()
+        {
+            for y in rows {
+                for pixel_index in y * width + cols.start..y * width + cols.end {
+                    let channels = pixel_index * 3..pixel_index * 3 + 3;
+                    let luma_diff =
+                        (self.current_gray[pixel_index] - self.previous_gray[pixel_index]).abs();
+                    self.diff_buffer[pixel_index] = current[channels.clone()]
+                        .iter()
+                        .zip(&previous[channels])
+                        .fold(luma_diff, |largest, (a, b)| largest.max((a - b).abs()));
+                }
+            }
+            return;
+        }
+
+        if params.detection_mode == DetectionMode::FrameDiff {
+            for y in rows {
+                let row = y * width + cols.start..y * width + cols.end;
+                simd::abs_diff_row(
+                    &self.current_gray[row.clone()],
+                    &self.previous_gray[row.clone()],
+                    &mut self.diff_buffer[row],
+                );
+            }
+            return;
+        }
+
+        // The background starts out as the last frame
+        if self.background_gray.len() != self.previous_gray.len() {
+            self.background_gray.clone_from(&self.previous_gray);
+        }
+
+        for y in rows {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                let current_gray = self.current_gray[pixel_index];
+                let previous_gray = match self.background_model {
+                    BackgroundModel::RunningAverage => {
+                        let background = self.background_gray[pixel_index];
+                        self.background_gray[pixel_index] =
+                            background + (current_gray - background) * learning_rate;
+                        background
+                    }
+                    BackgroundModel::TemporalMedian => {
+                        // Frugal median: step towards the new value by a fixed amount, so
+                        // a change has to last about N frames to be fully absorbed
+                        let background = self.background_gray[pixel_index];
+                        self.background_gray[pixel_index] = if current_gray > background {
+                            (background + background_step).min(current_gray)
+                        } else {
+                            (background - background_step).max(current_gray)
+                        };
+                        background
+                    }
+                };
+                self.diff_buffer[pixel_index] = (current_gray - previous_gray).abs();
+            }
+        }
+    }
+
+    // Follow each pixel's luminance mean and standard deviation for `adaptive_threshold`.
+    // Deviations are clipped to the current threshold before they update the variance so
+    // moving objects don't read as noise.
+    fn update_noise(&mut self, params: &MotionOptions, rows: Range<usize>) {
+        if !params.adaptive_threshold {
+            if !self.noise_sigma.is_empty() {
+                self.noise_mean = Vec::new();
+                self.noise_sigma = Vec::new();
+            }
+            return;
+        }
+        // The estimate starts out as the current frame with a moderate noise level
+        if self.noise_sigma.len() != self.current_gray.len() {
+            self.noise_mean.clone_from(&self.current_gray);
+            self.noise_sigma = vec![NOISE_INITIAL_SIGMA; self.current_gray.len()];
+        }
+
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let k = params.adaptive_k.max(1.0);
+        for y in rows {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                let sigma = self.noise_sigma[pixel_index];
+                let deviation = self.current_gray[pixel_index] - self.noise_mean[pixel_index];
+                let clipped = deviation.clamp(-k * sigma, k * sigma);
+                self.noise_mean[pixel_index] += clipped * NOISE_LEARNING_RATE;
+                let variance = (1.0 - NOISE_LEARNING_RATE)
+                    * (sigma * sigma + NOISE_LEARNING_RATE * clipped * clipped);
+                self.noise_sigma[pixel_index] = variance.sqrt().max(NOISE_MIN_SIGMA);
+            }
+        }
+    }
+
+    // Whether the whole difference just computed is a scene change (see
+    // `scene_change_threshold`); flags the frame, restarts the background model if asked to,
+    // and returns whether detection should be skipped for it
+    fn detect_scene_change(&mut self, params: &MotionOptions) -> bool {
+        if params.scene_change_threshold <= 0.0 {
+            return false;
+        }
+        let width = self.width as usize;
+        let rect = self.active_rect();
+        let changed: usize = rect
+            .rows()
+            .map(|y| {
+                let row = y * width + rect.x..y * width + rect.x + rect.width;
+                self.diff_buffer[row]
+                    .iter()
+                    .filter(|&&diff| diff > params.threshold)
+                    .count()
+            })
+            .sum();
+        let area = (rect.width * rect.height).max(1);
+        if changed as f32 <= params.scene_change_threshold * area as f32 {
+            return false;
+        }
+
+        self.frame_flags |= FRAME_SCENE_CHANGE;
+        if params.scene_change_reset {
+            // The background and noise estimates are re-seeded from the next frame's
+            // reference, which is this frame
+            self.background_gray.clear();
+            self.noise_mean = Vec::new();
+            self.noise_sigma = Vec::new();
+            self.hysteresis_state.fill(0);
+        }
+        params.scene_change_suppress
+    }
+
+    // Threshold hysteresis on a band of `diff_buffer`: pixels that are neither above the high
+    // threshold nor above the low one while already moving are cleared, and which pixels
+    // move is remembered for the next frame. Detection then thresholds at the low one.
+    fn hold_threshold(&mut self, params: &MotionOptions, rows: Range<usize>) {
+        let Some((high, low)) = params.hysteresis() else {
+            if !self.hysteresis_state.is_empty() {
+                self.hysteresis_state = Vec::new();
+            }
+            return;
+        };
+        let pixels = self.diff_buffer.len();
+        if self.hysteresis_state.len() != pixels.div_ceil(64) {
+            self.hysteresis_state = vec![0; pixels.div_ceil(64)];
+        }
+
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        for y in rows {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                let weighted =
+                    self.diff_buffer[pixel_index] * self.luts.radial_sensitivity[pixel_index];
+                let falloff = self.luts.distance[pixel_index] * self.radial_profile.threshold_rise;
+                let (word, bit) = (pixel_index / 64, 1u64 << (pixel_index % 64));
+                let was_moving = self.hysteresis_state[word] & bit != 0;
+                let moving = weighted > high + falloff || (was_moving && weighted > low + falloff);
+                if moving {
+                    self.hysteresis_state[word] |= bit;
+                } else {
+                    self.hysteresis_state[word] &= !bit;
+                    self.diff_buffer[pixel_index] = 0.0;
+                }
+            }
+        }
+    }
+
+    // Erode and then dilate `diff_buffer` with 3x3 min / max filters. Thresholding commutes
+    // with both, so this is the morphology of the thresholded motion map.
+    fn morph_diff(&mut self, params: &MotionOptions) {
+        if params.erode == 0 && params.dilate == 0 {
+            if !self.morphology_buffer.is_empty() {
+                self.morphology_buffer = Vec::new();
+            }
+            return;
+        }
+        let width = self.width as usize;
+        let rect = self.active_rect();
+        if rect.width == 0 || rect.height == 0 {
+            return;
+        }
+        let mut scratch = std::mem::take(&mut self.morphology_buffer);
+        scratch.resize(self.diff_buffer.len(), 0.0);
+
+        let last_x = rect.x + rect.width - 1;
+        let last_y = rect.y + rect.height - 1;
+        let erode = f32::min as fn(f32, f32) -> f32;
+        for (iterations, combine) in [(params.erode, erode), (params.dilate, f32::max)] {
+            for _ in 0..iterations.min(MAX_MORPHOLOGY_ITERATIONS) {
+                // Separable: along each row into the scratch buffer, then down each column
+                // back, with the rect edges clamped
+                for y in rect.rows() {
+                    let row = y * width;
+                    for x in rect.cols() {
+                        let left = self.diff_buffer[row + x.saturating_sub(1).max(rect.x)];
+                        let right = self.diff_buffer[row + (x + 1).min(last_x)];
+                        scratch[row + x] = combine(combine(left, self.diff_buffer[row + x]), right);
+                    }
+                }
+                for y in rect.rows() {
+                    let above = y.saturating_sub(1).max(rect.y) * width;
+                    let below = (y + 1).min(last_y) * width;
+                    for x in rect.cols() {
+                        self.diff_buffer[y * width + x] = combine(
+                            combine(scratch[above + x], scratch[y * width + x]),
+                            scratch[below + x],
+                        );
+                    }
+                }
+            }
+        }
+        self.morphology_buffer = scratch;
+    }
+
+    // Blur the moved trails in `temp_buffer` with the `diffusion` option; needs the whole
+    // frame moved, so it runs between movement and detection
+    fn diffuse_trails(&mut self, params: &MotionOptions) {
+        let sigma = if params.diffusion.is_finite() {
+            params.diffusion.min(MAX_DIFFUSION)
+        } else {
+            0.0
+        };
+        if sigma <= 0.0 {
+            if !self.diffusion_buffers[0].is_empty() {
+                self.diffusion_buffers = Default::default();
+            }
+            return;
+        }
+        let width = self.width as usize;
+        let rect = self.active_rect();
+        let [blurred, scratch] = &mut self.diffusion_buffers;
+        blurred.resize(self.temp_buffer.len(), 0.0);
+        scratch.resize(self.temp_buffer.len(), 0.0);
+
+        gaussian_blur(&self.temp_buffer, blurred, scratch, width, rect, sigma);
+        // Only the active area of the moved trails is read, so the blurred buffer can take
+        // their place wholesale
+        std::mem::swap(&mut self.temp_buffer, blurred);
+    }
+
+    // Whole-frame filtering of `diff_buffer` between differencing and detection
+    fn filter_diff(&mut self) {
+        self.convolve_diff();
+        let Some(dog) = self.dog_filter else {
+            return;
+        };
+        let width = self.width as usize;
+        let rect = self.active_rect();
+        let [inner, outer, scratch] = &mut self.dog_buffers;
+        for buffer in [&mut *inner, &mut *outer, &mut *scratch] {
+            buffer.resize(self.diff_buffer.len(), 0.0);
+        }
+
+        gaussian_blur(
+            &self.diff_buffer,
+            inner,
+            scratch,
+            width,
+            rect,
+            dog.inner_sigma,
+        );
+        gaussian_blur(
+            &self.diff_buffer,
+            outer,
+            scratch,
+            width,
+            rect,
+            dog.outer_sigma,
+        );
+        // Keep structures of the band's scale: fine noise is blurred away by the inner
+        // Gaussian, slow gradients cancel against the outer one
+        for y in rect.rows() {
+            for pixel_index in y * width + rect.x..y * width + rect.x + rect.width {
+                self.diff_buffer[pixel_index] = (inner[pixel_index] - outer[pixel_index]).max(0.0);
+            }
+        }
+    }
+
+    // Replace each difference with the magnitude of the `set_detection_kernel` weights over
+    // its neighbourhood; neighbours outside the active area repeat its edge
+    fn convolve_diff(&mut self) {
+        let Some(kernel) = &self.detection_kernel else {
+            return;
+        };
+        let width = self.width as usize;
+        let rect = self.active_rect();
+        let radius = kernel.size / 2;
+        let mut convolved = std::mem::take(&mut self.kernel_buffer);
+        convolved.resize(self.diff_buffer.len(), 0.0);
+        for y in rect.rows() {
+            for x in rect.cols() {
+                let mut sum = 0.0;
+                for (ky, row) in kernel.weights.chunks_exact(kernel.size).enumerate() {
+                    let sample_y = (y + ky)
+                        .saturating_sub(radius)
+                        .clamp(rect.y, rect.y + rect.height - 1);
+                    for (kx, weight) in row.iter().enumerate() {
+                        let sample_x = (x + kx)
+                            .saturating_sub(radius)
+                            .clamp(rect.x, rect.x + rect.width - 1);
+                        sum += weight * self.diff_buffer[sample_y * width + sample_x];
+                    }
+                }
+                convolved[y * width + x] = sum.abs();
+            }
+        }
+        for y in rect.rows() {
+            let row = y * width + rect.x..y * width + rect.x + rect.width;
+            self.diff_buffer[row.clone()].copy_from_slice(&convolved[row]);
+        }
+        self.kernel_buffer = convolved;
+    }
+
+    fn update_palette(&mut self, params: &MotionOptions) {
+        let curve = LevelCurve {
+            gamma: params.gamma,
+            contrast: params.contrast,
+            posterize_levels: params.posterize_levels,
+        };
+        let key = (params.output_mode, params.tint_color, curve);
+        if self.palette_key != key {
+            self.palette = build_palette(key.0, key.1, key.2);
+            self.palette_key = key;
+        }
+    }
+
+    // The detection threshold of each pixel is `threshold + offsets[pixel] * offset_scale`:
+    // the fixed threshold rising towards the edges, or a multiple of the pixel's noise
+    fn threshold_terms(&self) -> (f32, &[f32], f32) {
+        match self.adaptive_k {
+            Some(k) if !self.noise_sigma.is_empty() => (0.0, &self.noise_sigma, k),
+            _ => (
+                self.motion_threshold,
+                &self.luts.distance,
+                self.radial_profile.threshold_rise,
+            ),
+        }
+    }
+
+    // 256-bin histogram of the frame differences over the active area
+    fn diff_histogram(&self) -> [u32; 256] {
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        let mut histogram = [0; 256];
+        for y in self.active_rows() {
+            for &difference in &self.diff_buffer[y * width + cols.start..y * width + cols.end] {
+                histogram[difference.clamp(0.0, 255.0) as usize] += 1;
+            }
+        }
+        histogram
+    }
+
+    // Move the `auto_threshold` towards the Otsu threshold of this frame's differences
+    fn update_auto_threshold(&mut self, params: &MotionOptions) {
+        if params.auto_threshold == AutoThreshold::Off {
+            self.auto_threshold = None;
+            return;
+        }
+        let target = otsu_threshold(&self.diff_histogram())
+            .clamp(AUTO_THRESHOLD_RANGE.0, AUTO_THRESHOLD_RANGE.1);
+        let current = self.auto_threshold.unwrap_or(params.threshold);
+        self.auto_threshold = Some(current + (target - current) * AUTO_THRESHOLD_SMOOTHING);
+    }
+
+    // Zone coverage from the pixels that passed the threshold this frame
+    fn update_zones(&mut self) {
+        if self.zones.is_empty() {
+            return;
+        }
+        let mut zones = std::mem::take(&mut self.zones);
+        zones.update(self.width, self.height, |pixel_index| {
+            self.is_moving(pixel_index)
+        });
+        self.zones = zones;
+    }
+
+    // Push the fluid of the `fluid` output mode with the motion of the frame: each cell gets
+    // the optical flow of its pixels as velocity and dye for the share of them that moved,
+    // then the fluid takes a step
+    fn update_fluid(&mut self, params: &MotionOptions) {
+        if params.output_mode != OutputMode::Fluid {
+            self.fluid = Fluid::default();
+            return;
+        }
+        let cell_size = fluid_cell_size(params) as usize;
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (grid_width, grid_height) = (width.div_ceil(cell_size), height.div_ceil(cell_size));
+        let mut fluid = std::mem::take(&mut self.fluid);
+        fluid.resize(grid_width, grid_height);
+        // Before `finish_frame` the newest frame is still `current_gray`
+        let flow = self.block_flow(cell_size, &self.current_gray, &self.previous_gray);
+        let (rows, cols) = (self.active_rows(), self.active_cols());
+        for cell_y in 0..grid_height {
+            let cell_rows =
+                (cell_y * cell_size).max(rows.start)..((cell_y + 1) * cell_size).min(rows.end);
+            for cell_x in 0..grid_width {
+                let cell_cols =
+                    (cell_x * cell_size).max(cols.start)..((cell_x + 1) * cell_size).min(cols.end);
+                let moving = cell_rows
+                    .clone()
+                    .flat_map(|y| cell_cols.clone().map(move |x| y * width + x))
+                    .filter(|pixel_index| self.is_moving(*pixel_index))
+                    .count();
+                if moving == 0 {
+                    continue;
+                }
+                let cell = cell_y * grid_width + cell_x;
+                let share = moving as f32 / (cell_size * cell_size) as f32;
+                // The flow is in pixels, the fluid moves in cells
+                let scale = params.fluid_force / cell_size as f32;
+                fluid.inject(
+                    cell,
+                    (flow[cell * 2] * scale, flow[cell * 2 + 1] * scale),
+                    share * params.fluid_force * 255.0,
+                );
+            }
+        }
+        fluid.step(FluidSettings {
+            viscosity: params.fluid_viscosity,
+            dissipation: params.fluid_dissipation,
+        });
+        self.fluid = fluid;
+    }
+
+    // Heat the pixels of the `dwell` output mode that moved in this frame by its length, up to
+    // `dwell_max_ms`, and cool the others by `dwell_cooling` times it
+    fn update_dwell(&mut self, params: &MotionOptions) {
+        if params.output_mode != OutputMode::Dwell {
+            self.dwell_ms = Vec::new();
+            return;
+        }
+        let pixels = (self.width * self.height) as usize;
+        if self.dwell_ms.len() != pixels {
+            self.dwell_ms = vec![0.0; pixels];
+        }
+        let heating = self.frame_elapsed_ms;
+        let cooling = self.frame_elapsed_ms * params.dwell_cooling;
+        let mut dwell_ms = std::mem::take(&mut self.dwell_ms);
+        for (pixel_index, dwell) in dwell_ms.iter_mut().enumerate() {
+            *dwell = if self.is_moving(pixel_index) {
+                (*dwell + heating).min(params.dwell_max_ms)
+            } else {
+                (*dwell - cooling).max(0.0)
+            };
+        }
+        self.dwell_ms = dwell_ms;
+    }
+
+    fn update_silhouette(&mut self, params: &MotionOptions) {
+        if params.output_mode != OutputMode::Silhouette {
+            self.silhouette = Silhouette::default();
+            return;
+        }
+        let mut silhouette = std::mem::take(&mut self.silhouette);
+        silhouette.update(
+            (self.width as usize, self.height as usize),
+            self.active_rows(),
+            self.active_cols(),
+            params.silhouette_smoothing,
+            |pixel_index| self.is_moving(pixel_index),
+        );
+        self.silhouette = silhouette;
+    }
+
+    fn update_motion_trigger(&mut self) {
+        if let Some(trigger) = &mut self.motion_trigger {
+            trigger.update(
+                self.frame_totals.stats().active_percent,
+                self.stream_time_ms,
+            );
+        }
+    }
+
+    // Keep the analysed frame for `set_frame_capture` and capture it if its motion set off
+    // the trigger
+    fn update_capture(&mut self, input: FrameInput) {
+        let Some(mut capture) = self.frame_capture.take() else {
+            return;
+        };
+        let size = (self.frame_width, self.frame_height);
+        capture.update(
+            self.frame_totals.stats().energy,
+            self.stream_time_ms,
+            size,
+            |pixels| {
+                for pixel_index in 0..self.frame_pixels() {
+                    let [r, g, b] = self.input_rgb(input, pixel_index);
+                    pixels.extend_from_slice(&[r, g, b, 255]);
+                }
+            },
+        );
+        self.frame_capture = Some(capture);
+    }
+
+    // Spacing of the pixels detection runs on; every sample fills its whole block. Reduced
+    // power tiers detect once per 2x2 block, `grid_mode` on a sparser grid.
+    fn detection_step(&self, params: &MotionOptions) -> usize {
+        let tier_step = match self.power_tier {
+            PowerTier::Full => 1,
+            PowerTier::Reduced | PowerTier::Idle => 2,
+        };
+        tier_step.max(grid_step(params) as usize)
+    }
+
+    // Collect the samples of `grid_mode` into `motion_grid` and replace the blocks they
+    // filled with a bilinear interpolation between them. The trails themselves keep the
+    // blocks; the direction output mode keeps them on screen as well.
+    fn interpolate_grid(&mut self, output_data: &mut [u8], params: &MotionOptions) {
+        if grid_step(params) <= 1 {
+            if !self.motion_grid.is_empty() {
+                self.motion_grid = Vec::new();
+                self.motion_grid_width = 0;
+            }
+            return;
+        }
+        let step = self.detection_step(params);
+        let width = self.width as usize;
+        let (rows, cols) = (self.active_rows(), self.active_cols());
+        let grid_width = cols.len().div_ceil(step);
+        let grid_height = rows.len().div_ceil(step);
+        self.motion_grid.clear();
+        for grid_y in 0..grid_height {
+            let row_base = (rows.start + grid_y * step) * width + cols.start;
+            self.motion_grid.extend(
+                (0..grid_width).map(|grid_x| self.persistence_buffer[row_base + grid_x * step]),
+            );
+        }
+        self.motion_grid_width = grid_width;
+        if params.output_mode == OutputMode::Direction || self.motion_grid.is_empty() {
+            return;
+        }
+
+        // Position of a pixel between its two nearest samples along one axis
+        let between = |offset: usize, samples: usize| {
+            let position = offset as f32 / step as f32;
+            let first = (position as usize).min(samples - 1);
+            (first, (first + 1).min(samples - 1), position - first as f32)
+        };
+        for y in rows.clone() {
+            let (top, bottom, fy) = between(y - rows.start, grid_height);
+            for x in cols.clone() {
+                let (left, right, fx) = between(x - cols.start, grid_width);
+                let sample =
+                    |grid_x: usize, grid_y: usize| self.motion_grid[grid_y * grid_width + grid_x];
+                let upper = sample(left, top) + (sample(right, top) - sample(left, top)) * fx;
+                let lower =
+                    sample(left, bottom) + (sample(right, bottom) - sample(left, bottom)) * fx;
+                let level = upper + (lower - upper) * fy;
+                let color = self.palette[level.clamp(0.0, 255.0) as usize];
+                self.output_format
+                    .write(output_data, y * width + x, color, 255);
+            }
+        }
+    }
+
+    // Motion detection, persistence and output for a band of rows; movement and the frame
+    // difference for the same rows must already be in `temp_buffer` and `diff_buffer`. With
+    // the `threads` feature full-quality frames are split across the worker pool.
+    fn detect_rows(&mut self, output_data: &mut [u8], params: &MotionOptions, rows: Range<usize>) {
+        self.update_palette(params);
+        // With hysteresis `hold_threshold` already cleared everything that isn't moving
+        self.motion_threshold = params.hysteresis().map_or(params.threshold, |(_, low)| low);
+        self.adaptive_k = params.adaptive_threshold.then_some(params.adaptive_k);
+        let mut persistence_buffer = std::mem::take(&mut self.persistence_buffer);
+        let (threshold, threshold_offsets, offset_scale) = self.threshold_terms();
+        let band = rows.clone();
+        let detection = Detection {
+            width: self.width as usize,
+            cols: self.active_cols(),
+            active_rows: self.active_rows(),
+            step: self.detection_step(params),
+            palette: &self.palette,
+            threshold,
+            threshold_offsets,
+            offset_scale,
+            radial_sensitivity_lut: &self.luts.radial_sensitivity,
+            diff_buffer: &self.diff_buffer,
+            temp_buffer: &self.temp_buffer,
+            mask: (!self.mask.is_empty()).then_some(&self.mask[..]),
+            fade: self.trail_fade(params),
+            decay_map: (!self.decay_map.is_empty()).then_some(&self.decay_map[..]),
+            time_scale: self.frame_time_scale,
+            output_format: self.output_format,
+        };
+
+        // Blocks of the reduced tiers can straddle band edges, and those tiers are cheap
+        // enough to stay on one thread
+        #[cfg(feature = "threads")]
+        let totals = if detection.step == 1 {
+            use rayon::prelude::*;
+
+            let width = detection.width;
+            let bytes_per_pixel = detection.output_format.bytes_per_pixel();
+            let band_rows = parallel_band_rows(rows.len());
+            let span = rows.start * width..rows.end * width;
+            persistence_buffer[span.clone()]
+                .par_chunks_mut(band_rows * width)
+                .zip(
+                    output_data[span.start * bytes_per_pixel..span.end * bytes_per_pixel]
+                        .par_chunks_mut(band_rows * width * bytes_per_pixel),
+                )
+                .enumerate()
+                .map(|(index, (persistence, output))| {
+                    let start = rows.start + index * band_rows;
+                    let band = start..(start + band_rows).min(rows.end);
+                    let offset = start * width;
+                    detection.detect_band(
+                        params,
+                        band,
+                        &mut RowBand::new(persistence, offset),
+                        output,
+                        offset,
+                    )
+                })
+                .reduce(FrameTotals::default, FrameTotals::merge)
+        } else {
+            detection.detect_band(
+                params,
+                rows,
+                &mut RowBand::new(&mut persistence_buffer, 0),
+                output_data,
+                0,
+            )
+        };
+        #[cfg(not(feature = "threads"))]
+        let totals = detection.detect_band(
+            params,
+            rows,
+            &mut RowBand::new(&mut persistence_buffer, 0),
+            output_data,
+            0,
+        );
+
+        self.persistence_buffer = persistence_buffer;
+        self.frame_totals = self.frame_totals.merge(totals);
+        self.spawn_particles(band.clone());
+        self.color_by_direction(output_data, params, band, true);
+    }
+
+    // Recolor the trails of a band of rows for `output_mode: "direction"`: hue from the
+    // direction of the motion that left them, brightness from their intensity. With
+    // `estimate` the direction is first re-estimated wherever this frame moved, from the
+    // spatial and temporal gradients of the current and previous frame over a 3x3 window
+    // (the data term of Horn-Schunck). Elsewhere the last direction stays in place, also
+    // when the trails move on.
+    fn color_by_direction(
+        &mut self,
+        output_data: &mut [u8],
+        params: &MotionOptions,
+        rows: Range<usize>,
+        estimate: bool,
+    ) {
+        if params.output_mode != OutputMode::Direction {
+            if !self.direction_hue.is_empty() {
+                self.direction_hue = Vec::new();
+            }
+            return;
+        }
+        if self.direction_hue.len() != self.persistence_buffer.len() {
+            self.direction_hue = vec![0.0; self.persistence_buffer.len()];
+        }
+        let width = self.width as usize;
+        let cols = self.active_cols();
+        if rows.is_empty() || cols.is_empty() {
+            return;
+        }
+
+        let mut direction_hue = std::mem::take(&mut self.direction_hue);
+        if estimate {
+            // Gradients stay inside the band, whose rows are the only ones decoded for sure
+            let (top, bottom) = (rows.start, rows.end - 1);
+            let (left, right) = (cols.start, cols.end - 1);
+            let gray = |index: usize| (self.current_gray[index] + self.previous_gray[index]) * 0.5;
+            for y in rows.clone() {
+                for x in cols.clone() {
+                    let pixel_index = y * width + x;
+                    if !self.is_moving(pixel_index) {
+                        continue;
+                    }
+                    let (mut flow_x, mut flow_y) = (0.0, 0.0);
+                    for ny in y.saturating_sub(1).max(top)..=(y + 1).min(bottom) {
+                        for nx in x.saturating_sub(1).max(left)..=(x + 1).min(right) {
+                            let index = ny * width + nx;
+                            let gradient_x = gray(ny * width + (nx + 1).min(right))
+                                - gray(ny * width + nx.saturating_sub(1).max(left));
+                            let gradient_y = gray((ny + 1).min(bottom) * width + nx)
+                                - gray(ny.saturating_sub(1).max(top) * width + nx);
+                            let temporal = self.current_gray[index] - self.previous_gray[index];
+                            flow_x -= gradient_x * temporal;
+                            flow_y -= gradient_y * temporal;
+                        }
+                    }
+                    if flow_x != 0.0 || flow_y != 0.0 {
+                        direction_hue[pixel_index] = flow_y.atan2(flow_x) / TAU;
+                    }
+                }
+            }
+        }
+
+        self.direction_hue = direction_hue;
+
+        for y in rows {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                // The grayscale palette of this mode carries the level curve
+                let level = self.palette
+                    [self.persistence_buffer[pixel_index].clamp(0.0, 255.0) as usize][0];
+                let color = hue_to_rgb(self.direction_hue[pixel_index])
+                    .map(|channel| (channel * level as f32).round() as u8);
+                self.output_format
+                    .write(output_data, pixel_index, color, 255);
+            }
+        }
+    }
+
+    // 16-bit per channel RGBA input (scientific/industrial cameras); the extra precision is
+    // carried through the diff so motion below 8-bit quantization becomes detectable
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion_rgba64(
+        &mut self,
+        current_data: &[u16],
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.process_input(FrameInput::Rgba64(current_data), output_data, options)
+            .map_err(js_error)
+    }
+
+    // 16-bit single-channel luminance input, one sample per pixel
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion_gray16(
+        &mut self,
+        current_data: &[u16],
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.process_input(FrameInput::Gray16(current_data), output_data, options)
+            .map_err(js_error)
+    }
+
+    // 8-bit grayscale input, one byte per pixel, for callers that already convert to luma
+    // on the GPU (a quarter of the RGBA transfer, and no weighting per pixel here)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion_gray(
+        &mut self,
+        gray: &[u8],
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.process_input(FrameInput::Gray8(gray), output_data, options)
+            .map_err(js_error)
+    }
+
+    // Planar 8-bit RGB input from three separate planes of width * height bytes each
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion_planar(
+        &mut self,
+        r_plane: &[u8],
+        g_plane: &[u8],
+        b_plane: &[u8],
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        let input = FrameInput::Planar {
+            r: r_plane,
+            g: g_plane,
+            b: b_plane,
+        };
+        self.process_input(input, output_data, options)
+            .map_err(js_error)
+    }
+
+    // Planar 8-bit RGB input as one buffer holding the R, G and B planes back to back
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion_planar_packed(
+        &mut self,
+        planes: &[u8],
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let plane_len = self.frame_pixels();
+        if planes.len() < plane_len * 3 {
+            return Err(js_error(format!(
+                "planes hold {} bytes, three {}x{} planes need {}",
+                planes.len(),
+                self.frame_width,
+                self.frame_height,
+                plane_len * 3
+            )));
+        }
+        let (r_plane, rest) = planes.split_at(plane_len);
+        let (g_plane, b_plane) = rest.split_at(plane_len);
+        self.process_motion_planar(r_plane, g_plane, b_plane, output_data, options)
+    }
+
+    // 4:2:0 YUV input as delivered by `VideoFrame.copyTo` (I420 or NV12). The luma plane is
+    // used as is for detection; chroma is only read to show the input outside the ROI. For
+    // NV12 pass the interleaved UV plane as `u_plane` and an empty `v_plane`. Strides are in
+    // bytes, 0 meaning tightly packed rows.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn process_motion_yuv(
+        &mut self,
+        y_plane: &[u8],
+        u_plane: &[u8],
+        v_plane: &[u8],
+        y_stride: u32,
+        uv_stride: u32,
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        let width = self.frame_width as usize;
+        let packed_uv_stride = if v_plane.is_empty() {
+            width.div_ceil(2) * 2
+        } else {
+            width.div_ceil(2)
+        };
+        let input = FrameInput::Yuv420 {
+            y_plane,
+            u_plane,
+            v_plane,
+            y_stride: if y_stride == 0 {
+                width
+            } else {
+                y_stride as usize
+            },
+            uv_stride: if uv_stride == 0 {
+                packed_uv_stride
+            } else {
+                uv_stride as usize
+            },
+        };
+        self.process_input(input, output_data, options)
+            .map_err(js_error)
+    }
+
+    // Linear floating-point RGBA input (e.g. WebGPU HDR readbacks), mapped through the
+    // exposure set with `set_hdr_exposure` before differencing
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion_f32(
+        &mut self,
+        current_data: &[f32],
+        output_data: &mut [u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.process_input(FrameInput::RgbaF32(current_data), output_data, options)
+            .map_err(js_error)
+    }
+
+    // Band-pass the frame difference with a Difference of Gaussians so only motion
+    // structures between the two scales (in pixels) register, suppressing both sensor noise
+    // and slow illumination gradients. Disabling frees the filter buffers.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_dog_saliency(&mut self, enabled: bool, inner_sigma: f32, outer_sigma: f32) {
+        if !enabled {
+            self.dog_filter = None;
+            self.dog_buffers = Default::default();
+            return;
+        }
+        let inner_sigma = inner_sigma.max(0.1);
+        self.dog_filter = Some(DogFilter {
+            inner_sigma,
+            outer_sigma: outer_sigma.max(inner_sigma + 0.1),
+        });
+    }
+
+    // Detect on a weighted neighbourhood of each pixel's difference instead of the pixel
+    // alone: `weights` is a row-major `size` x `size` kernel (odd `size` up to 9), e.g. a
+    // box blur against sensor noise or a Sobel-like kernel that favours moving edges. The
+    // magnitude of the sum is compared with the threshold, so the weights set the scale.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_detection_kernel(&mut self, weights: &[f32], size: u32) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if size.is_multiple_of(2) || size > MAX_DETECTION_KERNEL_SIZE {
+            return Err(js_error(format!(
+                "detection kernel size must be odd and at most {}, got {}",
+                MAX_DETECTION_KERNEL_SIZE, size
+            )));
+        }
+        if weights.len() != (size * size) as usize {
+            return Err(js_error(format!(
+                "a {0}x{0} detection kernel needs {1} weights, got {2}",
+                size,
+                size * size,
+                weights.len()
+            )));
+        }
+        if !weights.iter().all(|weight| weight.is_finite()) {
+            return Err(js_error(
+                "detection kernel weights must be finite".to_string(),
+            ));
+        }
+        self.detection_kernel = Some(DetectionKernel {
+            weights: weights.to_vec(),
+            size: size as usize,
+        });
+        Ok(())
+    }
+
+    // Back to per-pixel differences; frees the kernel buffer
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_detection_kernel(&mut self) {
+        self.detection_kernel = None;
+        self.kernel_buffer = Vec::new();
+    }
+
+    // Ease the movement parameters (speed, angle, rotation, amplitude, zoom, ...) from those of
+    // the last frame to `options` over `duration_frames` frames, so switching presets live
+    // doesn't pop. Pass `options` from the next frame on: while the transition runs, their
+    // movement parameters are replaced by the eased ones. A new move type takes over at once,
+    // cross-faded as set by `set_move_transition_frames`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn transition_to(&mut self, options: &MotionOptions, duration_frames: u32) {
+        self.parameter_transition = match self.last_options {
+            Some(from) if duration_frames > 0 => Some(ParameterTransition {
+                from,
+                to: *options,
+                frame: 0,
+                total_frames: duration_frames,
+            }),
+            _ => None,
+        };
+    }
+
+    // Frames over which switching move_type cross-fades from the old movement to the new
+    // one (0 switches immediately)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_move_transition_frames(&mut self, frames: u32) {
+        self.move_transition_frames = frames;
+        if frames == 0 {
+            self.move_transition = None;
+            self.transition_buffer = Vec::new();
+        }
+    }
+
+    // Replace the radial sensitivity profile; the lookup tables are rebuilt right away
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_radial_profile(&mut self, profile: &RadialProfile) {
+        self.radial_profile = RadialProfile {
+            falloff: profile.falloff.clamp(0.0, 1.0),
+            min_sensitivity: profile.min_sensitivity.clamp(0.0, 1.0),
+            exponent: profile.exponent.max(0.01),
+            inverted: profile.inverted,
+            threshold_rise: profile.threshold_rise.max(0.0),
+        };
+        self.build_luts(
+            self.content_rect
+                .unwrap_or(Rect::full(self.width, self.height)),
+        );
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn radial_profile(&self) -> RadialProfile {
+        self.radial_profile
+    }
+
+    // Radial distortion of a fisheye or wide-angle lens, so radial and spiral movement, the
+    // kaleidoscope and the radial profile work in true distances around the optical center
+    // (`cx`, `cy` in fractions of the picture, 0.5 = middle) rather than picture pixels.
+    // `k1` and `k2` are the coefficients of the usual polynomial model over the distance in
+    // half-diagonals; barrel distortion (fisheye) has positive ones. Rebuilds the tables.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_lens_model(&mut self, k1: f32, k2: f32, cx: f32, cy: f32) {
+        self.lens_model = Some(LensModel {
+            k1,
+            k2,
+            cx: cx.clamp(0.0, 1.0),
+            cy: cy.clamp(0.0, 1.0),
+        });
+        self.build_luts(
+            self.content_rect
+                .unwrap_or(Rect::full(self.width, self.height)),
+        );
+    }
+
+    // Back to an ideal lens centered on the picture
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_lens_model(&mut self) {
+        if self.lens_model.take().is_some() {
+            self.build_luts(
+                self.content_rect
+                    .unwrap_or(Rect::full(self.width, self.height)),
+            );
+        }
+    }
+
+    // Keep the polar tables of the spiral, radial and kaleidoscope modes as 16-bit fixed
+    // point and square distances on the fly, for memory-constrained devices: the tables take
+    // 4 rather than 12 bytes per pixel, at steps of under a fifth of a pixel even in 4K.
+    // Between frames the trails and the cached previous frame are kept as 16-bit fixed point
+    // too (steps of 1/256, clamped to 0..255 like the trails) and the movement scratch is
+    // freed, 8 of the 32 bytes per pixel of `estimate_memory_bytes`; a frame works on f32
+    // copies, since the SIMD paths read them directly, so this mostly helps apps keeping
+    // several detectors, which take turns with the memory. Reading the trails between frames
+    // (`persistence`, `persistence_ptr`, the render calls) unpacks a copy until the next
+    // frame. Rebuilds the tables.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_low_memory(&mut self, enabled: bool) {
+        if self.low_memory != enabled {
+            self.low_memory = enabled;
+            self.build_luts(
+                self.content_rect
+                    .unwrap_or(Rect::full(self.width, self.height)),
+            );
+            if enabled {
+                self.pack_state();
+            } else {
+                self.unpack_state();
+            }
+        }
+    }
+
+    // Drop the lookup tables that only the radial, spiral, wave, zoom and kaleidoscope modes
+    // read; they are built again the next time one of those runs. Tables of a detector
+    // created through a `DetectorContext` stay, since other detectors may share them.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn free_unused_luts(&mut self) {
+        if let Some(luts) = Rc::get_mut(&mut self.luts) {
+            luts.free_polar();
+        }
+    }
+
+    // Number of downsampled persistence levels (0 = off, at most 4) used by radial and spiral
+    // movement: displacements beyond a few pixels read a pre-filtered coarse level, which
+    // avoids the aliasing of nearest-pixel sampling at large speeds
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_pyramid_levels(&mut self, levels: u32) {
+        self.pyramid_levels = levels.min(PYRAMID_MAX_LEVELS);
+        if self.pyramid_levels == 0 {
+            self.persistence_pyramid = Vec::new();
+        }
+    }
+
+    // Automatically drop to cheaper processing while the scene is still and return to full
+    // quality as soon as motion is seen again; `power_tier` reports the current level
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_power_saving(&mut self, enabled: bool) {
+        self.power_saving = enabled;
+        self.quiet_frames = 0;
+        self.frames_since_analysis = 0;
+        self.power_tier = PowerTier::Full;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn power_tier(&self) -> PowerTier {
+        self.power_tier
+    }
+
+    // Keep frames within `target_ms` (0 = off) by trading the quality radii and then the
+    // processing scale for speed. The detector times its own processing with
+    // `performance.now()` unless the caller supplies timings with `report_frame_time_ms`;
+    // `process_async` frames are only counted through the latter.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_target_frame_time_ms(&mut self, target_ms: f32) {
+        self.frame_budget = (target_ms > 0.0).then(|| FrameBudget::new(target_ms));
+        self.update_quality_radii();
+    }
+
+    // Time the caller measured for the last frame, e.g. including drawing it; from then on
+    // only reported timings drive the frame budget
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn report_frame_time_ms(&mut self, frame_ms: f32) {
+        if let Some(budget) = &mut self.frame_budget {
+            budget.external = true;
+        }
+        self.update_frame_budget(frame_ms);
+    }
+
+    // Smoothed frame time the budget controller is working from (undefined when off)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn average_frame_time_ms(&self) -> Option<f32> {
+        self.frame_budget.and_then(|budget| budget.average_ms)
+    }
+
+    // Background estimate for `detection_mode: "background_sub"`. `frames` is the window of
+    // the temporal median (the running average uses the `learning_rate` option instead);
+    // the estimate is re-seeded from the last frame.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_background_model(&mut self, model: BackgroundModel, frames: u32) {
+        self.background_model = model;
+        self.background_step = 255.0 / frames.max(1) as f32;
+        self.background_gray.clear();
+    }
+
+    // Exposure compensation in stops and the tone curve applied to floating-point input
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_hdr_exposure(&mut self, exposure_ev: f32, tone_mapping: ToneMapping) {
+        self.hdr_exposure_scale = exposure_ev.exp2();
+        self.hdr_tone_mapping = tone_mapping;
+    }
+
+    // Same as `process_motion_with_cache`, but writes into the detector-owned output buffer
+    // so the result can be drawn with `render_to_context` without allocating ImageData in JS
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_motion(
+        &mut self,
+        current_data: &[u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.process_owned_output(current_data, options)
+            .map_err(js_error)
+    }
+
+    // Several frames of recorded video at once, packed back to back in the input format, so
+    // offline processing pays the JS call overhead once. The final composite ends up in the
+    // detector-owned output buffer as with `process_motion`; with `collect_stats` the result
+    // holds the `get_motion_stats` after each frame (unchanged over frames that skip
+    // detection), otherwise it is empty.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_batch(
+        &mut self,
+        frames: &[u8],
+        frame_count: u32,
+        options: &MotionOptions,
+        collect_stats: bool,
+    ) -> Result<Vec<MotionStats>, ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let frame_len = self.frame_pixels() * self.input_format_for(options).bytes_per_pixel();
+        if frame_count == 0 || frames.len() != frame_len * frame_count as usize {
+            return Err(js_error(format!(
+                "batch holds {} bytes, {} frames of {}x{} need {}",
+                frames.len(),
+                frame_count,
+                self.frame_width,
+                self.frame_height,
+                frame_len * frame_count as usize
+            )));
+        }
+        let mut stats = Vec::with_capacity(if collect_stats {
+            frame_count as usize
+        } else {
+            0
+        });
+        for frame in frames.chunks_exact(frame_len) {
+            self.process_owned_output(frame, options)
+                .map_err(js_error)?;
+            if collect_stats {
+                stats.push(self.last_stats);
+            }
+        }
+        Ok(stats)
+    }
+
+    fn process_owned_output(
+        &mut self,
+        current_data: &[u8],
+        options: &MotionOptions,
+    ) -> Result<(), String> {
+        let mut output = std::mem::take(&mut self.output_buffer);
+        output.resize(self.output_len(), 0);
+        let result = self.process(current_data, &mut output, options);
+        self.output_buffer = output;
+        result
+    }
+
+    // Blit the last output from `process_motion` straight from WASM memory onto a 2D canvas
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn render_to_context(&self, ctx: &CanvasRenderingContext2d) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if self.output_buffer.is_empty() {
+            return Ok(());
+        }
+        if self.output_format != PixelFormat::Rgba {
+            return Err(js_error(
+                "render_to_context requires RGBA output".to_string(),
+            ));
+        }
+
+        let (width, height) = self.output_size();
+        let image_data = ImageData::new_with_u8_clamped_array_and_sh(
+            Clamped(&self.output_buffer),
+            width,
+            height,
+        )
+        .map_err(js_exception)?;
+        ctx.put_image_data(&image_data, 0.0, 0.0)
+            .map_err(js_exception)
+    }
+
+    // Upload the last output from `process_motion` into a caller-allocated RGBA8 (RGB8 / R8
+    // for RGB / gray output) texture of the output size, so WebGL pipelines can consume it
+    // without a canvas
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn upload_to_texture(
+        &self,
+        gl: &WebGl2RenderingContext,
+        texture: &WebGlTexture,
+    ) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if self.output_buffer.is_empty() {
+            return Ok(());
+        }
+        let format = match self.output_format {
+            PixelFormat::Rgba | PixelFormat::Rgbx => WebGl2RenderingContext::RGBA,
+            PixelFormat::Rgb => WebGl2RenderingContext::RGB,
+            PixelFormat::Gray => WebGl2RenderingContext::RED,
+            PixelFormat::Bgra => {
+                return Err(js_error("WebGL has no BGRA upload format".to_string()))
+            }
+        };
+
+        let (width, height) = self.output_size();
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+        // RGB and gray rows are not 4-byte aligned in general
+        gl.pixel_storei(WebGl2RenderingContext::UNPACK_ALIGNMENT, 1);
+        gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_u8_array(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            0,
+            0,
+            width as i32,
+            height as i32,
+            format,
+            WebGl2RenderingContext::UNSIGNED_BYTE,
+            Some(&self.output_buffer),
+        )
+        .map_err(js_exception)
+    }
+
+    // Upload the raw persistence buffer (0..255 floats) into a caller-allocated R32F texture,
+    // leaving coloring and compositing of the trails to a shader
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn upload_persistence_to_texture(
+        &self,
+        gl: &WebGl2RenderingContext,
+        texture: &WebGlTexture,
+    ) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        gl.bind_texture(WebGl2RenderingContext::TEXTURE_2D, Some(texture));
+
+        // Safety: the view is consumed by the upload below before any allocation can
+        // grow (and detach) the WASM memory
+        let view = unsafe { js_sys::Float32Array::view(self.trails()) };
+        gl.tex_sub_image_2d_with_i32_and_i32_and_u32_and_type_and_opt_array_buffer_view(
+            WebGl2RenderingContext::TEXTURE_2D,
+            0,
+            0,
+            0,
+            self.width as i32,
+            self.height as i32,
+            WebGl2RenderingContext::RED,
+            WebGl2RenderingContext::FLOAT,
+            Some(&view),
+        )
+        .map_err(js_exception)
+    }
+
+    // Capture the current video frame (scaled to the detector resolution) and process it
+    // into the detector-owned output buffer, so integrations need no manual capture code
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn process_from_video(
+        &mut self,
+        video: &HtmlVideoElement,
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        // HAVE_CURRENT_DATA: nothing to draw before the first frame is decoded
+        if video.ready_state() < 2 {
+            return Ok(());
+        }
+
+        let context = self.capture_context()?;
+        let width = self.frame_width as f64;
+        let height = self.frame_height as f64;
+        context
+            .draw_image_with_html_video_element_and_dw_and_dh(video, 0.0, 0.0, width, height)
+            .map_err(js_exception)?;
+        let frame = context
+            .get_image_data(0.0, 0.0, width, height)
+            .map_err(js_exception)?;
+        self.process_canvas_pixels(&frame.data(), options)
+    }
+
+    // Worker entry point: process an `ImageData` (e.g. read back from an `OffscreenCanvas`
+    // inside the worker) of the detector resolution and return the output in a JS-owned
+    // buffer, posted back with `postMessage(frame.to_message(), frame.transfer_list())`
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn process_image_data(
+        &mut self,
+        frame: &ImageData,
+        options: &MotionOptions,
+    ) -> Result<WorkerFrame, ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if (frame.width(), frame.height()) != (self.frame_width, self.frame_height) {
+            return Err(js_error(format!(
+                "frame is {}x{}, expected {}x{}",
+                frame.width(),
+                frame.height(),
+                self.frame_width,
+                self.frame_height
+            )));
+        }
+        self.process_canvas_pixels(&frame.data(), options)?;
+        Ok(self.worker_frame())
+    }
+
+    // Worker entry point for an `ImageBitmap` transferred from the page (e.g. from
+    // `createImageBitmap(video)`); it is scaled to the detector resolution like
+    // `process_from_video`. The caller still owns the bitmap and should `close()` it.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn process_image_bitmap(
+        &mut self,
+        bitmap: &ImageBitmap,
+        options: &MotionOptions,
+    ) -> Result<WorkerFrame, ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let context = self.capture_context()?;
+        let width = self.frame_width as f64;
+        let height = self.frame_height as f64;
+        context
+            .draw_image_with_image_bitmap_and_dw_and_dh(bitmap, 0.0, 0.0, width, height)
+            .map_err(js_exception)?;
+        let frame = context
+            .get_image_data(0.0, 0.0, width, height)
+            .map_err(js_exception)?;
+        self.process_canvas_pixels(&frame.data(), options)?;
+        Ok(self.worker_frame())
+    }
+
+    // Canvas pixels are always RGBA, whatever the configured input format
+    #[cfg(feature = "wasm")]
+    fn process_canvas_pixels(
+        &mut self,
+        pixels: &[u8],
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        let input_format = std::mem::replace(&mut self.input_format, PixelFormat::Rgba);
+        let options = MotionOptions {
+            input_format: None,
+            ..*options
+        };
+        let result = self.process_owned_output(pixels, &options);
+        self.input_format = input_format;
+        result.map_err(js_error)
+    }
+
+    // The last output copied out of WASM memory into a buffer JS can transfer
+    #[cfg(feature = "wasm")]
+    fn worker_frame(&self) -> WorkerFrame {
+        let pixels = js_sys::Uint8ClampedArray::new_with_length(self.output_buffer.len() as u32);
+        pixels.copy_from(&self.output_buffer);
+        let (width, height) = self.output_size();
+        WorkerFrame {
+            width,
+            height,
+            format: self.output_format,
+            stats: self.last_stats,
+            pixels,
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    fn capture_context(&mut self) -> Result<OffscreenCanvasRenderingContext2d, ExportError> {
+        if let Some(context) = &self.capture_context {
+            return Ok(context.clone());
+        }
+
+        let canvas =
+            OffscreenCanvas::new(self.frame_width, self.frame_height).map_err(js_exception)?;
+        // Hint the browser to keep the canvas CPU-side since we read it back every frame
+        let context_options = js_sys::Object::new();
+        js_sys::Reflect::set(&context_options, &"willReadFrequently".into(), &true.into())
+            .map_err(js_exception)?;
+        let context = canvas
+            .get_context_with_context_options("2d", &context_options)
+            .map_err(js_exception)?
+            .ok_or_else(|| js_error("2D context is not available".to_string()))?
+            .dyn_into::<OffscreenCanvasRenderingContext2d>()
+            .map_err(|_| js_error("2D context is not available".to_string()))?;
+
+        self.capture_context = Some(context.clone());
+        Ok(context)
+    }
+
+    // Dispatch one movement mode over a band of rows into `temp_buffer`; `begin_movement`
+    // must run first. With the `threads` feature the band is split across the worker pool.
+    fn move_rows(&mut self, params: &MotionOptions, rows: Range<usize>) {
+        let params = &self
+            .movement_pipeline
+            .last()
+            .map_or(*params, |step| self.timed(step));
+        let mut temp_buffer = std::mem::take(&mut self.temp_buffer);
+        let mut transition_buffer = std::mem::take(&mut self.transition_buffer);
+        let context = self.frame_context();
+
+        #[cfg(feature = "threads")]
+        {
+            use rayon::prelude::*;
+
+            let width = self.width as usize;
+            let band_rows = parallel_band_rows(rows.len());
+            let span = rows.start * width..rows.end * width;
+            let band = |index: usize| {
+                let start = rows.start + index * band_rows;
+                start..(start + band_rows).min(rows.end)
+            };
+            let targets = temp_buffer[span.clone()]
+                .par_chunks_mut(band_rows * width)
+                .enumerate();
+            if transition_buffer.is_empty() {
+                targets.for_each(|(index, target)| {
+                    let band = band(index);
+                    let offset = band.start * width;
+                    context.move_band(
+                        params,
+                        band,
+                        &mut RowBand::new(target, offset),
+                        &mut RowBand::new(&mut [], offset),
+                    );
+                });
+            } else {
+                targets
+                    .zip(transition_buffer[span].par_chunks_mut(band_rows * width))
+                    .for_each(|((index, target), outgoing)| {
+                        let band = band(index);
+                        let offset = band.start * width;
+                        context.move_band(
+                            params,
+                            band,
+                            &mut RowBand::new(target, offset),
+                            &mut RowBand::new(outgoing, offset),
+                        );
+                    });
+            }
+        }
+        #[cfg(not(feature = "threads"))]
+        context.move_band(
+            params,
+            rows,
+            &mut RowBand::new(&mut temp_buffer, 0),
+            &mut RowBand::new(&mut transition_buffer, 0),
+        );
+
+        self.temp_buffer = temp_buffer;
+        self.transition_buffer = transition_buffer;
+    }
+
+    // Run one movement pass over the whole frame into `temp_buffer`
+    fn move_full(&mut self, pass: impl FnOnce(&FrameContext, &mut RowBand)) {
+        let mut temp_buffer = std::mem::take(&mut self.temp_buffer);
+        pass(
+            &self.frame_context(),
+            &mut RowBand::new(&mut temp_buffer, 0),
+        );
+        self.temp_buffer = temp_buffer;
+    }
+
+    // Read-only view of the state the movement passes need
+    fn frame_context(&self) -> FrameContext<'_> {
+        FrameContext {
+            width: self.width,
+            height: self.height,
+            cols: self.active_cols(),
+            source: &self.persistence_buffer,
+            persistence_pyramid: &self.persistence_pyramid,
+            luts: &self.luts,
+            lens: self.luts.lens.as_ref(),
+            center_x: self.center_x,
+            center_y: self.center_y,
+            high_quality_radius: self.high_quality_radius,
+            medium_quality_radius: self.medium_quality_radius,
+            phase: self.phase,
+            orientation_quarter_turns: self.orientation_quarter_turns,
+            move_transition: self.move_transition,
+            flow_field: self.flow_field.as_ref(),
+            perspective: self.perspective.as_ref(),
+            attractors: &self.attractors,
+            shake_offset: self.shake_offset,
+        }
+    }
+
+    // Size the movement target before any rows are moved. The move modes write every active
+    // pixel, so whatever the buffer held from its last turn as the trails is not cleared.
+    fn begin_movement(&mut self) {
+        self.temp_buffer.resize(self.persistence_buffer.len(), 0.0);
+    }
+
+    // Per-frame movement setup that must happen exactly once, however the rows are split
+    fn begin_frame_movement(&mut self, params: &MotionOptions) {
+        // All but the last pipeline step are applied to the trails here, one after another;
+        // the last one is moved band by band like a single move type
+        let pipeline = std::mem::take(&mut self.movement_pipeline);
+        let params = match pipeline.split_last() {
+            Some((last, earlier)) => {
+                for step in earlier {
+                    self.apply_movement_step(&self.timed(step));
+                }
+                self.timed(last)
+            }
+            None => *params,
+        };
+        self.movement_pipeline = pipeline;
+
+        self.begin_movement();
+        self.advance_move_transition(&params);
+        let outgoing = self.move_transition.map(|t| t.from);
+        self.prepare_movement(&params, outgoing.as_ref());
+        self.advance_particles(&params);
+    }
+
+    // Particles move once per frame with the trails, along the move mode's field if asked to
+    fn advance_particles(&mut self, params: &MotionOptions) {
+        let Some(mut particles) = self.particles.take() else {
+            return;
+        };
+        let (width, height) = (self.width as usize, self.height as usize);
+        if particles.follow_movement {
+            let context = self.frame_context();
+            particles.advance(width, height, context.velocity_field(params));
+        } else {
+            particles.advance(width, height, |_, _| (0.0, 0.0));
+        }
+        self.particles = Some(particles);
+    }
+
+    // Pixels of a band of rows that moved this frame spawn particles
+    fn spawn_particles(&mut self, rows: Range<usize>) {
+        let Some(mut particles) = self.particles.take() else {
+            return;
+        };
+        let width = self.width as usize;
+        for y in rows {
+            for x in self.active_cols() {
+                if self.is_moving(y * width + x) {
+                    particles.spawn(x, y);
+                }
+            }
+        }
+        self.particles = Some(particles);
+    }
+
+    // Move the active area by one intermediate pipeline step and keep the result as the
+    // trails the next step starts from
+    fn apply_movement_step(&mut self, step: &MotionOptions) {
+        self.begin_movement();
+        self.prepare_movement(step, None);
+        let rows = self.active_rows();
+        self.move_full(|context, target| context.move_rows_with(step, rows, target));
+
+        // The moved buffer becomes the trails; the old trails are the next movement target
+        std::mem::swap(&mut self.persistence_buffer, &mut self.temp_buffer);
+        self.restore_outside_active();
+    }
+
+    // Copy the trails outside the active area back from `temp_buffer` after a swap, which
+    // only moved the active area
+    fn restore_outside_active(&mut self) {
+        let active = self.active_rect();
+        if active == Rect::full(self.width, self.height) {
+            return;
+        }
+        let width = self.width as usize;
+        for y in 0..self.height as usize {
+            let row = y * width;
+            let spans = if active.rows().contains(&y) {
+                [
+                    row..row + active.x,
+                    row + active.x + active.width..row + width,
+                ]
+            } else {
+                [row..row + width, row..row]
+            };
+            for span in spans {
+                self.persistence_buffer[span.clone()].copy_from_slice(&self.temp_buffer[span]);
+            }
+        }
+    }
+
+    // Pyramid and animation phase for a movement pass (and the mode it is fading out from)
+    fn prepare_movement(&mut self, params: &MotionOptions, outgoing: Option<&MotionOptions>) {
+        let outgoing_type = outgoing.and_then(|from| from.move_type);
+        let uses = |move_type: MoveType| {
+            params.move_type == Some(move_type) || outgoing_type == Some(move_type)
+        };
+        if self.pyramid_levels > 0
+            && [
+                MoveType::Radial,
+                MoveType::Spiral,
+                MoveType::Zoom,
+                MoveType::Rotate,
+                MoveType::Feedback,
+            ]
+            .into_iter()
+            .any(uses)
+        {
+            self.build_pyramid();
+        }
+        let animated = |move_type: Option<MoveType>| {
+            matches!(move_type, Some(MoveType::Wave | MoveType::Turbulence))
+        };
+        if uses(MoveType::Wave) || uses(MoveType::Turbulence) {
+            // Increment the phase for animation; the phase is shared, so a wave or noise
+            // field fading in or out keeps moving smoothly across the switch
+            let increment = if animated(params.move_type) {
+                params.phase_increment
+            } else {
+                outgoing.map_or(0.0, |from| from.phase_increment)
+            };
+            self.phase += increment;
+        }
+        if uses(MoveType::Shake) {
+            let shaking = if params.move_type == Some(MoveType::Shake) {
+                params
+            } else {
+                outgoing.unwrap_or(params)
+            };
+            self.advance_shake(shaking);
+        }
+    }
+
+    // Draw this frame's shake offset, restarting the sequence when the seed changed
+    fn advance_shake(&mut self, params: &MotionOptions) {
+        if self.shake_seed != Some(params.seed) {
+            self.shake_rng = Prng::new(params.seed);
+            self.shake_seed = Some(params.seed);
+        }
+        let magnitude = params.shake_magnitude;
+        self.shake_offset = (
+            self.shake_rng.next_signed() * magnitude,
+            self.shake_rng.next_signed() * magnitude,
+        );
+    }
+
+    // Start a cross-fade when move_type changed since the last frame, or step the running one
+    fn advance_move_transition(&mut self, params: &MotionOptions) {
+        if let Some(transition) = &mut self.move_transition {
+            transition.frames_left -= 1;
+            if transition.frames_left == 0 {
+                self.move_transition = None;
+                self.transition_buffer = Vec::new();
+            }
+        }
+
+        let previous = self.last_move_params.replace(*params);
+        if let Some(previous) = previous {
+            if previous.move_type != params.move_type && self.move_transition_frames > 0 {
+                self.move_transition = Some(MoveTransition {
+                    from: previous,
+                    frames_left: self.move_transition_frames,
+                    total_frames: self.move_transition_frames,
+                });
+            }
+        }
+
+        if self.move_transition.is_some() {
+            self.transition_buffer
+                .resize(self.persistence_buffer.len(), 0.0);
+        }
+    }
+
+    // 2x2 box-filter the persistence buffer into each pyramid level
+    fn build_pyramid(&mut self) {
+        let mut levels = std::mem::take(&mut self.persistence_pyramid);
+        levels.truncate(self.pyramid_levels as usize);
+
+        let mut source_width = self.width as usize;
+        let mut source_height = self.height as usize;
+        for level_index in 0..self.pyramid_levels as usize {
+            let width = source_width.div_ceil(2);
+            let height = source_height.div_ceil(2);
+            if level_index == levels.len() {
+                levels.push(PyramidLevel {
+                    width,
+                    height,
+                    data: vec![0.0; width * height],
+                });
+            }
+
+            let (finer, coarser) = levels.split_at_mut(level_index);
+            let source = match finer.last() {
+                Some(level) => &level.data,
+                None => &self.persistence_buffer,
+            };
+            let target = &mut coarser[0].data;
+            for y in 0..height {
+                let y0 = y * 2;
+                let y1 = (y0 + 1).min(source_height - 1);
+                for x in 0..width {
+                    let x0 = x * 2;
+                    let x1 = (x0 + 1).min(source_width - 1);
+                    target[y * width + x] = (source[y0 * source_width + x0]
+                        + source[y0 * source_width + x1]
+                        + source[y1 * source_width + x0]
+                        + source[y1 * source_width + x1])
+                        * 0.25;
+                }
+            }
+
+            source_width = width;
+            source_height = height;
+        }
+
+        self.persistence_pyramid = levels;
+    }
+
+    pub fn move_in_direction(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Direction.apply(options, context, rows, target));
+    }
+
+    pub fn move_radially(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        if self.pyramid_levels > 0 {
+            self.build_pyramid();
+        }
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Radial.apply(options, context, rows, target));
+    }
+
+    pub fn move_spiral(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        if self.pyramid_levels > 0 {
+            self.build_pyramid();
+        }
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Spiral.apply(options, context, rows, target));
+    }
+
+    pub fn move_wave(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        self.phase += options.phase_increment;
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Wave.apply(options, context, rows, target));
+    }
+
+    pub fn move_zoom(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        if self.pyramid_levels > 0 {
+            self.build_pyramid();
+        }
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Zoom.apply(options, context, rows, target));
+    }
+
+    pub fn move_feedback(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        if self.pyramid_levels > 0 {
+            self.build_pyramid();
+        }
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Feedback.apply(options, context, rows, target));
+    }
+
+    pub fn move_rotate(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        if self.pyramid_levels > 0 {
+            self.build_pyramid();
+        }
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Rotate.apply(options, context, rows, target));
+    }
+
+    pub fn move_turbulence(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        self.phase += options.phase_increment;
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| {
+            movement::Turbulence.apply(options, context, rows, target)
+        });
+    }
+
+    pub fn move_flow(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Flow.apply(options, context, rows, target));
+    }
+
+    pub fn move_attract(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Attract.apply(options, context, rows, target));
+    }
+
+    pub fn move_perspective(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Warp.apply(options, context, rows, target));
+    }
+
+    pub fn move_shake(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        self.advance_shake(options);
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Shake.apply(options, context, rows, target));
+    }
+
+    pub fn move_smear(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Smear.apply(options, context, rows, target));
+    }
+
+    pub fn move_diffuse(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| movement::Diffuse.apply(options, context, rows, target));
+    }
+
+    pub fn move_kaleidoscope(&mut self, options: &MotionOptions) {
+        self.begin_movement();
+        let rows = 0..self.height as usize;
+        self.move_full(|context, target| {
+            movement::Kaleidoscope.apply(options, context, rows, target)
+        });
+    }
+
+    // Vector field for the flow move mode as interleaved (dx, dy) pixel offsets per frame on
+    // a grid `grid_width` cells wide, row-major, stretched over the frame and interpolated
+    // between cell centers. A per-pixel field uses the frame width; the output of
+    // `compute_optical_flow` fits with ceil(width / block_size).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_flow_field(&mut self, field: &[f32], grid_width: u32) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let grid_width = grid_width as usize;
+        if grid_width == 0 || field.is_empty() || !field.len().is_multiple_of(grid_width * 2) {
+            return Err(js_error(format!(
+                "flow field of {} values is not a grid of (dx, dy) pairs {} cells wide",
+                field.len(),
+                grid_width
+            )));
+        }
+        self.flow_field = Some(FlowField {
+            grid_width,
+            grid_height: field.len() / (grid_width * 2),
+            vectors: field.to_vec(),
+        });
+        Ok(())
+    }
+
+    // Without a field the flow mode leaves the trails in place
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_flow_field(&mut self) {
+        self.flow_field = None;
+    }
+
+    // Per-frame movement of the frame's corners for the perspective move mode, as (dx, dy)
+    // pairs for top-left, top-right, bottom-right and bottom-left in fractions of the frame
+    // size. E.g. top corners moving inwards and down tip the picture away into the screen.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_perspective_corners(&mut self, offsets: &[f32]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let offsets: [f32; 8] = offsets.try_into().map_err(|_| {
+            js_error(format!(
+                "perspective needs 8 corner offsets, got {}",
+                offsets.len()
+            ))
+        })?;
+        self.perspective = Some(
+            Perspective::from_corner_offsets(offsets)
+                .ok_or_else(|| js_error("perspective corners collapse the frame".to_string()))?,
+        );
+        Ok(())
+    }
+
+    // The perspective warp as a row-major 3x3 homography applied every frame, mapping
+    // (x, y, 1) in fractions of the frame size to where that point goes
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_perspective_matrix(&mut self, matrix: &[f32]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let matrix: [f32; 9] = matrix.try_into().map_err(|_| {
+            js_error(format!(
+                "perspective matrix needs 9 values, got {}",
+                matrix.len()
+            ))
+        })?;
+        self.perspective = Some(
+            Perspective::from_matrix(matrix)
+                .ok_or_else(|| js_error("perspective matrix is not invertible".to_string()))?,
+        );
+        Ok(())
+    }
+
+    // Without a warp the perspective mode leaves the trails in place
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_perspective(&mut self) {
+        self.perspective = None;
+    }
+
+    // Points for the attract move mode as an array of `{x, y, strength, radius}`: x and y
+    // in fractions of the frame, strength in pixels per frame at the point (negative
+    // repels) fading out at `radius` pixels. Null or an empty array removes them all.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn set_attractors(&mut self, points: JsValue) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if points.is_null() || points.is_undefined() {
+            self.set_attractor_points(Vec::new());
+            return Ok(());
+        }
+        if !js_sys::Array::is_array(&points) {
+            return Err(js_error("attractors must be an array".to_string()));
+        }
+
+        let mut attractors = Vec::new();
+        for point in js_sys::Array::from(&points).iter() {
+            if !point.is_object() {
+                return Err(js_error("attractors must be objects".to_string()));
+            }
+            let field = |name: &str, default: Option<f32>| {
+                let value = js_sys::Reflect::get(&point, &name.into()).map_err(js_exception)?;
+                match value.as_f64() {
+                    Some(number) => Ok(number as f32),
+                    None if value.is_undefined() => default
+                        .ok_or_else(|| js_error(format!("attractor needs a numeric {}", name))),
+                    None => Err(js_error(format!("attractor {} must be a number", name))),
+                }
+            };
+            attractors.push(Attractor {
+                x: field("x", None)?,
+                y: field("y", None)?,
+                strength: field("strength", Some(ATTRACTOR_DEFAULT_STRENGTH))?,
+                radius: field("radius", Some(ATTRACTOR_DEFAULT_RADIUS))?,
+            });
+        }
+        if attractors.len() > MAX_ATTRACTORS {
+            return Err(js_error(format!(
+                "at most {} attractors are supported",
+                MAX_ATTRACTORS
+            )));
+        }
+        self.set_attractor_points(attractors);
+        Ok(())
+    }
+
+    // Named trigger zones as an array of `{name, rect: [x, y, width, height]}` or
+    // `{name, polygon: [x0, y0, x1, y1, ...]}` in fractions of the frame, each with an
+    // optional `threshold` (fraction of the zone moving, default 0.02). Replaces the previous
+    // zones and drops their pending events; null or an empty array removes them all.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn define_zones(&mut self, zones: JsValue) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if zones.is_null() || zones.is_undefined() {
+            self.set_zones(Vec::new());
+            return Ok(());
+        }
+        if !js_sys::Array::is_array(&zones) {
+            return Err(js_error("zones must be an array".to_string()));
+        }
+
+        let numbers = |value: JsValue, what: &str| -> Result<Vec<f32>, ExportError> {
+            if !js_sys::Array::is_array(&value) {
+                return Err(js_error(format!("zone {} must be an array", what)));
+            }
+            js_sys::Array::from(&value)
+                .iter()
+                .map(|number| {
+                    number
+                        .as_f64()
+                        .map(|number| number as f32)
+                        .ok_or_else(|| js_error(format!("zone {} must hold numbers", what)))
+                })
+                .collect()
+        };
+        let mut parsed = Vec::new();
+        for zone in js_sys::Array::from(&zones).iter() {
+            if !zone.is_object() {
+                return Err(js_error("zones must be objects".to_string()));
+            }
+            let field = |name: &str| js_sys::Reflect::get(&zone, &name.into());
+            let name = field("name")
+                .map_err(js_exception)?
+                .as_string()
+                .ok_or_else(|| js_error("zone needs a name".to_string()))?;
+            let rect = field("rect").map_err(js_exception)?;
+            let polygon = field("polygon").map_err(js_exception)?;
+            let mut parsed_zone = if !rect.is_undefined() {
+                match numbers(rect, "rect")?[..] {
+                    [x, y, width, height] => Zone::rect(&name, x, y, width, height),
+                    _ => return Err(js_error("zone rect needs 4 numbers".to_string())),
+                }
+            } else if !polygon.is_undefined() {
+                let points = numbers(polygon, "polygon")?;
+                if points.len() < 6 || !points.len().is_multiple_of(2) {
+                    return Err(js_error(
+                        "zone polygon needs at least 3 (x, y) corners".to_string(),
+                    ));
+                }
+                let corners = points.chunks_exact(2).map(|xy| (xy[0], xy[1])).collect();
+                Zone::polygon(&name, corners)
+            } else {
+                return Err(js_error(format!("zone {} needs a rect or a polygon", name)));
+            };
+            let threshold = field("threshold").map_err(js_exception)?;
+            if !threshold.is_undefined() {
+                parsed_zone.threshold = threshold
+                    .as_f64()
+                    .ok_or_else(|| js_error("zone threshold must be a number".to_string()))?
+                    as f32;
+            }
+            parsed.push(parsed_zone);
+        }
+        if parsed.len() > MAX_ZONES {
+            return Err(js_error(format!(
+                "at most {} zones are supported",
+                MAX_ZONES
+            )));
+        }
+        self.set_zones(parsed);
+        Ok(())
+    }
+
+    // Enter, exit and active events of the zones since the last call, oldest first. Only
+    // analysed frames update the zones; at most 256 events are kept between polls.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn poll_zone_events(&mut self) -> Vec<ZoneEvent> {
+        self.zones.drain_events()
+    }
+
+    // Debounced motion started / stopped events for recording triggers: motion starts once
+    // at least `min_active_percent` of the analysed pixels (and any at all) have moved for
+    // `start_after` frames or milliseconds in a row, and stops once fewer have for
+    // `stop_after`. Replaces the previous trigger and its pending events.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_motion_trigger(
+        &mut self,
+        min_active_percent: f32,
+        start_after: f32,
+        stop_after: f32,
+        unit: DebounceUnit,
+    ) {
+        self.motion_trigger = Some(MotionTrigger::new(
+            min_active_percent,
+            start_after,
+            stop_after,
+            unit,
+        ));
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_motion_trigger(&mut self) {
+        self.motion_trigger = None;
+    }
+
+    // Snapshot the input when motion starts: once the `energy` of an analysed frame's motion
+    // stats rises above `trigger_energy`, that frame and up to `pre_trigger_frames` (at most
+    // 30) analysed frames before it are kept as RGBA for `take_captures`. Motion has to drop
+    // below the trigger again before the next capture; past `max_captures` frames (at most
+    // 256) the oldest are dropped. Replaces the previous capture settings and captures.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_frame_capture(
+        &mut self,
+        trigger_energy: f32,
+        pre_trigger_frames: u32,
+        max_captures: u32,
+    ) {
+        self.frame_capture = Some(FrameCapture::new(
+            trigger_energy,
+            pre_trigger_frames.min(MAX_PRE_TRIGGER_FRAMES) as usize,
+            max_captures.min(MAX_CAPTURES) as usize,
+        ));
+    }
+
+    // Stop capturing and free the captures and the ring of recent frames
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_frame_capture(&mut self) {
+        self.frame_capture = None;
+    }
+
+    // Captured frames since the last call, oldest first; the triggering frame of each
+    // capture comes after its pre-trigger frames
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn take_captures(&mut self) -> Vec<CapturedFrame> {
+        self.frame_capture
+            .as_mut()
+            .map_or_else(Vec::new, FrameCapture::drain)
+    }
+
+    // Captured frames waiting for `take_captures`
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn pending_captures(&self) -> usize {
+        self.frame_capture.as_ref().map_or(0, FrameCapture::pending)
+    }
+
+    // Keyframed options that animate on the stream clock (see `stream_time_ms`), as
+    // `{loop, keyframes: [{time, easing, options: {name: value, ...}}]}` with `time` in
+    // milliseconds from the start. From its first keyframe on an option takes the timeline's
+    // value instead of the one passed with the frame; numbers move to their next keyframe
+    // with the keyframe's `easing` ("linear" by default, "ease_in", "ease_out",
+    // "ease_in_out" or "step"), strings and flags switch at it. A looping timeline starts
+    // over at its last keyframe. Null removes the timeline.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn load_timeline(&mut self, timeline: JsValue) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let result = self.read_timeline(timeline);
+        if let Err(message) = &result {
+            self.diagnostics
+                .log(LogLevel::Error, format!("timeline rejected: {}", message));
+        }
+        result.map_err(js_error)
+    }
+
+    #[cfg(feature = "wasm")]
+    fn read_timeline(&mut self, timeline: JsValue) -> Result<(), String> {
+        if timeline.is_null() || timeline.is_undefined() {
+            self.timeline = None;
+            return Ok(());
+        }
+        let field = |object: &JsValue, name: &str| {
+            js_sys::Reflect::get(object, &name.into()).unwrap_or(JsValue::UNDEFINED)
+        };
+        let looping = field(&timeline, "loop").as_bool().unwrap_or(false);
+        let frames = field(&timeline, "keyframes");
+        if !js_sys::Array::is_array(&frames) {
+            return Err("timeline keyframes must be an array".to_string());
+        }
+
+        let mut keyframes = Vec::new();
+        for frame in js_sys::Array::from(&frames).iter() {
+            let time_ms = field(&frame, "time")
+                .as_f64()
+                .ok_or("keyframes need a time")? as f32;
+            let easing = match field(&frame, "easing").as_string() {
+                Some(name) => {
+                    Easing::parse(&name).ok_or_else(|| format!("unknown easing: {}", name))?
+                }
+                None => Easing::Linear,
+            };
+            let options = field(&frame, "options");
+            if !options.is_object() {
+                return Err("keyframe options must be an object".to_string());
+            }
+            for entry in js_sys::Object::entries(options.unchecked_ref()).iter() {
+                let entry: js_sys::Array = entry.unchecked_into();
+                let option = entry.get(0).as_string().unwrap_or_default();
+                let value = entry.get(1);
+                let value = if let Some(text) = value.as_string() {
+                    KeyframeValue::Text(text)
+                } else if let Some(flag) = value.as_bool() {
+                    KeyframeValue::Bool(flag)
+                } else if let Some(number) = value.as_f64() {
+                    KeyframeValue::Number(number)
+                } else {
+                    return Err(format!(
+                        "keyframe option {} must be a number, string or boolean",
+                        option
+                    ));
+                };
+                keyframes.push(Keyframe {
+                    time_ms,
+                    option,
+                    value,
+                    easing,
+                });
+            }
+        }
+        self.timeline = Some(Timeline::new(keyframes, looping)?);
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_timeline(&mut self) {
+        self.timeline = None;
+    }
+
+    // Milliseconds into the timeline of `load_timeline` (0 without one)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn timeline_position_ms(&self) -> f32 {
+        self.timeline.as_ref().map_or(0.0, Timeline::position_ms)
+    }
+
+    // Projector-to-camera mapping for `suppress_self_feedback` as a row-major 3x3 homography
+    // taking (x, y, 1) in fractions of the output frame to fractions of the camera frame
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_feedback_homography(&mut self, matrix: &[f32]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let matrix: [f32; 9] = matrix.try_into().map_err(|_| {
+            js_error(format!(
+                "feedback homography needs 9 values, got {}",
+                matrix.len()
+            ))
+        })?;
+        self.feedback_homography = Some(
+            Perspective::from_matrix(matrix)
+                .ok_or_else(|| js_error("feedback homography is not invertible".to_string()))?,
+        );
+        Ok(())
+    }
+
+    // The camera sees the output unwarped, pixel for pixel
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_feedback_homography(&mut self) {
+        self.feedback_homography = None;
+    }
+
+    // Never detect motion where the current frame is within `tolerance` (RGB distance,
+    // 0..442) of a key color, e.g. a green screen or the projected trails of a setup whose
+    // camera sees its own output
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_ignore_color(&mut self, r: u8, g: u8, b: u8, tolerance: f32) {
+        self.ignore_color = Some(([r, g, b], tolerance.max(0.0)));
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_ignore_color(&mut self) {
+        self.ignore_color = None;
+    }
+
+    // Started and stopped events of the motion trigger since the last call, oldest first;
+    // at most 256 are kept between polls
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn poll_events(&mut self) -> Vec<MotionEvent> {
+        self.motion_trigger
+            .as_mut()
+            .map_or_else(Vec::new, MotionTrigger::drain_events)
+    }
+
+    // Whether the motion trigger is between a started and a stopped event
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn is_motion_active(&self) -> bool {
+        self.motion_trigger
+            .as_ref()
+            .is_some_and(MotionTrigger::is_active)
+    }
+
+    // Milliseconds of stream processed since creation or `reset_all_state`: the frame
+    // intervals summed, `delta_time_ms` where it is set. Motion events are stamped with it.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn stream_time_ms(&self) -> f64 {
+        self.stream_time_ms
+    }
+
+    // Start the `accumulation_mode` summary over
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn reset_accumulation(&mut self) {
+        self.accumulation_buffer.fill(0.0);
+        self.accumulated_frames = 0;
+    }
+
+    // The accumulated motion as an RGBA image of the processing size, through the palette of
+    // the last frame's output mode. Averages are normalized to their peak so rare motion
+    // still shows.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn accumulation_image(&self) -> Vec<u8> {
+        let mut image = vec![0; self.processing_pixels() * 4];
+        let gain = match self.accumulation_source {
+            AccumulationMode::Average => {
+                let peak = self
+                    .accumulation_buffer
+                    .iter()
+                    .fold(0.0f32, |a, &b| a.max(b));
+                if peak > 0.0 {
+                    255.0 / peak
+                } else {
+                    0.0
+                }
+            }
+            _ => 1.0,
+        };
+        for (pixel_index, &value) in self.accumulation_buffer.iter().enumerate() {
+            let color = self.palette[(value * gain).clamp(0.0, 255.0) as usize];
+            PixelFormat::Rgba.write(&mut image, pixel_index, color, 255);
+        }
+        image
+    }
+
+    // Keep the last `frames` output frames (or trail states) of every processed frame in a
+    // ring buffer, e.g. for long-exposure composites or exported loops; 0 stops recording
+    // and frees it, and at most `MAX_RECORDED_FRAMES` are kept. Changing the source or
+    // length drops what was recorded.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn record_frames(&mut self, frames: u32, source: RecordingSource) {
+        if frames == 0 {
+            self.recording = None;
+            return;
+        }
+        let capacity = frames.min(MAX_RECORDED_FRAMES) as usize;
+        if let Some(recording) = &mut self.recording {
+            if recording.source == source && recording.capacity == capacity {
+                return;
+            }
+        }
+        self.recording = Some(Recording {
+            source,
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+        });
+    }
+
+    // Recorded frames, oldest first, packed back to back (`recording_frame_len` bytes
+    // each), and the ring emptied; recording goes on with the next frame
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn drain_recording(&mut self) -> Vec<u8> {
+        let Some(recording) = &mut self.recording else {
+            return Vec::new();
+        };
+        let mut packed = Vec::with_capacity(recording.frames.iter().map(Vec::len).sum());
+        for frame in recording.frames.drain(..) {
+            packed.extend_from_slice(&frame);
+        }
+        packed
+    }
+
+    // Frames currently held by the recording
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn recorded_frames(&self) -> usize {
+        self.recording
+            .as_ref()
+            .map_or(0, |recording| recording.frames.len())
+    }
+
+    // Size in bytes of one recorded frame for the recording source
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn recording_frame_len(&self) -> usize {
+        match self.recording.as_ref().map(|recording| recording.source) {
+            Some(RecordingSource::Output) => self.output_len(),
+            Some(RecordingSource::Persistence) => self.processing_pixels(),
+            None => 0,
+        }
+    }
+
+    // Per-frame modulation signals (an array or typed array of numbers, e.g. FFT bands) that
+    // modulated options refer to by index, such as `speed: {mod: 0, base: 2, depth: 5}`.
+    // They hold until the next call.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn set_modulation(&mut self, signals: JsValue) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if !js_sys::Array::is_array(&signals) && !js_sys::ArrayBuffer::is_view(&signals) {
+            return Err(js_error("modulation signals must be an array".to_string()));
+        }
+        self.set_modulation_signals(&js_sys::Float32Array::new(&signals).to_vec());
+        Ok(())
+    }
+
+    // Add a trail layer with its own decay and movement (`options`; its detection options are
+    // ignored), fed by the same motion as the main trails and blended over them and the
+    // layers added before it, e.g. quick sparks over long ghost trails. Returns its index.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn add_persistence_layer(
+        &mut self,
+        options: &MotionOptions,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> usize {
+        self.persistence_layers.push(PersistenceLayer {
+            params: *options,
+            opacity: opacity.clamp(0.0, 1.0),
+            blend_mode,
+            persistence: Vec::new(),
+            moved: Vec::new(),
+        });
+        self.persistence_layers.len() - 1
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_persistence_layer(
+        &mut self,
+        index: usize,
+        options: &MotionOptions,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        let layer = self
+            .persistence_layers
+            .get_mut(index)
+            .ok_or_else(|| js_error("persistence layer index out of range".to_string()))?;
+        layer.params = *options;
+        layer.opacity = opacity.clamp(0.0, 1.0);
+        layer.blend_mode = blend_mode;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn persistence_layer_count(&self) -> usize {
+        self.persistence_layers.len()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_persistence_layers(&mut self) {
+        self.persistence_layers = Vec::new();
+    }
+
+    // Stroboscopic echoes: the main trails of `delays[i]` frames ago are added to the output
+    // at `gains[i]`, up to 8 taps reaching back at most 64 frames. Unlike a slower decay the
+    // copies stay separate, like a multi-exposure photo. No delays turns it off.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_echo(&mut self, delays: &[u32], gains: &[f32]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if delays.len() != gains.len() {
+            return Err(js_error(format!(
+                "echo has {} delays but {} gains",
+                delays.len(),
+                gains.len()
+            )));
+        }
+        if delays.len() > MAX_ECHO_TAPS {
+            return Err(js_error(format!(
+                "echo has {} taps, at most {} are supported",
+                delays.len(),
+                MAX_ECHO_TAPS
+            )));
+        }
+        if let Some(&delay) = delays
+            .iter()
+            .find(|&&delay| delay == 0 || delay > MAX_ECHO_DELAY)
+        {
+            return Err(js_error(format!(
+                "echo delay {} is outside 1..={} frames",
+                delay, MAX_ECHO_DELAY
+            )));
+        }
+        if delays.is_empty() {
+            self.echo = None;
+            return Ok(());
+        }
+        let taps = delays
+            .iter()
+            .zip(gains)
+            .map(|(&delay, &gain)| (delay as usize, gain.clamp(0.0, 1.0)))
+            .collect();
+        // Frames already kept stay usable when the taps change
+        let mut history = self
+            .echo
+            .take()
+            .map_or_else(VecDeque::new, |echo| echo.history);
+        let longest = delays.iter().copied().max().unwrap_or(0) as usize;
+        while history.len() > longest {
+            history.pop_front();
+        }
+        self.echo = Some(Echo { taps, history });
+        Ok(())
+    }
+
+    // Copy of the trail state (one value per pixel, row-major) for snapshots; restore it with
+    // `import_persistence` on a detector of the same size
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn export_persistence(&self) -> Vec<f32> {
+        self.trails().to_vec()
+    }
+
+    // Restore trails saved with `export_persistence`, e.g. when resuming an installation or
+    // in another tab. The frame cache is kept, so only the trails change.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn import_persistence(&mut self, data: &[f32]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        self.unpack_state();
+        if data.len() != self.processing_pixels() {
+            return Err(js_error(format!(
+                "persistence snapshot has {} values, expected {}",
+                data.len(),
+                self.processing_pixels()
+            )));
+        }
+        // Saved snapshots may have been edited or damaged; keep the pipeline's value range
+        for (value, &saved) in self.persistence_buffer.iter_mut().zip(data) {
+            *value = if saved.is_finite() {
+                saved.clamp(0.0, 255.0)
+            } else {
+                0.0
+            };
+        }
+        // Trails restored outside the ROI fade out like after an ROI change
+        self.outside_roi_fading = self.roi.is_some();
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn reset_persistence(&mut self) {
+        self.unpack_state();
+        for val in &mut self.persistence_buffer {
+            *val = 0.0;
+        }
+        for layer in &mut self.persistence_layers {
+            layer.persistence.fill(0.0);
+        }
+        if let Some(echo) = &mut self.echo {
+            echo.history.clear();
+        }
+    }
+
+    // Drop every buffer, table and setting now instead of when JS garbage collects the
+    // handle, for apps that create and throw away many detectors. wasm-bindgen's own `free`
+    // does that too but leaves a handle that throws on any use; after `dispose` the handle
+    // stays valid and every method that can throw does: processing, `resize`, `input_ptr`,
+    // `output_ptr`, the render and upload calls and the setters taking data (masks, maps,
+    // kernels, zones, timelines and the like). Methods without an error to report are no-ops
+    // on an empty 1x1 detector: getters return its empty state, and plain setters and resets
+    // configure it without allocating frame buffers again. Tables shared through a
+    // `DetectorContext` are released to the other detectors.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn dispose(&mut self) {
+        if self.disposed {
+            return;
+        }
+        let level = self.diagnostics.level();
+        *self = MotionDetector::new_in(1, 1, None);
+        self.diagnostics.set_level(level);
+        self.diagnostics
+            .log(LogLevel::Info, "detector disposed".to_string());
+        self.disposed = true;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn is_disposed(&self) -> bool {
+        self.disposed
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn reset_all_state(&mut self) {
+        self.unpack_state();
+        // Reset persistence buffer
+        for val in &mut self.persistence_buffer {
+            *val = 0.0;
+        }
+        for layer in &mut self.persistence_layers {
+            layer.persistence.fill(0.0);
+        }
+        if let Some(echo) = &mut self.echo {
+            echo.history.clear();
+        }
+
+        // Reset temp buffer
+        self.temp_buffer.clear();
+
+        // Reset previous frame cache
+        self.previous_gray.fill(0.0);
+        self.temporal_history = None;
+
+        // Reset first frame flag
+        self.is_first_frame = true;
+        self.last_stats = MotionStats::default();
+        self.last_frame_ms = None;
+
+        // Reset phase for wave animations
+        self.phase = 0.0;
+
+        // Forget the previous move mode so the next frame doesn't cross-fade
+        self.last_move_params = None;
+        self.move_transition = None;
+        self.transition_buffer = Vec::new();
+        self.last_options = None;
+        self.parameter_transition = None;
+        self.tracker.tracks.clear();
+        self.zones.reset();
+        self.silhouette = Silhouette::default();
+        self.fluid = Fluid::default();
+        self.dwell_ms = Vec::new();
+        self.diagnostics.reset();
+        if let Some(capture) = &mut self.frame_capture {
+            capture.restart();
+        }
+        if let Some(trigger) = &mut self.motion_trigger {
+            trigger.reset();
+        }
+        self.stream_time_ms = 0.0;
+        if let Some(timeline) = &mut self.timeline {
+            timeline.rewind();
+        }
+        self.last_timestamp_us = None;
+        self.dropped_frames = 0;
+        self.effective_fps = 0.0;
+        self.feedback_gray.clear();
+        self.auto_threshold = None;
+        if let Some(particles) = &mut self.particles {
+            particles.clear();
+        }
+
+        // The noise estimate is learnt again from the next frames
+        self.noise_mean = Vec::new();
+        self.noise_sigma = Vec::new();
+        self.hysteresis_state = Vec::new();
+        self.frames_until_detection = 0;
+        // Shake offsets start over from the seed
+        self.shake_seed = None;
+    }
+
+    // What goes to the console (warnings by default): rejected frames and settings at
+    // `error`, options outside their range at `warn` (once per change of the options),
+    // dropped frames at `info` and every frame's stage timings at `debug`. `debug_info`
+    // has all of it whatever the level.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_log_level(&mut self, level: LogLevel) {
+        self.diagnostics.set_level(level);
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn log_level(&self) -> LogLevel {
+        self.diagnostics.level()
+    }
+
+    // JSON object of the frame count, the last 64 messages (`{frame, level, message}`), the
+    // options of the last frame outside their range (`{option, value, clamped_to}`) and the
+    // milliseconds per stage of the last frame (`{stage, ms}` in order, plus `total_ms`)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn debug_info(&self) -> String {
+        self.diagnostics.to_json()
+    }
+
+    // Pipeline and buffer health report as JSON (see `self_test_report`), for diagnosing
+    // device-specific bug reports from the field
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn self_test(&self) -> String {
+        self.self_test_report().to_string()
+    }
+
+    // Machine-readable benchmark for picking a quality preset at page load: resolution, move
+    // mode, average per-stage milliseconds, megapixels per second and buffer memory
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn benchmark(&self, frames: u32, options: &MotionOptions) -> String {
+        self.benchmark_report(frames, options).to_string()
+    }
+
+    // Total allocated bytes of the buffers and LUTs, the `total_bytes` of `memory_report`
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn memory_usage_bytes(&self) -> f64 {
+        self.memory_bytes() as f64
+    }
+
+    // JSON object with the allocated byte size of every internal buffer and LUT plus the
+    // total, so embedders can verify the footprint before choosing a quality preset
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn memory_report(&self) -> String {
+        let buffers: serde_json::Map<String, serde_json::Value> = self
+            .memory_breakdown()
+            .into_iter()
+            .map(|(name, bytes)| (name.to_string(), bytes.into()))
+            .collect();
+        serde_json::json!({
+            "buffers": buffers,
+            "total_bytes": self.memory_bytes(),
+        })
+        .to_string()
+    }
+
+    // Ordered movement steps applied one after another each frame instead of the options'
+    // single `move_type`, e.g. `[{type: "direction", speed: 2}, {type: "wave"}]`. Each step
+    // is an options object (`type` is short for `move_type`); values it leaves out take
+    // their defaults. An empty array, null or undefined goes back to the frame options.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen]
+    pub fn set_movement_pipeline(&mut self, steps: JsValue) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if steps.is_null() || steps.is_undefined() {
+            self.set_movement_steps(Vec::new());
+            return Ok(());
+        }
+        if !js_sys::Array::is_array(&steps) {
+            return Err(js_error("movement pipeline must be an array".to_string()));
+        }
+
+        let mut pipeline = Vec::new();
+        for step in js_sys::Array::from(&steps).iter() {
+            if !step.is_object() {
+                return Err(js_error("movement steps must be objects".to_string()));
+            }
+            let mut options = MotionOptions::default();
+            for entry in js_sys::Object::entries(step.unchecked_ref()).iter() {
+                let entry: js_sys::Array = entry.unchecked_into();
+                let key = entry.get(0).as_string().unwrap_or_default();
+                let key = if key == "type" { "move_type" } else { &key };
+                options.set_js_option(key, &entry.get(1))?;
+            }
+            pipeline.push(options);
+        }
+        self.set_movement_steps(pipeline);
+        Ok(())
+    }
+
+    // Restrict detection, movement and output to a rectangle (clamped to the frame). Trails
+    // outside it decay to zero with the frame's `decay_rate`; `clear_roi` processes the
+    // whole frame again.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_roi(&mut self, x: u32, y: u32, width: u32, height: u32) {
+        let x = x.min(self.width) as usize;
+        let y = y.min(self.height) as usize;
+        let roi = Rect {
+            x,
+            y,
+            width: (width as usize).min(self.width as usize - x),
+            height: (height as usize).min(self.height as usize - y),
+        };
+        if self.roi == Some(roi) {
+            return;
+        }
+        self.roi = Some(roi);
+        self.active_area_changed(true);
+    }
+
+    // Process the whole frame again; trails left outside the old ROI take part in movement
+    // and decay like any others
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_roi(&mut self) {
+        if self.roi.take().is_some() {
+            self.active_area_changed(false);
+        }
+    }
+
+    // Per-pixel mask (one byte per processing pixel, `persistence_width` x
+    // `persistence_height`) that detected motion is multiplied by: 0 ignores a pixel, 255
+    // keeps it as is. For timestamps, screens or trees that should never leave trails.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_mask(&mut self, mask_data: &[u8]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if mask_data.len() != self.processing_pixels() {
+            return Err(js_error(format!(
+                "mask has {} values, expected {}",
+                mask_data.len(),
+                self.processing_pixels()
+            )));
+        }
+        self.mask = mask_data
+            .iter()
+            .map(|&value| value as f32 / 255.0)
+            .collect();
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_mask(&mut self) {
+        self.mask = Vec::new();
+    }
+
+    // Decay rate per pixel (0..1, like `decay_rate`, one per processing pixel:
+    // `persistence_width` x `persistence_height`) used instead of `decay_rate`, so trails can
+    // linger in some regions and vanish quickly in others. Rates are scaled to the frame
+    // length like the option; motion history fades and persistence layers keep their own.
+    // NaN and infinite rates are rejected.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_decay_map(&mut self, rates: &[f32]) -> Result<(), ExportError> {
+        self.try_set_decay_map(rates).map_err(js_error)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_decay_map(&mut self) {
+        self.decay_map = Vec::new();
+    }
+
+    // Ramp of the `dwell` output mode as 2 to 16 0xRRGGBB colors, from a pixel that has just
+    // started to move to one at `dwell_max_ms`, e.g. white, yellow, red and black
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_dwell_colors(&mut self, colors: &[u32]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if !(2..=MAX_DWELL_COLORS).contains(&colors.len()) {
+            return Err(js_error(format!(
+                "dwell ramp has {} colors, expected 2 to {}",
+                colors.len(),
+                MAX_DWELL_COLORS
+            )));
+        }
+        self.dwell_ramp = build_ramp(colors);
+        Ok(())
+    }
+
+    // Back to the default dwell ramp
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn reset_dwell_colors(&mut self) {
+        self.dwell_ramp = build_ramp(&DEFAULT_DWELL_COLORS);
+    }
+
+    // Switch to a new resolution without rebuilding the detector: the lookup tables are
+    // rebuilt, trails are rescaled (not cleared) and the ROI and mask are scaled along. The next frame
+    // at the new size re-primes the frame cache. Throws like the constructor for resolutions
+    // without pixels, too large or over the limit of `with_memory_limit`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn resize(&mut self, width: u32, height: u32) -> Result<(), ExportError> {
+        self.try_resize(width, height).map_err(js_error)
+    }
+
+    fn resize_processing(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        self.unpack_state();
+        let (old_width, old_height) = (self.width as usize, self.height as usize);
+        let buffer_size = (width as usize)
+            .checked_mul(height as usize)
+            .expect("resolution checked by try_resize");
+
+        self.persistence_buffer = resample_bilinear(
+            &self.persistence_buffer,
+            old_width,
+            old_height,
+            width as usize,
+            height as usize,
+        );
+        if !self.mask.is_empty() {
+            self.mask = resample_bilinear(
+                &self.mask,
+                old_width,
+                old_height,
+                width as usize,
+                height as usize,
+            );
+        }
+        if !self.decay_map.is_empty() {
+            self.decay_map = resample_bilinear(
+                &self.decay_map,
+                old_width,
+                old_height,
+                width as usize,
+                height as usize,
+            );
+        }
+        self.roi = self.roi.map(|roi| {
+            let scale_x = |value: usize| value * width as usize / old_width.max(1);
+            let scale_y = |value: usize| value * height as usize / old_height.max(1);
+            Rect {
+                x: scale_x(roi.x),
+                y: scale_y(roi.y),
+                width: scale_x(roi.width),
+                height: scale_y(roi.height),
+            }
+        });
+        self.width = width;
+        self.height = height;
+
+        // Per-pixel state that can't be carried over is re-created at the new size
+        self.previous_gray = vec![0.0; buffer_size];
+        self.current_gray = vec![0.0; buffer_size];
+        self.stabilized_gray = Vec::new();
+        self.diff_buffer = vec![0.0; buffer_size];
+        self.motion_grid.clear();
+        self.motion_grid_width = 0;
+        self.background_gray = Vec::new();
+        self.temp_buffer = Vec::with_capacity(buffer_size);
+        self.transition_buffer = Vec::new();
+        self.persistence_pyramid = Vec::new();
+        self.dog_buffers = Default::default();
+        self.kernel_buffer = Vec::new();
+        self.dwell_ms = Vec::new();
+        self.diffusion_buffers = Default::default();
+        for layer in &mut self.persistence_layers {
+            layer.persistence = Vec::new();
+            layer.moved = Vec::new();
+        }
+        if let Some(echo) = &mut self.echo {
+            echo.history.clear();
+        }
+        self.morphology_buffer = Vec::new();
+        self.noise_mean = Vec::new();
+        self.noise_sigma = Vec::new();
+        self.accumulation_buffer = Vec::new();
+        self.hysteresis_state = Vec::new();
+        self.channel_frames = Default::default();
+        self.output_buffer = Vec::new();
+        self.input_buffer = Vec::new();
+        self.scaled_output = Vec::new();
+        self.tracker.tracks.clear();
+        if let Some(particles) = &mut self.particles {
+            particles.clear();
+        }
+        // Recorded frames of the old size can't be packed with new ones
+        if let Some(recording) = &mut self.recording {
+            recording.frames.clear();
+        }
+        if let Some(capture) = &mut self.frame_capture {
+            capture.restart();
+        }
+
+        // Letterbox bars are looked for again at the new size
+        self.content_rect = None;
+        self.letterbox_candidate = None;
+        self.frames_until_letterbox_check = 0;
+        self.build_luts(Rect::full(width, height));
+        self.is_first_frame = true;
+    }
+
+    // Camera motion between the last two processed frames: the shift of up to `max_shift`
+    // pixels (and with `with_rotation` the turn) that best lines the older frame up with the
+    // newer one over the active area. A confident estimate close to the motion of the
+    // subjects means the camera panned rather than something moving in front of it.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn estimate_global_motion(&self, max_shift: u32, with_rotation: bool) -> GlobalMotion {
+        if self.is_first_frame {
+            return GlobalMotion::default();
+        }
+        // After `finish_frame` the newest frame is `previous_gray`, see `compute_optical_flow`
+        let rect = self.active_rect();
+        let newer = self.previous_frame();
+        FramePair {
+            newer: &newer,
+            older: &self.current_gray,
+            width: self.width as usize,
+            rows: rect.rows(),
+            cols: rect.cols(),
+        }
+        .estimate(max_shift, with_rotation)
+    }
+
+    // Per-block motion vectors between the last two processed frames, as interleaved
+    // (dx, dy) pixel offsets for blocks of `block_size` pixels in row-major order; the grid is
+    // ceil(width / block_size) blocks wide. Lucas-Kanade on each block, so it's most accurate
+    // for displacements of a few pixels; blocks without texture report (0, 0).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn compute_optical_flow(&self, block_size: u32) -> Vec<f32> {
+        // After `finish_frame` the newest frame is `previous_gray` and the one before it is
+        // still in `current_gray` until the next frame is decoded
+        self.block_flow(
+            block_size.max(2) as usize,
+            &self.previous_frame(),
+            &self.current_gray,
+        )
+    }
+
+    // Lucas-Kanade flow of `compute_optical_flow` from `older` to `newer`
+    fn block_flow(&self, block_size: usize, newer: &[f32], older: &[f32]) -> Vec<f32> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let grid_width = width.div_ceil(block_size);
+        let grid_height = height.div_ceil(block_size);
+        let mut flow = vec![0.0; grid_width * grid_height * 2];
+        if self.is_first_frame || width < 3 || height < 3 {
+            return flow;
+        }
+
+        let active = self.active_rect();
+        let brightness = |x: usize, y: usize| (newer[y * width + x] + older[y * width + x]) * 0.5;
+
+        for block_y in 0..grid_height {
+            for block_x in 0..grid_width {
+                let (mut sxx, mut sxy, mut syy, mut sxt, mut syt) = (0.0, 0.0, 0.0, 0.0, 0.0);
+                let rows =
+                    (block_y * block_size).max(1)..((block_y + 1) * block_size).min(height - 1);
+                let cols =
+                    (block_x * block_size).max(1)..((block_x + 1) * block_size).min(width - 1);
+                for y in rows {
+                    for x in cols.clone() {
+                        if !active.contains(x, y) {
+                            continue;
+                        }
+                        let ix = (brightness(x + 1, y) - brightness(x - 1, y)) * 0.5;
+                        let iy = (brightness(x, y + 1) - brightness(x, y - 1)) * 0.5;
+                        let it = newer[y * width + x] - older[y * width + x];
+                        sxx += ix * ix;
+                        sxy += ix * iy;
+                        syy += iy * iy;
+                        sxt += ix * it;
+                        syt += iy * it;
+                    }
+                }
+
+                // Solve the 2x2 normal equations; flat blocks are ill-conditioned
+                let determinant = sxx * syy - sxy * sxy;
+                if determinant.abs() < 1e-3 * (sxx + syy).max(1.0) {
+                    continue;
+                }
+                let index = (block_y * grid_width + block_x) * 2;
+                flow[index] = (-syy * sxt + sxy * syt) / determinant;
+                flow[index + 1] = (sxy * sxt - sxx * syt) / determinant;
+            }
+        }
+        flow
+    }
+
+    // Trail levels (0-255) at the samples of `grid_mode` after the last analysed frame,
+    // row-major and `motion_grid_width` samples wide; cheaper to scan for presence or zones
+    // than the full-resolution output. Empty while `grid_mode` is off.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_motion_grid(&self) -> Vec<f32> {
+        self.motion_grid.clone()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn motion_grid_width(&self) -> u32 {
+        self.motion_grid_width as u32
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn motion_grid_height(&self) -> u32 {
+        self.motion_grid
+            .len()
+            .checked_div(self.motion_grid_width)
+            .unwrap_or(0) as u32
+    }
+
+    // Whether pixels outside the ROI show the input frame instead of black
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_roi_passthrough(&mut self, passthrough: bool) {
+        self.roi_passthrough = passthrough;
+    }
+
+    // Clockwise rotation (0/90/180/270) of the picture inside the buffers, e.g. 90 for a
+    // portrait phone stream delivered as a landscape frame. Directional options then refer
+    // to the upright picture. Other angles are rounded to the nearest quarter turn.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_orientation(&mut self, degrees: u32) {
+        self.orientation_quarter_turns = ((degrees % 360 + 45) / 90) % 4;
+    }
+
+    // Detect constant black bars (letterbox / pillarbox) on the input and leave them out of
+    // detection and of the radial center-weighting. Disabling restores the full frame.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_auto_crop(&mut self, enabled: bool) {
+        self.auto_crop = enabled;
+        self.letterbox_candidate = None;
+        self.frames_until_letterbox_check = 0;
+        if !enabled && self.content_rect.is_some() {
+            self.set_content_rect(None);
+        }
+    }
+
+    // Motion energy, active pixel percentage and motion centroid of the last analysed frame,
+    // for triggering events in JS without reading pixels back
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_motion_stats(&self) -> MotionStats {
+        self.last_stats
+    }
+
+    // FRAME_* bits of the last processed frame; bit 0 (1) is a scene change
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn last_frame_flags(&self) -> u32 {
+        self.frame_flags
+    }
+
+    // Animation phase of the wave and turbulence modes, advanced by `phase_increment` each
+    // frame. Copying it between detectors (e.g. the halves of a split screen) keeps their
+    // animations in step.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn phase(&self) -> f32 {
+        self.phase
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_phase(&mut self, phase: f32) {
+        if phase.is_finite() {
+            self.phase = phase;
+        }
+    }
+
+    // Whether the next frame only becomes the reference (after creation and resets)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn is_first_frame(&self) -> bool {
+        self.is_first_frame
+    }
+
+    // Distances from the center (in processing pixels) within which the move modes run at
+    // full and at medium precision, and the distance of the corners; the quality radii shrink
+    // under a tight frame budget
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn high_quality_radius(&self) -> f32 {
+        self.high_quality_radius
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn medium_quality_radius(&self) -> f32 {
+        self.medium_quality_radius
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn max_radius(&self) -> f32 {
+        self.max_radius
+    }
+
+    // Length of the last frame in reference (60 Hz) frames, which the per-frame rates were
+    // scaled by
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn frame_time_scale(&self) -> f32 {
+        self.frame_time_scale
+    }
+
+    // Options the last frame ran with, including a running `transition_to` (before
+    // modulation and frame-time scaling); None before the first frame
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn last_options(&self) -> Option<MotionOptions> {
+        self.last_options
+    }
+
+    // Decay factor the trails faded by in the last frame, after modulation and frame-time
+    // scaling (1 before the first frame)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn effective_decay_rate(&self) -> f32 {
+        self.last_options
+            .map_or(1.0, |options| self.timed(&options).decay_rate)
+    }
+
+    // Trail levels summed per column and per row at processing resolution, both from one
+    // pass over the trails when called, e.g. for bar-graph views or telling left from right
+    // movement by which columns fill up
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_motion_projections(&self) -> MotionProjections {
+        let width = self.width as usize;
+        let mut columns = vec![0.0; width];
+        let rows = self
+            .trails()
+            .chunks_exact(width)
+            .map(|row| {
+                let mut sum = 0.0;
+                for (column, &level) in columns.iter_mut().zip(row) {
+                    *column += level;
+                    sum += level;
+                }
+                sum
+            })
+            .collect();
+        MotionProjections { columns, rows }
+    }
+
+    // Pixels of the active area per difference level (0..255) in the last analysed frame,
+    // e.g. for plotting the noise floor against `threshold`
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_diff_histogram(&self) -> Vec<u32> {
+        self.diff_histogram().to_vec()
+    }
+
+    // The threshold `auto_threshold` settled on, or None while it's off
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn auto_threshold(&self) -> Option<f32> {
+        self.auto_threshold
+    }
+
+    // Connected regions (8-neighbourhood) of the pixels that passed the threshold in the last
+    // analysed frame, with at least `min_area` pixels each, largest first
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn detect_blobs(&self, min_area: u32) -> Vec<Blob> {
+        if self.is_first_frame {
+            return Vec::new();
+        }
+        let width = self.width as usize;
+        let active = self.active_rect();
+        let mut visited = vec![false; self.processing_pixels()];
+        let mut stack = Vec::new();
+        let mut blobs = Vec::new();
+
+        for y in active.rows() {
+            for x in active.cols() {
+                let seed = y * width + x;
+                if visited[seed] || !self.is_moving(seed) {
+                    continue;
+                }
+                visited[seed] = true;
+                stack.push((x, y));
+                let (mut min_x, mut min_y, mut max_x, mut max_y) = (x, y, x, y);
+                let (mut area, mut sum_x, mut sum_y) = (0u32, 0.0, 0.0);
+
+                while let Some((x, y)) = stack.pop() {
+                    area += 1;
+                    sum_x += x as f64;
+                    sum_y += y as f64;
+                    min_x = min_x.min(x);
+                    max_x = max_x.max(x);
+                    min_y = min_y.min(y);
+                    max_y = max_y.max(y);
+
+                    for ny in y.saturating_sub(1)..=y + 1 {
+                        for nx in x.saturating_sub(1)..=x + 1 {
+                            let neighbour = ny * width + nx;
+                            if active.contains(nx, ny)
+                                && !visited[neighbour]
+                                && self.is_moving(neighbour)
+                            {
+                                visited[neighbour] = true;
+                                stack.push((nx, ny));
+                            }
+                        }
+                    }
+                }
+
+                if area >= min_area.max(1) {
+                    blobs.push(Blob {
+                        x: min_x as u32,
+                        y: min_y as u32,
+                        width: (max_x - min_x + 1) as u32,
+                        height: (max_y - min_y + 1) as u32,
+                        area,
+                        centroid_x: (sum_x / area as f64) as f32,
+                        centroid_y: (sum_y / area as f64) as f32,
+                    });
+                }
+            }
+        }
+        blobs.sort_by_key(|blob| std::cmp::Reverse(blob.area));
+        blobs
+    }
+
+    // Particles spawned by motion: each pixel above the threshold spawns one with
+    // `spawn_chance` per analysed frame, up to `max_particles`, which then drifts off for
+    // `lifetime_frames` frames, carried along by the move mode too with `follow_movement`.
+    // 0 particles turns them off. They are drawn by `render_particles`.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_particles(
+        &mut self,
+        max_particles: u32,
+        lifetime_frames: u32,
+        spawn_chance: f32,
+        follow_movement: bool,
+    ) {
+        self.particles = (max_particles > 0).then(|| {
+            ParticleField::new(
+                (max_particles as usize).min(MAX_PARTICLES),
+                lifetime_frames,
+                spawn_chance,
+                follow_movement,
+            )
+        });
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn particle_count(&self) -> usize {
+        self.particles.as_ref().map_or(0, ParticleField::len)
+    }
+
+    // Composite the particles over an output frame of the last `process_*` call, in the
+    // output format and at frame resolution, brightest channel winning
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn render_particles(&self, output_data: &mut [u8]) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if output_data.len() < self.frame_output_len() {
+            return Err(js_error(format!(
+                "output holds {} bytes, a {}x{} frame needs {}",
+                output_data.len(),
+                self.frame_width,
+                self.frame_height,
+                self.frame_output_len()
+            )));
+        }
+        if let Some(particles) = &self.particles {
+            particles.render(
+                &mut output_data[..self.frame_output_len()],
+                self.output_format,
+                self.frame_width as usize,
+                self.processing_factor as usize,
+                &self.palette,
+            );
+        }
+        Ok(())
+    }
+
+    // A/B view for tuning `threshold` and `decay_rate`, written in one pass over an output
+    // frame (output format, frame resolution): one half shows the raw difference of the last
+    // analysed frame in gray, red where it passed the threshold, the other half the trails
+    // through the output palette, without compositing or effects
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn render_comparison(
+        &self,
+        output_data: &mut [u8],
+        layout: ComparisonLayout,
+    ) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if output_data.len() < self.frame_output_len() {
+            return Err(js_error(format!(
+                "output holds {} bytes, a {}x{} frame needs {}",
+                output_data.len(),
+                self.frame_width,
+                self.frame_height,
+                self.frame_output_len()
+            )));
+        }
+        let (frame_width, frame_height) = (self.frame_width as usize, self.frame_height as usize);
+        let (width, height) = (self.width as usize, self.height as usize);
+        let factor = self.processing_factor as usize;
+        let trails = self.trails();
+        for frame_y in 0..frame_height {
+            let y = (frame_y / factor).min(height - 1);
+            for frame_x in 0..frame_width {
+                let pixel_index = y * width + (frame_x / factor).min(width - 1);
+                let difference_side = match layout {
+                    ComparisonLayout::SideBySide => frame_x < frame_width / 2,
+                    ComparisonLayout::TopBottom => frame_y < frame_height / 2,
+                };
+                let color = if difference_side {
+                    let level = self.diff_buffer[pixel_index].clamp(0.0, 255.0) as u8;
+                    if self.is_moving(pixel_index) {
+                        // Weak passes stay visible
+                        [level.max(96), 0, 0]
+                    } else {
+                        [level; 3]
+                    }
+                } else {
+                    self.palette[trails[pixel_index].clamp(0.0, 255.0) as usize]
+                };
+                self.output_format
+                    .write(output_data, frame_y * frame_width + frame_x, color, 255);
+            }
+        }
+        Ok(())
+    }
+
+    // Trail levels quantized to `palette_len` (1-256) evenly spaced palette indices, one
+    // byte per pixel at processing resolution (`persistence_width` wide), for LED matrices
+    // and other indexed-color outputs that can't take RGBA frames
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn render_indexed(&self, palette_len: u32) -> Vec<u8> {
+        let palette_len = palette_len.clamp(1, 256) as f32;
+        self.trails()
+            .iter()
+            .map(|&level| (level.clamp(0.0, 255.0) * palette_len / 256.0) as u8)
+            .collect()
+    }
+
+    // Mean trail level (0-255) of each cell when the frame is cut into `cols` x `rows`
+    // cells, row-major, e.g. one value per character of a terminal or per LED; cells finer
+    // than the processing resolution repeat its pixels
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn render_cells(&self, cols: u32, rows: u32) -> Vec<u8> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let (cols, rows) = (cols as usize, rows as usize);
+        // Pixel span of cell `index` of `count` cells over `size` pixels, never empty
+        let span = |index: usize, count: usize, size: usize| {
+            let start = (index * size / count).min(size - 1);
+            start..((index + 1) * size / count).max(start + 1)
+        };
+        let trails = self.trails();
+        let mut cells = Vec::with_capacity(cols * rows);
+        for row in 0..rows {
+            let cell_rows = span(row, rows, height);
+            for col in 0..cols {
+                let cell_cols = span(col, cols, width);
+                let area = cell_rows.len() * cell_cols.len();
+                let sum: f32 = cell_rows
+                    .clone()
+                    .map(|y| {
+                        trails[y * width + cell_cols.start..y * width + cell_cols.end]
+                            .iter()
+                            .sum::<f32>()
+                    })
+                    .sum();
+                cells.push((sum / area as f32).clamp(0.0, 255.0) as u8);
+            }
+        }
+        cells
+    }
+
+    // Associate the blobs of the last analysed frame with the tracks of earlier calls (call
+    // once per frame) and return the confirmed tracks with their stable IDs and velocities
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn update_tracks(&mut self) -> Vec<Track> {
+        let blobs = self.detect_blobs(self.tracker.min_area);
+        self.tracker.update(&blobs);
+        self.tracker.confirmed()
+    }
+
+    // Tracker tuning: smallest blob followed, furthest a track's predicted centroid may be
+    // from its match in pixels, frames a blob must be matched before its track is reported
+    // and frames a track survives unmatched. Existing tracks are kept.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_tracking(
+        &mut self,
+        min_area: u32,
+        max_distance: f32,
+        birth_frames: u32,
+        death_frames: u32,
+    ) {
+        self.tracker.min_area = min_area;
+        self.tracker.max_distance = max_distance.max(0.0);
+        self.tracker.birth_frames = birth_frames.max(1);
+        self.tracker.death_frames = death_frames;
+    }
+
+    // Picture area found by letterbox detection as [x, y, width, height]
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn content_bounds(&self) -> Vec<u32> {
+        let rect = self
+            .content_rect
+            .unwrap_or(Rect::full(self.width, self.height));
+        [rect.x, rect.y, rect.width, rect.height]
+            .map(|value| value as u32)
+            .to_vec()
+    }
+
+    // Channel layout of the frames passed in and of the output written back
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_input_format(&mut self, format: PixelFormat) {
+        self.input_format = format;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_output_format(&mut self, format: PixelFormat) {
+        self.output_format = format;
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_buffer_size(&self) -> usize {
+        self.processing_pixels()
+    }
+
+    // Zero-copy frame access: JS builds `Uint8ClampedArray` views over `wasm.memory` at these
+    // pointers, writes the camera frame into the input view and calls `process_in_place`.
+    // Views are invalidated when WASM memory grows, so re-create them from fresh pointers
+    // after any call that may allocate (detect it by `view.byteLength === 0`).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn input_ptr(&mut self) -> Result<*mut u8, ExportError> {
+        self.try_input_ptr().map_err(js_error)
+    }
+
+    // FNV-1a hash of the detector-owned output of the last `process_motion`,
+    // `process_async` or `process_in_place` call, for comparing runs across browsers and
+    // versions (build with the `deterministic` feature); `testing::fnv1a` hashes other buffers
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn hash_output(&self) -> u64 {
+        testing::fnv1a(&self.output_buffer)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn output_ptr(&mut self) -> Result<*const u8, ExportError> {
+        self.try_output_ptr().map_err(js_error)
+    }
+
+    // Size in bytes of the input frame behind `input_ptr` in the configured input format
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn buffer_len(&self) -> usize {
+        self.frame_pixels() * self.input_format.bytes_per_pixel()
+    }
+
+    // Size in bytes of the output frame behind `output_ptr`
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn output_buffer_len(&self) -> usize {
+        self.output_len()
+    }
+
+    // Write only the frame region at (`x`, `y`) of `width` x `height` pixels to outputs of
+    // `target_width` x `target_height`, scaled to fit with its aspect ratio kept and black
+    // bars on the sides it doesn't fill; detection still covers the whole frame. For a
+    // display whose aspect ratio differs from the camera's, without a canvas resize. The
+    // output buffers passed to `process` must then have the target size (`output_len`).
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_output_viewport(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        target_width: u32,
+        target_height: u32,
+    ) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        if width == 0
+            || height == 0
+            || x.saturating_add(width) > self.frame_width
+            || y.saturating_add(height) > self.frame_height
+        {
+            return Err(js_error(format!(
+                "viewport {}x{} at {}, {} is not inside the {}x{} frame",
+                width, height, x, y, self.frame_width, self.frame_height
+            )));
+        }
+        check_resolution(target_width, target_height).map_err(js_error)?;
+        self.output_viewport = Some(OutputViewport {
+            x,
+            y,
+            width,
+            height,
+            target_width,
+            target_height,
+        });
+        Ok(())
+    }
+
+    // Back to outputs of the whole frame at its own size
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn clear_output_viewport(&mut self) {
+        self.output_viewport = None;
+        self.viewport_frame = Vec::new();
+    }
+
+    // Pixel size of the outputs, the viewport's target size with `set_output_viewport`
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn output_width(&self) -> u32 {
+        self.output_size().0
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn output_height(&self) -> u32 {
+        self.output_size().1
+    }
+
+    // Zero-copy access to the trails for GPU coloring: `new Float32Array(wasm.memory.buffer,
+    // persistence_ptr(), persistence_len())` is the persistence buffer, one f32 per
+    // processing pixel (`persistence_width()` x `persistence_height()`), row-major from the
+    // top-left, values 0..255 (brighter = fresher motion), little-endian as WASM memory
+    // always is. Upload it as an R32F texture with `texImage2D(..., gl.RED, gl.FLOAT, view)`
+    // or `GPUQueue.writeTexture` into an `r32float` texture. Movement pipelines swap the
+    // buffer each frame, resizes reallocate it and low-memory detectors unpack a new copy
+    // (see `set_low_memory`), so take the pointer after every frame rather than keeping the
+    // view.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn persistence_ptr(&self) -> *const f32 {
+        self.trails().as_ptr()
+    }
+
+    // Length of the persistence buffer in f32 values
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn persistence_len(&self) -> usize {
+        self.processing_pixels()
+    }
+
+    // Processing resolution of the persistence buffer, which differs from the frame size
+    // with `processing_scale` below 1
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn persistence_width(&self) -> u32 {
+        self.width
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn persistence_height(&self) -> u32 {
+        self.height
+    }
+
+    // Process the frame written through `input_ptr` into the buffer behind `output_ptr`
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process_in_place(&mut self, options: &MotionOptions) -> Result<(), ExportError> {
+        self.check_disposed().map_err(js_error)?;
+        // The input buffer is sized for the format the frame arrives in
+        let input_len = self.frame_pixels() * self.input_format_for(options).bytes_per_pixel();
+        let mut input = std::mem::take(&mut self.input_buffer);
+        let mut output = std::mem::take(&mut self.output_buffer);
+        input.resize(input_len, 0);
+        output.resize(self.output_len(), 0);
+        let result = self.process_input(FrameInput::packed(&input), &mut output, options);
+        self.input_buffer = input;
+        self.output_buffer = output;
+        result.map_err(js_error)
+    }
+}
+
+// `fluid_cell_size` in its range
+fn fluid_cell_size(params: &MotionOptions) -> u32 {
+    params.fluid_cell_size.clamp(2, MAX_FLUID_CELL_SIZE)
+}
+
+// `grid_mode` in its range
+fn grid_step(params: &MotionOptions) -> u32 {
+    params.grid_mode.min(MAX_GRID_STEP)
+}
+
+// Level splitting a histogram into the two classes with the largest between-class
+// variance (Otsu's method); pixels above it are the upper class
+fn otsu_threshold(histogram: &[u32; 256]) -> f32 {
+    let total: f64 = histogram.iter().map(|&count| count as f64).sum();
+    let weighted_total: f64 = histogram
+        .iter()
+        .enumerate()
+        .map(|(level, &count)| level as f64 * count as f64)
+        .sum();
+    let (mut lower, mut lower_weighted) = (0.0, 0.0);
+    let (mut best_level, mut best_variance) = (0, 0.0);
+    for (level, &count) in histogram.iter().enumerate() {
+        lower += count as f64;
+        lower_weighted += level as f64 * count as f64;
+        let upper = total - lower;
+        if lower == 0.0 || upper == 0.0 {
+            continue;
+        }
+        let mean_difference = lower_weighted / lower - (weighted_total - lower_weighted) / upper;
+        let variance = lower * upper * mean_difference * mean_difference;
+        if variance > best_variance {
+            best_level = level;
+            best_variance = variance;
+        }
+    }
+    best_level as f32
+}
+
+// Tables for `key` from `context`, or built for this detector alone without one
+fn shared_luts(context: Option<&DetectorContext>, key: LutKey) -> Rc<DetectorLuts> {
+    match context {
+        Some(context) => context.luts(key),
+        None => Rc::new(DetectorLuts::build(key)),
+    }
+}
+
+struct TrackState {
+    id: u32,
+    blob: Blob,
+    velocity_x: f32,
+    velocity_y: f32,
+    age: u32,
+    hits: u32,
+    missed: u32,
+}
+
+// Nearest-centroid tracker over `detect_blobs` output
+struct Tracker {
+    min_area: u32,
+    max_distance: f32,
+    birth_frames: u32,
+    death_frames: u32,
+    tracks: Vec<TrackState>,
+    next_id: u32,
+}
+
+impl Default for Tracker {
+    fn default() -> Tracker {
+        Tracker {
+            min_area: 64,
+            max_distance: 48.0,
+            birth_frames: 3,
+            death_frames: 10,
+            tracks: Vec::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl Tracker {
+    fn update(&mut self, blobs: &[Blob]) {
+        // Greedy association, closest pairs first, against where each track should be now
+        let mut pairs = Vec::new();
+        for (track_index, track) in self.tracks.iter().enumerate() {
+            let predicted_x = track.blob.centroid_x + track.velocity_x * (track.missed + 1) as f32;
+            let predicted_y = track.blob.centroid_y + track.velocity_y * (track.missed + 1) as f32;
+            for (blob_index, blob) in blobs.iter().enumerate() {
+                let distance = (blob.centroid_x - predicted_x).hypot(blob.centroid_y - predicted_y);
+                if distance <= self.max_distance {
+                    pairs.push((distance, track_index, blob_index));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut blob_matched = vec![false; blobs.len()];
+        for (_, track_index, blob_index) in pairs {
+            if track_matched[track_index] || blob_matched[blob_index] {
+                continue;
+            }
+            track_matched[track_index] = true;
+            blob_matched[blob_index] = true;
+
+            let track = &mut self.tracks[track_index];
+            let blob = blobs[blob_index];
+            let frames = (track.missed + 1) as f32;
+            let velocity_x = (blob.centroid_x - track.blob.centroid_x) / frames;
+            let velocity_y = (blob.centroid_y - track.blob.centroid_y) / frames;
+            // The first match sets the velocity, later ones smooth it
+            let weight = if track.hits == 1 { 1.0 } else { 0.5 };
+            track.velocity_x += (velocity_x - track.velocity_x) * weight;
+            track.velocity_y += (velocity_y - track.velocity_y) * weight;
+            track.blob = blob;
+            track.hits += 1;
+            track.missed = 0;
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(&track_matched) {
+            track.age += 1;
+            if !matched {
+                track.missed += 1;
+            }
+        }
+        // Unconfirmed tracks die on their first miss, as they are most likely noise
+        let (birth_frames, death_frames) = (self.birth_frames, self.death_frames);
+        self.tracks.retain(|track| {
+            track.missed == 0 || (track.hits >= birth_frames && track.missed <= death_frames)
+        });
+
+        for (blob, _) in blobs
+            .iter()
+            .zip(&blob_matched)
+            .filter(|(_, matched)| !**matched)
+        {
+            self.tracks.push(TrackState {
+                id: self.next_id,
+                blob: *blob,
+                velocity_x: 0.0,
+                velocity_y: 0.0,
+                age: 0,
+                hits: 1,
+                missed: 0,
+            });
+            self.next_id += 1;
+        }
+    }
+
+    fn confirmed(&self) -> Vec<Track> {
+        self.tracks
+            .iter()
+            .filter(|track| track.hits >= self.birth_frames)
+            .map(|track| Track {
+                id: track.id,
+                x: track.blob.x,
+                y: track.blob.y,
+                width: track.blob.width,
+                height: track.blob.height,
+                centroid_x: track.blob.centroid_x,
+                centroid_y: track.blob.centroid_y,
+                velocity_x: track.velocity_x,
+                velocity_y: track.velocity_y,
+                age: track.age,
+                missed: track.missed,
+            })
+            .collect()
+    }
+}
+
+// Motion totals gathered by the detection loop, per band and then per frame
+#[derive(Clone, Copy, Default)]
+struct FrameTotals {
+    active_pixels: usize,
+    sampled_pixels: usize,
+    energy: f64,
+    energy_x: f64,
+    energy_y: f64,
+}
+
+impl FrameTotals {
+    fn merge(self, other: FrameTotals) -> FrameTotals {
+        FrameTotals {
+            active_pixels: self.active_pixels + other.active_pixels,
+            sampled_pixels: self.sampled_pixels + other.sampled_pixels,
+            energy: self.energy + other.energy,
+            energy_x: self.energy_x + other.energy_x,
+            energy_y: self.energy_y + other.energy_y,
+        }
+    }
+
+    fn stats(&self) -> MotionStats {
+        let centroid = |weighted: f64| (self.energy > 0.0).then(|| (weighted / self.energy) as f32);
+        MotionStats {
+            energy: self.energy as f32,
+            active_percent: self.active_pixels as f32 * 100.0 / self.sampled_pixels.max(1) as f32,
+            centroid_x: centroid(self.energy_x),
+            centroid_y: centroid(self.energy_y),
+        }
+    }
+}
+
+// The state the detection pass reads; persistence and output are written band by band
+struct Detection<'a> {
+    width: usize,
+    cols: Range<usize>,
+    active_rows: Range<usize>,
+    step: usize,
+    palette: &'a [[u8; 3]; 256],
+    threshold: f32,
+    threshold_offsets: &'a [f32],
+    offset_scale: f32,
+    radial_sensitivity_lut: &'a [f32],
+    diff_buffer: &'a [f32],
+    temp_buffer: &'a [f32],
+    mask: Option<&'a [f32]>,
+    fade: TrailFade,
+    decay_map: Option<&'a [f32]>,
+    time_scale: f32,
+    output_format: PixelFormat,
+}
+
+impl Detection<'_> {
+    // Detect one band of rows; `persistence_buffer` and `output_data` hold the band's rows,
+    // the latter starting at pixel `output_offset`. Returns the band's motion totals.
+    fn detect_band(
+        &self,
+        params: &MotionOptions,
+        rows: Range<usize>,
+        persistence_buffer: &mut RowBand,
+        output_data: &mut [u8],
+        output_offset: usize,
+    ) -> FrameTotals {
+        let Detection {
+            width,
+            step,
+            threshold,
+            offset_scale,
+            output_format,
+            ..
+        } = *self;
+        let cols = self.cols.clone();
+        let active_rows = self.active_rows.clone();
+        let mut totals = FrameTotals::default();
+        // Each sample stands for a whole block in the reduced tiers
+        let block_area = (step * step) as f64;
+        let sensitivity = params.sensitivity;
+
+        // Cache-friendly motion detection processing: Process in row-major order
+        // This improves spatial locality for better cache utilization
+        for y in rows {
+            if !(y - active_rows.start).is_multiple_of(step) {
+                continue;
+            }
+            let row_base = y * width;
+            let samples = cols.len().div_ceil(step);
+            let mut row_energy = 0.0;
+            let mut row_energy_x = 0.0;
+
+            for chunk_start in (0..samples).step_by(DETECT_CHUNK) {
+                // Thresholding against the pre-computed lookup tables runs over a chunk at a
+                // time so the row kernel can vectorize it
+                let mut enhanced = [0.0; DETECT_CHUNK];
+                let enhanced = &mut enhanced[..DETECT_CHUNK.min(samples - chunk_start)];
+                let first = row_base + cols.start + chunk_start * step;
+                let mut active_pixels = simd::enhance_row(
+                    &self.diff_buffer[first..],
+                    &self.threshold_offsets[first..],
+                    &self.radial_sensitivity_lut[first..],
+                    step,
+                    threshold,
+                    offset_scale,
+                    sensitivity,
+                    enhanced,
+                );
+                if let Some(mask) = self.mask {
+                    // Masked-out motion neither shows up nor counts as activity
+                    for (sample, value) in enhanced.iter_mut().enumerate() {
+                        *value *= mask[first + sample * step];
+                    }
+                    active_pixels = enhanced.iter().filter(|value| **value > 0.0).count();
+                }
+                totals.active_pixels += active_pixels;
+                totals.sampled_pixels += enhanced.len();
+
+                for (sample, &enhanced_diff) in enhanced.iter().enumerate() {
+                    let x = cols.start + (chunk_start + sample) * step;
+                    let pixel_index = row_base + x;
+                    row_energy += enhanced_diff;
+                    row_energy_x += enhanced_diff * x as f32;
+
+                    // Apply persistence
+                    let persisted_motion = self
+                        .fade
+                        .at(self.decay_map, self.time_scale, pixel_index)
+                        .persist(params, enhanced_diff, self.temp_buffer[pixel_index]);
+
+                    // Output through the palette of the output mode
+                    let color = self.palette[persisted_motion.min(255.0) as usize];
+
+                    if step == 1 {
+                        // Update persistence buffer
+                        persistence_buffer[pixel_index] = persisted_motion;
+                        output_format.write(output_data, pixel_index - output_offset, color, 255);
+                    } else {
+                        for block_y in y..(y + step).min(active_rows.end) {
+                            for block_x in x..(x + step).min(cols.end) {
+                                let block_index = block_y * width + block_x;
+                                persistence_buffer[block_index] = persisted_motion;
+                                output_format.write(
+                                    output_data,
+                                    block_index - output_offset,
+                                    color,
+                                    255,
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+
+            totals.energy += row_energy as f64 * block_area;
+            totals.energy_x += row_energy_x as f64 * block_area;
+            totals.energy_y += row_energy as f64 * y as f64 * block_area;
+        }
+
+        totals
+    }
+}
+
+// How far a frame got, for updating the persistence layers the same way
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum LayerFrame {
+    // Skipped for power saving: trails only fade
+    Idle,
+    // Movement-only frame of `process_every_n`: trails move and fade
+    Moved,
+    // Trails move, fade and take up the detected motion
+    Analysed,
+}
+
+// Ring of unfiltered luma frames for `temporal_filter`
+#[derive(Default)]
+struct TemporalHistory {
+    frames: Vec<Vec<f32>>,
+    // Slot of the frame being decoded, and how many earlier frames the ring holds
+    next: usize,
+    filled: usize,
+}
+
+// Delayed copies of the main trails of `set_echo`
+struct Echo {
+    // Frames back and gain of each copy
+    taps: Vec<(usize, f32)>,
+    // Trails of the last frames as output levels, newest last; as many as the longest delay
+    history: VecDeque<Vec<u8>>,
+}
+
+// An extra trail buffer of a detector with its own fade and movement, see
+// `add_persistence_layer`
+struct PersistenceLayer {
+    params: MotionOptions,
+    opacity: f32,
+    blend_mode: BlendMode,
+    persistence: Vec<f32>,
+    // Movement target of the layer's trails (both empty until the first frame)
+    moved: Vec<f32>,
+}
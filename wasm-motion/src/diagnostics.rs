@@ -0,0 +1,245 @@
+// Per-detector logging of `set_log_level` and the diagnostics of `debug_info`: what the
+// detector rejected or clamped and how long the stages of the last frame took. Everything is
+// kept whatever the level, which only decides what also goes to the console.
+use std::collections::VecDeque;
+
+use serde_json::json;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{now_ms, MotionOptions, OptionValue};
+
+// Messages kept for `debug_info` before the oldest are dropped
+const MAX_LOG_MESSAGES: usize = 64;
+
+// How much goes to the console; each level includes the ones above it
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    // Frames and settings the detector rejected
+    Error,
+    // Options outside their range, once each time the options change
+    Warn,
+    // Frames dropped on purpose
+    Info,
+    // The stage timings of every frame
+    Debug,
+}
+
+impl LogLevel {
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        match name {
+            "off" => Some(LogLevel::Off),
+            "error" => Some(LogLevel::Error),
+            "warn" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+        }
+    }
+}
+
+// An option outside the range `set_option` accepts, and the value it would clamp it to
+struct ClampedOption {
+    option: String,
+    value: f64,
+    clamped_to: f64,
+}
+
+struct LogMessage {
+    frame: u64,
+    level: LogLevel,
+    message: String,
+}
+
+pub(crate) struct Diagnostics {
+    level: LogLevel,
+    // Frames processed, numbering the messages
+    frame: u64,
+    messages: VecDeque<LogMessage>,
+    // The options last checked for clamped values, and what they had
+    checked_options: Option<MotionOptions>,
+    clamped: Vec<ClampedOption>,
+    // Milliseconds per stage of the last frame, in order, and when the running stage began
+    stages: Vec<(&'static str, f64)>,
+    stage_start_ms: f64,
+}
+
+impl Diagnostics {
+    pub(crate) fn new() -> Diagnostics {
+        Diagnostics {
+            level: LogLevel::Warn,
+            frame: 0,
+            messages: VecDeque::new(),
+            checked_options: None,
+            clamped: Vec::new(),
+            stages: Vec::new(),
+            stage_start_ms: 0.0,
+        }
+    }
+
+    pub(crate) fn level(&self) -> LogLevel {
+        self.level
+    }
+
+    pub(crate) fn set_level(&mut self, level: LogLevel) {
+        self.level = level;
+    }
+
+    pub(crate) fn log(&mut self, level: LogLevel, message: String) {
+        if level <= self.level && level != LogLevel::Off {
+            write_console(&format!("motion-detection {}: {}", level.name(), message));
+        }
+        if self.messages.len() == MAX_LOG_MESSAGES {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(LogMessage {
+            frame: self.frame,
+            level,
+            message,
+        });
+    }
+
+    // Start timing a frame and look for clamped options if they changed since the last one
+    pub(crate) fn begin_frame(&mut self, params: &MotionOptions) {
+        self.frame += 1;
+        self.stages.clear();
+        self.stage_start_ms = now_ms();
+        if self.checked_options.as_ref() == Some(params) {
+            return;
+        }
+        self.checked_options = Some(*params);
+        self.clamped = clamped_options(params);
+        for index in 0..self.clamped.len() {
+            let clamped = &self.clamped[index];
+            let message = format!(
+                "option {} is {}, outside its range; set_option would use {}",
+                clamped.option, clamped.value, clamped.clamped_to
+            );
+            self.log(LogLevel::Warn, message);
+        }
+    }
+
+    // Milliseconds per stage of the last frame, in pipeline order
+    pub(crate) fn stages(&self) -> &[(&'static str, f64)] {
+        &self.stages
+    }
+
+    // End the running stage as `stage` and start the next
+    pub(crate) fn end_stage(&mut self, stage: &'static str) {
+        let now = now_ms();
+        self.stages.push((stage, now - self.stage_start_ms));
+        self.stage_start_ms = now;
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        if self.level < LogLevel::Debug {
+            return;
+        }
+        let stages: Vec<_> = self
+            .stages
+            .iter()
+            .map(|(stage, ms)| format!("{} {:.2} ms", stage, ms))
+            .collect();
+        let message = format!("frame {}: {}", self.frame, stages.join(", "));
+        self.log(LogLevel::Debug, message);
+    }
+
+    // Back to no frames, keeping the level
+    pub(crate) fn reset(&mut self) {
+        *self = Diagnostics {
+            level: self.level,
+            ..Diagnostics::new()
+        };
+    }
+
+    pub(crate) fn to_json(&self) -> String {
+        let messages: Vec<_> = self
+            .messages
+            .iter()
+            .map(|message| {
+                json!({
+                    "frame": message.frame,
+                    "level": message.level.name(),
+                    "message": message.message,
+                })
+            })
+            .collect();
+        let clamped: Vec<_> = self
+            .clamped
+            .iter()
+            .map(|clamped| {
+                json!({
+                    "option": clamped.option,
+                    "value": clamped.value,
+                    "clamped_to": clamped.clamped_to,
+                })
+            })
+            .collect();
+        let stages: Vec<_> = self
+            .stages
+            .iter()
+            .map(|(stage, ms)| json!({ "stage": stage, "ms": ms }))
+            .collect();
+        json!({
+            "log_level": self.level.name(),
+            "frame": self.frame,
+            "messages": messages,
+            "clamped_options": clamped,
+            "stages": stages,
+            "total_ms": self.stages.iter().map(|(_, ms)| ms).sum::<f64>(),
+        })
+        .to_string()
+    }
+}
+
+// The numeric options of `params` that `set_option` would change, found by setting every
+// one of them again and comparing the saved configurations
+fn clamped_options(params: &MotionOptions) -> Vec<ClampedOption> {
+    let numbers = |options: &MotionOptions| -> Vec<(String, f64)> {
+        let config: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&options.get_config_json()).unwrap_or_default();
+        config
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_f64()?)))
+            .collect()
+    };
+    let given = numbers(params);
+    let mut clamped = *params;
+    for (name, value) in &given {
+        // Every saved number is a `set_option` number
+        let _ = clamped.set_option(name, OptionValue::Number(*value));
+    }
+    given
+        .into_iter()
+        .zip(numbers(&clamped))
+        .filter(|((_, value), (_, clamped_to))| value != clamped_to)
+        .map(|((option, value), (_, clamped_to))| ClampedOption {
+            option,
+            value,
+            clamped_to,
+        })
+        .collect()
+}
+
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+fn write_console(message: &str) {
+    crate::log(message);
+}
+
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+fn write_console(message: &str) {
+    eprintln!("{}", message);
+}
@@ -0,0 +1,245 @@
+// Coarse stable-fluids simulation of `output_mode: "fluid"` (after Stam, "Real-Time Fluid
+// Dynamics for Games"): motion pushes velocity and density into a grid of cells several
+// pixels wide, the velocity is kept divergence-free and carries itself and the density
+// along, and the density is what the output shows.
+
+// Jacobi iterations of the viscosity and pressure solves; the grid is coarse, so a few
+// converge well enough to look right
+const DIFFUSE_ITERATIONS: usize = 8;
+const PRESSURE_ITERATIONS: usize = 16;
+// Share of the velocity kept per step, so stirred fluid settles once motion stops
+const VELOCITY_DECAY: f32 = 0.98;
+// Largest velocity in cells per step, so a fast motion can't send density across the grid
+const MAX_VELOCITY: f32 = 4.0;
+
+// How the fluid behaves, from the fluid_* options
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct FluidSettings {
+    pub(crate) viscosity: f32,
+    pub(crate) dissipation: f32,
+}
+
+#[derive(Default)]
+pub(crate) struct Fluid {
+    width: usize,
+    height: usize,
+    velocity_x: Vec<f32>,
+    velocity_y: Vec<f32>,
+    density: Vec<f32>,
+    // Previous values of the field being solved or advected, and the pressure and
+    // divergence of the projection
+    scratch: Vec<f32>,
+    scratch_y: Vec<f32>,
+    pressure: Vec<f32>,
+    divergence: Vec<f32>,
+}
+
+impl Fluid {
+    // An empty fluid of `width` x `height` cells, unless it already has that size
+    pub(crate) fn resize(&mut self, width: usize, height: usize) {
+        if (self.width, self.height) == (width, height) && !self.density.is_empty() {
+            return;
+        }
+        let cells = width * height;
+        *self = Fluid {
+            width,
+            height,
+            velocity_x: vec![0.0; cells],
+            velocity_y: vec![0.0; cells],
+            density: vec![0.0; cells],
+            scratch: vec![0.0; cells],
+            scratch_y: vec![0.0; cells],
+            pressure: vec![0.0; cells],
+            divergence: vec![0.0; cells],
+        };
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        [
+            &self.velocity_x,
+            &self.velocity_y,
+            &self.density,
+            &self.scratch,
+            &self.scratch_y,
+            &self.pressure,
+            &self.divergence,
+        ]
+        .iter()
+        .map(|buffer| buffer.capacity() * std::mem::size_of::<f32>())
+        .sum()
+    }
+
+    // Push a cell with (velocity_x, velocity_y) in cells per step and add density (0..255)
+    pub(crate) fn inject(&mut self, cell: usize, velocity: (f32, f32), density: f32) {
+        self.velocity_x[cell] += velocity.0;
+        self.velocity_y[cell] += velocity.1;
+        self.density[cell] = (self.density[cell] + density).min(255.0);
+    }
+
+    // One step: diffuse and advect the velocity, keeping it divergence-free, then carry the
+    // density along and fade it
+    pub(crate) fn step(&mut self, settings: FluidSettings) {
+        if self.density.is_empty() {
+            return;
+        }
+        let (width, height) = (self.width, self.height);
+        for velocity in [&mut self.velocity_x, &mut self.velocity_y] {
+            for value in velocity.iter_mut() {
+                *value = (*value * VELOCITY_DECAY).clamp(-MAX_VELOCITY, MAX_VELOCITY);
+            }
+        }
+
+        if settings.viscosity > 0.0 {
+            for velocity in [&mut self.velocity_x, &mut self.velocity_y] {
+                self.scratch.copy_from_slice(velocity);
+                diffuse(velocity, &self.scratch, settings.viscosity, width, height);
+            }
+        }
+        self.project();
+
+        self.scratch.copy_from_slice(&self.velocity_x);
+        self.scratch_y.copy_from_slice(&self.velocity_y);
+        let (previous_x, previous_y) = (&self.scratch, &self.scratch_y);
+        for (field, previous) in [
+            (&mut self.velocity_x, previous_x),
+            (&mut self.velocity_y, previous_y),
+        ] {
+            advect(field, previous, previous_x, previous_y, width, height);
+        }
+        self.project();
+
+        self.scratch.copy_from_slice(&self.density);
+        advect(
+            &mut self.density,
+            &self.scratch,
+            &self.velocity_x,
+            &self.velocity_y,
+            width,
+            height,
+        );
+        let dissipation = settings.dissipation.clamp(0.0, 1.0);
+        for value in &mut self.density {
+            *value *= dissipation;
+        }
+    }
+
+    // Remove the divergent part of the velocity: solve for the pressure whose gradient
+    // accounts for it and subtract that gradient
+    fn project(&mut self) {
+        let (width, height) = (self.width, self.height);
+        let at = |field: &[f32], x: isize, y: isize| {
+            let x = x.clamp(0, width as isize - 1) as usize;
+            let y = y.clamp(0, height as isize - 1) as usize;
+            field[y * width + x]
+        };
+        for y in 0..height {
+            for x in 0..width {
+                let (x, y) = (x as isize, y as isize);
+                self.divergence[y as usize * width + x as usize] = -0.5
+                    * (at(&self.velocity_x, x + 1, y) - at(&self.velocity_x, x - 1, y)
+                        + at(&self.velocity_y, x, y + 1)
+                        - at(&self.velocity_y, x, y - 1));
+            }
+        }
+        self.pressure.fill(0.0);
+        diffuse_poisson(
+            &mut self.pressure,
+            &self.divergence,
+            &mut self.scratch,
+            width,
+            height,
+        );
+        for y in 0..height {
+            for x in 0..width {
+                let index = y * width + x;
+                let (x, y) = (x as isize, y as isize);
+                self.velocity_x[index] -=
+                    0.5 * (at(&self.pressure, x + 1, y) - at(&self.pressure, x - 1, y));
+                self.velocity_y[index] -=
+                    0.5 * (at(&self.pressure, x, y + 1) - at(&self.pressure, x, y - 1));
+            }
+        }
+    }
+
+    // Density (0..255) at a point in cell units, bilinear between cell centers
+    pub(crate) fn density_at(&self, x: f32, y: f32) -> f32 {
+        let (width, height) = (self.width, self.height);
+        if self.density.is_empty() {
+            return 0.0;
+        }
+        let x = (x - 0.5).clamp(0.0, (width - 1) as f32);
+        let y = (y - 0.5).clamp(0.0, (height - 1) as f32);
+        let (x0, y0) = (x as usize, y as usize);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let row = |y: usize| {
+            self.density[y * width + x0] * (1.0 - fx) + self.density[y * width + x1] * fx
+        };
+        row(y0) * (1.0 - fy) + row(y1) * fy
+    }
+}
+
+// Implicit diffusion of `field` from `previous` at `rate` per step, by Jacobi iteration
+fn diffuse(field: &mut [f32], previous: &[f32], rate: f32, width: usize, height: usize) {
+    for _ in 0..DIFFUSE_ITERATIONS {
+        for y in 0..height {
+            for x in 0..width {
+                let neighbours = field[y * width + x.saturating_sub(1)]
+                    + field[y * width + (x + 1).min(width - 1)]
+                    + field[y.saturating_sub(1) * width + x]
+                    + field[(y + 1).min(height - 1) * width + x];
+                field[y * width + x] =
+                    (previous[y * width + x] + rate * neighbours) / (1.0 + 4.0 * rate);
+            }
+        }
+    }
+}
+
+// Jacobi solve of the pressure Poisson equation, edges reflecting
+fn diffuse_poisson(
+    pressure: &mut [f32],
+    divergence: &[f32],
+    scratch: &mut [f32],
+    width: usize,
+    height: usize,
+) {
+    for _ in 0..PRESSURE_ITERATIONS {
+        scratch.copy_from_slice(pressure);
+        for y in 0..height {
+            for x in 0..width {
+                let neighbours = scratch[y * width + x.saturating_sub(1)]
+                    + scratch[y * width + (x + 1).min(width - 1)]
+                    + scratch[y.saturating_sub(1) * width + x]
+                    + scratch[(y + 1).min(height - 1) * width + x];
+                pressure[y * width + x] = (divergence[y * width + x] + neighbours) / 4.0;
+            }
+        }
+    }
+}
+
+// Semi-Lagrangian advection: each cell takes the value of `previous` where the velocity
+// traces it back to
+fn advect(
+    field: &mut [f32],
+    previous: &[f32],
+    velocity_x: &[f32],
+    velocity_y: &[f32],
+    width: usize,
+    height: usize,
+) {
+    let max_x = (width - 1) as f32;
+    let max_y = (height - 1) as f32;
+    for y in 0..height {
+        for x in 0..width {
+            let index = y * width + x;
+            let source_x = (x as f32 - velocity_x[index]).clamp(0.0, max_x);
+            let source_y = (y as f32 - velocity_y[index]).clamp(0.0, max_y);
+            let (x0, y0) = (source_x as usize, source_y as usize);
+            let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+            let (fx, fy) = (source_x - x0 as f32, source_y - y0 as f32);
+            let row =
+                |y: usize| previous[y * width + x0] * (1.0 - fx) + previous[y * width + x1] * fx;
+            field[index] = row(y0) * (1.0 - fy) + row(y1) * fy;
+        }
+    }
+}
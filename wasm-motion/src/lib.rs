@@ -1,587 +1,2529 @@
+#[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::*;
-use wasm_bindgen::JsValue;
-
-// Import the `console.log` function from the `console` module for debugging
+#[cfg(feature = "wasm")]
+use wasm_bindgen::{JsCast, JsValue};
+
+mod capture;
+mod context;
+mod detector;
+mod diagnostics;
+mod fluid;
+mod movement;
+mod particles;
+#[cfg(feature = "python")]
+mod python;
+mod registration;
+mod render;
+pub mod simd;
+pub mod testing;
+mod timeline;
+mod trigger;
+mod zones;
+
+pub use capture::CapturedFrame;
+pub use context::DetectorContext;
+pub use detector::{MotionDetector, FRAME_SCENE_CHANGE};
+pub use diagnostics::LogLevel;
+pub use movement::Attractor;
+pub use render::{BlendMode, Compositor, PixelFormat, ToneMapping};
+pub use timeline::{Easing, Keyframe, KeyframeValue};
+pub use trigger::{DebounceUnit, MotionEvent, MotionEventKind};
+pub use zones::{Zone, ZoneEvent, ZoneEventKind, ZoneShape};
+
+// `initThreadPool(n)` for JS; must resolve before processing with the `threads` feature
+#[cfg(all(feature = "threads", target_arch = "wasm32"))]
+pub use wasm_bindgen_rayon::init_thread_pool;
+
+// Import `console.log` for the messages of `set_log_level`
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_namespace = console)]
     fn log(s: &str);
+
+    #[wasm_bindgen(js_name = setTimeout)]
+    fn set_timeout(callback: &js_sys::Function, delay_ms: i32) -> JsValue;
+
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
 }
 
-// Define a macro for console logging
-macro_rules! console_log {
-    ($($t:tt)*) => (log(&format_args!($($t)*).to_string()))
+// Monotonic milliseconds for timing measurements
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+fn now_ms() -> f64 {
+    performance_now()
 }
 
-#[wasm_bindgen]
-pub struct MotionDetector {
-    width: u32,
-    height: u32,
-    persistence_buffer: Vec<f32>,
-    // Optimization #1: Pre-computed lookup tables
-    distance_lut: Vec<f32>,
-    radial_sensitivity_lut: Vec<f32>,
-    // Optimization for spiral movement: Pre-computed polar coordinates
-    polar_angle_lut: Vec<f32>,
-    polar_distance_lut: Vec<f32>,
-    // Optimization #3: Pre-computed squared distances for fast comparisons
-    polar_distance_squared_lut: Vec<f32>,
-    // Optimization #2: Reusable buffer to avoid allocations
-    temp_buffer: Vec<f32>,
-    // Optimization #6: Cache previous frame in Rust (50% less data transfer)
-    previous_frame_cache: Vec<u8>,
-    is_first_frame: bool,
-    phase: f32,
-    // Optimization #6: Distance-based processing thresholds for approximation
-    center_x: f32,
-    center_y: f32,
-    // Distance thresholds for different quality levels
-    high_quality_radius: f32,
-    medium_quality_radius: f32,
+#[cfg(not(all(feature = "wasm", target_arch = "wasm32")))]
+fn now_ms() -> f64 {
+    static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    START
+        .get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_secs_f64()
+        * 1000.0
+}
+
+// Rows processed between event-loop yields in `process_async`
+#[cfg(feature = "wasm")]
+const DEFAULT_ASYNC_CHUNK_ROWS: u32 = 64;
+
+// Frame length the per-frame rates are tuned for (60 fps); `delta_time_ms` scales from it
+const REFERENCE_FRAME_MS: f32 = 1000.0 / 60.0;
+
+// Samples thresholded per `simd::enhance_row` call in `detect_rows`
+const DETECT_CHUNK: usize = 64;
+
+// Per-pixel noise estimate of `adaptive_threshold`: how fast it follows the scene, where it
+// starts and the floor that keeps quantization steps of a clean camera from passing
+const NOISE_LEARNING_RATE: f32 = 0.02;
+const NOISE_INITIAL_SIGMA: f32 = 4.0;
+const NOISE_MIN_SIGMA: f32 = 1.0;
+
+// Largest frame a detector accepts: each pixel takes a few dozen bytes of f32 buffers,
+// which have to fit in the 4 GiB address space of wasm32
+const MAX_DIMENSION: u32 = 16384;
+const MAX_PIXELS: u64 = 4096 * 4096;
+
+fn check_resolution(width: u32, height: u32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("resolution {}x{} has no pixels", width, height));
+    }
+    if width > MAX_DIMENSION || height > MAX_DIMENSION || width as u64 * height as u64 > MAX_PIXELS
+    {
+        return Err(format!(
+            "resolution {}x{} is too large (at most {} pixels, {} per side)",
+            width, height, MAX_PIXELS, MAX_DIMENSION
+        ));
+    }
+    Ok(())
+}
+
+// Bytes per pixel a detector allocates with the default options: seven f32 frame buffers and
+// lookup tables plus the RGBA output buffer of the owned-output entry points. Options like
+// background subtraction, persistence layers or recording add to it, as do the polar tables
+// once a mode moving around the center runs.
+const BASE_BYTES_PER_PIXEL: u64 = 32;
+
+fn estimated_memory_bytes(width: u32, height: u32) -> u64 {
+    width as u64 * height as u64 * BASE_BYTES_PER_PIXEL
+}
+
+// `check_resolution`, and the estimated footprint within `max_bytes`
+fn check_memory(width: u32, height: u32, max_bytes: u64) -> Result<(), String> {
+    check_resolution(width, height)?;
+    let needed = estimated_memory_bytes(width, height);
+    if needed > max_bytes {
+        return Err(format!(
+            "resolution {}x{} needs about {} bytes, over the limit of {}",
+            width, height, needed, max_bytes
+        ));
+    }
+    Ok(())
+}
+
+// Error of the fallible exported methods: thrown as an `Error` with the message in JS, the
+// message itself in builds without the `wasm` feature
+#[cfg(feature = "wasm")]
+type ExportError = JsError;
+#[cfg(not(feature = "wasm"))]
+type ExportError = String;
+
+#[cfg(feature = "wasm")]
+fn js_error(message: String) -> ExportError {
+    JsError::new(&message)
 }
 
+#[cfg(not(feature = "wasm"))]
+fn js_error(message: String) -> ExportError {
+    message
+}
+
+// A JS exception raised by a browser API as an `ExportError`, keeping its message
+#[cfg(feature = "wasm")]
+fn js_exception(error: JsValue) -> ExportError {
+    let message = match error.dyn_ref::<js_sys::Error>() {
+        Some(error) => String::from(error.message()),
+        None => error.as_string().unwrap_or_else(|| format!("{:?}", error)),
+    };
+    JsError::new(&message)
+}
+
+// Whether this build runs the grayscale/diff/threshold stages on WASM SIMD; builds without
+// it (or for browsers without SIMD support) use the scalar loops
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn simd_enabled() -> bool {
+    simd::ENABLED
+}
+
+// Device performance report for bug reports and comparing devices: the whole pipeline over
+// `frames` synthetic frames at `resolution` ("WIDTHxHEIGHT", e.g. "1280x720"), with the
+// average milliseconds per stage and checksums of the output that only differ between
+// builds, not runs. See `MotionDetector::run_selftest_report` for the fields.
+#[cfg(feature = "wasm")]
 #[wasm_bindgen]
-impl MotionDetector {
-    #[wasm_bindgen(constructor)]
-    pub fn new(width: u32, height: u32) -> MotionDetector {
-        let center_x = width as f32 / 2.0;
-        let center_y = height as f32 / 2.0;
-        let max_radius = ((center_x * center_x) + (center_y * center_y)).sqrt();
-        let inv_max_radius = 1.0 / max_radius;
-        let buffer_size = (width * height) as usize;
-
-        // Pre-allocate all vectors with exact capacity to avoid reallocations
-        let mut distance_lut = Vec::with_capacity(buffer_size);
-        let mut radial_sensitivity_lut = Vec::with_capacity(buffer_size);
-        let mut polar_angle_lut = Vec::with_capacity(buffer_size);
-        let mut polar_distance_lut = Vec::with_capacity(buffer_size);
-        let mut polar_distance_squared_lut = Vec::with_capacity(buffer_size);
-
-        // Cache-friendly initialization: Process row by row to improve spatial locality
-        for y in 0..height {
-            let y_f32 = y as f32;
-            let dy = y_f32 - center_y;
-
-            for x in 0..width {
-                let x_f32 = x as f32;
-                let dx = x_f32 - center_x;
-                let distance_squared = dx * dx + dy * dy;
-                let distance = distance_squared.sqrt();
-                let normalized_distance = distance * inv_max_radius;
-                let radial_sensitivity = (1.0 - normalized_distance * 0.9).max(0.1);
-
-                // Pre-compute polar coordinates for spiral movement
-                let angle = dy.atan2(dx);
-
-                distance_lut.push(normalized_distance);
-                radial_sensitivity_lut.push(radial_sensitivity);
-                polar_angle_lut.push(angle);
-                polar_distance_lut.push(distance);
-                polar_distance_squared_lut.push(distance_squared);
-            }
-        }
+pub fn run_selftest(resolution: &str, frames: u32) -> Result<JsValue, ExportError> {
+    let (width, height) = resolution
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .ok_or_else(|| {
+            js_error(format!(
+                "resolution must be WIDTHxHEIGHT, got {}",
+                resolution
+            ))
+        })?;
+    let report = MotionDetector::run_selftest_report(width, height, frames).map_err(js_error)?;
+    js_sys::JSON::parse(&report.to_string()).map_err(js_exception)
+}
 
-        MotionDetector {
-            width,
-            height,
-            // Initialize persistence buffer with zero for better cache locality
-            persistence_buffer: vec![0.0; buffer_size],
-            distance_lut,
-            radial_sensitivity_lut,
-            polar_angle_lut,
-            polar_distance_lut,
-            polar_distance_squared_lut,
-            // Pre-allocate temp buffer with exact capacity
-            temp_buffer: Vec::with_capacity(buffer_size),
-            // Pre-allocate frame cache with exact capacity (RGBA = 4 bytes per pixel)
-            previous_frame_cache: Vec::with_capacity(buffer_size * 4),
-            is_first_frame: true,
-            phase: 0.0,
-            // Optimization #6: Store center and radius for distance-based approximation
-            center_x,
-            center_y,
-            // Define quality levels: high quality for center 30%, medium for next 40%, low for outer 30%
-            high_quality_radius: max_radius * 0.3,
-            medium_quality_radius: max_radius * 0.7,
-        }
-    }
-
-    #[wasm_bindgen]
-    pub fn process_motion_with_cache(
-        &mut self,
-        current_data: &[u8],    // Only current frame - 50% less data transfer!
-        output_data: &mut [u8], // RGBA output for display
-        options: JsValue,
-    ) {
-        let width = self.width as usize;
-        let height = self.height as usize;
-
-        // First frame: just cache and return
-        if self.is_first_frame {
-            self.previous_frame_cache.clear();
-            self.previous_frame_cache.extend_from_slice(current_data);
-            self.is_first_frame = false;
-
-            // Output black frame for first frame
-            for i in 0..output_data.len() {
-                output_data[i] = if i % 4 == 3 { 255 } else { 0 }; // Set alpha to 255, RGB to 0
-            }
-            return;
-        }
-
-        // Extract parameters
-        let move_type = js_sys::Reflect::get(&options, &"move_type".into())
-            .unwrap_or(JsValue::from_str("direction"))
-            .as_string()
-            .unwrap_or_else(|| "direction".to_string());
-
-        // Perform motion based on type
-        match move_type.as_str() {
-            "direction" => self.move_in_direction(options.clone()),
-            "radial" => self.move_radially(options.clone()),
-            "spiral" => self.move_spiral(options.clone()),
-            "wave" => self.move_wave(options.clone()),
-            _ => console_log!("Unknown move type: {}", move_type),
-        }
-
-        // Extract parameters
-        let decay_rate = js_sys::Reflect::get(&options, &"decay_rate".into())
-            .unwrap_or(JsValue::from(0.95))
-            .as_f64()
-            .unwrap_or(0.95) as f32;
-
-        let threshold = js_sys::Reflect::get(&options, &"threshold".into())
-            .unwrap_or(JsValue::from(30.0))
-            .as_f64()
-            .unwrap_or(30.0) as f32;
-
-        let sensitivity = js_sys::Reflect::get(&options, &"sensitivity".into())
-            .unwrap_or(JsValue::from(1.0))
-            .as_f64()
-            .unwrap_or(1.0) as f32;
-
-        // Cache-friendly motion detection processing: Process in row-major order
-        // This improves spatial locality for better cache utilization
-        for y in 0..height {
-            let row_base = y * width;
-
-            for x in 0..width {
-                let pixel_index = row_base + x;
-                let rgba_index = pixel_index * 4;
-
-                // Fast grayscale conversion using integer arithmetic
-                let current_gray = ((current_data[rgba_index] as u32 * 77)
-                    + (current_data[rgba_index + 1] as u32 * 150)
-                    + (current_data[rgba_index + 2] as u32 * 29))
-                    >> 8;
-
-                let previous_gray = ((self.previous_frame_cache[rgba_index] as u32 * 77)
-                    + (self.previous_frame_cache[rgba_index + 1] as u32 * 150)
-                    + (self.previous_frame_cache[rgba_index + 2] as u32 * 29))
-                    >> 8;
-
-                // Use pre-computed lookup tables
-                let normalized_distance = self.distance_lut[pixel_index];
-                let radial_sensitivity = self.radial_sensitivity_lut[pixel_index];
-
-                // Motion detection with grayscale values
-                let diff = (current_gray as f32 - previous_gray as f32).abs();
-                let radial_weighted_diff = diff * radial_sensitivity;
-                let adaptive_threshold = threshold + normalized_distance * 40.0;
-
-                let filtered_diff = if radial_weighted_diff > adaptive_threshold {
-                    radial_weighted_diff
-                } else {
-                    0.0
-                };
+// Resolve a promise from a macrotask so the browser can render and handle input in between
+#[cfg(feature = "wasm")]
+async fn yield_to_event_loop() {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        set_timeout(&resolve, 0);
+    });
+    // The promise only ever resolves
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
 
-                let enhanced_diff =
-                    (filtered_diff * (sensitivity + radial_sensitivity * 0.5)).min(255.0);
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MoveType {
+    Direction,
+    Radial,
+    Spiral,
+    Wave,
+    // Scale the trails towards or away from a focal point, keeping their shape
+    Zoom,
+    // Spin the trails around a pivot without any radial motion
+    Rotate,
+    // Advect the trails along an evolving curl-noise field, like smoke
+    Turbulence,
+    // Advect the trails along the vector field given with `set_flow_field`
+    Flow,
+    // Mirror the trails into `segments` wedges around the center, like a kaleidoscope
+    Kaleidoscope,
+    // Jolt the trails by a random offset of up to `shake_magnitude` every frame, like a
+    // hand-held camera; the offsets repeat for the same `seed`
+    Shake,
+    // Pull the trails towards (or push them away from) the points set with `set_attractors`
+    Attract,
+    // Warp the trails by the projective transform of `set_perspective_corners` (or
+    // `set_perspective_matrix`) every frame, e.g. a plane tipping away into the screen
+    Perspective,
+    // Drag bright trails along `angle_radians` into streaks up to `strength` pixels long
+    // per frame at full brightness, dim ones barely, like glitch-art pixel sorting
+    Smear,
+    // Zoom the trails by `zoom_factor` and twist them by `twist` around the focal point in
+    // one pass every frame, so new motion feeds back into a spiralling video-feedback tunnel
+    Feedback,
+    // Spread the trails as heat by `diffuse_iterations` diffusion steps every frame, drifting
+    // along `angle_radians` with `diffuse_bias`: a soft glow left behind instead of motion
+    Diffuse,
+}
 
-                // Apply persistence
-                let previous_persistence = self.temp_buffer[pixel_index];
-                let persisted_motion = enhanced_diff.max(previous_persistence * decay_rate);
+impl MoveType {
+    pub fn parse(name: &str) -> Option<MoveType> {
+        match name {
+            "direction" => Some(MoveType::Direction),
+            "radial" => Some(MoveType::Radial),
+            "spiral" => Some(MoveType::Spiral),
+            "wave" => Some(MoveType::Wave),
+            "zoom" => Some(MoveType::Zoom),
+            "rotate" => Some(MoveType::Rotate),
+            "turbulence" => Some(MoveType::Turbulence),
+            "flow" => Some(MoveType::Flow),
+            "kaleidoscope" => Some(MoveType::Kaleidoscope),
+            "shake" => Some(MoveType::Shake),
+            "attract" => Some(MoveType::Attract),
+            "perspective" => Some(MoveType::Perspective),
+            "smear" => Some(MoveType::Smear),
+            "feedback" => Some(MoveType::Feedback),
+            "diffuse" => Some(MoveType::Diffuse),
+            _ => None,
+        }
+    }
 
-                // Update persistence buffer
-                self.persistence_buffer[pixel_index] = persisted_motion;
+    pub fn name(self) -> &'static str {
+        match self {
+            MoveType::Direction => "direction",
+            MoveType::Radial => "radial",
+            MoveType::Spiral => "spiral",
+            MoveType::Wave => "wave",
+            MoveType::Zoom => "zoom",
+            MoveType::Rotate => "rotate",
+            MoveType::Turbulence => "turbulence",
+            MoveType::Flow => "flow",
+            MoveType::Kaleidoscope => "kaleidoscope",
+            MoveType::Shake => "shake",
+            MoveType::Attract => "attract",
+            MoveType::Perspective => "perspective",
+            MoveType::Smear => "smear",
+            MoveType::Feedback => "feedback",
+            MoveType::Diffuse => "diffuse",
+        }
+    }
+}
 
-                // Output as grayscale RGBA for display
-                let smoothed_motion = persisted_motion.min(255.0) as u8;
-                output_data[rgba_index] = smoothed_motion;
-                output_data[rgba_index + 1] = smoothed_motion;
-                output_data[rgba_index + 2] = smoothed_motion;
-                output_data[rgba_index + 3] = 255;
-            }
+// How the persistence buffer is read at the fractional source positions of a movement
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SamplingMode {
+    // Round to the nearest pixel (fastest, stair-steps at slow speeds)
+    Nearest,
+    // Interpolate the four surrounding pixels for smooth sub-pixel motion
+    Bilinear,
+}
+
+impl SamplingMode {
+    pub fn parse(name: &str) -> Option<SamplingMode> {
+        match name {
+            "nearest" => Some(SamplingMode::Nearest),
+            "bilinear" => Some(SamplingMode::Bilinear),
+            _ => None,
         }
+    }
 
-        // Update cache with current frame for next iteration
-        self.previous_frame_cache.copy_from_slice(current_data);
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplingMode::Nearest => "nearest",
+            SamplingMode::Bilinear => "bilinear",
+        }
     }
+}
 
-    pub fn move_in_direction(&mut self, options: JsValue) {
-        let width = self.width as usize;
-        let height = self.height as usize;
+// Mirroring of the input while it is read, the `input_transform` shorthand for the
+// `flip_horizontal` / `flip_vertical` pair
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputTransform {
+    None,
+    // Left and right swapped, as front-facing cameras need
+    MirrorX,
+    MirrorY,
+    // Both, for a camera mounted upside down
+    Rotate180,
+}
 
-        let angle_radians = js_sys::Reflect::get(&options, &"angle_radians".into())
-            .unwrap_or(JsValue::from(0.0))
-            .as_f64()
-            .unwrap_or(0.0) as f32;
+impl InputTransform {
+    pub fn parse(name: &str) -> Option<InputTransform> {
+        match name {
+            "none" => Some(InputTransform::None),
+            "mirror_x" => Some(InputTransform::MirrorX),
+            "mirror_y" => Some(InputTransform::MirrorY),
+            "rotate_180" => Some(InputTransform::Rotate180),
+            _ => None,
+        }
+    }
 
-        let speed = js_sys::Reflect::get(&options, &"speed".into())
-            .unwrap_or(JsValue::from(0.0))
-            .as_f64()
-            .unwrap_or(0.0) as f32;
+    pub fn name(self) -> &'static str {
+        match self {
+            InputTransform::None => "none",
+            InputTransform::MirrorX => "mirror_x",
+            InputTransform::MirrorY => "mirror_y",
+            InputTransform::Rotate180 => "rotate_180",
+        }
+    }
 
-        self.temp_buffer.clear();
-        self.temp_buffer.resize(self.persistence_buffer.len(), 0.0);
+    fn from_flips(flip_horizontal: bool, flip_vertical: bool) -> InputTransform {
+        match (flip_horizontal, flip_vertical) {
+            (false, false) => InputTransform::None,
+            (true, false) => InputTransform::MirrorX,
+            (false, true) => InputTransform::MirrorY,
+            (true, true) => InputTransform::Rotate180,
+        }
+    }
 
-        // Early exit for minimal movement - avoid all calculations
-        if speed <= 1.0 {
-            self.temp_buffer.copy_from_slice(&self.persistence_buffer);
-            return;
+    // (flip_horizontal, flip_vertical)
+    fn flips(self) -> (bool, bool) {
+        match self {
+            InputTransform::None => (false, false),
+            InputTransform::MirrorX => (true, false),
+            InputTransform::MirrorY => (false, true),
+            InputTransform::Rotate180 => (true, true),
         }
+    }
+}
 
-        // Pre-compute movement values outside the loop
-        let move_x = angle_radians.cos() * speed;
-        let move_y = angle_radians.sin() * speed;
-        let move_x_int = move_x.round() as i32;
-        let move_y_int = move_y.round() as i32;
+// What movement reads where its source position falls outside the frame
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BoundaryMode {
+    // Nothing: trails vanish at the edges
+    Clear,
+    // The opposite edge, so trails leaving one side come back on the other
+    Wrap,
+    // The nearest edge pixel, smearing the border inwards
+    Clamp,
+    // The frame reflected at the edge
+    Mirror,
+}
 
-        // Cache-friendly processing: Process in row-major order with row-level optimizations
-        let width_i32 = width as i32;
-        let height_i32 = height as i32;
+impl BoundaryMode {
+    pub fn parse(name: &str) -> Option<BoundaryMode> {
+        match name {
+            "clear" => Some(BoundaryMode::Clear),
+            "wrap" => Some(BoundaryMode::Wrap),
+            "clamp" => Some(BoundaryMode::Clamp),
+            "mirror" => Some(BoundaryMode::Mirror),
+            _ => None,
+        }
+    }
 
-        // Process row by row for better cache locality
-        for y in 0..height {
-            let y_i32 = y as i32;
-            let source_y = y_i32 - move_y_int;
+    pub fn name(self) -> &'static str {
+        match self {
+            BoundaryMode::Clear => "clear",
+            BoundaryMode::Wrap => "wrap",
+            BoundaryMode::Clamp => "clamp",
+            BoundaryMode::Mirror => "mirror",
+        }
+    }
 
-            // Skip entire row if source_y is out of bounds
-            if source_y < 0 || source_y >= height_i32 {
-                // Row is out of bounds - temp_buffer already initialized to 0.0
-                continue;
+    // Pixel read for source pixel (x, y) of a `width` x `height` frame; None when cleared
+    #[inline]
+    fn resolve(self, x: i32, y: i32, width: usize, height: usize) -> Option<(usize, usize)> {
+        let axis = |value: i32, size: i32| match self {
+            BoundaryMode::Clear => (0..size).contains(&value).then_some(value),
+            BoundaryMode::Wrap => Some(value.rem_euclid(size)),
+            BoundaryMode::Clamp => Some(value.clamp(0, size - 1)),
+            BoundaryMode::Mirror => {
+                let folded = value.rem_euclid(2 * size);
+                Some(if folded < size {
+                    folded
+                } else {
+                    2 * size - 1 - folded
+                })
             }
+        };
+        Some((
+            axis(x, width as i32)? as usize,
+            axis(y, height as i32)? as usize,
+        ))
+    }
+}
 
-            let source_row_base = (source_y as usize) * width;
-            let dest_row_base = y * width;
+// Which color information motion is detected in. Gray inputs always use their luminance.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChannelMode {
+    // Weighted luma (77/150/29)
+    Luma,
+    // The brightest of R, G and B
+    Max,
+    // A single channel, e.g. for a red laser pointer
+    Red,
+    Green,
+    Blue,
+    // Each channel differenced on its own and the largest difference (or the luma one) wins;
+    // with the background models only the luma is compared against the background
+    PerChannel,
+}
 
-            // Process pixels in this row with cache-friendly access pattern
-            for x in 0..width {
-                let x_i32 = x as i32;
-                let source_x = x_i32 - move_x_int;
+impl ChannelMode {
+    pub fn parse(name: &str) -> Option<ChannelMode> {
+        match name {
+            "luma" => Some(ChannelMode::Luma),
+            "max" => Some(ChannelMode::Max),
+            "red" => Some(ChannelMode::Red),
+            "green" => Some(ChannelMode::Green),
+            "blue" => Some(ChannelMode::Blue),
+            "per_channel" => Some(ChannelMode::PerChannel),
+            _ => None,
+        }
+    }
 
-                if source_x >= 0 && source_x < width_i32 {
-                    let source_index = source_row_base + source_x as usize;
-                    let dest_index = dest_row_base + x;
-                    self.temp_buffer[dest_index] = self.persistence_buffer[source_index];
-                }
-                // Implicit else: temp_buffer[dest_index] remains 0.0 from initialization
-            }
+    pub fn name(self) -> &'static str {
+        match self {
+            ChannelMode::Luma => "luma",
+            ChannelMode::Max => "max",
+            ChannelMode::Red => "red",
+            ChannelMode::Green => "green",
+            ChannelMode::Blue => "blue",
+            ChannelMode::PerChannel => "per_channel",
         }
     }
 
-    pub fn move_radially(&mut self, options: JsValue) {
-        let width = self.width as usize;
-        let height = self.height as usize;
-
-        let speed = js_sys::Reflect::get(&options, &"speed".into())
-            .unwrap_or(JsValue::from(0.0))
-            .as_f64()
-            .unwrap_or(0.0) as f32;
-
-        self.temp_buffer.clear();
-        self.temp_buffer.resize(self.persistence_buffer.len(), 0.0);
-
-        // Radial movement processing - optimized to avoid expensive sqrt calls
-        if speed.abs() > 0.1 {
-            let speed_plus_threshold = speed + 50.0;
-            let speed_plus_threshold_squared = speed_plus_threshold * speed_plus_threshold;
-            let width_i32 = width as i32;
-            let height_i32 = height as i32;
-
-            // Cache-friendly processing: Process row by row for better memory locality
-            for y in 0..height {
-                let y_f32 = y as f32;
-                let dy = y_f32 - self.center_y;
-                let dest_row_base = y * width;
-
-                for x in 0..width {
-                    let pixel_index = dest_row_base + x;
-
-                    // Use pre-computed squared distance to avoid sqrt calculation
-                    let distance_squared = self.polar_distance_squared_lut[pixel_index];
-
-                    if distance_squared > speed_plus_threshold_squared {
-                        let distance = self.polar_distance_lut[pixel_index];
-
-                        // Optimization #6: Distance-based approximation for performance
-                        let effective_speed = if distance <= self.high_quality_radius {
-                            // High quality: Full precision for center area
-                            speed
-                        } else if distance <= self.medium_quality_radius {
-                            // Medium quality: Slightly reduced precision for middle area
-                            speed * 0.95
-                        } else {
-                            // Low quality: Reduced precision for distant pixels
-                            // Use coarser movement steps for better performance
-                            (speed * 0.8).round()
-                        };
-
-                        // Calculate pixel coordinates (optimized with row-level y calculation)
-                        let x_f32 = x as f32;
-                        let dx = x_f32 - self.center_x;
-
-                        // Normalize direction vector (reuse calculated distance)
-                        let inv_distance = 1.0 / distance;
-                        let norm_dx = dx * inv_distance;
-                        let norm_dy = dy * inv_distance;
-
-                        // Calculate source position
-                        let source_x = x_f32 - norm_dx * effective_speed;
-                        let source_y = y_f32 - norm_dy * effective_speed;
-
-                        let source_x_int = source_x.round() as i32;
-                        let source_y_int = source_y.round() as i32;
-
-                        // Optimized bounds check
-                        if source_x_int >= 0
-                            && source_x_int < width_i32
-                            && source_y_int >= 0
-                            && source_y_int < height_i32
-                        {
-                            let source_index =
-                                (source_y_int as usize * width) + source_x_int as usize;
-                            self.temp_buffer[pixel_index] = self.persistence_buffer[source_index];
-                        }
-                        // Implicit else: temp_buffer[pixel_index] remains 0.0 from initialization
-                    } else {
-                        // Center pixel stays the same
-                        self.temp_buffer[pixel_index] = self.persistence_buffer[pixel_index];
-                    }
-                }
+    // The grayscale value of an RGB pixel (0..255 channels) in this mode
+    #[inline]
+    fn gray(self, [r, g, b]: [f32; 3]) -> f32 {
+        match self {
+            ChannelMode::Luma | ChannelMode::PerChannel => {
+                (r * 77.0 + g * 150.0 + b * 29.0) / 256.0
             }
-        } else {
-            self.temp_buffer.copy_from_slice(&self.persistence_buffer);
+            ChannelMode::Max => r.max(g).max(b),
+            ChannelMode::Red => r,
+            ChannelMode::Green => g,
+            ChannelMode::Blue => b,
+        }
+    }
+}
+
+// What the accumulation buffer keeps of each pixel's motion, see `accumulation_image`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccumulationMode {
+    Off,
+    // The strongest motion seen
+    MaxHold,
+    // The mean motion over all analysed frames
+    Average,
+}
+
+impl AccumulationMode {
+    pub fn parse(name: &str) -> Option<AccumulationMode> {
+        match name {
+            "off" => Some(AccumulationMode::Off),
+            "max_hold" => Some(AccumulationMode::MaxHold),
+            "average" => Some(AccumulationMode::Average),
+            _ => None,
         }
     }
 
-    pub fn move_spiral(&mut self, options: JsValue) {
-        let width = self.width as usize;
-        let height = self.height as usize;
+    pub fn name(self) -> &'static str {
+        match self {
+            AccumulationMode::Off => "off",
+            AccumulationMode::MaxHold => "max_hold",
+            AccumulationMode::Average => "average",
+        }
+    }
+}
 
-        let speed = js_sys::Reflect::get(&options, &"speed".into())
-            .unwrap_or(JsValue::from(0.0))
-            .as_f64()
-            .unwrap_or(0.0) as f32;
+// Where the motion threshold comes from
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AutoThreshold {
+    // The `threshold` option
+    Off,
+    // Otsu's method on the histogram of the frame differences, smoothed over frames
+    Otsu,
+}
 
-        let rotation_speed = js_sys::Reflect::get(&options, &"rotation_speed".into())
-            .unwrap_or(JsValue::from(0.1))
-            .as_f64()
-            .unwrap_or(0.1) as f32;
+impl AutoThreshold {
+    pub fn parse(name: &str) -> Option<AutoThreshold> {
+        match name {
+            "off" => Some(AutoThreshold::Off),
+            "otsu" => Some(AutoThreshold::Otsu),
+            _ => None,
+        }
+    }
 
-        self.temp_buffer.clear();
-        self.temp_buffer.resize(self.persistence_buffer.len(), 0.0);
+    pub fn name(self) -> &'static str {
+        match self {
+            AutoThreshold::Off => "off",
+            AutoThreshold::Otsu => "otsu",
+        }
+    }
+}
 
-        // Spiral movement processing - Early exit for minimal movement
-        if !(speed.abs() > 0.1 || rotation_speed.abs() > 0.01) {
-            self.temp_buffer.copy_from_slice(&self.persistence_buffer);
-            return;
+// How a pixel's detected motion combines with its faded trail
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PersistenceMode {
+    // The brighter of the two, so trails never outshine the motion that made them
+    Max,
+    // Their sum (clamped), so repeated motion builds up into a glow
+    Additive,
+    // Blended towards the motion by `persistence_alpha`, trails fading by it as well
+    Lerp,
+    // Screen blend: brighter than either, but saturating softly instead of clipping
+    Screen,
+}
+
+impl PersistenceMode {
+    pub fn parse(name: &str) -> Option<PersistenceMode> {
+        match name {
+            "max" => Some(PersistenceMode::Max),
+            "additive" => Some(PersistenceMode::Additive),
+            "lerp" => Some(PersistenceMode::Lerp),
+            "screen" => Some(PersistenceMode::Screen),
+            _ => None,
         }
+    }
 
-        // Pre-compute constants
-        let width_i32 = width as i32;
-        let height_i32 = height as i32;
-        let speed_threshold = speed + 5.0;
+    pub fn name(self) -> &'static str {
+        match self {
+            PersistenceMode::Max => "max",
+            PersistenceMode::Additive => "additive",
+            PersistenceMode::Lerp => "lerp",
+            PersistenceMode::Screen => "screen",
+        }
+    }
+}
 
-        // Optimization #6: Distance-based quality processing for better performance
-        // Process pixels with different accuracy based on distance from center
-        for y in 0..height {
-            let dest_row_base = y * width;
+// Per-pixel filter over the luminance of the last `temporal_window` frames before they are
+// differenced
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TemporalFilter {
+    Off,
+    // Drops flicker shorter than half the window, e.g. compression artifacts
+    Median,
+    // Keeps the darkest value, so bright rain, snow or sparks never show up
+    Min,
+}
 
-            for x in 0..width {
-                let pixel_index = dest_row_base + x;
+impl TemporalFilter {
+    pub fn parse(name: &str) -> Option<TemporalFilter> {
+        match name {
+            "off" => Some(TemporalFilter::Off),
+            "median" => Some(TemporalFilter::Median),
+            "min" => Some(TemporalFilter::Min),
+            _ => None,
+        }
+    }
 
-                // Use pre-computed polar coordinates (eliminates expensive atan2 and sqrt calls)
-                let distance = self.polar_distance_lut[pixel_index];
-                let angle = self.polar_angle_lut[pixel_index];
+    pub fn name(self) -> &'static str {
+        match self {
+            TemporalFilter::Off => "off",
+            TemporalFilter::Median => "median",
+            TemporalFilter::Min => "min",
+        }
+    }
+}
 
-                // Early exit for center pixels using faster comparison
-                if distance <= speed_threshold {
-                    self.temp_buffer[pixel_index] = self.persistence_buffer[pixel_index];
-                    continue;
-                }
+// What each frame is compared against
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DetectionMode {
+    // The previous frame
+    FrameDiff,
+    // The background model chosen with `set_background_model`
+    BackgroundSub,
+}
 
-                // Optimization #6: Apply different quality levels based on distance
-                let (new_distance, new_angle) = if distance <= self.high_quality_radius {
-                    // High quality: Full precision for center area
-                    (distance - speed, angle - rotation_speed)
-                } else if distance <= self.medium_quality_radius {
-                    // Medium quality: Reduced rotation precision for middle area
-                    (distance - speed, angle - rotation_speed * 0.7)
-                } else {
-                    // Low quality: Simplified calculation for distant pixels
-                    // Use approximation: skip very small rotations for distant pixels
-                    if rotation_speed.abs() < 0.02 {
-                        (distance - speed, angle) // Skip rotation entirely
-                    } else {
-                        (distance - speed, angle - rotation_speed * 0.5)
-                    }
-                };
-
-                // Convert back to cartesian (still needs cos/sin, but eliminated atan2 and sqrt)
-                let source_x = self.center_x + new_distance * new_angle.cos();
-                let source_y = self.center_y + new_distance * new_angle.sin();
-
-                let source_x_int = source_x.round() as i32;
-                let source_y_int = source_y.round() as i32;
-
-                // Optimized bounds check with early exit
-                if source_x_int >= 0
-                    && source_x_int < width_i32
-                    && source_y_int >= 0
-                    && source_y_int < height_i32
-                {
-                    let source_index = (source_y_int as usize * width) + source_x_int as usize;
-                    self.temp_buffer[pixel_index] = self.persistence_buffer[source_index];
-                }
-                // Implicit else: temp_buffer[pixel_index] remains 0.0 from initialization
-            }
+impl DetectionMode {
+    pub fn parse(name: &str) -> Option<DetectionMode> {
+        match name {
+            "frame_diff" => Some(DetectionMode::FrameDiff),
+            "background_sub" => Some(DetectionMode::BackgroundSub),
+            _ => None,
         }
     }
 
-    pub fn move_wave(&mut self, options: JsValue) {
-        let width = self.width as usize;
-        let height = self.height as usize;
+    pub fn name(self) -> &'static str {
+        match self {
+            DetectionMode::FrameDiff => "frame_diff",
+            DetectionMode::BackgroundSub => "background_sub",
+        }
+    }
+}
 
-        let amplitude = js_sys::Reflect::get(&options, &"amplitude".into())
-            .unwrap_or(JsValue::from(5.0))
-            .as_f64()
-            .unwrap_or(5.0) as f32;
+// Color mapping of the persistence value in the output
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputMode {
+    Grayscale,
+    // Black -> blue -> cyan -> green -> yellow -> red with increasing intensity
+    Heatmap,
+    // Red for fresh motion through to violet for old trails; the age is estimated from how
+    // far a trail has decayed, so it follows the trails through every movement
+    HueByAge,
+    // Intensity times the `tint_color` option
+    Tint,
+    // Hue from the direction of the motion that left the trail (red = rightwards, then
+    // through green = downwards), brightness from its intensity: an optical-flow style view
+    Direction,
+    // Only the filled shapes of what moves, in `tint_color` on black, with neither the
+    // trails' detail nor the input frame: for installations that must not show faces
+    Silhouette,
+    // Motion stirs a coarse simulated fluid, see the `fluid_*` options: what moves pushes
+    // dye that swirls on and fades, shown in `tint_color` on black
+    Fluid,
+    // Color from how long motion has stayed at each pixel, through the `set_dwell_colors`
+    // ramp: a pixel heats up while motion keeps going there and cools down once it leaves,
+    // see the `dwell_*` options. For footfall and dwell maps rather than fresh motion.
+    Dwell,
+}
 
-        let frequency = js_sys::Reflect::get(&options, &"frequency".into())
-            .unwrap_or(JsValue::from(0.02))
-            .as_f64()
-            .unwrap_or(0.02) as f32;
+impl OutputMode {
+    pub fn parse(name: &str) -> Option<OutputMode> {
+        match name {
+            "grayscale" => Some(OutputMode::Grayscale),
+            "heatmap" => Some(OutputMode::Heatmap),
+            "hue_by_age" => Some(OutputMode::HueByAge),
+            "tint" => Some(OutputMode::Tint),
+            "direction" => Some(OutputMode::Direction),
+            "silhouette" => Some(OutputMode::Silhouette),
+            "fluid" => Some(OutputMode::Fluid),
+            "dwell" => Some(OutputMode::Dwell),
+            _ => None,
+        }
+    }
 
-        let phase_increment = js_sys::Reflect::get(&options, &"phase_increment".into())
-            .unwrap_or(JsValue::from(0.1))
-            .as_f64()
-            .unwrap_or(0.1) as f32;
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputMode::Grayscale => "grayscale",
+            OutputMode::Heatmap => "heatmap",
+            OutputMode::HueByAge => "hue_by_age",
+            OutputMode::Tint => "tint",
+            OutputMode::Direction => "direction",
+            OutputMode::Silhouette => "silhouette",
+            OutputMode::Fluid => "fluid",
+            OutputMode::Dwell => "dwell",
+        }
+    }
+}
 
-        // Increment the phase for animation
-        self.phase += phase_increment;
+// What the alpha channel of RGBA and BGRA output carries; the color stays the trails
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    // Always 255
+    Opaque,
+    // 255 for fresh motion down to 0 for faded trails, estimated from how far a trail has
+    // decayed like `hue_by_age`
+    Freshness,
+    // How clearly the last analysed frame's difference cleared the threshold: 0 at the
+    // threshold, 255 from twice it up; trails without new motion are 0
+    Confidence,
+}
+
+impl AlphaMode {
+    pub fn parse(name: &str) -> Option<AlphaMode> {
+        match name {
+            "opaque" => Some(AlphaMode::Opaque),
+            "freshness" => Some(AlphaMode::Freshness),
+            "confidence" => Some(AlphaMode::Confidence),
+            _ => None,
+        }
+    }
 
-        let direction = js_sys::Reflect::get(&options, &"direction".into())
-            .unwrap_or(JsValue::from(0)) // 0 = horizontal, 1 = vertical
-            .as_f64()
-            .unwrap_or(0.0) as i32;
+    pub fn name(self) -> &'static str {
+        match self {
+            AlphaMode::Opaque => "opaque",
+            AlphaMode::Freshness => "freshness",
+            AlphaMode::Confidence => "confidence",
+        }
+    }
+}
 
-        self.temp_buffer.clear();
-        self.temp_buffer.resize(self.persistence_buffer.len(), 0.0);
+// How the trail output is combined with the input frame in `output_data`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompositeMode {
+    // Trails only
+    Replace,
+    // Trails added onto the frame
+    OverlayAdd,
+    // Trails screened over the frame: lightens like add but never clips
+    OverlayScreen,
+    // The frame faded `composite_alpha` of the way to the trails
+    AlphaBlend,
+}
 
-        // Early exit for minimal wave effect
-        if amplitude.abs() <= 0.1 {
-            self.temp_buffer.copy_from_slice(&self.persistence_buffer);
-            return;
+impl CompositeMode {
+    pub fn parse(name: &str) -> Option<CompositeMode> {
+        match name {
+            "replace" => Some(CompositeMode::Replace),
+            "overlay_add" => Some(CompositeMode::OverlayAdd),
+            "overlay_screen" => Some(CompositeMode::OverlayScreen),
+            "alpha_blend" => Some(CompositeMode::AlphaBlend),
+            _ => None,
         }
+    }
 
-        // Pre-compute constants for optimization
-        let width_i32 = width as i32;
-        let height_i32 = height as i32;
+    pub fn name(self) -> &'static str {
+        match self {
+            CompositeMode::Replace => "replace",
+            CompositeMode::OverlayAdd => "overlay_add",
+            CompositeMode::OverlayScreen => "overlay_screen",
+            CompositeMode::AlphaBlend => "alpha_blend",
+        }
+    }
+}
 
-        // Optimization #6: Distance-based quality wave processing with cache-friendly access
-        if direction == 0 {
-            // Horizontal wave - cache-friendly row-by-row processing
-            for y in 0..height {
-                let y_f32 = y as f32;
-                let distance_from_center = self.polar_distance_lut[y * width + width / 2];
+// Per-frame options for the `process_*` methods. A typed object instead of a plain JS
+// object, so values are validated once in Rust and no field lookups happen per frame;
+// the fields are exposed to JS as properties of the same name. Since those properties
+// (and modulation routes) write the fields without going through `set_option`, any value,
+// NaN and infinity included, can reach the detector, which clamps the fields where it
+// reads them.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionOptions {
+    // None leaves the trails in place
+    pub move_type: Option<MoveType>,
+    pub decay_rate: f32,
+    pub threshold: f32,
+    // Pick `threshold` from the differences of the last frames instead (`threshold` is the
+    // starting point); see `get_diff_histogram`
+    pub auto_threshold: AutoThreshold,
+    pub sensitivity: f32,
+    pub angle_radians: f32,
+    pub speed: f32,
+    pub rotation_speed: f32,
+    pub amplitude: f32,
+    pub frequency: f32,
+    pub phase_increment: f32,
+    // 0 = horizontal, 1 = vertical
+    pub direction: i32,
+    // Mirror the input while reading it (e.g. selfie cameras)
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub sampling_mode: SamplingMode,
+    // Channel layout of the frames passed in from now on, like `set_input_format`, so a
+    // capture pipeline delivering BGRA or packed RGB needs no swizzle pass; None keeps the
+    // detector's format
+    pub input_format: Option<PixelFormat>,
+    pub output_mode: OutputMode,
+    // 0xRRGGBB color used by the `tint`, `silhouette` and `fluid` output modes
+    pub tint_color: u32,
+    // Share of the last silhouette kept each analysed frame (0 = none, towards 1 steadier
+    // but slower to follow)
+    pub silhouette_smoothing: f32,
+    // Pixels per cell of the `fluid` grid, how much the fluid's velocity spreads to its
+    // neighbours per frame, the share of the dye kept per frame and how hard motion pushes
+    pub fluid_cell_size: u32,
+    pub fluid_viscosity: f32,
+    pub fluid_dissipation: f32,
+    pub fluid_force: f32,
+    // Milliseconds of motion at a pixel that reach the hot end of the `dwell` ramp, and how
+    // fast a pixel cools without motion relative to how fast it heats up with it
+    pub dwell_max_ms: f32,
+    pub dwell_cooling: f32,
+    // Trails over the input frame in the output instead of on black, saving the canvas
+    // compositing pass; `composite_alpha` is the weight of the trails in `alpha_blend`
+    pub composite_mode: CompositeMode,
+    pub composite_alpha: f32,
+    // Per-pixel freshness or confidence in the output's alpha channel, so canvas or WebGL
+    // compositing can fade trails without a second buffer
+    pub alpha_mode: AlphaMode,
+    // Shaping of the trail levels at output time: gamma (above 1 lifts faint trails),
+    // contrast around mid gray and quantization to `posterize_levels` bands (0 = off)
+    pub gamma: f32,
+    pub contrast: f32,
+    pub posterize_levels: u32,
+    // Darkening of the output towards the edges, applied after detection so it doesn't
+    // change what is picked up (0 strength = off): full brightness out to
+    // `output_vignette_radius` (1 = the corners), fading over `output_vignette_softness`
+    pub output_vignette_strength: f32,
+    pub output_vignette_radius: f32,
+    pub output_vignette_softness: f32,
+    pub detection_mode: DetectionMode,
+    // Weight of each new frame in the running-average background
+    pub learning_rate: f32,
+    // Scale per frame of the zoom mode (> 1 zooms in) and its focal point as fractions of
+    // the frame; the feedback mode also turns the trails by `twist` radians per frame
+    pub zoom_factor: f32,
+    pub focal_x: f32,
+    pub focal_y: f32,
+    pub twist: f32,
+    // Pivot of the rotate mode as fractions of the frame
+    pub pivot_x: f32,
+    pub pivot_y: f32,
+    // Noise field of the turbulence mode: seed, feature size in pixels and peak speed.
+    // `strength` is also the streak length per frame of full-brightness smear trails.
+    pub seed: u32,
+    pub scale: f32,
+    pub strength: f32,
+    // Motion history mode: active pixels are stamped at full brightness that fades out
+    // linearly over this many milliseconds instead of by `decay_rate` (0 = off)
+    pub history_duration: f32,
+    // How new motion combines with the faded trails, and the weight of the motion in the
+    // `lerp` mode (0..1)
+    pub persistence_mode: PersistenceMode,
+    pub persistence_alpha: f32,
+    // Time since the previous frame. When set, the per-frame rates (decay, speeds, phase)
+    // are scaled from their values at REFERENCE_FRAME_MS so trails look the same at any
+    // frame rate, and timed fades use it instead of the wall clock (0 = per call).
+    pub delta_time_ms: f32,
+    // Resolution of detection and movement relative to the frame (1, 1/2, 1/3, ... down to
+    // 1/8): the input is box-averaged down and the output upscaled bilinearly. ROI, mask,
+    // blobs and trails are then in processing pixels.
+    pub processing_scale: f32,
+    pub boundary_mode: BoundaryMode,
+    // Gaussian blur (sigma in pixels, at most MAX_DIFFUSION) of the moved trails every
+    // frame, so they bleed into their surroundings like ink in water (0 = off)
+    pub diffusion: f32,
+    // 3x3 erosions and then dilations of the motion map before it reaches the trails:
+    // erosion removes speckle from single noisy pixels, dilation closes gaps again (each at
+    // most MAX_MORPHOLOGY_ITERATIONS)
+    pub erode: u32,
+    pub dilate: u32,
+    // Threshold each pixel at `adaptive_k` standard deviations of its own luminance noise,
+    // estimated over recent frames, instead of `threshold` plus the radial falloff
+    pub adaptive_threshold: bool,
+    pub adaptive_k: f32,
+    // Per-pixel motion summary kept since `reset_accumulation`, e.g. for heatmaps of the last
+    // few minutes
+    pub accumulation_mode: AccumulationMode,
+    // Hysteresis instead of the single `threshold`: a pixel starts moving above
+    // `threshold_high` and keeps moving until it drops below `threshold_low`, so subjects
+    // hovering around the threshold don't flicker (0 = off; not with `adaptive_threshold`)
+    pub threshold_high: f32,
+    pub threshold_low: f32,
+    pub channel_mode: ChannelMode,
+    // Detect motion on every n-th frame only; the frames in between just move and fade the
+    // trails, so detection can run at e.g. 15 Hz while the animation stays at 60 Hz (1 to
+    // MAX_PROCESS_EVERY_N)
+    pub process_every_n: u32,
+    // Wedges of the kaleidoscope mode and the angle of the first wedge's edge
+    pub segments: u32,
+    pub segment_offset: f32,
+    // Largest offset of the shake mode along each axis
+    pub shake_magnitude: f32,
+    // Heat diffusion steps per frame of the diffuse mode (0..=32), and how strongly they
+    // drift along `angle_radians` (0 = evenly in all directions, 1 = all downstream)
+    pub diffuse_iterations: u32,
+    pub diffuse_bias: f32,
+    // Scene-change detection: a frame where more than this fraction of the active area
+    // differs by over `threshold` is taken as a cut or an exposure jump rather than motion
+    // (0 = off). Such frames are flagged in `last_frame_flags`; `scene_change_suppress`
+    // keeps their difference out of the trails and `scene_change_reset` restarts the
+    // background model from them.
+    pub scene_change_threshold: f32,
+    pub scene_change_suppress: bool,
+    pub scene_change_reset: bool,
+    // Scale each frame so its mean luminance matches the frame (or background) it is
+    // compared with, so webcam auto-exposure hunting doesn't read as full-frame motion
+    pub exposure_compensation: bool,
+    // Shift the cached previous frame by the camera translation `estimate_global_motion`
+    // finds before each difference, so shake of a hand-held camera doesn't light up the whole
+    // frame. Frame differencing only; the per-channel differences of
+    // `channel_mode: "per_channel"` stay unaligned.
+    pub stabilize: bool,
+    // Subtract the previous output, warped by `set_feedback_homography` into camera space
+    // and scaled by `self_feedback_gain`, from each frame before differencing, so a camera
+    // that sees the projected trails doesn't detect them again
+    pub suppress_self_feedback: bool,
+    pub self_feedback_gain: f32,
+    // Median or minimum of each pixel over the last `temporal_window` frames (2..=9) instead
+    // of the frame itself; costs a frame of memory per window slot. The per-channel
+    // differences of `channel_mode: "per_channel"` stay unfiltered.
+    pub temporal_filter: TemporalFilter,
+    pub temporal_window: u32,
+    // Surveillance mode: difference only every n-th pixel along each axis and interpolate the
+    // motion in between for display (0 or 1 = off, at most MAX_GRID_STEP). The coarse grid is
+    // available through `get_motion_grid` for integrations that only need presence or zones.
+    pub grid_mode: u32,
+    // Numeric options driven by the detector's modulation signals, see `modulate`
+    modulations: [Option<Modulation>; MAX_MODULATIONS],
+}
 
-                // Optimization #6: Apply different wave quality based on distance
-                let effective_amplitude = if distance_from_center <= self.high_quality_radius {
-                    amplitude
-                } else if distance_from_center <= self.medium_quality_radius {
-                    amplitude * 0.9
+// Longest window of `temporal_filter`
+pub(crate) const MAX_TEMPORAL_WINDOW: usize = 9;
+
+// Most diffusion steps per frame of the diffuse mode; each widens its kernel by a pixel
+pub(crate) const MAX_DIFFUSE_ITERATIONS: u32 = 32;
+
+// Longest detection stride of `process_every_n`, so output never stalls for long
+pub(crate) const MAX_PROCESS_EVERY_N: u32 = 8;
+
+// Sparsest sampling of `grid_mode`, in pixels between samples
+pub(crate) const MAX_GRID_STEP: u32 = 16;
+
+// Most erosions or dilations per frame; each is a full pass over the motion map
+pub(crate) const MAX_MORPHOLOGY_ITERATIONS: u32 = 8;
+
+// Widest blur of the `diffusion` option, in pixels of sigma; its kernel spans six sigmas
+pub(crate) const MAX_DIFFUSION: f32 = 8.0;
+
+// Coarsest grid of the fluid output mode, in pixels per cell
+pub(crate) const MAX_FLUID_CELL_SIZE: u32 = 32;
+
+// Modulated options per `MotionOptions`
+const MAX_MODULATIONS: usize = 8;
+
+type OptionField = fn(&mut MotionOptions) -> &mut f32;
+
+// Numeric options that can follow a modulation signal
+const MODULATED_OPTIONS: [(&str, OptionField); 22] = [
+    ("decay_rate", |o| &mut o.decay_rate),
+    ("threshold", |o| &mut o.threshold),
+    ("threshold_high", |o| &mut o.threshold_high),
+    ("threshold_low", |o| &mut o.threshold_low),
+    ("sensitivity", |o| &mut o.sensitivity),
+    ("angle_radians", |o| &mut o.angle_radians),
+    ("speed", |o| &mut o.speed),
+    ("rotation_speed", |o| &mut o.rotation_speed),
+    ("amplitude", |o| &mut o.amplitude),
+    ("frequency", |o| &mut o.frequency),
+    ("phase_increment", |o| &mut o.phase_increment),
+    ("zoom_factor", |o| &mut o.zoom_factor),
+    ("focal_x", |o| &mut o.focal_x),
+    ("focal_y", |o| &mut o.focal_y),
+    ("twist", |o| &mut o.twist),
+    ("pivot_x", |o| &mut o.pivot_x),
+    ("pivot_y", |o| &mut o.pivot_y),
+    ("scale", |o| &mut o.scale),
+    ("strength", |o| &mut o.strength),
+    ("diffusion", |o| &mut o.diffusion),
+    ("segment_offset", |o| &mut o.segment_offset),
+    ("shake_magnitude", |o| &mut o.shake_magnitude),
+];
+
+// A modulated option (index into MODULATED_OPTIONS): its set value plus `depth` times the
+// signal at index `signal`
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Modulation {
+    option: usize,
+    signal: usize,
+    depth: f32,
+}
+
+impl Default for MotionOptions {
+    fn default() -> MotionOptions {
+        MotionOptions {
+            move_type: Some(MoveType::Direction),
+            decay_rate: 0.95,
+            threshold: 30.0,
+            auto_threshold: AutoThreshold::Off,
+            sensitivity: 1.0,
+            angle_radians: 0.0,
+            speed: 0.0,
+            rotation_speed: 0.1,
+            amplitude: 5.0,
+            frequency: 0.02,
+            phase_increment: 0.1,
+            direction: 0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            sampling_mode: SamplingMode::Nearest,
+            input_format: None,
+            output_mode: OutputMode::Grayscale,
+            tint_color: 0xffffff,
+            silhouette_smoothing: 0.0,
+            fluid_cell_size: 8,
+            fluid_viscosity: 0.0,
+            fluid_dissipation: 0.97,
+            fluid_force: 1.0,
+            dwell_max_ms: 5000.0,
+            dwell_cooling: 1.0,
+            composite_mode: CompositeMode::Replace,
+            composite_alpha: 0.5,
+            alpha_mode: AlphaMode::Opaque,
+            gamma: 1.0,
+            contrast: 1.0,
+            posterize_levels: 0,
+            output_vignette_strength: 0.0,
+            output_vignette_radius: 0.6,
+            output_vignette_softness: 0.4,
+            detection_mode: DetectionMode::FrameDiff,
+            learning_rate: 0.05,
+            zoom_factor: 1.02,
+            focal_x: 0.5,
+            focal_y: 0.5,
+            twist: 0.02,
+            pivot_x: 0.5,
+            pivot_y: 0.5,
+            seed: 0,
+            scale: 48.0,
+            strength: 2.0,
+            history_duration: 0.0,
+            persistence_mode: PersistenceMode::Max,
+            persistence_alpha: 0.5,
+            delta_time_ms: 0.0,
+            processing_scale: 1.0,
+            boundary_mode: BoundaryMode::Clear,
+            diffusion: 0.0,
+            erode: 0,
+            dilate: 0,
+            adaptive_threshold: false,
+            adaptive_k: 3.0,
+            accumulation_mode: AccumulationMode::Off,
+            threshold_high: 0.0,
+            threshold_low: 0.0,
+            channel_mode: ChannelMode::Luma,
+            process_every_n: 1,
+            segments: 6,
+            segment_offset: 0.0,
+            shake_magnitude: 2.0,
+            diffuse_iterations: 4,
+            diffuse_bias: 0.0,
+            scene_change_threshold: 0.0,
+            scene_change_suppress: true,
+            scene_change_reset: true,
+            exposure_compensation: false,
+            stabilize: false,
+            suppress_self_feedback: false,
+            self_feedback_gain: 1.0,
+            temporal_filter: TemporalFilter::Off,
+            temporal_window: 3,
+            grid_mode: 0,
+            modulations: [None; MAX_MODULATIONS],
+        }
+    }
+}
+
+// Option groups of `is_option_group`: each field and the flat option it maps to
+const OPTION_GROUPS: &[(&str, &[(&str, &str)])] = &[
+    ("morphology", &[("erode", "erode"), ("dilate", "dilate")]),
+    (
+        "temporal_filter",
+        &[("type", "temporal_filter"), ("window", "temporal_window")],
+    ),
+];
+
+impl MotionOptions {
+    // The options behind `MotionDetectorBuilder::with_preset`
+    pub fn preset(name: &str) -> Option<MotionOptions> {
+        let defaults = MotionOptions::default();
+        match name {
+            // Short trails drifting slowly upwards, only for clear motion
+            "subtle" => Some(MotionOptions {
+                move_type: Some(MoveType::Direction),
+                angle_radians: -std::f32::consts::FRAC_PI_2,
+                speed: 1.0,
+                decay_rate: 0.9,
+                threshold: 35.0,
+                sensitivity: 0.8,
+                diffusion: 0.5,
+                ..defaults
+            }),
+            // Long, colorful trails spiralling out of the center
+            "psychedelic" => Some(MotionOptions {
+                move_type: Some(MoveType::Spiral),
+                speed: 2.0,
+                rotation_speed: 0.05,
+                decay_rate: 0.98,
+                threshold: 15.0,
+                sensitivity: 2.0,
+                output_mode: OutputMode::HueByAge,
+                diffusion: 1.0,
+                ..defaults
+            }),
+            // Trails in place on a noise-aware, despeckled heatmap with a max-hold summary
+            "surveillance" => Some(MotionOptions {
+                move_type: None,
+                decay_rate: 0.85,
+                adaptive_threshold: true,
+                erode: 1,
+                dilate: 1,
+                output_mode: OutputMode::Heatmap,
+                accumulation_mode: AccumulationMode::MaxHold,
+                ..defaults
+            }),
+            _ => None,
+        }
+    }
+
+    // These options with the per-frame rates scaled for frames `scale` reference frames long
+    fn scaled_in_time(&self, scale: f32) -> MotionOptions {
+        if scale == 1.0 {
+            return *self;
+        }
+        MotionOptions {
+            decay_rate: self.decay_rate.powf(scale),
+            speed: self.speed * scale,
+            rotation_speed: self.rotation_speed * scale,
+            phase_increment: self.phase_increment * scale,
+            zoom_factor: self.zoom_factor.powf(scale),
+            twist: self.twist * scale,
+            strength: self.strength * scale,
+            ..*self
+        }
+    }
+
+    // Drive a numeric option from modulation signal `signal` (see
+    // `MotionDetector::set_modulation`): every frame it becomes its set value (the base) plus
+    // `depth` times the signal. Modulating an option again replaces its route.
+    pub fn modulate(&mut self, key: &str, signal: usize, depth: f32) -> Result<(), String> {
+        let option = MODULATED_OPTIONS
+            .iter()
+            .position(|(name, _)| *name == key)
+            .ok_or_else(|| format!("option {} can't be modulated", key))?;
+        let slot = self
+            .modulations
+            .iter()
+            .position(|route| route.is_some_and(|route| route.option == option))
+            .or_else(|| self.modulations.iter().position(Option::is_none))
+            .ok_or_else(|| format!("at most {} options can be modulated", MAX_MODULATIONS))?;
+        self.modulations[slot] = Some(Modulation {
+            option,
+            signal,
+            depth,
+        });
+        Ok(())
+    }
+
+    // Start and hold thresholds when hysteresis is on
+    fn hysteresis(&self) -> Option<(f32, f32)> {
+        (self.threshold_high > 0.0 && !self.adaptive_threshold).then(|| {
+            (
+                self.threshold_high,
+                self.threshold_low.min(self.threshold_high),
+            )
+        })
+    }
+
+    // These options with every modulation route applied to `signals`; missing signals are 0
+    fn modulated(&self, signals: &[f32]) -> MotionOptions {
+        let mut options = *self;
+        for route in self.modulations.iter().flatten() {
+            let signal = signals.get(route.signal).copied().unwrap_or(0.0);
+            *(MODULATED_OPTIONS[route.option].1)(&mut options) += route.depth * signal;
+        }
+        options
+    }
+
+    // Set a single option by its JS name, for native front-ends (CLI config files, Python
+    // keyword arguments) that want unknown names and bad values reported instead of ignored
+    pub fn set_option(&mut self, key: &str, value: OptionValue) -> Result<(), String> {
+        let number = || match value {
+            OptionValue::Number(number) => Ok(number as f32),
+            _ => Err(format!("option {} expects a number", key)),
+        };
+        // A number that is neither NaN nor infinite, rejecting those before any clamping
+        let finite = || {
+            number().and_then(|number| {
+                if number.is_finite() {
+                    Ok(number)
                 } else {
-                    amplitude * 0.7 // Reduced amplitude for distant rows
-                };
-
-                let wave_offset = (y_f32 * frequency + self.phase).sin() * effective_amplitude;
-                let dest_row_base = y * width;
-
-                for x in 0..width {
-                    let pixel_index = dest_row_base + x;
-                    let source_x = (x as f32 - wave_offset).round() as i32;
-                    let source_y = y as i32;
-
-                    if source_x >= 0 && source_x < width_i32 {
-                        let source_index = (source_y as usize * width) + source_x as usize;
-                        self.temp_buffer[pixel_index] = self.persistence_buffer[source_index];
-                    }
-                    // Implicit else: temp_buffer[pixel_index] remains 0.0 from initialization
+                    Err(format!("option {} expects a finite number", key))
                 }
+            })
+        };
+        // Whole numbers from `minimum` to `maximum`, read from the f64 so that large ones
+        // like seeds keep every digit
+        let integer = |minimum: f64, maximum: f64| match value {
+            OptionValue::Number(number)
+                if number.fract() == 0.0 && (minimum..=maximum).contains(&number) =>
+            {
+                Ok(number)
             }
-        } else {
-            // Vertical wave - cache-friendly column processing with row-major access
-            for y in 0..height {
-                let dest_row_base = y * width;
-
-                for x in 0..width {
-                    let pixel_index = dest_row_base + x;
-                    let x_f32 = x as f32;
-                    let distance_from_center = self.polar_distance_lut[pixel_index];
-
-                    // Optimization #6: Apply different wave quality based on distance
-                    let effective_amplitude = if distance_from_center <= self.high_quality_radius {
-                        amplitude
-                    } else if distance_from_center <= self.medium_quality_radius {
-                        amplitude * 0.9
-                    } else {
-                        amplitude * 0.7 // Reduced amplitude for distant pixels
-                    };
-
-                    let wave_offset = (x_f32 * frequency + self.phase).sin() * effective_amplitude;
-                    let source_x = x as i32;
-                    let source_y = (y as f32 - wave_offset).round() as i32;
-
-                    if source_y >= 0 && source_y < height_i32 {
-                        let source_index = (source_y as usize * width) + source_x as usize;
-                        self.temp_buffer[pixel_index] = self.persistence_buffer[source_index];
-                    }
-                    // Implicit else: temp_buffer[pixel_index] remains 0.0 from initialization
+            _ => Err(format!(
+                "option {} expects an integer from {} to {}",
+                key, minimum, maximum
+            )),
+        };
+        let flag = || match value {
+            OptionValue::Bool(flag) => Ok(flag),
+            _ => Err(format!("option {} expects a boolean", key)),
+        };
+
+        let text = || match value {
+            OptionValue::Text(text) => Ok(text),
+            _ => Err(format!("option {} expects a string", key)),
+        };
+        let unknown = |name: &str| format!("unknown {}: {}", key, name);
+
+        match key {
+            "move_type" => {
+                let name = text()?;
+                self.move_type = Some(MoveType::parse(name).ok_or_else(|| unknown(name))?);
+            }
+            "decay_rate" => self.decay_rate = number()?,
+            "input_format" => {
+                let name = text()?;
+                self.input_format = Some(PixelFormat::parse(name).ok_or_else(|| unknown(name))?);
+            }
+            "threshold" => self.threshold = number()?,
+            "sensitivity" => self.sensitivity = number()?,
+            "angle_radians" => self.angle_radians = number()?,
+            "speed" => self.speed = number()?,
+            "rotation_speed" => self.rotation_speed = number()?,
+            "amplitude" => self.amplitude = number()?,
+            "frequency" => self.frequency = number()?,
+            "phase_increment" => self.phase_increment = number()?,
+            "direction" => self.direction = integer(0.0, 1.0)? as i32,
+            "flip_horizontal" => self.flip_horizontal = flag()?,
+            "flip_vertical" => self.flip_vertical = flag()?,
+            "input_transform" => {
+                let name = text()?;
+                (self.flip_horizontal, self.flip_vertical) = InputTransform::parse(name)
+                    .ok_or_else(|| unknown(name))?
+                    .flips();
+            }
+            "sampling_mode" => {
+                let name = text()?;
+                self.sampling_mode = SamplingMode::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "output_mode" => {
+                let name = text()?;
+                self.output_mode = OutputMode::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "tint_color" => self.tint_color = integer(0.0, 0xffffff as f64)? as u32,
+            "silhouette_smoothing" => self.silhouette_smoothing = number()?.clamp(0.0, 0.99),
+            "fluid_cell_size" => {
+                self.fluid_cell_size = (number()? as u32).clamp(2, MAX_FLUID_CELL_SIZE)
+            }
+            "fluid_viscosity" => self.fluid_viscosity = number()?.clamp(0.0, 1.0),
+            "fluid_dissipation" => self.fluid_dissipation = number()?.clamp(0.0, 1.0),
+            "fluid_force" => self.fluid_force = number()?.clamp(0.0, 10.0),
+            "dwell_max_ms" => self.dwell_max_ms = number()?.clamp(100.0, 600_000.0),
+            "dwell_cooling" => self.dwell_cooling = number()?.clamp(0.0, 100.0),
+            "composite_mode" => {
+                let name = text()?;
+                // `alpha_blend(0.3)` sets the alpha along with the mode
+                if let Some(alpha) = name
+                    .strip_prefix("alpha_blend(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                {
+                    self.composite_alpha = alpha.trim().parse().map_err(|_| unknown(name))?;
+                    self.composite_mode = CompositeMode::AlphaBlend;
+                } else {
+                    self.composite_mode =
+                        CompositeMode::parse(name).ok_or_else(|| unknown(name))?;
+                }
+            }
+            "composite_alpha" => self.composite_alpha = number()?,
+            "alpha_mode" => {
+                let name = text()?;
+                self.alpha_mode = AlphaMode::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "gamma" => self.gamma = number()?,
+            "contrast" => self.contrast = number()?,
+            "posterize_levels" => self.posterize_levels = number()?.max(0.0) as u32,
+            "output_vignette_strength" => self.output_vignette_strength = number()?,
+            "output_vignette_radius" => self.output_vignette_radius = number()?,
+            "output_vignette_softness" => self.output_vignette_softness = number()?,
+            "detection_mode" => {
+                let name = text()?;
+                self.detection_mode = DetectionMode::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "learning_rate" => self.learning_rate = number()?,
+            "zoom_factor" => self.zoom_factor = number()?,
+            "focal_x" => self.focal_x = number()?,
+            "focal_y" => self.focal_y = number()?,
+            "twist" => self.twist = number()?,
+            "pivot_x" => self.pivot_x = number()?,
+            "pivot_y" => self.pivot_y = number()?,
+            "seed" => self.seed = integer(0.0, u32::MAX as f64)? as u32,
+            "scale" => self.scale = number()?,
+            "strength" => self.strength = number()?,
+            "history_duration" => self.history_duration = number()?,
+            "persistence_mode" => {
+                let name = text()?;
+                // `lerp(0.2)` sets the alpha along with the mode
+                if let Some(alpha) = name
+                    .strip_prefix("lerp(")
+                    .and_then(|rest| rest.strip_suffix(')'))
+                {
+                    let alpha: f32 = alpha.trim().parse().map_err(|_| unknown(name))?;
+                    self.persistence_alpha = alpha.clamp(0.0, 1.0);
+                    self.persistence_mode = PersistenceMode::Lerp;
+                } else {
+                    self.persistence_mode =
+                        PersistenceMode::parse(name).ok_or_else(|| unknown(name))?;
+                }
+            }
+            "persistence_alpha" => self.persistence_alpha = number()?.clamp(0.0, 1.0),
+            "delta_time_ms" => self.delta_time_ms = number()?,
+            "processing_scale" => self.processing_scale = finite()?.clamp(0.125, 1.0),
+            "diffusion" => self.diffusion = finite()?.clamp(0.0, MAX_DIFFUSION),
+            "erode" => {
+                self.erode = finite()?.clamp(0.0, MAX_MORPHOLOGY_ITERATIONS as f32) as u32;
+            }
+            "dilate" => {
+                self.dilate = finite()?.clamp(0.0, MAX_MORPHOLOGY_ITERATIONS as f32) as u32;
+            }
+            "adaptive_threshold" => self.adaptive_threshold = flag()?,
+            "adaptive_k" => self.adaptive_k = number()?,
+            "boundary_mode" => {
+                let name = text()?;
+                self.boundary_mode = BoundaryMode::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "threshold_high" => self.threshold_high = number()?,
+            "threshold_low" => self.threshold_low = number()?,
+            "process_every_n" => {
+                self.process_every_n = finite()?.clamp(1.0, MAX_PROCESS_EVERY_N as f32) as u32;
+            }
+            "grid_mode" => {
+                // A whole sample spacing, not a level to clamp
+                let step = number()?;
+                if step.fract() != 0.0 || !(0.0..=MAX_GRID_STEP as f32).contains(&step) {
+                    return Err(unknown(&step.to_string()));
                 }
+                self.grid_mode = step as u32;
             }
+            "segments" => self.segments = number()?.max(1.0) as u32,
+            "segment_offset" => self.segment_offset = number()?,
+            "shake_magnitude" => self.shake_magnitude = number()?,
+            "diffuse_iterations" => {
+                self.diffuse_iterations =
+                    number()?.clamp(0.0, MAX_DIFFUSE_ITERATIONS as f32) as u32;
+            }
+            "diffuse_bias" => self.diffuse_bias = number()?.clamp(0.0, 1.0),
+            "scene_change_threshold" => self.scene_change_threshold = number()?,
+            "scene_change_suppress" => self.scene_change_suppress = flag()?,
+            "scene_change_reset" => self.scene_change_reset = flag()?,
+            "exposure_compensation" => self.exposure_compensation = flag()?,
+            "stabilize" => self.stabilize = flag()?,
+            "suppress_self_feedback" => self.suppress_self_feedback = flag()?,
+            "self_feedback_gain" => self.self_feedback_gain = number()?.clamp(0.0, 4.0),
+            "channel_mode" => {
+                let name = text()?;
+                self.channel_mode = ChannelMode::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "auto_threshold" => {
+                let name = text()?;
+                self.auto_threshold = AutoThreshold::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "temporal_filter" => {
+                let name = text()?;
+                self.temporal_filter = TemporalFilter::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            "temporal_window" => {
+                self.temporal_window = number()?.clamp(2.0, MAX_TEMPORAL_WINDOW as f32) as u32;
+            }
+            "accumulation_mode" => {
+                let name = text()?;
+                self.accumulation_mode =
+                    AccumulationMode::parse(name).ok_or_else(|| unknown(name))?;
+            }
+            _ => return Err(format!("unknown option: {}", key)),
         }
+        Ok(())
+    }
+
+    // `morphology: {erode, dilate}` and `temporal_filter: {type, window}` group related
+    // options for the JS and CLI front-ends; whether `key` names such a group
+    pub fn is_option_group(key: &str) -> bool {
+        OPTION_GROUPS.iter().any(|(group, _)| *group == key)
+    }
+
+    // The flat option that `field` of the option group `group` stands for
+    pub fn grouped_option(group: &str, field: &str) -> Result<&'static str, String> {
+        OPTION_GROUPS
+            .iter()
+            .filter(|(name, _)| *name == group)
+            .flat_map(|(_, fields)| fields.iter())
+            .find(|(name, _)| *name == field)
+            .map(|&(_, option)| option)
+            .ok_or_else(|| format!("unknown option: {}.{}", group, field))
     }
 
-    #[wasm_bindgen]
-    pub fn reset_persistence(&mut self) {
-        for val in &mut self.persistence_buffer {
-            *val = 0.0;
+    // Native counterpart of `apply_config_json`
+    pub fn try_apply_config_json(&mut self, json: &str) -> Result<(), String> {
+        let config: serde_json::Value =
+            serde_json::from_str(json).map_err(|error| format!("invalid config: {}", error))?;
+        let serde_json::Value::Object(entries) = config else {
+            return Err("config must be a JSON object".to_string());
+        };
+        let mut options = *self;
+        for (key, value) in &entries {
+            let value = match value {
+                serde_json::Value::Null if key == "move_type" => {
+                    options.move_type = None;
+                    continue;
+                }
+                serde_json::Value::Null if key == "input_format" => {
+                    options.input_format = None;
+                    continue;
+                }
+                serde_json::Value::String(text) => OptionValue::Text(text),
+                serde_json::Value::Bool(flag) => OptionValue::Bool(*flag),
+                serde_json::Value::Number(number) => {
+                    OptionValue::Number(number.as_f64().unwrap_or_default())
+                }
+                _ => {
+                    return Err(format!(
+                        "option {} must be a number, string or boolean",
+                        key
+                    ))
+                }
+            };
+            options.set_option(key, value)?;
         }
+        if entries.contains_key("input_transform") {
+            for (key, flip) in [
+                ("flip_horizontal", options.flip_horizontal),
+                ("flip_vertical", options.flip_vertical),
+            ] {
+                if entries
+                    .get(key)
+                    .is_some_and(|value| value.as_bool() != Some(flip))
+                {
+                    return Err(format!(
+                        "{} conflicts with input_transform {}",
+                        key,
+                        options.input_transform().name()
+                    ));
+                }
+            }
+        }
+        *self = options;
+        Ok(())
+    }
+}
+
+// JSON schema of the per-frame options accepted by the `process_*` methods, for host apps
+// that generate tweak panels or validate presets against this build. Defaults come from
+// `MotionOptions::default`; `modes` lists the move types an option affects (all if absent),
+// and `output_modes` and `composite_modes` likewise the output and composite modes.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn describe_parameters() -> String {
+    use serde_json::json;
+    use std::f32::consts::PI;
+
+    let defaults = MotionOptions::default();
+    let number = |name: &str, min: f32, max: f32, default: f32, units: &str| {
+        json!({
+            "name": name,
+            "type": "number",
+            "minimum": min,
+            "maximum": max,
+            "default": default,
+            "units": units,
+        })
+    };
+    let for_modes = |mut parameter: serde_json::Value, modes: &[&str]| {
+        parameter["modes"] = json!(modes);
+        parameter
+    };
+    let flag =
+        |name: &str, default: bool| json!({ "name": name, "type": "boolean", "default": default });
+
+    let parameters = vec![
+        json!({
+            "name": "move_type",
+            "type": "string",
+            "enum": [
+                "direction",
+                "radial",
+                "spiral",
+                "wave",
+                "zoom",
+                "rotate",
+                "turbulence",
+                "flow",
+                "kaleidoscope",
+                "shake",
+                "attract",
+                "perspective",
+                "smear",
+                "feedback",
+                "diffuse"
+            ],
+            "default": defaults.move_type.map_or("direction", MoveType::name),
+        }),
+        number(
+            "decay_rate",
+            0.0,
+            1.0,
+            defaults.decay_rate,
+            "factor per frame",
+        ),
+        number("threshold", 0.0, 255.0, defaults.threshold, "gray levels"),
+        number("sensitivity", 0.0, 10.0, defaults.sensitivity, "gain"),
+        for_modes(
+            number(
+                "angle_radians",
+                0.0,
+                2.0 * PI,
+                defaults.angle_radians,
+                "radians",
+            ),
+            &["direction", "smear", "diffuse"],
+        ),
+        for_modes(
+            number("speed", -30.0, 100.0, defaults.speed, "pixels per frame"),
+            &["direction", "radial", "spiral"],
+        ),
+        for_modes(
+            number(
+                "rotation_speed",
+                -PI,
+                PI,
+                defaults.rotation_speed,
+                "radians per frame",
+            ),
+            &["spiral", "rotate"],
+        ),
+        for_modes(
+            number("amplitude", 0.0, 500.0, defaults.amplitude, "pixels"),
+            &["wave"],
+        ),
+        for_modes(
+            number(
+                "frequency",
+                0.001,
+                2.0,
+                defaults.frequency,
+                "radians per pixel",
+            ),
+            &["wave"],
+        ),
+        for_modes(
+            number(
+                "phase_increment",
+                0.0,
+                2.0 * PI,
+                defaults.phase_increment,
+                "radians per frame",
+            ),
+            &["wave", "turbulence"],
+        ),
+        for_modes(
+            json!({
+                "name": "direction",
+                "type": "integer",
+                "enum": [0, 1],
+                "default": defaults.direction,
+                "description": "0 = horizontal, 1 = vertical",
+            }),
+            &["wave"],
+        ),
+        flag("flip_horizontal", defaults.flip_horizontal),
+        flag("flip_vertical", defaults.flip_vertical),
+        json!({
+            "name": "input_transform",
+            "type": "string",
+            "enum": ["none", "mirror_x", "mirror_y", "rotate_180"],
+            "default": defaults.input_transform().name(),
+        }),
+        json!({
+            "name": "sampling_mode",
+            "type": "string",
+            "enum": ["nearest", "bilinear"],
+            "default": defaults.sampling_mode.name(),
+        }),
+        json!({
+            "name": "boundary_mode",
+            "type": "string",
+            "enum": ["clear", "wrap", "clamp", "mirror"],
+            "default": defaults.boundary_mode.name(),
+        }),
+        json!({
+            "name": "output_mode",
+            "type": "string",
+            "enum": [
+                "grayscale",
+                "heatmap",
+                "hue_by_age",
+                "tint",
+                "direction",
+                "silhouette",
+                "fluid",
+                "dwell",
+            ],
+            "default": defaults.output_mode.name(),
+        }),
+        json!({
+            "name": "tint_color",
+            "type": "integer",
+            "minimum": 0,
+            "maximum": 0xffffff,
+            "default": defaults.tint_color,
+            "units": "0xRRGGBB",
+            "output_modes": ["tint", "silhouette", "fluid"],
+        }),
+        json!({
+            "name": "silhouette_smoothing",
+            "type": "number",
+            "minimum": 0.0,
+            "maximum": 0.99,
+            "default": defaults.silhouette_smoothing,
+            "output_modes": ["silhouette"],
+        }),
+        json!({
+            "name": "fluid_cell_size",
+            "type": "integer",
+            "minimum": 2,
+            "maximum": MAX_FLUID_CELL_SIZE,
+            "default": defaults.fluid_cell_size,
+            "units": "pixels",
+            "output_modes": ["fluid"],
+        }),
+        json!({
+            "name": "fluid_viscosity",
+            "type": "number",
+            "minimum": 0.0,
+            "maximum": 1.0,
+            "default": defaults.fluid_viscosity,
+            "output_modes": ["fluid"],
+        }),
+        json!({
+            "name": "fluid_dissipation",
+            "type": "number",
+            "minimum": 0.0,
+            "maximum": 1.0,
+            "default": defaults.fluid_dissipation,
+            "output_modes": ["fluid"],
+        }),
+        json!({
+            "name": "fluid_force",
+            "type": "number",
+            "minimum": 0.0,
+            "maximum": 10.0,
+            "default": defaults.fluid_force,
+            "output_modes": ["fluid"],
+        }),
+        json!({
+            "name": "dwell_max_ms",
+            "type": "number",
+            "minimum": 100.0,
+            "maximum": 600_000.0,
+            "default": defaults.dwell_max_ms,
+            "units": "ms",
+            "output_modes": ["dwell"],
+        }),
+        json!({
+            "name": "dwell_cooling",
+            "type": "number",
+            "minimum": 0.0,
+            "maximum": 100.0,
+            "default": defaults.dwell_cooling,
+            "output_modes": ["dwell"],
+        }),
+        json!({
+            "name": "input_format",
+            "type": "string",
+            "enum": ["rgba", "bgra", "rgb", "rgbx", "gray"],
+            "default": defaults.input_format.unwrap_or(PixelFormat::Rgba).name(),
+        }),
+        json!({
+            "name": "composite_mode",
+            "type": "string",
+            "enum": ["replace", "overlay_add", "overlay_screen", "alpha_blend"],
+            "default": defaults.composite_mode.name(),
+        }),
+        json!({
+            "name": "composite_alpha",
+            "type": "number",
+            "minimum": 0.0,
+            "maximum": 1.0,
+            "default": defaults.composite_alpha,
+            "units": "weight of the trails",
+            "composite_modes": ["alpha_blend"],
+        }),
+        json!({
+            "name": "alpha_mode",
+            "type": "string",
+            "enum": ["opaque", "freshness", "confidence"],
+            "default": defaults.alpha_mode.name(),
+        }),
+        number("gamma", 0.1, 5.0, defaults.gamma, "exponent"),
+        number("contrast", 0.0, 4.0, defaults.contrast, "gain"),
+        json!({
+            "name": "posterize_levels",
+            "type": "integer",
+            "minimum": 0,
+            "maximum": 255,
+            "default": defaults.posterize_levels,
+            "description": "0 = off",
+        }),
+        number(
+            "output_vignette_strength",
+            0.0,
+            1.0,
+            defaults.output_vignette_strength,
+            "darkening at the edges",
+        ),
+        number(
+            "output_vignette_radius",
+            0.0,
+            1.0,
+            defaults.output_vignette_radius,
+            "fraction of the half diagonal",
+        ),
+        number(
+            "output_vignette_softness",
+            0.0,
+            1.0,
+            defaults.output_vignette_softness,
+            "fraction of the half diagonal",
+        ),
+        json!({
+            "name": "detection_mode",
+            "type": "string",
+            "enum": ["frame_diff", "background_sub"],
+            "default": defaults.detection_mode.name(),
+        }),
+        number(
+            "learning_rate",
+            0.0,
+            1.0,
+            defaults.learning_rate,
+            "weight per frame",
+        ),
+        for_modes(
+            number(
+                "zoom_factor",
+                0.5,
+                2.0,
+                defaults.zoom_factor,
+                "scale per frame",
+            ),
+            &["zoom", "feedback"],
+        ),
+        for_modes(
+            number("focal_x", 0.0, 1.0, defaults.focal_x, "fraction of width"),
+            &["zoom", "feedback"],
+        ),
+        for_modes(
+            number("focal_y", 0.0, 1.0, defaults.focal_y, "fraction of height"),
+            &["zoom", "feedback"],
+        ),
+        for_modes(
+            number("twist", -PI, PI, defaults.twist, "radians per frame"),
+            &["feedback"],
+        ),
+        for_modes(
+            number("pivot_x", 0.0, 1.0, defaults.pivot_x, "fraction of width"),
+            &["rotate"],
+        ),
+        for_modes(
+            number("pivot_y", 0.0, 1.0, defaults.pivot_y, "fraction of height"),
+            &["rotate"],
+        ),
+        for_modes(
+            json!({
+                "name": "seed",
+                "type": "integer",
+                "minimum": 0,
+                "maximum": u32::MAX,
+                "default": defaults.seed,
+            }),
+            &["turbulence", "shake"],
+        ),
+        for_modes(
+            number("scale", 4.0, 512.0, defaults.scale, "pixels"),
+            &["turbulence"],
+        ),
+        for_modes(
+            number("strength", 0.0, 20.0, defaults.strength, "pixels per frame"),
+            &["turbulence", "smear"],
+        ),
+        for_modes(
+            json!({
+                "name": "segments",
+                "type": "integer",
+                "minimum": 1,
+                "maximum": 32,
+                "default": defaults.segments,
+            }),
+            &["kaleidoscope"],
+        ),
+        for_modes(
+            number(
+                "segment_offset",
+                0.0,
+                2.0 * PI,
+                defaults.segment_offset,
+                "radians",
+            ),
+            &["kaleidoscope"],
+        ),
+        for_modes(
+            number(
+                "shake_magnitude",
+                0.0,
+                20.0,
+                defaults.shake_magnitude,
+                "pixels",
+            ),
+            &["shake"],
+        ),
+        for_modes(
+            json!({
+                "name": "diffuse_iterations",
+                "type": "integer",
+                "minimum": 0,
+                "maximum": MAX_DIFFUSE_ITERATIONS,
+                "default": defaults.diffuse_iterations,
+            }),
+            &["diffuse"],
+        ),
+        for_modes(
+            number("diffuse_bias", 0.0, 1.0, defaults.diffuse_bias, "fraction"),
+            &["diffuse"],
+        ),
+        number(
+            "history_duration",
+            0.0,
+            10000.0,
+            defaults.history_duration,
+            "milliseconds",
+        ),
+        json!({
+            "name": "persistence_mode",
+            "type": "string",
+            "enum": ["max", "additive", "lerp", "screen"],
+            "default": defaults.persistence_mode.name(),
+        }),
+        json!({
+            "name": "persistence_alpha",
+            "type": "number",
+            "minimum": 0.0,
+            "maximum": 1.0,
+            "default": defaults.persistence_alpha,
+            "units": "weight of new motion",
+            "persistence_modes": ["lerp"],
+        }),
+        number(
+            "delta_time_ms",
+            0.0,
+            1000.0,
+            defaults.delta_time_ms,
+            "milliseconds",
+        ),
+        number(
+            "processing_scale",
+            0.125,
+            1.0,
+            defaults.processing_scale,
+            "fraction of the frame",
+        ),
+        number(
+            "diffusion",
+            0.0,
+            MAX_DIFFUSION,
+            defaults.diffusion,
+            "pixels",
+        ),
+        number(
+            "erode",
+            0.0,
+            MAX_MORPHOLOGY_ITERATIONS as f32,
+            defaults.erode as f32,
+            "iterations",
+        ),
+        number(
+            "dilate",
+            0.0,
+            MAX_MORPHOLOGY_ITERATIONS as f32,
+            defaults.dilate as f32,
+            "iterations",
+        ),
+        flag("adaptive_threshold", defaults.adaptive_threshold),
+        number(
+            "adaptive_k",
+            1.0,
+            10.0,
+            defaults.adaptive_k,
+            "standard deviations",
+        ),
+        number(
+            "threshold_high",
+            0.0,
+            255.0,
+            defaults.threshold_high,
+            "gray levels",
+        ),
+        number(
+            "threshold_low",
+            0.0,
+            255.0,
+            defaults.threshold_low,
+            "gray levels",
+        ),
+        number(
+            "process_every_n",
+            1.0,
+            MAX_PROCESS_EVERY_N as f32,
+            defaults.process_every_n as f32,
+            "frames",
+        ),
+        number(
+            "grid_mode",
+            0.0,
+            MAX_GRID_STEP as f32,
+            defaults.grid_mode as f32,
+            "pixels",
+        ),
+        number(
+            "scene_change_threshold",
+            0.0,
+            1.0,
+            defaults.scene_change_threshold,
+            "fraction of the frame",
+        ),
+        flag("scene_change_suppress", defaults.scene_change_suppress),
+        flag("scene_change_reset", defaults.scene_change_reset),
+        flag("exposure_compensation", defaults.exposure_compensation),
+        flag("stabilize", defaults.stabilize),
+        flag("suppress_self_feedback", defaults.suppress_self_feedback),
+        number(
+            "self_feedback_gain",
+            0.0,
+            4.0,
+            defaults.self_feedback_gain,
+            "camera level per output level",
+        ),
+        json!({
+            "name": "channel_mode",
+            "type": "string",
+            "enum": ["luma", "max", "red", "green", "blue", "per_channel"],
+            "default": defaults.channel_mode.name(),
+        }),
+        json!({
+            "name": "auto_threshold",
+            "type": "string",
+            "enum": ["off", "otsu"],
+            "default": defaults.auto_threshold.name(),
+        }),
+        json!({
+            "name": "temporal_filter",
+            "type": "string",
+            "enum": ["off", "median", "min"],
+            "default": defaults.temporal_filter.name(),
+        }),
+        number(
+            "temporal_window",
+            2.0,
+            MAX_TEMPORAL_WINDOW as f32,
+            defaults.temporal_window as f32,
+            "frames",
+        ),
+        json!({
+            "name": "accumulation_mode",
+            "type": "string",
+            "enum": ["off", "max_hold", "average"],
+            "default": defaults.accumulation_mode.name(),
+        }),
+    ];
+
+    json!({ "version": env!("CARGO_PKG_VERSION"), "parameters": parameters }).to_string()
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MotionOptions {
+    // Defaults, as used by the web app before any tweaking
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> MotionOptions {
+        MotionOptions::default()
     }
 
-    #[wasm_bindgen]
-    pub fn reset_all_state(&mut self) {
-        // Reset persistence buffer
-        for val in &mut self.persistence_buffer {
-            *val = 0.0;
+    // Build options from a plain object such as a saved preset. Unlike assigning properties,
+    // unknown names and wrongly typed values are reported as errors.
+    #[cfg(feature = "wasm")]
+    #[wasm_bindgen(js_name = fromObject)]
+    pub fn from_object(object: &JsValue) -> Result<MotionOptions, ExportError> {
+        let mut options = MotionOptions::default();
+        let entries = js_sys::Object::entries(object.unchecked_ref());
+        for entry in entries.iter() {
+            let entry: js_sys::Array = entry.unchecked_into();
+            let key = entry.get(0).as_string().unwrap_or_default();
+            options.set_js_option(&key, &entry.get(1))?;
         }
+        Ok(options)
+    }
 
-        // Reset temp buffer
-        self.temp_buffer.clear();
+    #[cfg(feature = "wasm")]
+    fn set_js_option(&mut self, key: &str, value: &JsValue) -> Result<(), ExportError> {
+        // Grouped options, see `is_option_group`
+        if MotionOptions::is_option_group(key) && value.is_object() {
+            for entry in js_sys::Object::entries(value.unchecked_ref()).iter() {
+                let entry: js_sys::Array = entry.unchecked_into();
+                let field = entry.get(0).as_string().unwrap_or_default();
+                let option = MotionOptions::grouped_option(key, &field).map_err(js_error)?;
+                self.set_js_option(option, &entry.get(1))?;
+            }
+            return Ok(());
+        }
+        // `{mod, base, depth}` drives a numeric option from a modulation signal
+        if value.is_object() {
+            let field = |name: &str| {
+                js_sys::Reflect::get(value, &name.into())
+                    .ok()
+                    .and_then(|value| value.as_f64())
+            };
+            let signal = field("mod")
+                .ok_or_else(|| js_error(format!("option {} needs a mod signal index", key)))?;
+            if let Some(base) = field("base") {
+                self.set_option(key, OptionValue::Number(base))
+                    .map_err(js_error)?;
+            }
+            let depth = field("depth").unwrap_or(1.0) as f32;
+            return self.modulate(key, signal as usize, depth).map_err(js_error);
+        }
+        let text = value.as_string();
+        let value = if let Some(text) = &text {
+            OptionValue::Text(text)
+        } else if let Some(flag) = value.as_bool() {
+            OptionValue::Bool(flag)
+        } else if let Some(number) = value.as_f64() {
+            OptionValue::Number(number)
+        } else {
+            return Err(js_error(format!(
+                "option {} must be a number, string or boolean",
+                key
+            )));
+        };
+        self.set_option(key, value).map_err(js_error)
+    }
 
-        // Reset previous frame cache
-        self.previous_frame_cache.clear();
+    // Every option as a JSON object of `set_option` names and values, for saving presets
+    // and undo / redo; modulations are left out
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn get_config_json(&self) -> String {
+        use serde_json::json;
+
+        let entries = [
+            ("move_type", json!(self.move_type.map(MoveType::name))),
+            ("decay_rate", json!(self.decay_rate)),
+            (
+                "input_format",
+                json!(self.input_format.map(PixelFormat::name)),
+            ),
+            ("threshold", json!(self.threshold)),
+            ("auto_threshold", json!(self.auto_threshold.name())),
+            ("sensitivity", json!(self.sensitivity)),
+            ("angle_radians", json!(self.angle_radians)),
+            ("speed", json!(self.speed)),
+            ("rotation_speed", json!(self.rotation_speed)),
+            ("amplitude", json!(self.amplitude)),
+            ("frequency", json!(self.frequency)),
+            ("phase_increment", json!(self.phase_increment)),
+            ("direction", json!(self.direction)),
+            ("flip_horizontal", json!(self.flip_horizontal)),
+            ("flip_vertical", json!(self.flip_vertical)),
+            ("input_transform", json!(self.input_transform().name())),
+            ("sampling_mode", json!(self.sampling_mode.name())),
+            ("output_mode", json!(self.output_mode.name())),
+            ("tint_color", json!(self.tint_color)),
+            ("silhouette_smoothing", json!(self.silhouette_smoothing)),
+            ("fluid_cell_size", json!(self.fluid_cell_size)),
+            ("fluid_viscosity", json!(self.fluid_viscosity)),
+            ("fluid_dissipation", json!(self.fluid_dissipation)),
+            ("fluid_force", json!(self.fluid_force)),
+            ("dwell_max_ms", json!(self.dwell_max_ms)),
+            ("dwell_cooling", json!(self.dwell_cooling)),
+            ("composite_mode", json!(self.composite_mode.name())),
+            ("composite_alpha", json!(self.composite_alpha)),
+            ("alpha_mode", json!(self.alpha_mode.name())),
+            ("gamma", json!(self.gamma)),
+            ("contrast", json!(self.contrast)),
+            ("posterize_levels", json!(self.posterize_levels)),
+            (
+                "output_vignette_strength",
+                json!(self.output_vignette_strength),
+            ),
+            ("output_vignette_radius", json!(self.output_vignette_radius)),
+            (
+                "output_vignette_softness",
+                json!(self.output_vignette_softness),
+            ),
+            ("detection_mode", json!(self.detection_mode.name())),
+            ("learning_rate", json!(self.learning_rate)),
+            ("zoom_factor", json!(self.zoom_factor)),
+            ("focal_x", json!(self.focal_x)),
+            ("focal_y", json!(self.focal_y)),
+            ("twist", json!(self.twist)),
+            ("pivot_x", json!(self.pivot_x)),
+            ("pivot_y", json!(self.pivot_y)),
+            ("seed", json!(self.seed)),
+            ("scale", json!(self.scale)),
+            ("strength", json!(self.strength)),
+            ("history_duration", json!(self.history_duration)),
+            ("persistence_mode", json!(self.persistence_mode.name())),
+            ("persistence_alpha", json!(self.persistence_alpha)),
+            ("delta_time_ms", json!(self.delta_time_ms)),
+            ("processing_scale", json!(self.processing_scale)),
+            ("boundary_mode", json!(self.boundary_mode.name())),
+            ("diffusion", json!(self.diffusion)),
+            ("erode", json!(self.erode)),
+            ("dilate", json!(self.dilate)),
+            ("adaptive_threshold", json!(self.adaptive_threshold)),
+            ("adaptive_k", json!(self.adaptive_k)),
+            ("accumulation_mode", json!(self.accumulation_mode.name())),
+            ("threshold_high", json!(self.threshold_high)),
+            ("threshold_low", json!(self.threshold_low)),
+            ("channel_mode", json!(self.channel_mode.name())),
+            ("process_every_n", json!(self.process_every_n)),
+            ("segments", json!(self.segments)),
+            ("segment_offset", json!(self.segment_offset)),
+            ("shake_magnitude", json!(self.shake_magnitude)),
+            ("diffuse_iterations", json!(self.diffuse_iterations)),
+            ("diffuse_bias", json!(self.diffuse_bias)),
+            ("scene_change_threshold", json!(self.scene_change_threshold)),
+            ("scene_change_suppress", json!(self.scene_change_suppress)),
+            ("scene_change_reset", json!(self.scene_change_reset)),
+            ("exposure_compensation", json!(self.exposure_compensation)),
+            ("stabilize", json!(self.stabilize)),
+            ("suppress_self_feedback", json!(self.suppress_self_feedback)),
+            ("self_feedback_gain", json!(self.self_feedback_gain)),
+            ("temporal_filter", json!(self.temporal_filter.name())),
+            ("temporal_window", json!(self.temporal_window)),
+            ("grid_mode", json!(self.grid_mode)),
+        ];
+        let config: serde_json::Map<String, serde_json::Value> = entries
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        serde_json::Value::Object(config).to_string()
+    }
 
-        // Reset first frame flag
-        self.is_first_frame = true;
+    // Set the options of a JSON object like the one of `get_config_json`; options it leaves
+    // out keep their values, and nothing changes if any entry is invalid. Null clears
+    // `move_type` and `input_format`. `input_transform` and the flips it stands for must
+    // agree when both are given, so editing one in a saved config can't be silently undone
+    // by the other.
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn apply_config_json(&mut self, json: &str) -> Result<(), ExportError> {
+        self.try_apply_config_json(json).map_err(js_error)
+    }
 
-        // Reset phase for wave animations
-        self.phase = 0.0;
+    // The flips as one of the `input_transform` names
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn input_transform(&self) -> InputTransform {
+        InputTransform::from_flips(self.flip_horizontal, self.flip_vertical)
     }
 
-    #[wasm_bindgen]
-    pub fn get_buffer_size(&self) -> usize {
-        self.persistence_buffer.len()
+    // Independent copy, e.g. for `process_async`, which takes ownership of its options
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn copy(&self) -> MotionOptions {
+        *self
+    }
+}
+
+pub enum OptionValue<'a> {
+    Number(f64),
+    Text(&'a str),
+    Bool(bool),
+}
+
+// What `record_frames` keeps of each frame
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordingSource {
+    // The output frame as written, in the output format at frame resolution
+    Output,
+    // The trails, one byte (0..255) per processing pixel
+    Persistence,
+}
+
+// How `render_comparison` splits the frame
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ComparisonLayout {
+    // Difference on the left half, trails on the right
+    SideBySide,
+    // Difference on the top half, trails on the bottom
+    TopBottom,
+}
+
+// Processing level chosen by automatic power saving (see `set_power_saving`)
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerTier {
+    // Every frame at full resolution
+    Full,
+    // Detection on a 2x2 grid, every frame
+    Reduced,
+    // Detection on a 2x2 grid, every `POWER_IDLE_INTERVAL` frames; trails only fade between
+    Idle,
+}
+
+// Background estimate used by the `background_sub` detection mode
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundModel {
+    // Exponential running average with the `learning_rate` option, so slow lighting
+    // changes are absorbed instead of flashing the whole frame
+    RunningAverage,
+    // Streaming approximation of the per-pixel median of the last N frames, so brief
+    // occlusions don't become part of the background
+    TemporalMedian,
+}
+
+// How detection sensitivity falls off from the center towards the edges, see
+// `set_radial_profile`. With `d` the distance from the center (0..1) and `curve` =
+// d^exponent (or 1 - d^exponent when inverted), each pixel's diff is weighted by
+// max(1 - curve * falloff, min_sensitivity) and its threshold raised by curve * threshold_rise.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RadialProfile {
+    pub falloff: f32,
+    pub min_sensitivity: f32,
+    pub exponent: f32,
+    // Emphasize the edges instead of the center
+    pub inverted: bool,
+    pub threshold_rise: f32,
+}
+
+impl Default for RadialProfile {
+    fn default() -> RadialProfile {
+        RadialProfile {
+            falloff: 0.9,
+            min_sensitivity: 0.1,
+            exponent: 1.0,
+            inverted: false,
+            threshold_rise: 40.0,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl RadialProfile {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> RadialProfile {
+        RadialProfile::default()
+    }
+
+    // The same sensitivity and threshold everywhere, e.g. with the output vignette taking
+    // over the look of the falloff
+    pub fn flat() -> RadialProfile {
+        RadialProfile {
+            falloff: 0.0,
+            threshold_rise: 0.0,
+            ..RadialProfile::default()
+        }
     }
 }
+
+impl RadialProfile {
+    // Profile curve at normalized distance `distance` from the center
+    fn curve(&self, distance: f32) -> f32 {
+        let curve = distance.min(1.0).powf(self.exponent);
+        if self.inverted {
+            1.0 - curve
+        } else {
+            curve
+        }
+    }
+}
+
+// Motion summary of the last analysed frame, see `get_motion_stats`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MotionStats {
+    // Sum of the thresholded, amplified difference (0..255 per pixel)
+    pub energy: f32,
+    // Share of the analysed pixels above the threshold, 0..100
+    pub active_percent: f32,
+    // Energy-weighted center of the motion in pixels; undefined without motion
+    pub centroid_x: Option<f32>,
+    pub centroid_y: Option<f32>,
+}
+
+// Camera motion between two frames, see `estimate_global_motion`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GlobalMotion {
+    // Shift of the picture in pixels (positive right and down)
+    pub dx: f32,
+    pub dy: f32,
+    // Turn around the center of the active area in radians, clockwise on screen
+    pub rotation: f32,
+    // How clearly the frames line up at this shift (0..1); low for flat scenes or when
+    // subjects rather than the camera move
+    pub confidence: f32,
+}
+
+// Trails summed over each column and each row, see `get_motion_projections`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MotionProjections {
+    columns: Vec<f32>,
+    rows: Vec<f32>,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MotionProjections {
+    // One sum per column, left to right (`persistence_width` values)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn columns(&self) -> Vec<f32> {
+        self.columns.clone()
+    }
+
+    // One sum per row, top to bottom (`persistence_height` values)
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn rows(&self) -> Vec<f32> {
+        self.rows.clone()
+    }
+}
+
+// A connected region of moving pixels, see `detect_blobs`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug)]
+pub struct Blob {
+    // Bounding box in pixels
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    // Moving pixels in the region and their mean position
+    pub area: u32,
+    pub centroid_x: f32,
+    pub centroid_y: f32,
+}
+
+// Output of `process_image_data` / `process_image_bitmap`. The pixels live in their own
+// `ArrayBuffer` outside WASM memory; with RGBA output the page can wrap them with
+// `new ImageData(pixels, width, height)`.
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+pub struct WorkerFrame {
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    stats: MotionStats,
+    pixels: js_sys::Uint8ClampedArray,
+}
+
+#[cfg(feature = "wasm")]
+#[wasm_bindgen]
+impl WorkerFrame {
+    #[wasm_bindgen(getter)]
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn stats(&self) -> MotionStats {
+        self.stats
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn pixels(&self) -> js_sys::Uint8ClampedArray {
+        self.pixels.clone()
+    }
+
+    // Plain `{width, height, format, stats, pixels}` object for `postMessage`; the wrapper
+    // itself points into the worker's WASM memory and can't be cloned to the page
+    pub fn to_message(&self) -> Result<js_sys::Object, ExportError> {
+        let set = |object: &js_sys::Object, key: &str, value: &JsValue| {
+            js_sys::Reflect::set(object, &key.into(), value).map_err(js_exception)
+        };
+        let stats = js_sys::Object::new();
+        set(&stats, "energy", &self.stats.energy.into())?;
+        set(&stats, "active_percent", &self.stats.active_percent.into())?;
+        set(&stats, "centroid_x", &self.stats.centroid_x.into())?;
+        set(&stats, "centroid_y", &self.stats.centroid_y.into())?;
+
+        let message = js_sys::Object::new();
+        set(&message, "width", &self.width.into())?;
+        set(&message, "height", &self.height.into())?;
+        set(&message, "format", &self.format.into())?;
+        set(&message, "stats", &stats)?;
+        set(&message, "pixels", &self.pixels)?;
+        Ok(message)
+    }
+
+    // The buffers to list as transferables in `postMessage` so the pixels move instead of
+    // being copied
+    pub fn transfer_list(&self) -> js_sys::Array {
+        js_sys::Array::of1(&self.pixels.buffer())
+    }
+}
+
+// Processing cost of a detector made by `MotionDetectorBuilder`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Quality {
+    // Half-resolution processing and power saving, for phones
+    Low,
+    // Full resolution, nearest-pixel movement
+    Medium,
+    // Full resolution, bilinear movement and a persistence pyramid
+    High,
+}
+
+// Checked construction of a detector with a starting configuration:
+// `new MotionDetectorBuilder().with_resolution(640, 480).with_preset("subtle").build()`.
+// Problems are reported by `build` rather than by the `with_*` calls, so they chain;
+// `options()` returns the per-frame options to pass with each frame.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug)]
+pub struct MotionDetectorBuilder {
+    width: u32,
+    height: u32,
+    quality: Quality,
+    options: MotionOptions,
+    // First invalid setting, reported by `build`
+    error: Option<String>,
+}
+
+impl Default for MotionDetectorBuilder {
+    fn default() -> MotionDetectorBuilder {
+        MotionDetectorBuilder {
+            width: 0,
+            height: 0,
+            quality: Quality::Medium,
+            options: MotionOptions::default(),
+            error: None,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl MotionDetectorBuilder {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new() -> MotionDetectorBuilder {
+        MotionDetectorBuilder::default()
+    }
+
+    pub fn with_resolution(mut self, width: u32, height: u32) -> MotionDetectorBuilder {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    pub fn with_quality(mut self, quality: Quality) -> MotionDetectorBuilder {
+        self.quality = quality;
+        self
+    }
+
+    // Start from a named look: "subtle", "psychedelic" or "surveillance"
+    pub fn with_preset(mut self, name: &str) -> MotionDetectorBuilder {
+        match MotionOptions::preset(name) {
+            Some(options) => self.options = options,
+            None => {
+                self.error
+                    .get_or_insert_with(|| format!("unknown preset: {}", name));
+            }
+        }
+        self
+    }
+
+    // Start from these options instead of a preset
+    pub fn with_options(mut self, options: &MotionOptions) -> MotionDetectorBuilder {
+        self.options = *options;
+        self
+    }
+
+    // The per-frame options of the preset, adjusted for the quality
+    pub fn options(&self) -> MotionOptions {
+        let mut options = self.options;
+        match self.quality {
+            Quality::Low => options.processing_scale = 0.5,
+            Quality::Medium => {}
+            Quality::High => options.sampling_mode = SamplingMode::Bilinear,
+        }
+        options
+    }
+
+    #[cfg(feature = "wasm")]
+    pub fn build(&self) -> Result<MotionDetector, JsError> {
+        self.try_build().map_err(js_error)
+    }
+}
+
+impl MotionDetectorBuilder {
+    // Native counterpart of `build`
+    pub fn try_build(&self) -> Result<MotionDetector, String> {
+        if let Some(error) = &self.error {
+            return Err(error.clone());
+        }
+        let mut detector = MotionDetector::try_new(self.width, self.height)?;
+        match self.quality {
+            Quality::Low => detector.set_power_saving(true),
+            Quality::Medium => {}
+            Quality::High => detector.set_pyramid_levels(2),
+        }
+        Ok(detector)
+    }
+}
+
+// A tracked moving object, see `update_tracks`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug)]
+pub struct Track {
+    // Stable for the life of the track and never reused
+    pub id: u32,
+    // Bounding box and centroid of the last matched blob, in pixels
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub centroid_x: f32,
+    pub centroid_y: f32,
+    // Smoothed centroid movement in pixels per frame
+    pub velocity_x: f32,
+    pub velocity_y: f32,
+    // Frames since the track was first seen, and frames since it was last matched
+    pub age: u32,
+    pub missed: u32,
+}
@@ -0,0 +1,2196 @@
+// Move modes of the trail buffer. Each mode implements `Movement` over a `FrameContext`, the
+// read-only frame state it samples, so modes don't depend on the detector itself.
+use std::f32::consts::{PI, TAU};
+use std::ops::Range;
+
+use crate::context::{DetectorLuts, LensProjection, PolarLuts};
+use crate::{BoundaryMode, MotionOptions, MoveType, SamplingMode, MAX_DIFFUSE_ITERATIONS};
+
+// Frames over which a move_type switch cross-fades by default
+pub(crate) const DEFAULT_MOVE_TRANSITION_FRAMES: u32 = 12;
+
+// Cross-fade from the movement of the previous move_type to the new one
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct MoveTransition {
+    pub(crate) from: MotionOptions,
+    pub(crate) frames_left: u32,
+    pub(crate) total_frames: u32,
+}
+
+// Movement parameters easing from one preset to another over a number of frames, see
+// `MotionDetector::transition_to`
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ParameterTransition {
+    pub(crate) from: MotionOptions,
+    pub(crate) to: MotionOptions,
+    pub(crate) frame: u32,
+    pub(crate) total_frames: u32,
+}
+
+impl ParameterTransition {
+    // `params` with the movement parameters of the current frame of the transition and the
+    // target's move type (whose switch the move transition cross-fades); returns None once
+    // the transition is over
+    pub(crate) fn step(&mut self, params: &MotionOptions) -> Option<MotionOptions> {
+        if self.frame >= self.total_frames {
+            return None;
+        }
+        self.frame += 1;
+        let t = self.frame as f32 / self.total_frames as f32;
+        // Smoothstep, so the parameters don't jerk at either end
+        let t = t * t * (3.0 - 2.0 * t);
+        let (from, to) = (&self.from, &self.to);
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+        // Angles take the short way round
+        let angle_delta = (to.angle_radians - from.angle_radians + PI).rem_euclid(TAU) - PI;
+        Some(MotionOptions {
+            move_type: to.move_type,
+            direction: to.direction,
+            angle_radians: from.angle_radians + angle_delta * t,
+            speed: lerp(from.speed, to.speed),
+            rotation_speed: lerp(from.rotation_speed, to.rotation_speed),
+            amplitude: lerp(from.amplitude, to.amplitude),
+            frequency: lerp(from.frequency, to.frequency),
+            phase_increment: lerp(from.phase_increment, to.phase_increment),
+            zoom_factor: lerp(from.zoom_factor, to.zoom_factor),
+            focal_x: lerp(from.focal_x, to.focal_x),
+            focal_y: lerp(from.focal_y, to.focal_y),
+            pivot_x: lerp(from.pivot_x, to.pivot_x),
+            pivot_y: lerp(from.pivot_y, to.pivot_y),
+            scale: lerp(from.scale, to.scale),
+            strength: lerp(from.strength, to.strength),
+            shake_magnitude: lerp(from.shake_magnitude, to.shake_magnitude),
+            segment_offset: lerp(from.segment_offset, to.segment_offset),
+            ..*params
+        })
+    }
+}
+
+// Coarsest persistence pyramid level, and the displacement (in pixels) up to which the
+// full-resolution buffer is sampled directly; each coarser level covers twice the distance
+pub(crate) const PYRAMID_MAX_LEVELS: u32 = 4;
+pub(crate) const PYRAMID_FINE_DISPLACEMENT: f32 = 4.0;
+
+// One 2x downsampled level of the persistence pyramid
+pub(crate) struct PyramidLevel {
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) data: Vec<f32>,
+}
+
+// Small deterministic PRNG (SplitMix64) for effects that need a random sequence
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Prng {
+    state: u64,
+}
+
+impl Prng {
+    pub(crate) fn new(seed: u32) -> Prng {
+        Prng { state: seed as u64 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Uniform in -1..1
+    pub(crate) fn next_signed(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+    }
+}
+
+// Hash of a lattice point to -1..1, the random values of the turbulence noise
+pub(crate) fn lattice_value(seed: u32, x: i32, y: i32, z: i32) -> f32 {
+    let mut hash = seed
+        ^ (x as u32).wrapping_mul(0x8da6_b343)
+        ^ (y as u32).wrapping_mul(0xd816_3841)
+        ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x2c1b_3c6d);
+    hash ^= hash >> 12;
+    hash = hash.wrapping_mul(0x297a_2d39);
+    hash ^= hash >> 15;
+    hash as f32 / u32::MAX as f32 * 2.0 - 1.0
+}
+
+// Spatial gradient (d/dx, d/dy) of smooth 3D value noise, in lattice units; z is time
+pub(crate) fn value_noise_gradient(seed: u32, x: f32, y: f32, z: f32) -> (f32, f32) {
+    let (x0, y0, z0) = (x.floor(), y.floor(), z.floor());
+    let (fx, fy, fz) = (x - x0, y - y0, z - z0);
+    let (ix, iy, iz) = (x0 as i32, y0 as i32, z0 as i32);
+
+    // Quintic fade, and its derivative for the analytic gradient
+    let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let fade_derivative = |t: f32| 30.0 * t * t * (t * (t - 2.0) + 1.0);
+    let (u, v, w) = (fade(fx), fade(fy), fade(fz));
+
+    // Blend the two time slices first, leaving a bilinear patch in x and y
+    let corner = |dx: i32, dy: i32| {
+        let now = lattice_value(seed, ix + dx, iy + dy, iz);
+        let next = lattice_value(seed, ix + dx, iy + dy, iz + 1);
+        now + (next - now) * w
+    };
+    let (a, b, c, d) = (corner(0, 0), corner(1, 0), corner(0, 1), corner(1, 1));
+    let cross = a - b - c + d;
+    (
+        (b - a + cross * v) * fade_derivative(fx),
+        (c - a + cross * u) * fade_derivative(fy),
+    )
+}
+
+// Mutable window onto the rows of a full-frame buffer, indexed with full-frame pixel
+// indexes so band-wise passes read like whole-frame ones
+pub(crate) struct RowBand<'a> {
+    data: &'a mut [f32],
+    offset: usize,
+}
+
+impl<'a> RowBand<'a> {
+    pub(crate) fn new(data: &'a mut [f32], offset: usize) -> RowBand<'a> {
+        RowBand { data, offset }
+    }
+
+    fn span_mut(&mut self, span: Range<usize>) -> &mut [f32] {
+        &mut self.data[span.start - self.offset..span.end - self.offset]
+    }
+}
+
+impl std::ops::Index<usize> for RowBand<'_> {
+    type Output = f32;
+
+    #[inline]
+    fn index(&self, pixel_index: usize) -> &f32 {
+        &self.data[pixel_index - self.offset]
+    }
+}
+
+impl std::ops::IndexMut<usize> for RowBand<'_> {
+    #[inline]
+    fn index_mut(&mut self, pixel_index: usize) -> &mut f32 {
+        &mut self.data[pixel_index - self.offset]
+    }
+}
+
+// Rows per band handed to the worker pool: a few bands per thread so uneven rows balance
+#[cfg(feature = "threads")]
+pub(crate) fn parallel_band_rows(rows: usize) -> usize {
+    rows.div_ceil(rayon::current_num_threads() * 4).max(16)
+}
+
+// The state the movement passes read, borrowed from the detector so row bands can be moved
+// on several threads while `temp_buffer` is written band by band. `source` holds the
+// previous trails; a compositor layer swaps in its own.
+pub(crate) struct FrameContext<'a> {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) cols: Range<usize>,
+    pub(crate) source: &'a [f32],
+    pub(crate) persistence_pyramid: &'a [PyramidLevel],
+    // Lookup tables; the polar ones are built the first time a mode asks for them
+    pub(crate) luts: &'a DetectorLuts,
+    // Lens model the polar tables were built with, if any (see `set_lens_model`)
+    pub(crate) lens: Option<&'a LensProjection>,
+    pub(crate) center_x: f32,
+    pub(crate) center_y: f32,
+    pub(crate) high_quality_radius: f32,
+    pub(crate) medium_quality_radius: f32,
+    pub(crate) phase: f32,
+    pub(crate) orientation_quarter_turns: u32,
+    pub(crate) move_transition: Option<MoveTransition>,
+    pub(crate) flow_field: Option<&'a FlowField>,
+    pub(crate) perspective: Option<&'a Perspective>,
+    pub(crate) attractors: &'a [Attractor],
+    pub(crate) shake_offset: (f32, f32),
+}
+
+// A move mode: writes the moved trails of `rows` into `target`, reading the previous ones
+// from `ctx.source`. Every active pixel of the rows must be written, since the target still
+// holds an older frame. Modes only see the frame through `FrameContext`, so adding one
+// means a struct here and an arm in `movement_for`.
+pub(crate) trait Movement {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    );
+
+    // How far the mode carries a trail at buffer point (x, y) this frame, for things that
+    // travel with the trails such as particles. Modes that rearrange the trails rather than
+    // carry them along leave them in place.
+    fn velocity(
+        &self,
+        _params: &MotionOptions,
+        _ctx: &FrameContext,
+        _x: f32,
+        _y: f32,
+    ) -> (f32, f32) {
+        (0.0, 0.0)
+    }
+}
+
+// The mode implementing `move_type`
+pub(crate) fn movement_for(move_type: Option<MoveType>) -> &'static dyn Movement {
+    match move_type {
+        Some(MoveType::Direction) => &Direction,
+        Some(MoveType::Radial) => &Radial,
+        Some(MoveType::Spiral) => &Spiral,
+        Some(MoveType::Wave) => &Wave,
+        Some(MoveType::Zoom) => &Zoom,
+        Some(MoveType::Rotate) => &Rotate,
+        Some(MoveType::Turbulence) => &Turbulence,
+        Some(MoveType::Flow) => &Flow,
+        Some(MoveType::Kaleidoscope) => &Kaleidoscope,
+        Some(MoveType::Shake) => &Shake,
+        Some(MoveType::Attract) => &Attract,
+        Some(MoveType::Perspective) => &Warp,
+        Some(MoveType::Smear) => &Smear,
+        Some(MoveType::Feedback) => &Feedback,
+        Some(MoveType::Diffuse) => &Diffuse,
+        // Unknown modes leave the trails in place
+        None => &Unmoved,
+    }
+}
+
+impl FrameContext<'_> {
+    fn active_cols(&self) -> Range<usize> {
+        self.cols.clone()
+    }
+
+    // Picture distance from the center of a distance in the polar tables, and back
+    fn picture_distance(&self, distance: f32) -> f32 {
+        self.lens
+            .map_or(distance, |lens| lens.picture_distance(distance))
+    }
+
+    fn true_distance(&self, distance: f32) -> f32 {
+        self.lens
+            .map_or(distance, |lens| lens.true_distance(distance))
+    }
+
+    fn polar(&self) -> &PolarLuts {
+        self.luts.polar()
+    }
+
+    // Move one band of rows, cross-fading from the outgoing mode during a transition
+    pub(crate) fn move_band(
+        &self,
+        params: &MotionOptions,
+        rows: Range<usize>,
+        target: &mut RowBand,
+        outgoing: &mut RowBand,
+    ) {
+        let Some(transition) = self.move_transition else {
+            self.move_rows_with(params, rows, target);
+            return;
+        };
+
+        // Render the outgoing mode into the spare buffer, then blend it into the incoming one
+        self.move_rows_with(&transition.from, rows.clone(), outgoing);
+        self.move_rows_with(params, rows.clone(), target);
+
+        let incoming_weight =
+            1.0 - transition.frames_left as f32 / (transition.total_frames + 1) as f32;
+        let width = self.width as usize;
+        let span = rows.start * width..rows.end * width;
+        for (moved, outgoing) in target
+            .span_mut(span.clone())
+            .iter_mut()
+            .zip(outgoing.span_mut(span).iter())
+        {
+            *moved = outgoing + (*moved - outgoing) * incoming_weight;
+        }
+    }
+
+    // `Movement::velocity` of the mode of `params` anywhere in the frame
+    pub(crate) fn velocity_field(
+        &self,
+        params: &MotionOptions,
+    ) -> impl Fn(f32, f32) -> (f32, f32) + '_ {
+        let params = self.oriented_params(params);
+        let movement = movement_for(params.move_type);
+        move |x, y| movement.velocity(&params, self, x, y)
+    }
+
+    pub(crate) fn move_rows_with(
+        &self,
+        params: &MotionOptions,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let params = &self.oriented_params(params);
+        movement_for(params.move_type).apply(params, self, rows, target);
+    }
+
+    // Map display-space movement parameters into buffer space for rotated sources. Radial
+    // and spiral movement are symmetric around the center, so only the direction angle, the
+    // wave axis and the zoom focal point and rotation pivot change; the center-based LUTs are
+    // unaffected by quarter turns. The wave's displacement and argument are mapped by
+    // `wave_orientation`.
+    fn oriented_params(&self, params: &MotionOptions) -> MotionOptions {
+        let turns = self.orientation_quarter_turns;
+        if turns == 0 {
+            return *params;
+        }
+
+        let mut oriented = *params;
+        oriented.angle_radians += turns as f32 * std::f32::consts::FRAC_PI_2;
+        oriented.segment_offset += turns as f32 * std::f32::consts::FRAC_PI_2;
+        if turns % 2 == 1 {
+            oriented.direction = if params.direction == 0 { 1 } else { 0 };
+        }
+        (oriented.focal_x, oriented.focal_y) = self.oriented_point(params.focal_x, params.focal_y);
+        (oriented.pivot_x, oriented.pivot_y) = self.oriented_point(params.pivot_x, params.pivot_y);
+        oriented
+    }
+
+    // For a wave along buffer axis `direction` of the oriented params: the sign its
+    // displacement takes in buffer space, and whether its argument runs from the far edge.
+    // Together they make the wave on a rotated source the exact rotation of the display one.
+    fn wave_orientation(&self, direction: i32) -> (f32, bool) {
+        match (self.orientation_quarter_turns, direction == 0) {
+            (0, _) => (1.0, false),
+            (2, _) => (-1.0, true),
+            (1, false) | (3, true) => (1.0, true),
+            _ => (-1.0, false),
+        }
+    }
+
+    // Map a display-space point, in fractions of the frame, into buffer space by rotating its
+    // offset from the center with the frame
+    fn oriented_point(&self, x: f32, y: f32) -> (f32, f32) {
+        let (offset_x, offset_y) = (x - 0.5, y - 0.5);
+        let (offset_x, offset_y) = match self.orientation_quarter_turns {
+            0 => (offset_x, offset_y),
+            1 => (-offset_y, offset_x),
+            2 => (-offset_x, -offset_y),
+            _ => (offset_y, -offset_x),
+        };
+        (0.5 + offset_x, 0.5 + offset_y)
+    }
+
+    fn copy_rows_unmoved(&self, rows: Range<usize>, target: &mut RowBand) {
+        let width = self.width as usize;
+        let span = rows.start * width..rows.end * width;
+        target
+            .span_mut(span.clone())
+            .copy_from_slice(&self.source[span]);
+    }
+
+    // Sample moved persistence from the pyramid level matching `displacement`; None when
+    // the displacement is small enough for the full-resolution buffer (or the pyramid is
+    // off). Coordinates are full-resolution and must already be in bounds.
+    #[inline]
+    fn sample_pyramid(&self, source_x: f32, source_y: f32, displacement: f32) -> Option<f32> {
+        if self.persistence_pyramid.is_empty() || displacement < PYRAMID_FINE_DISPLACEMENT {
+            return None;
+        }
+        let level_index = ((displacement / PYRAMID_FINE_DISPLACEMENT).log2() as usize)
+            .min(self.persistence_pyramid.len() - 1);
+        let level = &self.persistence_pyramid[level_index];
+        let scale = 1.0 / (2 << level_index) as f32;
+        let x = ((source_x * scale) as usize).min(level.width - 1);
+        let y = ((source_y * scale) as usize).min(level.height - 1);
+        Some(level.data[y * level.width + x])
+    }
+
+    // Nearest-pixel read of the persistence buffer, from the pyramid level matching
+    // `displacement` when that is large; None where the boundary mode clears the source
+    #[inline]
+    fn sample_nearest(
+        &self,
+        boundary: BoundaryMode,
+        source_x: f32,
+        source_y: f32,
+        displacement: f32,
+    ) -> Option<f32> {
+        let width = self.width as usize;
+        let (x, y) = (source_x.round() as i32, source_y.round() as i32);
+        let (resolved_x, resolved_y) = boundary.resolve(x, y, width, self.height as usize)?;
+        // The pyramid is read at the resolved position, keeping the sub-pixel part
+        let pyramid_x = source_x + (resolved_x as i32 - x) as f32;
+        let pyramid_y = source_y + (resolved_y as i32 - y) as f32;
+        Some(
+            self.sample_pyramid(pyramid_x, pyramid_y, displacement)
+                .unwrap_or(self.source[resolved_y * width + resolved_x]),
+        )
+    }
+
+    // Bilinear read of the persistence buffer; neighbours outside the frame are read
+    // through the boundary mode (cleared ones count as empty)
+    #[inline]
+    fn sample_bilinear(&self, boundary: BoundaryMode, source_x: f32, source_y: f32) -> f32 {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let x0 = source_x.floor();
+        let y0 = source_y.floor();
+        let fx = source_x - x0;
+        let fy = source_y - y0;
+        let (x0, y0) = (x0 as i32, y0 as i32);
+
+        let at = |x: i32, y: i32| {
+            boundary
+                .resolve(x, y, width, height)
+                .map_or(0.0, |(x, y)| self.source[y * width + x])
+        };
+        let top = at(x0, y0) + (at(x0 + 1, y0) - at(x0, y0)) * fx;
+        let bottom = at(x0, y0 + 1) + (at(x0 + 1, y0 + 1) - at(x0, y0 + 1)) * fx;
+        top + (bottom - top) * fy
+    }
+}
+
+pub(crate) struct Unmoved;
+
+impl Movement for Unmoved {
+    fn apply(
+        &self,
+        _params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        ctx.copy_rows_unmoved(rows, target);
+    }
+}
+
+pub(crate) struct Direction;
+
+impl Movement for Direction {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+        let height = ctx.height as usize;
+
+        let angle_radians = params.angle_radians;
+
+        let speed = params.speed;
+
+        // Early exit for minimal movement - avoid all calculations. Bilinear sampling can
+        // show sub-pixel speeds, nearest sampling would round them away.
+        let minimum_speed = match params.sampling_mode {
+            SamplingMode::Nearest => 1.0,
+            SamplingMode::Bilinear => 0.01,
+        };
+        if speed <= minimum_speed {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+
+        // Pre-compute movement values outside the loop
+        let move_x = angle_radians.cos() * speed;
+        let move_y = angle_radians.sin() * speed;
+
+        if params.sampling_mode == SamplingMode::Bilinear {
+            for y in rows {
+                let source_y = y as f32 - move_y;
+                for x in cols.clone() {
+                    target[y * width + x] =
+                        ctx.sample_bilinear(params.boundary_mode, x as f32 - move_x, source_y);
+                }
+            }
+            return;
+        }
+
+        let move_x_int = move_x.round() as i32;
+        let move_y_int = move_y.round() as i32;
+        let boundary = params.boundary_mode;
+
+        // Process row by row for better cache locality
+        for y in rows {
+            let source_y = y as i32 - move_y_int;
+
+            let dest_row_base = y * width;
+
+            // Clear the entire row if source_y is out of bounds
+            if boundary == BoundaryMode::Clear && (source_y < 0 || source_y >= height as i32) {
+                target
+                    .span_mut(dest_row_base + cols.start..dest_row_base + cols.end)
+                    .fill(0.0);
+                continue;
+            }
+
+            // Process pixels in this row with cache-friendly access pattern
+            for x in cols.clone() {
+                let source_x = x as i32 - move_x_int;
+
+                // Pixels moved in from outside a cleared boundary start empty
+                target[dest_row_base + x] = boundary
+                    .resolve(source_x, source_y, width, height)
+                    .map_or(0.0, |(source_x, source_y)| {
+                        ctx.source[source_y * width + source_x]
+                    });
+            }
+        }
+    }
+
+    fn velocity(
+        &self,
+        params: &MotionOptions,
+        _ctx: &FrameContext,
+        _x: f32,
+        _y: f32,
+    ) -> (f32, f32) {
+        let (sin, cos) = params.angle_radians.sin_cos();
+        (cos * params.speed, sin * params.speed)
+    }
+}
+
+pub(crate) struct Radial;
+
+impl Movement for Radial {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+
+        let speed = params.speed;
+
+        // Radial movement processing - optimized to avoid expensive sqrt calls
+        if speed.abs() > 0.1 {
+            let polar = ctx.polar();
+            let speed_plus_threshold = speed + 50.0;
+            let speed_plus_threshold_squared = speed_plus_threshold * speed_plus_threshold;
+
+            // Cache-friendly processing: Process row by row for better memory locality
+            for y in rows {
+                let y_f32 = y as f32;
+                let dy = y_f32 - ctx.center_y;
+                let dest_row_base = y * width;
+
+                for x in cols.clone() {
+                    let pixel_index = dest_row_base + x;
+
+                    // Use pre-computed squared distance to avoid sqrt calculation
+                    let distance_squared = polar.distance_squared(pixel_index);
+
+                    if distance_squared > speed_plus_threshold_squared {
+                        let distance = polar.distance.get(pixel_index);
+
+                        // Optimization #6: Distance-based approximation for performance
+                        let effective_speed = if distance <= ctx.high_quality_radius {
+                            // High quality: Full precision for center area
+                            speed
+                        } else if distance <= ctx.medium_quality_radius {
+                            // Medium quality: Slightly reduced precision for middle area
+                            speed * 0.95
+                        } else {
+                            // Low quality: Reduced precision for distant pixels
+                            // Use coarser movement steps for better performance
+                            (speed * 0.8).round()
+                        };
+
+                        // Calculate pixel coordinates (optimized with row-level y calculation)
+                        let x_f32 = x as f32;
+                        let dx = x_f32 - ctx.center_x;
+
+                        let (source_x, source_y) = match ctx.lens {
+                            // Step along the ray in true distance, then back into the picture
+                            Some(lens) => {
+                                let scale = lens.picture_distance(distance - effective_speed)
+                                    / dx.hypot(dy);
+                                (ctx.center_x + dx * scale, ctx.center_y + dy * scale)
+                            }
+                            None => {
+                                // Normalize direction vector (reuse calculated distance)
+                                let inv_distance = 1.0 / distance;
+                                let norm_dx = dx * inv_distance;
+                                let norm_dy = dy * inv_distance;
+
+                                // Calculate source position
+                                (
+                                    x_f32 - norm_dx * effective_speed,
+                                    y_f32 - norm_dy * effective_speed,
+                                )
+                            }
+                        };
+
+                        if params.sampling_mode == SamplingMode::Bilinear {
+                            target[pixel_index] = ctx
+                                .sample_pyramid(source_x, source_y, effective_speed.abs())
+                                .unwrap_or_else(|| {
+                                    ctx.sample_bilinear(params.boundary_mode, source_x, source_y)
+                                });
+                            continue;
+                        }
+
+                        target[pixel_index] = ctx
+                            .sample_nearest(
+                                params.boundary_mode,
+                                source_x,
+                                source_y,
+                                effective_speed.abs(),
+                            )
+                            .unwrap_or(0.0);
+                    } else {
+                        // Center pixel stays the same
+                        target[pixel_index] = ctx.source[pixel_index];
+                    }
+                }
+            }
+        } else {
+            ctx.copy_rows_unmoved(rows, target);
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = (x - ctx.center_x, y - ctx.center_y);
+        let distance = dx.hypot(dy);
+        // The center stays put, as in `apply`
+        if params.speed.abs() <= 0.1 || ctx.true_distance(distance) <= params.speed + 50.0 {
+            return (0.0, 0.0);
+        }
+        // With a lens model the step is taken in true distance
+        let step = match ctx.lens {
+            Some(lens) => {
+                lens.picture_distance(lens.true_distance(distance) + params.speed) - distance
+            }
+            None => params.speed,
+        };
+        (dx / distance * step, dy / distance * step)
+    }
+}
+
+pub(crate) struct Spiral;
+
+impl Movement for Spiral {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+
+        let speed = params.speed;
+
+        let rotation_speed = params.rotation_speed;
+
+        // Spiral movement processing - Early exit for minimal movement
+        if !(speed.abs() > 0.1 || rotation_speed.abs() > 0.01) {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+
+        // Pre-compute constants
+        let speed_threshold = speed + 5.0;
+        let polar = ctx.polar();
+
+        // Optimization #6: Distance-based quality processing for better performance
+        // Process pixels with different accuracy based on distance from center
+        for y in rows {
+            let dest_row_base = y * width;
+
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+
+                // Use pre-computed polar coordinates (eliminates expensive atan2 and sqrt calls)
+                let distance = polar.distance.get(pixel_index);
+                let angle = polar.angle.get(pixel_index);
+
+                // Early exit for center pixels using faster comparison
+                if distance <= speed_threshold {
+                    target[pixel_index] = ctx.source[pixel_index];
+                    continue;
+                }
+
+                // Optimization #6: Apply different quality levels based on distance
+                let (new_distance, new_angle) = if distance <= ctx.high_quality_radius {
+                    // High quality: Full precision for center area
+                    (distance - speed, angle - rotation_speed)
+                } else if distance <= ctx.medium_quality_radius {
+                    // Medium quality: Reduced rotation precision for middle area
+                    (distance - speed, angle - rotation_speed * 0.7)
+                } else {
+                    // Low quality: Simplified calculation for distant pixels
+                    // Use approximation: skip very small rotations for distant pixels
+                    if rotation_speed.abs() < 0.02 {
+                        (distance - speed, angle) // Skip rotation entirely
+                    } else {
+                        (distance - speed, angle - rotation_speed * 0.5)
+                    }
+                };
+
+                // Convert back to cartesian (still needs cos/sin, but eliminated atan2 and sqrt)
+                let source_distance = ctx.picture_distance(new_distance);
+                let source_x = ctx.center_x + source_distance * new_angle.cos();
+                let source_y = ctx.center_y + source_distance * new_angle.sin();
+
+                if params.sampling_mode == SamplingMode::Bilinear {
+                    // Distance travelled: radial step plus the arc of the rotation
+                    let displacement =
+                        (distance - new_distance).abs() + distance * (angle - new_angle).abs();
+                    target[pixel_index] = ctx
+                        .sample_pyramid(source_x, source_y, displacement)
+                        .unwrap_or_else(|| {
+                            ctx.sample_bilinear(params.boundary_mode, source_x, source_y)
+                        });
+                    continue;
+                }
+
+                // Distance travelled: radial step plus the arc of the rotation
+                let displacement =
+                    (distance - new_distance).abs() + distance * (angle - new_angle).abs();
+                target[pixel_index] = ctx
+                    .sample_nearest(params.boundary_mode, source_x, source_y, displacement)
+                    .unwrap_or(0.0);
+            }
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        // The point steps outwards and turns around the center, which stays put as in `apply`
+        let (dx, dy) = (x - ctx.center_x, y - ctx.center_y);
+        let (distance, angle) = (ctx.true_distance(dx.hypot(dy)), dy.atan2(dx));
+        if distance <= params.speed + 5.0 {
+            return (0.0, 0.0);
+        }
+        let (sin, cos) = (angle + params.rotation_speed).sin_cos();
+        let target_distance = ctx.picture_distance(distance + params.speed);
+        (
+            ctx.center_x + target_distance * cos - x,
+            ctx.center_y + target_distance * sin - y,
+        )
+    }
+}
+
+pub(crate) struct Wave;
+
+impl Movement for Wave {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+
+        let amplitude = params.amplitude;
+
+        let frequency = params.frequency;
+
+        let direction = params.direction;
+        let (sign, mirrored) = ctx.wave_orientation(direction);
+
+        // Early exit for minimal wave effect
+        if amplitude.abs() <= 0.1 {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+
+        // Optimization #6: Distance-based quality wave processing with cache-friendly access
+        let polar = ctx.polar();
+        if direction == 0 {
+            // Horizontal wave - cache-friendly row-by-row processing
+            for y in rows {
+                let wave_y = if mirrored {
+                    ctx.height as usize - 1 - y
+                } else {
+                    y
+                };
+                let distance_from_center = polar.distance.get(y * width + width / 2);
+
+                // Optimization #6: Apply different wave quality based on distance
+                let effective_amplitude = if distance_from_center <= ctx.high_quality_radius {
+                    amplitude
+                } else if distance_from_center <= ctx.medium_quality_radius {
+                    amplitude * 0.9
+                } else {
+                    amplitude * 0.7 // Reduced amplitude for distant rows
+                };
+
+                let wave_offset =
+                    (wave_y as f32 * frequency + ctx.phase).sin() * effective_amplitude * sign;
+                let dest_row_base = y * width;
+
+                for x in cols.clone() {
+                    let pixel_index = dest_row_base + x;
+                    if params.sampling_mode == SamplingMode::Bilinear {
+                        target[pixel_index] = ctx.sample_bilinear(
+                            params.boundary_mode,
+                            x as f32 - wave_offset,
+                            y as f32,
+                        );
+                        continue;
+                    }
+                    target[pixel_index] = ctx
+                        .sample_nearest(params.boundary_mode, x as f32 - wave_offset, y as f32, 0.0)
+                        .unwrap_or(0.0);
+                }
+            }
+        } else {
+            // Vertical wave - cache-friendly column processing with row-major access
+            for y in rows {
+                let dest_row_base = y * width;
+
+                for x in cols.clone() {
+                    let pixel_index = dest_row_base + x;
+                    let x_f32 = x as f32;
+                    let distance_from_center = polar.distance.get(pixel_index);
+
+                    // Optimization #6: Apply different wave quality based on distance
+                    let effective_amplitude = if distance_from_center <= ctx.high_quality_radius {
+                        amplitude
+                    } else if distance_from_center <= ctx.medium_quality_radius {
+                        amplitude * 0.9
+                    } else {
+                        amplitude * 0.7 // Reduced amplitude for distant pixels
+                    };
+
+                    let wave_x = if mirrored { width - 1 - x } else { x };
+                    let wave_offset =
+                        (wave_x as f32 * frequency + ctx.phase).sin() * effective_amplitude * sign;
+                    if params.sampling_mode == SamplingMode::Bilinear {
+                        target[pixel_index] = ctx.sample_bilinear(
+                            params.boundary_mode,
+                            x_f32,
+                            y as f32 - wave_offset,
+                        );
+                        continue;
+                    }
+                    target[pixel_index] = ctx
+                        .sample_nearest(params.boundary_mode, x_f32, y as f32 - wave_offset, 0.0)
+                        .unwrap_or(0.0);
+                }
+            }
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        let (sign, mirrored) = ctx.wave_orientation(params.direction);
+        let amplitude = params.amplitude * sign;
+        if params.direction == 0 {
+            let y = if mirrored {
+                ctx.height as f32 - 1.0 - y
+            } else {
+                y
+            };
+            ((y * params.frequency + ctx.phase).sin() * amplitude, 0.0)
+        } else {
+            let x = if mirrored {
+                ctx.width as f32 - 1.0 - x
+            } else {
+                x
+            };
+            (0.0, (x * params.frequency + ctx.phase).sin() * amplitude)
+        }
+    }
+}
+
+pub(crate) struct Zoom;
+
+impl Movement for Zoom {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+
+        // Early exit for no zoom (and factors that would flip the image)
+        let zoom_factor = params.zoom_factor;
+        if zoom_factor <= 0.0 || (zoom_factor - 1.0).abs() < 0.001 {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+
+        // Each pixel reads the point the zoom moves onto it, 1 / zoom_factor as far from the
+        // focal point
+        let inverse_zoom = 1.0 / zoom_factor;
+        let offset_x = (params.focal_x - 0.5) * ctx.width as f32;
+        let offset_y = (params.focal_y - 0.5) * ctx.height as f32;
+        let focal_x = ctx.center_x + offset_x;
+        let focal_y = ctx.center_y + offset_y;
+        // The polar LUT already holds the distance to a centered focal point
+        let centered = offset_x == 0.0 && offset_y == 0.0;
+        let use_pyramid = !ctx.persistence_pyramid.is_empty();
+        let polar = (use_pyramid && centered).then(|| ctx.polar());
+
+        for y in rows {
+            let dy = y as f32 - focal_y;
+            let source_y = focal_y + dy * inverse_zoom;
+            let dest_row_base = y * width;
+
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                let dx = x as f32 - focal_x;
+                let source_x = focal_x + dx * inverse_zoom;
+
+                // Distance travelled, only needed to pick a pyramid level
+                let displacement = if !use_pyramid {
+                    0.0
+                } else if let Some(polar) = polar {
+                    polar.distance.get(pixel_index) * (1.0 - inverse_zoom).abs()
+                } else {
+                    (dx * dx + dy * dy).sqrt() * (1.0 - inverse_zoom).abs()
+                };
+
+                if params.sampling_mode == SamplingMode::Bilinear {
+                    target[pixel_index] = ctx
+                        .sample_pyramid(source_x, source_y, displacement)
+                        .unwrap_or_else(|| {
+                            ctx.sample_bilinear(params.boundary_mode, source_x, source_y)
+                        });
+                    continue;
+                }
+
+                target[pixel_index] = ctx
+                    .sample_nearest(params.boundary_mode, source_x, source_y, displacement)
+                    .unwrap_or(0.0);
+            }
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        if params.zoom_factor <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let focal_x = ctx.center_x + (params.focal_x - 0.5) * ctx.width as f32;
+        let focal_y = ctx.center_y + (params.focal_y - 0.5) * ctx.height as f32;
+        let growth = params.zoom_factor - 1.0;
+        ((x - focal_x) * growth, (y - focal_y) * growth)
+    }
+}
+
+pub(crate) struct Rotate;
+
+impl Movement for Rotate {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+
+        let rotation_speed = params.rotation_speed;
+        if rotation_speed.abs() < 0.0001 {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+
+        // Each pixel reads the point the rotation moves onto it, so sample at -rotation_speed
+        let (sin, cos) = (-rotation_speed).sin_cos();
+        let pivot_x = params.pivot_x * ctx.width as f32;
+        let pivot_y = params.pivot_y * ctx.height as f32;
+        // Chord length per unit of distance from the pivot, for the pyramid level
+        let chord = 2.0 * (rotation_speed * 0.5).sin().abs();
+        let use_pyramid = !ctx.persistence_pyramid.is_empty();
+
+        for y in rows {
+            let dy = y as f32 - pivot_y;
+            let dest_row_base = y * width;
+
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                let dx = x as f32 - pivot_x;
+                let source_x = pivot_x + dx * cos - dy * sin;
+                let source_y = pivot_y + dx * sin + dy * cos;
+                let displacement = if use_pyramid {
+                    (dx * dx + dy * dy).sqrt() * chord
+                } else {
+                    0.0
+                };
+
+                if params.sampling_mode == SamplingMode::Bilinear {
+                    target[pixel_index] = ctx
+                        .sample_pyramid(source_x, source_y, displacement)
+                        .unwrap_or_else(|| {
+                            ctx.sample_bilinear(params.boundary_mode, source_x, source_y)
+                        });
+                    continue;
+                }
+
+                target[pixel_index] = ctx
+                    .sample_nearest(params.boundary_mode, source_x, source_y, displacement)
+                    .unwrap_or(0.0);
+            }
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        let (sin, cos) = params.rotation_speed.sin_cos();
+        let (dx, dy) = (
+            x - params.pivot_x * ctx.width as f32,
+            y - params.pivot_y * ctx.height as f32,
+        );
+        (dx * cos - dy * sin - dx, dx * sin + dy * cos - dy)
+    }
+}
+
+pub(crate) struct Feedback;
+
+impl Feedback {
+    fn focal_point(params: &MotionOptions, ctx: &FrameContext) -> (f32, f32) {
+        (
+            ctx.center_x + (params.focal_x - 0.5) * ctx.width as f32,
+            ctx.center_y + (params.focal_y - 0.5) * ctx.height as f32,
+        )
+    }
+}
+
+impl Movement for Feedback {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+
+        let zoom_factor = params.zoom_factor;
+        if zoom_factor <= 0.0 || ((zoom_factor - 1.0).abs() < 0.001 && params.twist.abs() < 0.0001)
+        {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+
+        // Zoom and twist fused into one similarity transform: each pixel reads the point
+        // it maps onto, turned back by the twist and 1 / zoom_factor as far from the focal
+        // point
+        let (sin, cos) = (-params.twist).sin_cos();
+        let (sin, cos) = (sin / zoom_factor, cos / zoom_factor);
+        let (focal_x, focal_y) = Feedback::focal_point(params, ctx);
+        // Distance travelled per unit of distance from the focal point, for the pyramid level
+        let travel = ((1.0 - cos).powi(2) + sin * sin).sqrt();
+        let use_pyramid = !ctx.persistence_pyramid.is_empty();
+
+        for y in rows {
+            let dy = y as f32 - focal_y;
+            let dest_row_base = y * width;
+
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                let dx = x as f32 - focal_x;
+                let source_x = focal_x + dx * cos - dy * sin;
+                let source_y = focal_y + dx * sin + dy * cos;
+                let displacement = if use_pyramid {
+                    (dx * dx + dy * dy).sqrt() * travel
+                } else {
+                    0.0
+                };
+
+                if params.sampling_mode == SamplingMode::Bilinear {
+                    target[pixel_index] = ctx
+                        .sample_pyramid(source_x, source_y, displacement)
+                        .unwrap_or_else(|| {
+                            ctx.sample_bilinear(params.boundary_mode, source_x, source_y)
+                        });
+                    continue;
+                }
+
+                target[pixel_index] = ctx
+                    .sample_nearest(params.boundary_mode, source_x, source_y, displacement)
+                    .unwrap_or(0.0);
+            }
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        if params.zoom_factor <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let (focal_x, focal_y) = Feedback::focal_point(params, ctx);
+        let (sin, cos) = params.twist.sin_cos();
+        let (sin, cos) = (sin * params.zoom_factor, cos * params.zoom_factor);
+        let (dx, dy) = (x - focal_x, y - focal_y);
+        (dx * cos - dy * sin - dx, dx * sin + dy * cos - dy)
+    }
+}
+
+pub(crate) struct Flow;
+
+impl Movement for Flow {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let Some(field) = ctx.flow_field else {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        };
+        let width = ctx.width as usize;
+        let height = ctx.height as usize;
+        let cols = ctx.active_cols();
+
+        for y in rows {
+            let dest_row_base = y * width;
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                // Each pixel reads the point the field carries onto it
+                let (dx, dy) = field.sample(x, y, width, height);
+                let source_x = x as f32 - dx;
+                let source_y = y as f32 - dy;
+
+                if params.sampling_mode == SamplingMode::Bilinear {
+                    target[pixel_index] =
+                        ctx.sample_bilinear(params.boundary_mode, source_x, source_y);
+                    continue;
+                }
+                target[pixel_index] = ctx
+                    .sample_nearest(params.boundary_mode, source_x, source_y, 0.0)
+                    .unwrap_or(0.0);
+            }
+        }
+    }
+
+    fn velocity(&self, _params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        let Some(field) = ctx.flow_field else {
+            return (0.0, 0.0);
+        };
+        let (width, height) = (ctx.width as usize, ctx.height as usize);
+        let x = (x.max(0.0) as usize).min(width - 1);
+        let y = (y.max(0.0) as usize).min(height - 1);
+        field.sample(x, y, width, height)
+    }
+}
+
+pub(crate) struct Warp;
+
+impl Movement for Warp {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let Some(perspective) = ctx.perspective else {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        };
+        let width = ctx.width as usize;
+        let (scale_x, scale_y) = (ctx.width as f32, ctx.height as f32);
+        let cols = ctx.active_cols();
+
+        for y in rows {
+            let dest_row_base = y * width;
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                // Each pixel reads the point the warp carries onto it; nothing comes from
+                // behind the viewer
+                let Some((source_u, source_v)) =
+                    Perspective::map(&perspective.inverse, x as f32 / scale_x, y as f32 / scale_y)
+                else {
+                    target[pixel_index] = 0.0;
+                    continue;
+                };
+                let (source_x, source_y) = (source_u * scale_x, source_v * scale_y);
+
+                if params.sampling_mode == SamplingMode::Bilinear {
+                    target[pixel_index] =
+                        ctx.sample_bilinear(params.boundary_mode, source_x, source_y);
+                    continue;
+                }
+                target[pixel_index] = ctx
+                    .sample_nearest(params.boundary_mode, source_x, source_y, 0.0)
+                    .unwrap_or(0.0);
+            }
+        }
+    }
+
+    fn velocity(&self, _params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        let (scale_x, scale_y) = (ctx.width as f32, ctx.height as f32);
+        ctx.perspective
+            .and_then(|perspective| {
+                Perspective::map(&perspective.forward, x / scale_x, y / scale_y)
+            })
+            .map_or((0.0, 0.0), |(u, v)| (u * scale_x - x, v * scale_y - y))
+    }
+}
+
+pub(crate) struct Turbulence;
+
+impl Movement for Turbulence {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+
+        let strength = params.strength;
+        if strength.abs() < 0.01 || params.scale <= 0.0 {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+        let inverse_scale = 1.0 / params.scale;
+
+        for y in rows {
+            let noise_y = y as f32 * inverse_scale;
+            let dest_row_base = y * width;
+
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                let (gradient_x, gradient_y) =
+                    value_noise_gradient(params.seed, x as f32 * inverse_scale, noise_y, ctx.phase);
+                // Curl of the noise: divergence-free, so trails swirl without bunching up
+                let source_x = x as f32 - gradient_y * strength;
+                let source_y = y as f32 + gradient_x * strength;
+
+                if params.sampling_mode == SamplingMode::Bilinear {
+                    target[pixel_index] =
+                        ctx.sample_bilinear(params.boundary_mode, source_x, source_y);
+                    continue;
+                }
+
+                target[pixel_index] = ctx
+                    .sample_nearest(params.boundary_mode, source_x, source_y, 0.0)
+                    .unwrap_or(0.0);
+            }
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        if params.scale <= 0.0 {
+            return (0.0, 0.0);
+        }
+        let inverse_scale = 1.0 / params.scale;
+        let (gradient_x, gradient_y) =
+            value_noise_gradient(params.seed, x * inverse_scale, y * inverse_scale, ctx.phase);
+        (gradient_y * params.strength, -gradient_x * params.strength)
+    }
+}
+
+pub(crate) struct Attract;
+
+impl Movement for Attract {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        if ctx.attractors.is_empty() {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+        // Points in buffer pixels with their pull, range and squared range
+        let points: Vec<(f32, f32, f32, f32, f32)> = ctx
+            .attractors
+            .iter()
+            .map(|attractor| {
+                let (x, y) = ctx.oriented_point(attractor.x, attractor.y);
+                let radius = attractor.radius.max(1.0);
+                (
+                    x * ctx.width as f32,
+                    y * ctx.height as f32,
+                    attractor.strength,
+                    radius,
+                    radius * radius,
+                )
+            })
+            .collect();
+
+        for y in rows {
+            let dest_row_base = y * width;
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                let (x_f32, y_f32) = (x as f32, y as f32);
+                let (mut velocity_x, mut velocity_y) = (0.0, 0.0);
+                for &(point_x, point_y, strength, radius, radius_squared) in &points {
+                    let (pull_x, pull_y) = attractor_pull(
+                        point_x - x_f32,
+                        point_y - y_f32,
+                        strength,
+                        radius,
+                        radius_squared,
+                    );
+                    velocity_x += pull_x;
+                    velocity_y += pull_y;
+                }
+                // Each pixel reads the point the field carries onto it
+                let source_x = x_f32 - velocity_x;
+                let source_y = y_f32 - velocity_y;
+
+                target[pixel_index] = if params.sampling_mode == SamplingMode::Bilinear {
+                    ctx.sample_bilinear(params.boundary_mode, source_x, source_y)
+                } else {
+                    ctx.sample_nearest(params.boundary_mode, source_x, source_y, 0.0)
+                        .unwrap_or(0.0)
+                };
+            }
+        }
+    }
+
+    fn velocity(&self, _params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        ctx.attractors
+            .iter()
+            .fold((0.0, 0.0), |(velocity_x, velocity_y), attractor| {
+                let (point_x, point_y) = ctx.oriented_point(attractor.x, attractor.y);
+                let radius = attractor.radius.max(1.0);
+                let (pull_x, pull_y) = attractor_pull(
+                    point_x * ctx.width as f32 - x,
+                    point_y * ctx.height as f32 - y,
+                    attractor.strength,
+                    radius,
+                    radius * radius,
+                );
+                (velocity_x + pull_x, velocity_y + pull_y)
+            })
+    }
+}
+
+// Pull towards an attractor `dx`, `dy` away with `radius` (and its square)
+#[inline]
+fn attractor_pull(dx: f32, dy: f32, strength: f32, radius: f32, radius_squared: f32) -> (f32, f32) {
+    let distance_squared = dx * dx + dy * dy;
+    if distance_squared >= radius_squared || distance_squared < 0.25 {
+        return (0.0, 0.0);
+    }
+    let distance = distance_squared.sqrt();
+    // Smooth falloff to zero at the radius; an attractor never pulls a trail past its point
+    let falloff = 1.0 - distance / radius;
+    let step = (strength * falloff * falloff).min(distance);
+    (dx / distance * step, dy / distance * step)
+}
+
+// Shift everything by this frame's shake offset, like a one-frame direction move
+pub(crate) struct Shake;
+
+impl Movement for Shake {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let (offset_x, offset_y) = ctx.shake_offset;
+        let step = MotionOptions {
+            angle_radians: offset_y.atan2(offset_x),
+            speed: offset_x.hypot(offset_y),
+            ..*params
+        };
+        Direction.apply(&step, ctx, rows, target);
+    }
+
+    fn velocity(
+        &self,
+        _params: &MotionOptions,
+        ctx: &FrameContext,
+        _x: f32,
+        _y: f32,
+    ) -> (f32, f32) {
+        ctx.shake_offset
+    }
+}
+
+pub(crate) struct Kaleidoscope;
+
+impl Movement for Kaleidoscope {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        if params.segments < 2 {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+        let segment = std::f32::consts::TAU / params.segments as f32;
+        let half_segment = segment * 0.5;
+        let polar = ctx.polar();
+
+        for y in rows {
+            let dest_row_base = y * width;
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                // Fold the angle into the first half wedge, mirroring every other half, so
+                // each wedge reflects the same slice of the trails; pixels already in that
+                // slice read themselves
+                let angle = (polar.angle.get(pixel_index) - params.segment_offset)
+                    .rem_euclid(std::f32::consts::TAU);
+                let local = angle % segment;
+                let folded = if local > half_segment {
+                    segment - local
+                } else {
+                    local
+                } + params.segment_offset;
+                let distance = ctx.picture_distance(polar.distance.get(pixel_index));
+                let source_x = ctx.center_x + distance * folded.cos();
+                let source_y = ctx.center_y + distance * folded.sin();
+
+                target[pixel_index] = if params.sampling_mode == SamplingMode::Bilinear {
+                    ctx.sample_bilinear(params.boundary_mode, source_x, source_y)
+                } else {
+                    ctx.sample_nearest(params.boundary_mode, source_x, source_y, 0.0)
+                        .unwrap_or(0.0)
+                };
+            }
+        }
+    }
+}
+
+// Longest streak the smear mode draws in one frame, bounding its per-pixel search
+const MAX_SMEAR_LENGTH: f32 = 64.0;
+
+pub(crate) struct Smear;
+
+impl Smear {
+    // How far a trail of this brightness is dragged per frame
+    #[inline]
+    fn reach(params: &MotionOptions, value: f32) -> f32 {
+        params.strength.min(MAX_SMEAR_LENGTH) * (value / 255.0).clamp(0.0, 1.0)
+    }
+}
+
+impl Movement for Smear {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        if params.strength <= 0.0 {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+        let width = ctx.width as usize;
+        let cols = ctx.active_cols();
+        let steps = params.strength.min(MAX_SMEAR_LENGTH).ceil() as usize;
+        let (sin, cos) = params.angle_radians.sin_cos();
+        let boundary = params.boundary_mode;
+
+        // Gather form of the streaks: each pixel keeps the brightest trail upstream along
+        // the axis that reaches it, with the last partial pixel of a streak faded by how
+        // much of it the streak covers
+        for y in rows {
+            let dest_row_base = y * width;
+            for x in cols.clone() {
+                let pixel_index = dest_row_base + x;
+                let mut value = ctx.source[pixel_index];
+                for step in 1..=steps {
+                    let distance = step as f32;
+                    let source_x = x as f32 - cos * distance;
+                    let source_y = y as f32 - sin * distance;
+                    let upstream = if params.sampling_mode == SamplingMode::Bilinear {
+                        ctx.sample_bilinear(boundary, source_x, source_y)
+                    } else {
+                        ctx.sample_nearest(boundary, source_x, source_y, 0.0)
+                            .unwrap_or(0.0)
+                    };
+                    let coverage =
+                        (Smear::reach(params, upstream) - distance + 1.0).clamp(0.0, 1.0);
+                    value = value.max(upstream * coverage);
+                }
+                target[pixel_index] = value;
+            }
+        }
+    }
+
+    fn velocity(&self, params: &MotionOptions, ctx: &FrameContext, x: f32, y: f32) -> (f32, f32) {
+        let value = ctx
+            .sample_nearest(params.boundary_mode, x, y, 0.0)
+            .unwrap_or(0.0);
+        let (sin, cos) = params.angle_radians.sin_cos();
+        let reach = Smear::reach(params, value);
+        (cos * reach, sin * reach)
+    }
+}
+
+// Share of a pixel's heat that flows to each neighbour along an axis per diffusion step,
+// half the stability limit of the explicit scheme so the kernel stays smooth
+const DIFFUSE_RATE: f32 = 0.25;
+
+pub(crate) struct Diffuse;
+
+impl Diffuse {
+    // Weights of the sources at offsets -iterations..=iterations along one axis after
+    // `iterations` diffusion steps, with `bias` (-1..1) of the flow towards positive offsets
+    fn kernel(iterations: usize, bias: f32) -> Vec<f32> {
+        let from_before = DIFFUSE_RATE * (1.0 + bias);
+        let from_after = DIFFUSE_RATE * (1.0 - bias);
+        let stay = 1.0 - 2.0 * DIFFUSE_RATE;
+        let mut kernel = vec![0.0; 2 * iterations + 1];
+        kernel[iterations] = 1.0;
+        let mut previous = kernel.clone();
+        for _ in 0..iterations {
+            previous.copy_from_slice(&kernel);
+            for (offset, weight) in kernel.iter_mut().enumerate() {
+                let before = previous.get(offset + 1).copied().unwrap_or(0.0);
+                let after = offset.checked_sub(1).map_or(0.0, |offset| previous[offset]);
+                *weight = previous[offset] * stay + before * from_before + after * from_after;
+            }
+        }
+        kernel
+    }
+}
+
+impl Movement for Diffuse {
+    fn apply(
+        &self,
+        params: &MotionOptions,
+        ctx: &FrameContext,
+        rows: Range<usize>,
+        target: &mut RowBand,
+    ) {
+        let iterations = params.diffuse_iterations.min(MAX_DIFFUSE_ITERATIONS) as usize;
+        let cols = ctx.active_cols();
+        // An empty active area (e.g. a zero-width ROI) has nothing to spread
+        if iterations == 0 || cols.is_empty() || rows.is_empty() {
+            ctx.copy_rows_unmoved(rows, target);
+            return;
+        }
+        let (width, height) = (ctx.width as usize, ctx.height as usize);
+        let boundary = params.boundary_mode;
+        let (sin, cos) = params.angle_radians.sin_cos();
+        let bias = params.diffuse_bias.clamp(0.0, 1.0);
+        let kernel_x = Diffuse::kernel(iterations, bias * cos);
+        let kernel_y = Diffuse::kernel(iterations, bias * sin);
+
+        // The steps of both axes commute, so all horizontal steps run first, over the band
+        // and the rows within reach of it, then all vertical ones
+        let reach = iterations as i32;
+        let first_row = rows.start as i32 - reach;
+        let band_rows = rows.len() + 2 * iterations;
+        let mut horizontal = vec![0.0; band_rows * cols.len()];
+        for (row, spread) in horizontal.chunks_exact_mut(cols.len()).enumerate() {
+            let y = first_row + row as i32;
+            for (x, spread) in cols.clone().zip(spread.iter_mut()) {
+                *spread = kernel_x
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &weight)| weight > 0.0)
+                    .filter_map(|(offset, &weight)| {
+                        let source_x = x as i32 + offset as i32 - reach;
+                        boundary
+                            .resolve(source_x, y, width, height)
+                            .map(|(x, y)| weight * ctx.source[y * width + x])
+                    })
+                    .sum();
+            }
+        }
+        for y in rows.clone() {
+            let band_row = y - rows.start;
+            for (col, x) in cols.clone().enumerate() {
+                target[y * width + x] = kernel_y
+                    .iter()
+                    .enumerate()
+                    .map(|(offset, &weight)| {
+                        weight * horizontal[(band_row + offset) * cols.len() + col]
+                    })
+                    .sum();
+            }
+        }
+    }
+
+    fn velocity(
+        &self,
+        params: &MotionOptions,
+        _ctx: &FrameContext,
+        _x: f32,
+        _y: f32,
+    ) -> (f32, f32) {
+        // Each step moves the heat's center by twice the rate times the bias
+        let iterations = params.diffuse_iterations.min(MAX_DIFFUSE_ITERATIONS) as f32;
+        let drift = 2.0 * DIFFUSE_RATE * params.diffuse_bias.clamp(0.0, 1.0) * iterations;
+        let (sin, cos) = params.angle_radians.sin_cos();
+        (cos * drift, sin * drift)
+    }
+}
+
+// A point of the attract move mode, see `set_attractors`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Attractor {
+    pub x: f32,
+    pub y: f32,
+    pub strength: f32,
+    pub radius: f32,
+}
+
+// Attractors per detector (each costs a distance per pixel) and the values of the fields
+// `set_attractors` may leave out
+pub(crate) const MAX_ATTRACTORS: usize = 16;
+#[cfg(feature = "wasm")]
+pub(crate) const ATTRACTOR_DEFAULT_STRENGTH: f32 = 2.0;
+#[cfg(feature = "wasm")]
+pub(crate) const ATTRACTOR_DEFAULT_RADIUS: f32 = 64.0;
+
+// Vector field of the flow move mode, see `set_flow_field`
+pub(crate) struct FlowField {
+    pub(crate) grid_width: usize,
+    pub(crate) grid_height: usize,
+    pub(crate) vectors: Vec<f32>,
+}
+
+impl FlowField {
+    // Vector at pixel (x, y) of a `width` x `height` frame, bilinear between cell centers
+    fn sample(&self, x: usize, y: usize, width: usize, height: usize) -> (f32, f32) {
+        let taps = |position: usize, size: usize, cells: usize| {
+            let cell = ((position as f32 + 0.5) * cells as f32 / size as f32 - 0.5)
+                .clamp(0.0, (cells - 1) as f32);
+            let first = cell as usize;
+            (first, (first + 1).min(cells - 1), cell - first as f32)
+        };
+        let (x0, x1, tx) = taps(x, width, self.grid_width);
+        let (y0, y1, ty) = taps(y, height, self.grid_height);
+        let at = |cx: usize, cy: usize, component: usize| {
+            self.vectors[(cy * self.grid_width + cx) * 2 + component]
+        };
+        let component = |c: usize| {
+            let top = at(x0, y0, c) + (at(x1, y0, c) - at(x0, y0, c)) * tx;
+            let bottom = at(x0, y1, c) + (at(x1, y1, c) - at(x0, y1, c)) * tx;
+            top + (bottom - top) * ty
+        };
+        (component(0), component(1))
+    }
+}
+
+// Homography of the perspective move mode applied every frame, in fractions of the frame
+// size, with its inverse for reading the trails back; see `set_perspective_corners`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Perspective {
+    forward: [f32; 9],
+    inverse: [f32; 9],
+}
+
+impl Perspective {
+    // From a row-major 3x3 matrix; None if it can't be inverted
+    pub(crate) fn from_matrix(forward: [f32; 9]) -> Option<Perspective> {
+        let m = forward.map(|value| value as f64);
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0 * 3 + c0] * m[r1 * 3 + c1] - m[r0 * 3 + c1] * m[r1 * 3 + c0]
+        };
+        let adjugate = [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ];
+        let determinant = m[0] * adjugate[0] + m[1] * adjugate[3] + m[2] * adjugate[6];
+        if !determinant.is_finite() || determinant.abs() < 1e-12 {
+            return None;
+        }
+        Some(Perspective {
+            forward,
+            inverse: adjugate.map(|value| (value / determinant) as f32),
+        })
+    }
+
+    // The warp that moves the top-left, top-right, bottom-right and bottom-left corners of
+    // the frame by the given (dx, dy) offsets (Heckbert's square-to-quad mapping)
+    pub(crate) fn from_corner_offsets(offsets: [f32; 8]) -> Option<Perspective> {
+        let corner = |index: usize, (x, y): (f64, f64)| {
+            (
+                x + offsets[index * 2] as f64,
+                y + offsets[index * 2 + 1] as f64,
+            )
+        };
+        let (x0, y0) = corner(0, (0.0, 0.0));
+        let (x1, y1) = corner(1, (1.0, 0.0));
+        let (x2, y2) = corner(2, (1.0, 1.0));
+        let (x3, y3) = corner(3, (0.0, 1.0));
+        let (dx1, dx2, dx3) = (x1 - x2, x3 - x2, x0 - x1 + x2 - x3);
+        let (dy1, dy2, dy3) = (y1 - y2, y3 - y2, y0 - y1 + y2 - y3);
+        let determinant = dx1 * dy2 - dx2 * dy1;
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        let g = (dx3 * dy2 - dx2 * dy3) / determinant;
+        let h = (dx1 * dy3 - dx3 * dy1) / determinant;
+        Perspective::from_matrix(
+            [
+                x1 - x0 + g * x1,
+                x3 - x0 + h * x3,
+                x0,
+                y1 - y0 + g * y1,
+                y3 - y0 + h * y3,
+                y0,
+                g,
+                h,
+                1.0,
+            ]
+            .map(|value| value as f32),
+        )
+    }
+
+    // The point the warp takes to (u, v)
+    pub(crate) fn map_back(&self, u: f32, v: f32) -> Option<(f32, f32)> {
+        Perspective::map(&self.inverse, u, v)
+    }
+
+    // Where `matrix` takes (u, v); None for points it sends behind the viewer
+    fn map(matrix: &[f32; 9], u: f32, v: f32) -> Option<(f32, f32)> {
+        let w = matrix[6] * u + matrix[7] * v + matrix[8];
+        if w <= f32::EPSILON {
+            return None;
+        }
+        Some((
+            (matrix[0] * u + matrix[1] * v + matrix[2]) / w,
+            (matrix[3] * u + matrix[4] * v + matrix[5]) / w,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::LutKey;
+    use crate::{BoundaryMode, MotionOptions, MoveType, RadialProfile, SamplingMode};
+
+    const WIDTH: usize = 32;
+    const HEIGHT: usize = 24;
+
+    const MODES: [MoveType; 15] = [
+        MoveType::Direction,
+        MoveType::Radial,
+        MoveType::Spiral,
+        MoveType::Wave,
+        MoveType::Zoom,
+        MoveType::Rotate,
+        MoveType::Turbulence,
+        MoveType::Flow,
+        MoveType::Kaleidoscope,
+        MoveType::Shake,
+        MoveType::Attract,
+        MoveType::Perspective,
+        MoveType::Smear,
+        MoveType::Feedback,
+        MoveType::Diffuse,
+    ];
+
+    // Trails plus the lookup tables and inputs the modes read, laid out like the detector's
+    struct Scene {
+        source: Vec<f32>,
+        luts: DetectorLuts,
+        flow_field: FlowField,
+        attractors: [Attractor; 1],
+        perspective: Perspective,
+    }
+
+    impl Scene {
+        fn new(source: Vec<f32>) -> Scene {
+            Scene {
+                source,
+                luts: DetectorLuts::build(LutKey {
+                    width: WIDTH as u32,
+                    height: HEIGHT as u32,
+                    area: [0, 0, WIDTH, HEIGHT],
+                    profile: RadialProfile::default(),
+                    lens: None,
+                    packed: false,
+                }),
+                flow_field: FlowField {
+                    grid_width: 1,
+                    grid_height: 1,
+                    vectors: vec![3.0, 1.0],
+                },
+                attractors: [Attractor {
+                    x: 0.25,
+                    y: 0.75,
+                    strength: 3.0,
+                    radius: 20.0,
+                }],
+                // The top edge tips away from the viewer
+                perspective: Perspective::from_corner_offsets([
+                    0.04, 0.03, -0.04, 0.03, 0.0, 0.0, 0.0, 0.0,
+                ])
+                .unwrap(),
+            }
+        }
+
+        fn uniform(value: f32) -> Scene {
+            Scene::new(vec![value; WIDTH * HEIGHT])
+        }
+
+        fn context(&self) -> FrameContext<'_> {
+            let max_radius = (WIDTH as f32).hypot(HEIGHT as f32) / 2.0;
+            FrameContext {
+                width: WIDTH as u32,
+                height: HEIGHT as u32,
+                cols: 0..WIDTH,
+                source: &self.source,
+                persistence_pyramid: &[],
+                luts: &self.luts,
+                lens: None,
+                center_x: WIDTH as f32 / 2.0,
+                center_y: HEIGHT as f32 / 2.0,
+                high_quality_radius: max_radius * 0.3,
+                medium_quality_radius: max_radius * 0.7,
+                phase: 0.5,
+                orientation_quarter_turns: 0,
+                move_transition: None,
+                flow_field: Some(&self.flow_field),
+                perspective: Some(&self.perspective),
+                attractors: &self.attractors,
+                shake_offset: (2.0, -1.0),
+            }
+        }
+
+        // One movement pass over the whole frame; pixels a mode leaves out stay negative
+        fn moved(&self, params: &MotionOptions) -> Vec<f32> {
+            let ctx = self.context();
+            let mut target = vec![-1.0; WIDTH * HEIGHT];
+            ctx.move_rows_with(params, 0..HEIGHT, &mut RowBand::new(&mut target, 0));
+            target
+        }
+    }
+
+    // Options that make every mode move the trails by a few pixels
+    fn options(
+        move_type: MoveType,
+        boundary_mode: BoundaryMode,
+        sampling_mode: SamplingMode,
+    ) -> MotionOptions {
+        MotionOptions {
+            move_type: Some(move_type),
+            boundary_mode,
+            sampling_mode,
+            angle_radians: 0.3,
+            speed: 3.0,
+            rotation_speed: 0.2,
+            zoom_factor: 0.8,
+            ..MotionOptions::default()
+        }
+    }
+
+    #[test]
+    fn uniform_trails_stay_uniform_unless_the_boundary_clears() {
+        let scene = Scene::uniform(100.0);
+        for move_type in MODES {
+            for boundary in [
+                BoundaryMode::Wrap,
+                BoundaryMode::Clamp,
+                BoundaryMode::Mirror,
+            ] {
+                for sampling in [SamplingMode::Nearest, SamplingMode::Bilinear] {
+                    let moved = scene.moved(&options(move_type, boundary, sampling));
+                    assert!(
+                        moved.iter().all(|&value| (value - 100.0).abs() < 1e-3),
+                        "{} with {} boundary and {} sampling",
+                        move_type.name(),
+                        boundary.name(),
+                        sampling.name()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cleared_boundary_only_removes_trails() {
+        let scene = Scene::uniform(100.0);
+        for move_type in MODES {
+            for sampling in [SamplingMode::Nearest, SamplingMode::Bilinear] {
+                let moved = scene.moved(&options(move_type, BoundaryMode::Clear, sampling));
+                assert!(
+                    moved.iter().all(|&value| (0.0..=100.001).contains(&value)),
+                    "{} with {} sampling",
+                    move_type.name(),
+                    sampling.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn direction_reads_past_the_edges_through_the_boundary_mode() {
+        // A trail pixel on the left and one on the right edge of a row, moved 3 pixels right
+        let row = 5;
+        let lit = |moved: &[f32]| -> Vec<usize> {
+            (0..WIDTH)
+                .filter(|&x| moved[row * WIDTH + x] > 0.0)
+                .collect()
+        };
+        let mut source = vec![0.0; WIDTH * HEIGHT];
+        source[row * WIDTH] = 100.0;
+        source[row * WIDTH + WIDTH - 1] = 100.0;
+        let scene = Scene::new(source);
+        let mut params = options(
+            MoveType::Direction,
+            BoundaryMode::Clear,
+            SamplingMode::Nearest,
+        );
+        params.angle_radians = 0.0;
+
+        for (boundary, expected) in [
+            (BoundaryMode::Clear, vec![3]),
+            (BoundaryMode::Wrap, vec![2, 3]),
+            (BoundaryMode::Clamp, vec![0, 1, 2, 3]),
+            (BoundaryMode::Mirror, vec![2, 3]),
+        ] {
+            params.boundary_mode = boundary;
+            assert_eq!(lit(&scene.moved(&params)), expected, "{}", boundary.name());
+        }
+    }
+
+    #[test]
+    fn velocity_follows_the_moved_trails() {
+        // A 3x3 trail blob around (5, 8), whose centroid should travel by the velocity
+        let (x, y) = (5, 8);
+        let mut source = vec![0.0; WIDTH * HEIGHT];
+        for blob_y in y - 1..=y + 1 {
+            source[blob_y * WIDTH + x - 1..=blob_y * WIDTH + x + 1].fill(100.0);
+        }
+        let scene = Scene::new(source);
+        let centroid = |moved: &[f32]| {
+            let (mut sum, mut sum_x, mut sum_y) = (0.0, 0.0, 0.0);
+            for (pixel_index, &value) in moved.iter().enumerate() {
+                sum += value;
+                sum_x += value * (pixel_index % WIDTH) as f32;
+                sum_y += value * (pixel_index / WIDTH) as f32;
+            }
+            (sum_x / sum, sum_y / sum)
+        };
+
+        // Off-center quality tiers move the trails a little less than the velocity says.
+        // The kaleidoscope mirrors rather than carries the trails.
+        for move_type in MODES
+            .into_iter()
+            .filter(|&mode| mode != MoveType::Kaleidoscope)
+        {
+            let params = options(move_type, BoundaryMode::Clear, SamplingMode::Bilinear);
+            let (moved_x, moved_y) = centroid(&scene.moved(&params));
+            let (velocity_x, velocity_y) =
+                scene.context().velocity_field(&params)(x as f32, y as f32);
+            assert!(
+                (moved_x - (x as f32 + velocity_x)).abs() < 1.5
+                    && (moved_y - (y as f32 + velocity_y)).abs() < 1.5,
+                "{}: trails at ({}, {}), velocity ({}, {})",
+                move_type.name(),
+                moved_x,
+                moved_y,
+                velocity_x,
+                velocity_y
+            );
+        }
+    }
+
+    #[test]
+    fn wave_on_a_half_turned_source_is_the_half_turned_wave() {
+        // Rows and columns at different levels, so any mismatch shows
+        let source: Vec<f32> = (0..WIDTH * HEIGHT)
+            .map(|pixel_index| ((pixel_index % WIDTH) * 3 + (pixel_index / WIDTH) * 7) as f32)
+            .collect();
+        let turned = |frame: &[f32]| frame.iter().rev().copied().collect::<Vec<f32>>();
+        let moved = |scene: &Scene, quarter_turns: u32, params: &MotionOptions| {
+            let mut ctx = scene.context();
+            ctx.orientation_quarter_turns = quarter_turns;
+            // The quality tiers are centered a half pixel off the mirrored frame's
+            ctx.high_quality_radius = f32::MAX;
+            let mut target = vec![-1.0; WIDTH * HEIGHT];
+            ctx.move_rows_with(params, 0..HEIGHT, &mut RowBand::new(&mut target, 0));
+            target
+        };
+        let upright = Scene::new(source.clone());
+        let upside_down = Scene::new(turned(&source));
+
+        for direction in [0, 1] {
+            let params = MotionOptions {
+                amplitude: 4.0,
+                frequency: 0.4,
+                direction,
+                ..options(MoveType::Wave, BoundaryMode::Wrap, SamplingMode::Bilinear)
+            };
+            let expected = turned(&moved(&upright, 0, &params));
+            let actual = moved(&upside_down, 2, &params);
+            assert!(
+                expected
+                    .iter()
+                    .zip(&actual)
+                    .all(|(expected, actual)| (expected - actual).abs() < 1e-3),
+                "direction {}",
+                direction
+            );
+        }
+    }
+
+    #[test]
+    fn perspective_moves_the_corners_by_their_offsets() {
+        let offsets = [0.1, 0.05, -0.1, 0.05, 0.02, -0.03, 0.0, 0.0];
+        let perspective = Perspective::from_corner_offsets(offsets).unwrap();
+        for (index, corner) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)]
+            .into_iter()
+            .enumerate()
+        {
+            let (u, v) = Perspective::map(&perspective.forward, corner.0, corner.1).unwrap();
+            assert!((u - corner.0 - offsets[index * 2]).abs() < 1e-5);
+            assert!((v - corner.1 - offsets[index * 2 + 1]).abs() < 1e-5);
+            let (back_u, back_v) = Perspective::map(&perspective.inverse, u, v).unwrap();
+            assert!((back_u - corner.0).abs() < 1e-5 && (back_v - corner.1).abs() < 1e-5);
+        }
+        assert!(Perspective::from_matrix([1.0, 2.0, 0.0, 2.0, 4.0, 0.0, 0.0, 0.0, 1.0]).is_none());
+    }
+
+    #[test]
+    fn feedback_fuses_zoom_and_rotation() {
+        let source = (0..WIDTH * HEIGHT)
+            .map(|pixel_index| ((pixel_index * 37) % 251) as f32)
+            .collect();
+        let scene = Scene::new(source);
+        let params = |move_type, zoom_factor, twist| MotionOptions {
+            zoom_factor,
+            twist,
+            rotation_speed: twist,
+            ..options(move_type, BoundaryMode::Wrap, SamplingMode::Bilinear)
+        };
+        let close = |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-2);
+
+        assert!(close(
+            &scene.moved(&params(MoveType::Feedback, 1.1, 0.0)),
+            &scene.moved(&params(MoveType::Zoom, 1.1, 0.0))
+        ));
+        assert!(close(
+            &scene.moved(&params(MoveType::Feedback, 1.0, 0.3)),
+            &scene.moved(&params(MoveType::Rotate, 1.0, 0.3))
+        ));
+    }
+
+    #[test]
+    fn smear_drags_bright_trails_further_than_dim_ones() {
+        // A bright and a dim trail pixel on separate rows, smeared to the right
+        let streak = |moved: &[f32], row: usize| -> usize {
+            (0..WIDTH)
+                .filter(|&x| moved[row * WIDTH + x] >= 1.0)
+                .count()
+        };
+        let mut source = vec![0.0; WIDTH * HEIGHT];
+        source[4 * WIDTH + 2] = 255.0;
+        source[10 * WIDTH + 2] = 64.0;
+        let scene = Scene::new(source);
+        let mut params = options(MoveType::Smear, BoundaryMode::Clear, SamplingMode::Nearest);
+        params.angle_radians = 0.0;
+        params.strength = 8.0;
+
+        let moved = scene.moved(&params);
+        assert_eq!(streak(&moved, 4), 9);
+        assert_eq!(streak(&moved, 10), 3);
+        assert!(moved[4 * WIDTH + 2..4 * WIDTH + 11]
+            .iter()
+            .all(|&value| value == 255.0));
+    }
+
+    #[test]
+    fn diffuse_spreads_heat_without_losing_it() {
+        let mut source = vec![0.0; WIDTH * HEIGHT];
+        source[12 * WIDTH + 16] = 255.0;
+        let scene = Scene::new(source);
+        let mut params = options(MoveType::Diffuse, BoundaryMode::Wrap, SamplingMode::Nearest);
+        params.diffuse_iterations = 6;
+        params.diffuse_bias = 0.0;
+        let heat_center = |moved: &[f32]| {
+            let total: f32 = moved.iter().sum();
+            let x: f32 = moved
+                .iter()
+                .enumerate()
+                .map(|(index, value)| (index % WIDTH) as f32 * value)
+                .sum();
+            (total, x / total)
+        };
+
+        let spread = scene.moved(&params);
+        let (total, center_x) = heat_center(&spread);
+        assert!((total - 255.0).abs() < 1e-2);
+        assert!((center_x - 16.0).abs() < 1e-3);
+        assert!(spread[12 * WIDTH + 16] < 255.0 && spread[12 * WIDTH + 19] > 0.0);
+        assert_eq!(spread[12 * WIDTH + 23], 0.0);
+
+        // Biased heat drifts downstream by the speed the mode reports
+        params.angle_radians = 0.0;
+        params.diffuse_bias = 0.5;
+        let drifted = scene.moved(&params);
+        let (total, center_x) = heat_center(&drifted);
+        let (velocity_x, _) = Diffuse.velocity(&params, &scene.context(), 16.0, 12.0);
+        assert!((total - 255.0).abs() < 1e-2);
+        assert!((center_x - 16.0 - velocity_x).abs() < 1e-3);
+        assert!(velocity_x > 0.0);
+    }
+
+    #[test]
+    fn parameter_transition_eases_to_the_target() {
+        let from = MotionOptions {
+            speed: 0.0,
+            angle_radians: 0.1,
+            ..MotionOptions::default()
+        };
+        let to = MotionOptions {
+            move_type: Some(MoveType::Rotate),
+            speed: 8.0,
+            angle_radians: TAU - 0.1,
+            ..from
+        };
+        let mut transition = ParameterTransition {
+            from,
+            to,
+            frame: 0,
+            total_frames: 4,
+        };
+        let passed = MotionOptions {
+            threshold: 50.0,
+            ..from
+        };
+
+        let speeds: Vec<f32> = (0..4)
+            .map(|_| {
+                let stepped = transition.step(&passed).unwrap();
+                assert_eq!(stepped.move_type, Some(MoveType::Rotate));
+                assert_eq!(stepped.threshold, 50.0);
+                // Through 0, not the long way round
+                let angle = stepped.angle_radians.rem_euclid(TAU);
+                assert!(!(0.1..TAU - 0.1).contains(&angle));
+                stepped.speed
+            })
+            .collect();
+        assert!(speeds.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(speeds[1], 4.0);
+        assert_eq!(speeds[3], 8.0);
+        assert!(transition.step(&passed).is_none());
+    }
+
+    #[test]
+    fn unknown_mode_leaves_the_trails_in_place() {
+        let source: Vec<f32> = (0..WIDTH * HEIGHT).map(|index| index as f32).collect();
+        let scene = Scene::new(source.clone());
+        let params = MotionOptions {
+            move_type: None,
+            ..MotionOptions::default()
+        };
+        assert_eq!(scene.moved(&params), source);
+    }
+}
@@ -0,0 +1,166 @@
+// Particles spawned where motion passes the threshold and carried along by their own
+// velocity and, optionally, the move mode's field. The simulation stays in Rust so JS only
+// ever sees the composited pixels, not thousands of positions per frame.
+use crate::movement::Prng;
+use crate::render::PixelFormat;
+
+// Largest initial speed of a particle in pixels per frame, and the share of its velocity
+// kept from one frame to the next
+const PARTICLE_SPEED: f32 = 1.5;
+const PARTICLE_DRAG: f32 = 0.97;
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    x: f32,
+    y: f32,
+    velocity_x: f32,
+    velocity_y: f32,
+    // Frames left to live
+    life: u32,
+}
+
+pub(crate) struct ParticleField {
+    capacity: usize,
+    lifetime: u32,
+    // Chance of a moving pixel spawning a particle each analysed frame
+    spawn_chance: f32,
+    pub(crate) follow_movement: bool,
+    particles: Vec<Particle>,
+    rng: Prng,
+}
+
+impl ParticleField {
+    pub(crate) fn new(
+        capacity: usize,
+        lifetime: u32,
+        spawn_chance: f32,
+        follow_movement: bool,
+    ) -> ParticleField {
+        ParticleField {
+            capacity,
+            lifetime: lifetime.max(1),
+            spawn_chance: spawn_chance.clamp(0.0, 1.0),
+            follow_movement,
+            particles: Vec::with_capacity(capacity),
+            rng: Prng::new(0),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.particles.capacity() * std::mem::size_of::<Particle>()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    // Maybe spawn a particle at the center of pixel (x, y), heading off in a random
+    // direction; nothing happens while the field is full
+    pub(crate) fn spawn(&mut self, x: usize, y: usize) {
+        if self.particles.len() >= self.capacity
+            || self.rng.next_signed() * 0.5 + 0.5 >= self.spawn_chance
+        {
+            return;
+        }
+        self.particles.push(Particle {
+            x: x as f32 + 0.5,
+            y: y as f32 + 0.5,
+            velocity_x: self.rng.next_signed() * PARTICLE_SPEED,
+            velocity_y: self.rng.next_signed() * PARTICLE_SPEED,
+            life: self.lifetime,
+        });
+    }
+
+    // One frame: every particle moves by its velocity plus `field` at its position, ages,
+    // and is dropped once it has died or left the `width` x `height` frame
+    pub(crate) fn advance(
+        &mut self,
+        width: usize,
+        height: usize,
+        field: impl Fn(f32, f32) -> (f32, f32),
+    ) {
+        self.particles.retain_mut(|particle| {
+            let (field_x, field_y) = field(particle.x, particle.y);
+            particle.x += particle.velocity_x + field_x;
+            particle.y += particle.velocity_y + field_y;
+            particle.velocity_x *= PARTICLE_DRAG;
+            particle.velocity_y *= PARTICLE_DRAG;
+            particle.life -= 1;
+            particle.life > 0
+                && (0.0..width as f32).contains(&particle.x)
+                && (0.0..height as f32).contains(&particle.y)
+        });
+    }
+
+    // Composite the particles over `output` (`width` pixels wide in `format`), each as a
+    // `scale` x `scale` block at the palette color of its remaining life, keeping the
+    // brighter of it and what is already there in every channel
+    pub(crate) fn render(
+        &self,
+        output: &mut [u8],
+        format: PixelFormat,
+        width: usize,
+        scale: usize,
+        palette: &[[u8; 3]; 256],
+    ) {
+        let height = output.len() / format.bytes_per_pixel() / width.max(1);
+        for particle in &self.particles {
+            let level = particle.life as usize * 255 / self.lifetime as usize;
+            let color = palette[level.min(255)];
+            let (left, top) = (particle.x as usize * scale, particle.y as usize * scale);
+            for y in top..(top + scale).min(height) {
+                for x in left..(left + scale).min(width) {
+                    let pixel_index = y * width + x;
+                    let existing = format.read(output, pixel_index);
+                    let rgb = [0, 1, 2].map(|channel| existing[channel].max(color[channel]));
+                    format.write(output, pixel_index, rgb, 255);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn particles_die_after_their_lifetime() {
+        let mut field = ParticleField::new(8, 3, 1.0, false);
+        field.spawn(10, 10);
+        field.spawn(12, 10);
+        assert_eq!(field.len(), 2);
+
+        field.advance(32, 32, |_, _| (0.0, 0.0));
+        field.advance(32, 32, |_, _| (0.0, 0.0));
+        assert_eq!(field.len(), 2);
+        field.advance(32, 32, |_, _| (0.0, 0.0));
+        assert_eq!(field.len(), 0);
+    }
+
+    #[test]
+    fn the_field_carries_particles_out_of_the_frame() {
+        let mut field = ParticleField::new(8, 100, 1.0, true);
+        field.spawn(28, 16);
+        field.advance(32, 32, |_, _| (2.0, 0.0));
+        assert_eq!(field.len(), 1);
+        // Own velocity is at most PARTICLE_SPEED, so the field wins within a few frames
+        for _ in 0..4 {
+            field.advance(32, 32, |_, _| (2.0, 0.0));
+        }
+        assert_eq!(field.len(), 0);
+    }
+
+    #[test]
+    fn full_fields_spawn_no_more() {
+        let mut field = ParticleField::new(2, 10, 1.0, false);
+        for x in 0..5 {
+            field.spawn(x, 0);
+        }
+        assert_eq!(field.len(), 2);
+    }
+}
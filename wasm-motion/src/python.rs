@@ -0,0 +1,107 @@
+// Python bindings for offline analysis: the exact same detector that runs in the browser,
+// fed with numpy frames instead of canvas ImageData
+use numpy::ndarray::{Array2, Array3};
+use numpy::{IntoPyArray, PyArray2, PyArray3, PyReadonlyArray3, PyUntypedArrayMethods};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::{PyBool, PyDict};
+
+use crate::{MotionDetector, MotionOptions, OptionValue};
+
+#[pyclass(name = "MotionDetector", unsendable)]
+pub struct PyMotionDetector {
+    inner: MotionDetector,
+}
+
+#[pymethods]
+impl PyMotionDetector {
+    #[new]
+    fn new(width: u32, height: u32) -> PyResult<PyMotionDetector> {
+        Ok(PyMotionDetector {
+            inner: MotionDetector::try_new(width, height).map_err(PyValueError::new_err)?,
+        })
+    }
+
+    // Process one (height, width, 4) RGBA uint8 frame and return the RGBA output frame.
+    // Options use the same names and defaults as the JS options object.
+    #[pyo3(signature = (frame, **options))]
+    fn process<'py>(
+        &mut self,
+        py: Python<'py>,
+        frame: PyReadonlyArray3<'py, u8>,
+        options: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyArray3<u8>>> {
+        let width = self.inner.width() as usize;
+        let height = self.inner.height() as usize;
+        if frame.shape() != [height, width, 4] {
+            return Err(PyValueError::new_err(format!(
+                "expected a ({}, {}, 4) RGBA frame, got {:?}",
+                height,
+                width,
+                frame.shape()
+            )));
+        }
+
+        let params = params_from_dict(options)?;
+        let mut output = Array3::<u8>::zeros((height, width, 4));
+        let output_data = output
+            .as_slice_mut()
+            .expect("freshly allocated arrays are contiguous");
+
+        match frame.as_slice() {
+            Ok(current_data) => self.inner.process(current_data, output_data, &params),
+            // Non-contiguous views (e.g. channel slices) are copied into standard layout first
+            Err(_) => {
+                let current_data: Vec<u8> = frame.as_array().iter().copied().collect();
+                self.inner.process(&current_data, output_data, &params)
+            }
+        }
+        .map_err(PyValueError::new_err)?;
+
+        Ok(output.into_pyarray(py))
+    }
+
+    // Current persistence buffer as a (height, width) float32 array in 0..255, at the
+    // processing resolution
+    fn persistence<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray2<f32>> {
+        let (width, height) = self.inner.processing_size();
+        let shape = (height as usize, width as usize);
+        Array2::from_shape_vec(shape, self.inner.persistence().to_vec())
+            .expect("persistence buffer matches the detector resolution")
+            .into_pyarray(py)
+    }
+
+    fn reset_persistence(&mut self) {
+        self.inner.reset_persistence();
+    }
+
+    fn reset_all_state(&mut self) {
+        self.inner.reset_all_state();
+    }
+}
+
+fn params_from_dict(options: Option<&Bound<'_, PyDict>>) -> PyResult<MotionOptions> {
+    let mut params = MotionOptions::default();
+    let Some(options) = options else {
+        return Ok(params);
+    };
+
+    for (key, value) in options.iter() {
+        let key: String = key.extract()?;
+        let result = if let Ok(text) = value.extract::<String>() {
+            params.set_option(&key, OptionValue::Text(&text))
+        } else if value.is_instance_of::<PyBool>() {
+            params.set_option(&key, OptionValue::Bool(value.extract()?))
+        } else {
+            params.set_option(&key, OptionValue::Number(value.extract()?))
+        };
+        result.map_err(PyValueError::new_err)?;
+    }
+
+    Ok(params)
+}
+
+#[pymodule]
+fn motion_detection(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyMotionDetector>()
+}
@@ -0,0 +1,213 @@
+// Global (camera) motion between two luma frames, see `estimate_global_motion`: the shift
+// that best lines the older frame up with the newer one, found by block matching on a sparse
+// sample grid, optionally with the small turn that fits the shifts of a grid of blocks.
+use std::ops::Range;
+
+use crate::GlobalMotion;
+
+// Samples compared per candidate shift, spread over the region; bounds the cost of a search
+const MATCH_SAMPLES: usize = 4096;
+// Fewest samples a candidate must overlap to be trusted
+const MIN_OVERLAP: usize = 16;
+// Blocks per side of the grid the rotation is fitted to, how far a block's shift may differ
+// from the global one, and how distinct a block's best match must be to take part
+const ROTATION_GRID: usize = 4;
+const ROTATION_SEARCH: i32 = 2;
+const ROTATION_MIN_CONFIDENCE: f32 = 0.1;
+
+// Two luma frames of the same size and the region of them to compare
+pub(crate) struct FramePair<'a> {
+    pub(crate) newer: &'a [f32],
+    pub(crate) older: &'a [f32],
+    pub(crate) width: usize,
+    pub(crate) rows: Range<usize>,
+    pub(crate) cols: Range<usize>,
+}
+
+// Best shift of a search with its matching cost and the mean cost of all shifts tried
+struct Match {
+    dx: f32,
+    dy: f32,
+    cost: f32,
+    mean_cost: f32,
+}
+
+impl Match {
+    // How much better the best shift matches than a typical one: 0 for flat or
+    // featureless content, towards 1 for a clear match
+    fn confidence(&self) -> f32 {
+        if self.mean_cost <= 0.0 {
+            return 0.0;
+        }
+        ((self.mean_cost - self.cost) / self.mean_cost).clamp(0.0, 1.0)
+    }
+}
+
+impl FramePair<'_> {
+    // Global motion of the region, searched up to `max_shift` pixels either way
+    pub(crate) fn estimate(&self, max_shift: u32, with_rotation: bool) -> GlobalMotion {
+        let area = self.rows.len() * self.cols.len();
+        if area < MIN_OVERLAP {
+            return GlobalMotion::default();
+        }
+        let stride = ((area / MATCH_SAMPLES) as f32).sqrt().max(1.0) as usize;
+        let translation = self.search(
+            self.rows.clone(),
+            self.cols.clone(),
+            stride,
+            (0, 0),
+            max_shift as i32,
+        );
+        let Some(translation) = translation else {
+            return GlobalMotion::default();
+        };
+        let rotation = if with_rotation {
+            self.fit_rotation(&translation, stride)
+        } else {
+            0.0
+        };
+        GlobalMotion {
+            dx: translation.dx,
+            dy: translation.dy,
+            rotation,
+            confidence: translation.confidence(),
+        }
+    }
+
+    // Mean absolute difference between the samples (every `stride` pixels) of `rows` x
+    // `cols` in the newer frame and the older frame shifted by (dx, dy), over the part that
+    // stays inside the region; None when too little of it overlaps
+    fn cost(
+        &self,
+        rows: &Range<usize>,
+        cols: &Range<usize>,
+        stride: usize,
+        (dx, dy): (i32, i32),
+    ) -> Option<f32> {
+        let overlap = |range: &Range<usize>, region: &Range<usize>, shift: i32| {
+            let start = (range.start as i32).max(region.start as i32 + shift);
+            let end = (range.end as i32).min(region.end as i32 + shift);
+            (start < end).then_some(start as usize..end as usize)
+        };
+        let ys = overlap(rows, &self.rows, dy)?;
+        let xs = overlap(cols, &self.cols, dx)?;
+        let (mut sum, mut count) = (0.0, 0);
+        for y in ys.step_by(stride) {
+            let newer_row = y * self.width;
+            let older_row = (y as i32 - dy) as usize * self.width;
+            for x in xs.clone().step_by(stride) {
+                let older_x = (x as i32 - dx) as usize;
+                sum += (self.newer[newer_row + x] - self.older[older_row + older_x]).abs();
+                count += 1;
+            }
+        }
+        (count >= MIN_OVERLAP).then(|| sum / count as f32)
+    }
+
+    // Shift within `radius` of `around` that best matches `rows` x `cols`: a coarse pass
+    // over the whole range, then steps halving around the best candidate, then a parabola
+    // through the neighbouring costs for the sub-pixel part
+    fn search(
+        &self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        stride: usize,
+        around: (i32, i32),
+        radius: i32,
+    ) -> Option<Match> {
+        let cost = |shift: (i32, i32)| self.cost(&rows, &cols, stride, shift);
+        let mut step = (radius / 4).max(1);
+        let (mut best, mut best_cost) = (around, cost(around).unwrap_or(f32::MAX));
+        let (mut total, mut tried) = (0.0, 0);
+        for dy in (-radius..=radius).step_by(step as usize) {
+            for dx in (-radius..=radius).step_by(step as usize) {
+                let shift = (around.0 + dx, around.1 + dy);
+                if let Some(candidate) = cost(shift) {
+                    total += candidate;
+                    tried += 1;
+                    if candidate < best_cost {
+                        (best, best_cost) = (shift, candidate);
+                    }
+                }
+            }
+        }
+        if tried == 0 {
+            return None;
+        }
+        while step > 1 {
+            step = (step + 1) / 2;
+            let center = best;
+            for dy in [-step, 0, step] {
+                for dx in [-step, 0, step] {
+                    let shift = (center.0 + dx, center.1 + dy);
+                    let inside = (shift.0 - around.0).abs() <= radius
+                        && (shift.1 - around.1).abs() <= radius;
+                    if let Some(candidate) = cost(shift).filter(|_| inside) {
+                        if candidate < best_cost {
+                            (best, best_cost) = (shift, candidate);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Vertex of the parabola through the costs either side of the best shift
+        let refine = |before: Option<f32>, after: Option<f32>| match (before, after) {
+            (Some(before), Some(after)) => {
+                let curvature = before - 2.0 * best_cost + after;
+                if curvature > 0.0 {
+                    ((before - after) / (2.0 * curvature)).clamp(-0.5, 0.5)
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+        let fraction_x = refine(cost((best.0 - 1, best.1)), cost((best.0 + 1, best.1)));
+        let fraction_y = refine(cost((best.0, best.1 - 1)), cost((best.0, best.1 + 1)));
+        Some(Match {
+            dx: best.0 as f32 + fraction_x,
+            dy: best.1 as f32 + fraction_y,
+            cost: best_cost,
+            mean_cost: total / tried as f32,
+        })
+    }
+
+    // Turn (clockwise radians around the region's center) that best explains how the shifts
+    // of a grid of blocks differ from the global one; a turn moves each block at right
+    // angles to its offset from the center, in proportion to its distance
+    fn fit_rotation(&self, translation: &Match, stride: usize) -> f32 {
+        let around = (translation.dx.round() as i32, translation.dy.round() as i32);
+        let center_x = (self.cols.start + self.cols.end) as f32 * 0.5;
+        let center_y = (self.rows.start + self.rows.end) as f32 * 0.5;
+        let span = |range: &Range<usize>, index: usize| {
+            let start = range.start + index * range.len() / ROTATION_GRID;
+            start..range.start + (index + 1) * range.len() / ROTATION_GRID
+        };
+        let (mut moment, mut spread) = (0.0, 0.0);
+        for block_y in 0..ROTATION_GRID {
+            let rows = span(&self.rows, block_y);
+            for block_x in 0..ROTATION_GRID {
+                let cols = span(&self.cols, block_x);
+                let offset_x = (cols.start + cols.end) as f32 * 0.5 - center_x;
+                let offset_y = (rows.start + rows.end) as f32 * 0.5 - center_y;
+                let Some(block) = self.search(rows.clone(), cols, stride, around, ROTATION_SEARCH)
+                else {
+                    continue;
+                };
+                if block.confidence() < ROTATION_MIN_CONFIDENCE {
+                    continue;
+                }
+                let (residual_x, residual_y) =
+                    (block.dx - translation.dx, block.dy - translation.dy);
+                moment += offset_x * residual_y - offset_y * residual_x;
+                spread += offset_x * offset_x + offset_y * offset_y;
+            }
+        }
+        if spread > 0.0 {
+            moment / spread
+        } else {
+            0.0
+        }
+    }
+}
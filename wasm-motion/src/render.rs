@@ -0,0 +1,619 @@
+// Output side of the pipeline: palettes, pixel formats, tone mapping, trail fading and the
+// compositor that blends several detectors into one frame.
+// Fully saturated color for a hue in 0..1
+pub(crate) fn hue_to_rgb(hue: f32) -> [f32; 3] {
+    let h = hue.rem_euclid(1.0) * 6.0;
+    let x = 1.0 - (h % 2.0 - 1.0).abs();
+    match h as u32 {
+        0 => [1.0, x, 0.0],
+        1 => [x, 1.0, 0.0],
+        2 => [0.0, 1.0, x],
+        3 => [0.0, x, 1.0],
+        4 => [x, 0.0, 1.0],
+        _ => [1.0, 0.0, x],
+    }
+}
+use std::ops::Range;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+use crate::{js_error, ExportError, MotionDetector, MotionOptions, OutputMode, PersistenceMode};
+
+// Shaping of the persistence level before it is colored: gamma, then contrast around mid
+// gray, then quantization to `posterize_levels` steps (below 2 = off). Where there is no
+// trail the output stays black.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct LevelCurve {
+    pub(crate) gamma: f32,
+    pub(crate) contrast: f32,
+    pub(crate) posterize_levels: u32,
+}
+
+impl LevelCurve {
+    pub(crate) const IDENTITY: LevelCurve = LevelCurve {
+        gamma: 1.0,
+        contrast: 1.0,
+        posterize_levels: 0,
+    };
+
+    fn apply(self, level: usize) -> usize {
+        if level == 0 || self == LevelCurve::IDENTITY {
+            return level;
+        }
+        let t = (level as f32 / 255.0).powf(1.0 / self.gamma.max(0.01));
+        let mut t = ((t - 0.5) * self.contrast.max(0.0) + 0.5).clamp(0.0, 1.0);
+        if self.posterize_levels >= 2 {
+            let steps = (self.posterize_levels - 1) as f32;
+            t = (t * steps).round() / steps;
+        }
+        (t * 255.0).round() as usize
+    }
+}
+
+// Darkening of the output towards the frame edges, independent of the detection weighting of
+// `RadialProfile`: full brightness out to `radius` (normalized distance from the center, 1 at
+// the corners), then fading over `softness` down to 1 - `strength`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Vignette {
+    pub(crate) strength: f32,
+    pub(crate) radius: f32,
+    pub(crate) softness: f32,
+}
+
+impl Vignette {
+    pub(crate) fn is_off(self) -> bool {
+        self.strength <= 0.0
+    }
+
+    // Brightness factor at normalized distance `distance` from the center
+    pub(crate) fn gain(self, distance: f32) -> f32 {
+        let t = ((distance - self.radius) / self.softness.max(0.001)).clamp(0.0, 1.0);
+        let smooth = t * t * (3.0 - 2.0 * t);
+        1.0 - self.strength.clamp(0.0, 1.0) * smooth
+    }
+}
+
+// Output color for every persistence level, shaped by `curve`, so the palette costs one
+// lookup per pixel in the output pass
+pub(crate) fn build_palette(
+    mode: OutputMode,
+    tint_color: u32,
+    curve: LevelCurve,
+) -> [[u8; 3]; 256] {
+    const HEATMAP_STOPS: [[f32; 3]; 6] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.0, 255.0],
+        [0.0, 255.0, 255.0],
+        [0.0, 255.0, 0.0],
+        [255.0, 255.0, 0.0],
+        [255.0, 0.0, 0.0],
+    ];
+    let tint = [
+        ((tint_color >> 16) & 0xff) as f32,
+        ((tint_color >> 8) & 0xff) as f32,
+        (tint_color & 0xff) as f32,
+    ];
+
+    let mut palette = [[0; 3]; 256];
+    for (index, color) in palette.iter_mut().enumerate() {
+        let level = curve.apply(index);
+        let t = level as f32 / 255.0;
+        let rgb = match mode {
+            OutputMode::Grayscale => [level as f32; 3],
+            OutputMode::Heatmap => ramp_color(&HEATMAP_STOPS, t),
+            OutputMode::HueByAge if level == 0 => [0.0; 3],
+            OutputMode::HueByAge => {
+                // A trail decays geometrically from full intensity, so the log of its
+                // (unshaped) level is proportional to the frames since it was fresh
+                let freshness = (index as f32).ln() / 255f32.ln();
+                let brightness = t.sqrt() * 255.0;
+                hue_to_rgb((1.0 - freshness) * 0.75).map(|channel| channel * brightness)
+            }
+            OutputMode::Tint => tint.map(|channel| channel * t),
+            // Replaced by the filled motion mask afterwards, see `render_silhouette`
+            OutputMode::Silhouette => tint.map(|channel| channel * t),
+            // Indexed by the fluid's dye instead of the trails, see `render_fluid`
+            OutputMode::Fluid => tint.map(|channel| channel * t),
+            // Colored by the dwell time instead of the trails, see `render_dwell`
+            OutputMode::Dwell => tint.map(|channel| channel * t),
+            // The hue is added per pixel afterwards, see `color_by_direction`
+            OutputMode::Direction => [level as f32; 3],
+        };
+        *color = rgb.map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+    }
+    palette
+}
+
+// 256 colors evenly interpolated through 0xRRGGBB `colors` (at least two), first to last
+pub(crate) fn build_ramp(colors: &[u32]) -> [[u8; 3]; 256] {
+    let stops: Vec<[f32; 3]> = colors
+        .iter()
+        .map(|color| [16, 8, 0].map(|shift| ((color >> shift) & 0xff) as f32))
+        .collect();
+    let mut ramp = [[0; 3]; 256];
+    for (index, color) in ramp.iter_mut().enumerate() {
+        let rgb = ramp_color(&stops, index as f32 / 255.0);
+        *color = rgb.map(|channel| channel.round().clamp(0.0, 255.0) as u8);
+    }
+    ramp
+}
+
+// Color at `t` (0..1) of a gradient through evenly spaced `stops`
+fn ramp_color(stops: &[[f32; 3]], t: f32) -> [f32; 3] {
+    let position = t * (stops.len() - 1) as f32;
+    let index = (position as usize).min(stops.len() - 2);
+    let fraction = position - index as f32;
+    let (from, to) = (stops[index], stops[index + 1]);
+    [0, 1, 2].map(|channel| from[channel] + (to[channel] - from[channel]) * fraction)
+}
+
+// Channel layout of input frames and output buffers. Canvases, WebGPU readbacks and
+// native camera APIs disagree on channel order, so the swizzle happens in the same pass
+// as the grayscale read / output write instead of in JS.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgba,
+    Bgra,
+    Rgb,
+    // 4 bytes per pixel with an ignored padding byte (written as 255 on output)
+    Rgbx,
+    // Single-channel 8-bit luminance, for feeding further CV processing without the
+    // RGBA expansion
+    Gray,
+}
+
+impl PixelFormat {
+    pub fn parse(name: &str) -> Option<PixelFormat> {
+        match name {
+            "rgba" => Some(PixelFormat::Rgba),
+            "bgra" => Some(PixelFormat::Bgra),
+            "rgb" => Some(PixelFormat::Rgb),
+            "rgbx" => Some(PixelFormat::Rgbx),
+            "gray" => Some(PixelFormat::Gray),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PixelFormat::Rgba => "rgba",
+            PixelFormat::Bgra => "bgra",
+            PixelFormat::Rgb => "rgb",
+            PixelFormat::Rgbx => "rgbx",
+            PixelFormat::Gray => "gray",
+        }
+    }
+
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Gray => 1,
+            PixelFormat::Rgb => 3,
+            PixelFormat::Rgba | PixelFormat::Bgra | PixelFormat::Rgbx => 4,
+        }
+    }
+
+    // Byte offsets of the red, green and blue channels within a pixel; gray reads the
+    // single channel three times, which the luma weights turn back into the same value
+    pub(crate) fn rgb_offsets(self) -> (usize, usize, usize) {
+        match self {
+            PixelFormat::Gray => (0, 0, 0),
+            PixelFormat::Bgra => (2, 1, 0),
+            PixelFormat::Rgba | PixelFormat::Rgb | PixelFormat::Rgbx => (0, 1, 2),
+        }
+    }
+
+    // Read one pixel back as RGB; gray repeats its channel
+    #[inline]
+    pub(crate) fn read(self, output: &[u8], pixel_index: usize) -> [u8; 3] {
+        if self == PixelFormat::Gray {
+            return [output[pixel_index]; 3];
+        }
+        let base = pixel_index * self.bytes_per_pixel();
+        let (r, g, b) = self.rgb_offsets();
+        [output[base + r], output[base + g], output[base + b]]
+    }
+
+    // Write one pixel; formats with a fourth byte get `alpha` there
+    #[inline]
+    pub(crate) fn write(self, output: &mut [u8], pixel_index: usize, rgb: [u8; 3], alpha: u8) {
+        if self == PixelFormat::Gray {
+            output[pixel_index] =
+                ((rgb[0] as u32 * 77 + rgb[1] as u32 * 150 + rgb[2] as u32 * 29) >> 8) as u8;
+            return;
+        }
+
+        let base = pixel_index * self.bytes_per_pixel();
+        let (r, g, b) = self.rgb_offsets();
+        output[base + r] = rgb[0];
+        output[base + g] = rgb[1];
+        output[base + b] = rgb[2];
+        if self != PixelFormat::Rgb {
+            output[base + 3] = alpha;
+        }
+    }
+}
+
+// Tone curve mapping exposure-scaled HDR luminance into 0..1 before differencing
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapping {
+    // Hard clip at 1.0
+    Clamp,
+    // x / (1 + x)
+    Reinhard,
+    // Narkowicz ACES filmic fit
+    Filmic,
+}
+
+impl ToneMapping {
+    #[inline]
+    pub(crate) fn apply(self, linear: f32) -> f32 {
+        match self {
+            ToneMapping::Clamp => linear.clamp(0.0, 1.0),
+            ToneMapping::Reinhard => {
+                let linear = linear.max(0.0);
+                linear / (1.0 + linear)
+            }
+            ToneMapping::Filmic => {
+                let x = linear.max(0.0);
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }
+        }
+    }
+}
+
+// How trails fade from one frame to the next
+#[derive(Clone, Copy)]
+pub(crate) enum TrailFade {
+    // Multiplied by the decay rate every frame
+    Decay(f32),
+    // Motion history image: active pixels are stamped with full brightness, so a trail's
+    // value encodes how recently it moved; it loses this much per frame, scaled by the time
+    // since the last one. Storing recency in the trail lets the movement modes carry it.
+    History(f32),
+}
+
+impl TrailFade {
+    pub(crate) fn apply(self, value: f32) -> f32 {
+        match self {
+            TrailFade::Decay(rate) => value * rate,
+            TrailFade::History(step) => (value - step).max(0.0),
+        }
+    }
+
+    // This fade at `pixel_index` with the rate of a decay map (see `set_decay_map`) instead
+    // of `decay_rate`, scaled to the frame's length like the option; motion history fades
+    // keep their step
+    #[inline]
+    pub(crate) fn at(
+        self,
+        decay_map: Option<&[f32]>,
+        time_scale: f32,
+        pixel_index: usize,
+    ) -> TrailFade {
+        match (self, decay_map) {
+            (TrailFade::Decay(_), Some(map)) if time_scale == 1.0 => {
+                TrailFade::Decay(map[pixel_index])
+            }
+            (TrailFade::Decay(_), Some(map)) => TrailFade::Decay(map[pixel_index].powf(time_scale)),
+            _ => self,
+        }
+    }
+
+    // New trail value of a pixel from its detected motion and its moved previous trail,
+    // combined by `persistence_mode`
+    #[inline]
+    pub(crate) fn persist(self, params: &MotionOptions, motion: f32, previous: f32) -> f32 {
+        if let TrailFade::History(_) = self {
+            if motion > 0.0 {
+                return 255.0;
+            }
+        }
+        let faded = self.apply(previous);
+        match params.persistence_mode {
+            PersistenceMode::Max => motion.max(faded),
+            PersistenceMode::Additive => (motion + faded).min(255.0),
+            PersistenceMode::Lerp => faded + (motion - faded) * params.persistence_alpha,
+            PersistenceMode::Screen => {
+                255.0 - (255.0 - motion.min(255.0)) * (255.0 - faded) / 255.0
+            }
+        }
+    }
+}
+
+// Filled shapes of the moving areas for the `silhouette` output mode: the motion mask with
+// the holes it encloses filled (a frame difference mostly outlines a moving body), eased
+// over frames by `silhouette_smoothing`. Nothing of the picture itself reaches the output.
+#[derive(Default)]
+pub(crate) struct Silhouette {
+    // Share of recent frames each pixel was covered, 0..1; filled from one half up
+    coverage: Vec<f32>,
+    // Scratch of `update`: moving, background the area's edge reaches, or enclosed
+    mask: Vec<u8>,
+    stack: Vec<usize>,
+}
+
+const SILHOUETTE_ENCLOSED: u8 = 0;
+const SILHOUETTE_MOVING: u8 = 1;
+const SILHOUETTE_BACKGROUND: u8 = 2;
+
+impl Silhouette {
+    // Fold in the motion of an analysed frame over `rows` x `cols`
+    pub(crate) fn update(
+        &mut self,
+        (width, height): (usize, usize),
+        rows: Range<usize>,
+        cols: Range<usize>,
+        smoothing: f32,
+        is_moving: impl Fn(usize) -> bool,
+    ) {
+        if self.coverage.len() != width * height {
+            self.coverage = vec![0.0; width * height];
+        }
+        self.mask.clear();
+        self.mask.resize(width * height, SILHOUETTE_BACKGROUND);
+        if rows.is_empty() || cols.is_empty() {
+            return;
+        }
+        for y in rows.clone() {
+            for pixel_index in y * width + cols.start..y * width + cols.end {
+                self.mask[pixel_index] = if is_moving(pixel_index) {
+                    SILHOUETTE_MOVING
+                } else {
+                    SILHOUETTE_ENCLOSED
+                };
+            }
+        }
+
+        // Background is whatever still-pixels connect to the edge of the area
+        let edge = cols
+            .clone()
+            .flat_map(|x| [rows.start * width + x, (rows.end - 1) * width + x])
+            .chain(
+                rows.clone()
+                    .flat_map(|y| [y * width + cols.start, y * width + cols.end - 1]),
+            );
+        for pixel_index in edge {
+            if self.mask[pixel_index] == SILHOUETTE_ENCLOSED {
+                self.mask[pixel_index] = SILHOUETTE_BACKGROUND;
+                self.stack.push(pixel_index);
+            }
+        }
+        while let Some(pixel_index) = self.stack.pop() {
+            let (x, y) = (pixel_index % width, pixel_index / width);
+            let neighbours = [
+                (x > cols.start).then(|| pixel_index - 1),
+                (x + 1 < cols.end).then_some(pixel_index + 1),
+                (y > rows.start).then(|| pixel_index - width),
+                (y + 1 < rows.end).then_some(pixel_index + width),
+            ];
+            for neighbour in neighbours.into_iter().flatten() {
+                if self.mask[neighbour] == SILHOUETTE_ENCLOSED {
+                    self.mask[neighbour] = SILHOUETTE_BACKGROUND;
+                    self.stack.push(neighbour);
+                }
+            }
+        }
+
+        let rate = 1.0 - smoothing.clamp(0.0, 0.99);
+        for (coverage, &mask) in self.coverage.iter_mut().zip(&self.mask) {
+            let target = if mask == SILHOUETTE_BACKGROUND {
+                0.0
+            } else {
+                1.0
+            };
+            *coverage += (target - *coverage) * rate;
+        }
+    }
+
+    pub(crate) fn is_filled(&self, pixel_index: usize) -> bool {
+        self.coverage
+            .get(pixel_index)
+            .is_some_and(|&coverage| coverage >= 0.5)
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.coverage.capacity() * std::mem::size_of::<f32>()
+            + self.mask.capacity()
+            + self.stack.capacity() * std::mem::size_of::<usize>()
+    }
+}
+
+// How a compositor or persistence layer is combined with the layers below it
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    Add,
+    Screen,
+    Max,
+    Multiply,
+}
+
+impl BlendMode {
+    // Blend `layer` onto `base` (both 0..1) at the given opacity
+    #[inline]
+    pub(crate) fn apply(self, base: f32, layer: f32, opacity: f32) -> f32 {
+        match self {
+            BlendMode::Normal => base + (layer - base) * opacity,
+            BlendMode::Add => (base + layer * opacity).min(1.0),
+            BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - layer * opacity),
+            BlendMode::Max => base.max(layer * opacity),
+            BlendMode::Multiply => base * (1.0 - opacity + layer * opacity),
+        }
+    }
+}
+
+struct CompositorLayer {
+    detector: MotionDetector,
+    params: MotionOptions,
+    opacity: f32,
+    blend_mode: BlendMode,
+}
+
+// Several detectors over the same stream (different move modes, or zones via `set_roi`)
+// whose persistence buffers are blended into a single output, bottom layer first. Layers
+// share one scratch output, so adding a layer costs its buffers but no extra frame copy.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub struct Compositor {
+    width: u32,
+    height: u32,
+    layers: Vec<CompositorLayer>,
+    scratch_output: Vec<u8>,
+}
+
+// Native Rust API
+impl Compositor {
+    pub fn add_layer_with_params(
+        &mut self,
+        params: MotionOptions,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> usize {
+        self.layers.push(CompositorLayer {
+            detector: MotionDetector::new_in(self.width, self.height, None),
+            params,
+            opacity: opacity.clamp(0.0, 1.0),
+            blend_mode,
+        });
+        self.layers.len() - 1
+    }
+
+    // Direct access to a layer's detector for settings without a compositor wrapper
+    pub fn layer_mut(&mut self, index: usize) -> Option<&mut MotionDetector> {
+        self.layers.get_mut(index).map(|layer| &mut layer.detector)
+    }
+
+    // Run every layer on `current_data` (RGBA) and write the blended result as RGBA
+    pub fn process_frame(
+        &mut self,
+        current_data: &[u8],
+        output_data: &mut [u8],
+    ) -> Result<(), String> {
+        let frame_len = self.width as usize * self.height as usize * 4;
+        for (name, len) in [("input", current_data.len()), ("output", output_data.len())] {
+            if len < frame_len {
+                return Err(format!(
+                    "{} holds {} bytes, a {}x{} frame needs {}",
+                    name, len, self.width, self.height, frame_len
+                ));
+            }
+        }
+        self.scratch_output.resize(frame_len, 0);
+        // Layers are blended pixel by pixel, so they all run at full resolution
+        for layer in &mut self.layers {
+            let params = MotionOptions {
+                processing_scale: 1.0,
+                ..layer.params
+            };
+            layer
+                .detector
+                .process(current_data, &mut self.scratch_output, &params)?;
+        }
+
+        for (pixel_index, pixel) in output_data[..frame_len].chunks_exact_mut(4).enumerate() {
+            let mut value = 0.0;
+            for layer in &self.layers {
+                let motion = layer.detector.persistence()[pixel_index].min(255.0) / 255.0;
+                value = layer.blend_mode.apply(value, motion, layer.opacity);
+            }
+            let gray = (value.clamp(0.0, 1.0) * 255.0) as u8;
+            PixelFormat::Rgba.write(pixel, 0, [gray; 3], 255);
+        }
+        Ok(())
+    }
+
+    fn layer(&mut self, index: usize) -> Result<&mut CompositorLayer, ExportError> {
+        self.layers
+            .get_mut(index)
+            .ok_or_else(|| js_error("compositor layer index out of range".to_string()))
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl Compositor {
+    #[cfg_attr(feature = "wasm", wasm_bindgen(constructor))]
+    pub fn new(width: u32, height: u32) -> Compositor {
+        Compositor {
+            width,
+            height,
+            layers: Vec::new(),
+            scratch_output: Vec::new(),
+        }
+    }
+
+    // Add a layer on top with its own per-frame options; returns the layer index
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn add_layer(
+        &mut self,
+        options: &MotionOptions,
+        opacity: f32,
+        blend_mode: BlendMode,
+    ) -> usize {
+        self.add_layer_with_params(*options, opacity, blend_mode)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_layer_options(
+        &mut self,
+        index: usize,
+        options: &MotionOptions,
+    ) -> Result<(), ExportError> {
+        self.layer(index)?.params = *options;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_layer_opacity(&mut self, index: usize, opacity: f32) -> Result<(), ExportError> {
+        self.layer(index)?.opacity = opacity.clamp(0.0, 1.0);
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_layer_blend_mode(
+        &mut self,
+        index: usize,
+        blend_mode: BlendMode,
+    ) -> Result<(), ExportError> {
+        self.layer(index)?.blend_mode = blend_mode;
+        Ok(())
+    }
+
+    // Restrict a layer to a zone of the stream (see `MotionDetector::set_roi`)
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn set_layer_roi(
+        &mut self,
+        index: usize,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    ) -> Result<(), ExportError> {
+        self.layer(index)?.detector.set_roi(x, y, width, height);
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn process(
+        &mut self,
+        current_data: &[u8],
+        output_data: &mut [u8],
+    ) -> Result<(), ExportError> {
+        self.process_frame(current_data, output_data)
+            .map_err(js_error)
+    }
+
+    #[cfg_attr(feature = "wasm", wasm_bindgen)]
+    pub fn reset_all_state(&mut self) {
+        for layer in &mut self.layers {
+            layer.detector.reset_all_state();
+        }
+    }
+}
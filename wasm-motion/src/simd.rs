@@ -0,0 +1,151 @@
+// Row kernels for the grayscale, diff and threshold stages. With the `simd` feature on a
+// wasm32 build with `+simd128` they process 4 pixels per iteration; everywhere else, and
+// for strided samples and the tail of each row, they run the scalar loop.
+#[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+use core::arch::wasm32::*;
+
+// Whether the vector kernels were compiled in
+pub const ENABLED: bool = cfg!(all(
+    feature = "simd",
+    target_arch = "wasm32",
+    target_feature = "simd128"
+));
+
+// Fixed-point luma of 4-byte pixels (RGBA, BGRA, RGBX) into `dst`
+pub fn luma_row(src: &[u8], (r, g, b): (usize, usize, usize), dst: &mut [f32]) {
+    assert!(src.len() >= dst.len() * 4);
+    #[allow(unused_mut)]
+    let mut start = 0;
+
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    {
+        let mask = u32x4_splat(0xff);
+        let (wr, wg, wb) = (u32x4_splat(77), u32x4_splat(150), u32x4_splat(29));
+        while start + 4 <= dst.len() {
+            // Four pixels as little-endian u32 lanes, channels picked out by shifting
+            let pixels = unsafe { v128_load(src.as_ptr().add(start * 4) as *const v128) };
+            let channel = |offset: usize| v128_and(u32x4_shr(pixels, offset as u32 * 8), mask);
+            let gray = u32x4_shr(
+                u32x4_add(
+                    u32x4_add(u32x4_mul(channel(r), wr), u32x4_mul(channel(g), wg)),
+                    u32x4_mul(channel(b), wb),
+                ),
+                8,
+            );
+            unsafe {
+                v128_store(
+                    dst.as_mut_ptr().add(start) as *mut v128,
+                    f32x4_convert_u32x4(gray),
+                )
+            };
+            start += 4;
+        }
+    }
+
+    for (i, gray) in dst.iter_mut().enumerate().skip(start) {
+        let base = i * 4;
+        *gray = (((src[base + r] as u32 * 77)
+            + (src[base + g] as u32 * 150)
+            + (src[base + b] as u32 * 29))
+            >> 8) as f32;
+    }
+}
+
+// |current - reference| into `dst`
+pub fn abs_diff_row(current: &[f32], reference: &[f32], dst: &mut [f32]) {
+    assert!(current.len() >= dst.len() && reference.len() >= dst.len());
+    #[allow(unused_mut)]
+    let mut start = 0;
+
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    while start + 4 <= dst.len() {
+        unsafe {
+            let a = v128_load(current.as_ptr().add(start) as *const v128);
+            let b = v128_load(reference.as_ptr().add(start) as *const v128);
+            v128_store(
+                dst.as_mut_ptr().add(start) as *mut v128,
+                f32x4_abs(f32x4_sub(a, b)),
+            );
+        }
+        start += 4;
+    }
+
+    for i in start..dst.len() {
+        dst[i] = (current[i] - reference[i]).abs();
+    }
+}
+
+// Radially weighted, thresholded and amplified diff of every `stride`-th pixel into `dst`;
+// returns how many of them passed the threshold. Each pixel's threshold is `threshold`
+// plus its `offsets` value times `offset_scale`.
+#[allow(clippy::too_many_arguments)]
+pub fn enhance_row(
+    diff: &[f32],
+    offsets: &[f32],
+    radial: &[f32],
+    stride: usize,
+    threshold: f32,
+    offset_scale: f32,
+    sensitivity: f32,
+    dst: &mut [f32],
+) -> usize {
+    let last = dst.len().saturating_sub(1) * stride;
+    assert!(dst.is_empty() || diff.len().min(offsets.len()).min(radial.len()) > last);
+    #[allow(unused_mut)]
+    let mut start = 0;
+    let mut active = 0;
+
+    #[cfg(all(feature = "simd", target_arch = "wasm32", target_feature = "simd128"))]
+    if stride == 1 {
+        let (threshold4, sensitivity4) = (f32x4_splat(threshold), f32x4_splat(sensitivity));
+        let (scale4, half, max) = (
+            f32x4_splat(offset_scale),
+            f32x4_splat(0.5),
+            f32x4_splat(255.0),
+        );
+        while start + 4 <= dst.len() {
+            unsafe {
+                let radial4 = v128_load(radial.as_ptr().add(start) as *const v128);
+                let weighted =
+                    f32x4_mul(v128_load(diff.as_ptr().add(start) as *const v128), radial4);
+                let adaptive = f32x4_add(
+                    threshold4,
+                    f32x4_mul(
+                        v128_load(offsets.as_ptr().add(start) as *const v128),
+                        scale4,
+                    ),
+                );
+                let passed = f32x4_gt(weighted, adaptive);
+                let filtered = v128_and(weighted, passed);
+                let enhanced = f32x4_min(
+                    f32x4_mul(filtered, f32x4_add(sensitivity4, f32x4_mul(radial4, half))),
+                    max,
+                );
+                v128_store(dst.as_mut_ptr().add(start) as *mut v128, enhanced);
+                // Passing lanes have positive weighted diffs
+                active += i32x4_bitmask(f32x4_gt(filtered, f32x4_splat(0.0))).count_ones() as usize;
+            }
+            start += 4;
+        }
+    }
+
+    for (i, enhanced) in dst.iter_mut().enumerate().skip(start) {
+        let pixel_index = i * stride;
+        let radial_sensitivity = radial[pixel_index];
+        let radial_weighted_diff = diff[pixel_index] * radial_sensitivity;
+        let adaptive_threshold = threshold + offsets[pixel_index] * offset_scale;
+
+        let filtered_diff = if radial_weighted_diff > adaptive_threshold {
+            radial_weighted_diff
+        } else {
+            0.0
+        };
+        if filtered_diff > 0.0 {
+            active += 1;
+        }
+
+        *enhanced = (filtered_diff * (sensitivity + radial_sensitivity * 0.5)).min(255.0);
+    }
+
+    active
+}
@@ -0,0 +1,113 @@
+// Synthetic RGBA frames with known content and measurements of detector buffers, shared by
+// the self-test, the benchmark and the tests. Plain Rust, so usable from native tests as well
+// as from `wasm-bindgen-test`.
+use crate::movement::{lattice_value, Prng};
+
+// Left and top edge and side length of the square of `moving_square` in a frame
+pub fn square_position(width: u32, height: u32, frame_index: u32) -> (usize, usize, usize) {
+    let width = width as usize;
+    let height = height as usize;
+    let square = (width.min(height) / 8).max(1);
+    let travel = width.saturating_sub(square).max(1);
+    let square_x = (frame_index as usize * (square / 2).max(1)) % travel;
+    let square_y = height.saturating_sub(square) / 2;
+    (square_x, square_y, square)
+}
+
+// A bright square sweeping across a dim gradient, so every frame carries a known amount of
+// motion; `square_position` says where it is
+pub fn moving_square(width: u32, height: u32, frame_index: u32) -> Vec<u8> {
+    let (square_x, square_y, square) = square_position(width, height, frame_index);
+    let width = width as usize;
+    gray_frame(width, height as usize, |x, y| {
+        let inside = (square_x..square_x + square).contains(&x)
+            && (square_y..square_y + square).contains(&y);
+        if inside {
+            255
+        } else {
+            (x * 64 / width) as u8
+        }
+    })
+}
+
+// Full-range horizontal ramp shifted `offset` pixels to the right (wrapping), for static
+// scenes (fixed offset) and whole-frame motion (changing offset)
+pub fn gradient(width: u32, height: u32, offset: u32) -> Vec<u8> {
+    let width = width as usize;
+    let offset = offset as usize;
+    gray_frame(width, height as usize, |x, _| {
+        let shifted = (x + width - offset % width) % width;
+        (shifted * 255 / (width - 1).max(1)) as u8
+    })
+}
+
+// Smooth random blotches about 6 pixels across (from `seed`), shifted by
+// (`offset_x`, `offset_y`) pixels, so whole-frame motion can be matched in any direction
+pub fn texture(width: u32, height: u32, seed: u32, offset_x: i32, offset_y: i32) -> Vec<u8> {
+    const FEATURE: f32 = 6.0;
+    gray_frame(width as usize, height as usize, |x, y| {
+        let (u, v) = (
+            (x as i32 - offset_x) as f32 / FEATURE,
+            (y as i32 - offset_y) as f32 / FEATURE,
+        );
+        let (u0, v0) = (u.floor(), v.floor());
+        let (fu, fv) = (u - u0, v - v0);
+        let corner = |du: i32, dv: i32| lattice_value(seed, u0 as i32 + du, v0 as i32 + dv, 0);
+        let top = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * fu;
+        let bottom = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * fu;
+        (128.0 + (top + (bottom - top) * fv) * 100.0).round() as u8
+    })
+}
+
+// Mid-gray with per-pixel noise of up to `amplitude` levels either way, like a camera sensor;
+// the same seed gives the same frame
+pub fn noise(width: u32, height: u32, seed: u32, amplitude: u8) -> Vec<u8> {
+    let mut rng = Prng::new(seed);
+    gray_frame(width as usize, height as usize, |_, _| {
+        (128.0 + rng.next_signed() * amplitude as f32).round() as u8
+    })
+}
+
+fn gray_frame(width: usize, height: usize, mut value: impl FnMut(usize, usize) -> u8) -> Vec<u8> {
+    let mut frame = vec![0; width * height * 4];
+    for y in 0..height {
+        for x in 0..width {
+            let rgba_index = (y * width + x) * 4;
+            let value = value(x, y);
+            frame[rgba_index..rgba_index + 3].fill(value);
+            frame[rgba_index + 3] = 255;
+        }
+    }
+    frame
+}
+
+// 64-bit FNV-1a hash of a buffer, for golden-frame comparisons of outputs
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+// Pixels of a persistence buffer bright enough to show up in the output
+pub fn active_pixels(persistence: &[f32]) -> usize {
+    persistence.iter().filter(|&&value| value >= 1.0).count()
+}
+
+// Sum of a persistence buffer
+pub fn total_intensity(persistence: &[f32]) -> f64 {
+    persistence.iter().map(|&value| value as f64).sum()
+}
+
+// Intensity-weighted mean position of a persistence buffer `width` pixels wide; None when
+// it is empty
+pub fn centroid(persistence: &[f32], width: u32) -> Option<(f32, f32)> {
+    let width = width as usize;
+    let (mut sum, mut sum_x, mut sum_y) = (0.0f64, 0.0f64, 0.0f64);
+    for (pixel_index, &value) in persistence.iter().enumerate() {
+        let value = value as f64;
+        sum += value;
+        sum_x += value * (pixel_index % width) as f64;
+        sum_y += value * (pixel_index / width) as f64;
+    }
+    (sum > 0.0).then(|| ((sum_x / sum) as f32, (sum_y / sum) as f32))
+}
@@ -0,0 +1,182 @@
+// Keyframed option animation of `load_timeline`: each option steps through its keyframes
+// on the detector's stream clock, so a choreographed sequence runs without JS changing the
+// options every frame.
+use crate::{MotionOptions, OptionValue};
+
+// How an option moves from one keyframe to its next; strings and flags always step
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    // Hold the value until the next keyframe
+    Step,
+}
+
+impl Easing {
+    pub fn parse(name: &str) -> Option<Easing> {
+        match name {
+            "linear" => Some(Easing::Linear),
+            "ease_in" => Some(Easing::EaseIn),
+            "ease_out" => Some(Easing::EaseOut),
+            "ease_in_out" => Some(Easing::EaseInOut),
+            "step" => Some(Easing::Step),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Easing::Linear => "linear",
+            Easing::EaseIn => "ease_in",
+            Easing::EaseOut => "ease_out",
+            Easing::EaseInOut => "ease_in_out",
+            Easing::Step => "step",
+        }
+    }
+
+    // Share of the way to the next keyframe at `t` (0..1) of the time between them
+    fn apply(self, t: f32) -> f32 {
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Easing::Step => 0.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum KeyframeValue {
+    Number(f64),
+    Text(String),
+    Bool(bool),
+}
+
+impl KeyframeValue {
+    fn as_option(&self) -> OptionValue<'_> {
+        match self {
+            KeyframeValue::Number(number) => OptionValue::Number(*number),
+            KeyframeValue::Text(text) => OptionValue::Text(text),
+            KeyframeValue::Bool(flag) => OptionValue::Bool(*flag),
+        }
+    }
+}
+
+// The value one option (a `set_option` name) reaches at `time_ms` into the timeline, and
+// how it moves on to its next keyframe
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keyframe {
+    pub time_ms: f32,
+    pub option: String,
+    pub value: KeyframeValue,
+    pub easing: Easing,
+}
+
+// The keyframes of one option, in time order
+struct OptionTrack {
+    option: String,
+    keyframes: Vec<Keyframe>,
+}
+
+pub(crate) struct Timeline {
+    tracks: Vec<OptionTrack>,
+    // Time of the last keyframe, where a looping timeline starts over
+    duration_ms: f32,
+    looping: bool,
+    position_ms: f32,
+}
+
+impl Timeline {
+    // Fails on negative times and on options `set_option` doesn't take with their values
+    pub(crate) fn new(mut keyframes: Vec<Keyframe>, looping: bool) -> Result<Timeline, String> {
+        let mut check = MotionOptions::default();
+        for keyframe in &keyframes {
+            if !(keyframe.time_ms >= 0.0 && keyframe.time_ms.is_finite()) {
+                return Err(format!(
+                    "keyframe time {} of {} must be 0 or later",
+                    keyframe.time_ms, keyframe.option
+                ));
+            }
+            check.set_option(&keyframe.option, keyframe.value.as_option())?;
+        }
+
+        keyframes.sort_by(|a, b| a.time_ms.total_cmp(&b.time_ms));
+        let duration_ms = keyframes.last().map_or(0.0, |keyframe| keyframe.time_ms);
+        let mut tracks: Vec<OptionTrack> = Vec::new();
+        for keyframe in keyframes {
+            match tracks
+                .iter_mut()
+                .find(|track| track.option == keyframe.option)
+            {
+                Some(track) => track.keyframes.push(keyframe),
+                None => tracks.push(OptionTrack {
+                    option: keyframe.option.clone(),
+                    keyframes: vec![keyframe],
+                }),
+            }
+        }
+        Ok(Timeline {
+            tracks,
+            duration_ms,
+            looping,
+            position_ms: 0.0,
+        })
+    }
+
+    pub(crate) fn position_ms(&self) -> f32 {
+        self.position_ms
+    }
+
+    pub(crate) fn rewind(&mut self) {
+        self.position_ms = 0.0;
+    }
+
+    pub(crate) fn advance(&mut self, elapsed_ms: f32) {
+        self.position_ms += elapsed_ms;
+        if self.looping && self.duration_ms > 0.0 {
+            self.position_ms = self.position_ms.rem_euclid(self.duration_ms);
+        }
+    }
+
+    // `params` with every option that has reached its first keyframe set to its value at
+    // the current position
+    pub(crate) fn apply(&self, params: &MotionOptions) -> MotionOptions {
+        let mut options = *params;
+        for track in &self.tracks {
+            let next = track
+                .keyframes
+                .partition_point(|keyframe| keyframe.time_ms <= self.position_ms);
+            let Some(current) = next.checked_sub(1).map(|index| &track.keyframes[index]) else {
+                continue;
+            };
+            let value = match (&current.value, track.keyframes.get(next)) {
+                (KeyframeValue::Number(from), Some(to)) => match to.value {
+                    KeyframeValue::Number(to_value) => {
+                        let span = to.time_ms - current.time_ms;
+                        let t = current
+                            .easing
+                            .apply((self.position_ms - current.time_ms) / span);
+                        OptionValue::Number(from + (to_value - from) * t as f64)
+                    }
+                    _ => current.value.as_option(),
+                },
+                _ => current.value.as_option(),
+            };
+            // Checked when the timeline was built; integer options take the nearest whole
+            // value between keyframes
+            let number = match value {
+                OptionValue::Number(number) => Some(number),
+                _ => None,
+            };
+            if options.set_option(&track.option, value).is_err() {
+                if let Some(number) = number {
+                    let _ = options.set_option(&track.option, OptionValue::Number(number.round()));
+                }
+            }
+        }
+        options
+    }
+}
@@ -0,0 +1,130 @@
+// Debounced motion started / stopped signals of `set_motion_trigger`: each analysed frame
+// compares the share of moving pixels with a minimum, and a change only counts once it has
+// lasted the debounce time, so a flicker neither starts nor ends a recording.
+use std::collections::VecDeque;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// Queued events before the oldest are dropped
+const MAX_MOTION_EVENTS: usize = 256;
+
+// What the debounce times of `set_motion_trigger` count
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebounceUnit {
+    // Analysed frames
+    Frames,
+    // Stream time, see `stream_time_ms`
+    Milliseconds,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MotionEventKind {
+    MotionStarted,
+    MotionStopped,
+}
+
+// A debounced change of the motion state, see `poll_events`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionEvent {
+    pub kind: MotionEventKind,
+    // Stream time of the first frame of the change, before the debounce confirmed it
+    pub timestamp_ms: f64,
+    // Stream time of the frame that confirmed it
+    pub confirmed_ms: f64,
+    // Share of the analysed pixels above the threshold in the confirming frame, 0..100
+    pub active_percent: f32,
+}
+
+// The trigger settings and the motion state they debounce
+pub(crate) struct MotionTrigger {
+    min_active_percent: f32,
+    start_after: f32,
+    stop_after: f32,
+    unit: DebounceUnit,
+    active: bool,
+    // Since when and for how many analysed frames the activity has disagreed with `active`
+    change_since_ms: Option<f64>,
+    change_frames: u32,
+    events: VecDeque<MotionEvent>,
+}
+
+impl MotionTrigger {
+    pub(crate) fn new(
+        min_active_percent: f32,
+        start_after: f32,
+        stop_after: f32,
+        unit: DebounceUnit,
+    ) -> MotionTrigger {
+        MotionTrigger {
+            min_active_percent: min_active_percent.clamp(0.0, 100.0),
+            start_after: start_after.max(0.0),
+            stop_after: stop_after.max(0.0),
+            unit,
+            active: false,
+            change_since_ms: None,
+            change_frames: 0,
+            events: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn is_active(&self) -> bool {
+        self.active
+    }
+
+    // Take the activity of an analysed frame at stream time `now_ms`
+    pub(crate) fn update(&mut self, active_percent: f32, now_ms: f64) {
+        let moving = active_percent > 0.0 && active_percent >= self.min_active_percent;
+        if moving == self.active {
+            self.change_since_ms = None;
+            self.change_frames = 0;
+            return;
+        }
+        let since_ms = *self.change_since_ms.get_or_insert(now_ms);
+        self.change_frames += 1;
+        let lasted = match self.unit {
+            DebounceUnit::Frames => self.change_frames as f32,
+            DebounceUnit::Milliseconds => (now_ms - since_ms) as f32,
+        };
+        let debounce = if moving {
+            self.start_after
+        } else {
+            self.stop_after
+        };
+        if lasted < debounce {
+            return;
+        }
+
+        self.active = moving;
+        self.change_since_ms = None;
+        self.change_frames = 0;
+        if self.events.len() == MAX_MOTION_EVENTS {
+            self.events.pop_front();
+        }
+        self.events.push_back(MotionEvent {
+            kind: if moving {
+                MotionEventKind::MotionStarted
+            } else {
+                MotionEventKind::MotionStopped
+            },
+            timestamp_ms: since_ms,
+            confirmed_ms: now_ms,
+            active_percent,
+        });
+    }
+
+    pub(crate) fn drain_events(&mut self) -> Vec<MotionEvent> {
+        self.events.drain(..).collect()
+    }
+
+    // Back to no motion, keeping the settings
+    pub(crate) fn reset(&mut self) {
+        self.active = false;
+        self.change_since_ms = None;
+        self.change_frames = 0;
+        self.events.clear();
+    }
+}
@@ -0,0 +1,222 @@
+// Named trigger zones of `define_zones`: each analysed frame measures the share of a zone's
+// pixels that moved and turns threshold crossings into events for `poll_zone_events`.
+use std::collections::VecDeque;
+
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::*;
+
+// Zones per detector, queued events before the oldest are dropped, and the threshold of
+// zones that don't set one
+pub(crate) const MAX_ZONES: usize = 32;
+const MAX_ZONE_EVENTS: usize = 256;
+const ZONE_DEFAULT_THRESHOLD: f32 = 0.02;
+
+// Outline of a zone in fractions of the frame, so zones survive processing-scale changes
+#[derive(Clone, Debug, PartialEq)]
+pub enum ZoneShape {
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    // Corners in order; the outline closes back to the first one
+    Polygon(Vec<(f32, f32)>),
+}
+
+impl ZoneShape {
+    fn contains(&self, x: f32, y: f32) -> bool {
+        match self {
+            ZoneShape::Rect {
+                x: left,
+                y: top,
+                width,
+                height,
+            } => x >= *left && x < left + width && y >= *top && y < top + height,
+            // Even-odd rule, so self-intersecting outlines still give a definite inside
+            ZoneShape::Polygon(points) => {
+                let mut inside = false;
+                let mut previous = match points.last() {
+                    Some(&point) => point,
+                    None => return false,
+                };
+                for &(px, py) in points {
+                    let (qx, qy) = previous;
+                    if (py > y) != (qy > y) && x < px + (y - py) / (qy - py) * (qx - px) {
+                        inside = !inside;
+                    }
+                    previous = (px, py);
+                }
+                inside
+            }
+        }
+    }
+}
+
+// A named area that triggers once more than `threshold` of its pixels move
+#[derive(Clone, Debug, PartialEq)]
+pub struct Zone {
+    pub name: String,
+    pub shape: ZoneShape,
+    // Fraction (0..1) of the zone's pixels above the motion threshold
+    pub threshold: f32,
+}
+
+impl Zone {
+    pub fn rect(name: &str, x: f32, y: f32, width: f32, height: f32) -> Zone {
+        Zone {
+            name: name.to_string(),
+            shape: ZoneShape::Rect {
+                x,
+                y,
+                width,
+                height,
+            },
+            threshold: ZONE_DEFAULT_THRESHOLD,
+        }
+    }
+
+    pub fn polygon(name: &str, points: Vec<(f32, f32)>) -> Zone {
+        Zone {
+            name: name.to_string(),
+            shape: ZoneShape::Polygon(points),
+            threshold: ZONE_DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoneEventKind {
+    // Coverage rose to the zone's threshold
+    Enter,
+    // Coverage fell below it again
+    Exit,
+    // Every further analysed frame the zone stays above it
+    Active,
+}
+
+// A threshold crossing (or continued activity) of a zone, see `poll_zone_events`
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneEvent {
+    name: String,
+    pub kind: ZoneEventKind,
+    // Fraction of the zone's pixels moving in the frame of the event
+    pub coverage: f32,
+}
+
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+impl ZoneEvent {
+    // Name the zone was defined with
+    #[cfg_attr(feature = "wasm", wasm_bindgen(getter))]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+// A zone with the pixels it covers at the processing size and whether it is triggered
+struct ZoneState {
+    zone: Zone,
+    pixels: Vec<u32>,
+    active: bool,
+}
+
+// The zones of a detector and their undelivered events
+#[derive(Default)]
+pub(crate) struct Zones {
+    zones: Vec<ZoneState>,
+    // Processing size `pixels` were rasterized for
+    size: (u32, u32),
+    events: VecDeque<ZoneEvent>,
+}
+
+impl Zones {
+    pub(crate) fn set(&mut self, mut zones: Vec<Zone>) {
+        zones.truncate(MAX_ZONES);
+        self.zones = zones
+            .into_iter()
+            .map(|zone| ZoneState {
+                zone,
+                pixels: Vec::new(),
+                active: false,
+            })
+            .collect();
+        self.size = (0, 0);
+        self.events.clear();
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    // Measure each zone's coverage through `is_moving` and queue the events it causes
+    pub(crate) fn update(&mut self, width: u32, height: u32, is_moving: impl Fn(usize) -> bool) {
+        if self.size != (width, height) {
+            self.rasterize(width, height);
+        }
+        for state in &mut self.zones {
+            let moving = state
+                .pixels
+                .iter()
+                .filter(|&&pixel_index| is_moving(pixel_index as usize))
+                .count();
+            let coverage = moving as f32 / state.pixels.len().max(1) as f32;
+            let triggered = !state.pixels.is_empty() && coverage >= state.zone.threshold;
+            let kind = match (state.active, triggered) {
+                (false, true) => ZoneEventKind::Enter,
+                (true, false) => ZoneEventKind::Exit,
+                (true, true) => ZoneEventKind::Active,
+                (false, false) => continue,
+            };
+            state.active = triggered;
+            if self.events.len() == MAX_ZONE_EVENTS {
+                self.events.pop_front();
+            }
+            self.events.push_back(ZoneEvent {
+                name: state.zone.name.clone(),
+                kind,
+                coverage,
+            });
+        }
+    }
+
+    pub(crate) fn drain_events(&mut self) -> Vec<ZoneEvent> {
+        self.events.drain(..).collect()
+    }
+
+    // Zones start out untriggered again
+    pub(crate) fn reset(&mut self) {
+        for state in &mut self.zones {
+            state.active = false;
+        }
+        self.events.clear();
+    }
+
+    pub(crate) fn memory_bytes(&self) -> usize {
+        self.zones
+            .iter()
+            .map(|state| state.pixels.capacity() * std::mem::size_of::<u32>())
+            .sum()
+    }
+
+    // Pixels whose centers lie inside each zone
+    fn rasterize(&mut self, width: u32, height: u32) {
+        self.size = (width, height);
+        for state in &mut self.zones {
+            state.pixels.clear();
+            for y in 0..height {
+                let fy = (y as f32 + 0.5) / height as f32;
+                for x in 0..width {
+                    if state
+                        .zone
+                        .shape
+                        .contains((x as f32 + 0.5) / width as f32, fy)
+                    {
+                        state.pixels.push(y * width + x);
+                    }
+                }
+            }
+        }
+    }
+}
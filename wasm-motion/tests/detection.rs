@@ -0,0 +1,2008 @@
+use motion_detection::testing::{
+    active_pixels, centroid, fnv1a, gradient, moving_square, noise, square_position, texture,
+    total_intensity,
+};
+use motion_detection::{
+    AlphaMode, ComparisonLayout, CompositeMode, DebounceUnit, DetectorContext, Easing, Keyframe,
+    KeyframeValue, LogLevel, MotionDetector, MotionEventKind, MotionOptions, MoveType, OptionValue,
+    OutputMode, PersistenceMode, RecordingSource, Zone, ZoneEventKind, FRAME_SCENE_CHANGE,
+};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 48;
+
+fn run(detector: &mut MotionDetector, frames: &[Vec<u8>], options: &MotionOptions) -> Vec<u8> {
+    let mut output = vec![0; detector.output_len()];
+    for frame in frames {
+        detector
+            .process(frame, &mut output, options)
+            .expect("frames match the detector");
+    }
+    output
+}
+
+#[test]
+fn static_scene_leaves_no_trails() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames = vec![gradient(WIDTH, HEIGHT, 0); 5];
+    let output = run(&mut detector, &frames, &MotionOptions::default());
+
+    assert_eq!(active_pixels(detector.persistence()), 0);
+    assert!(output.chunks_exact(4).all(|pixel| pixel[..3] == [0, 0, 0]));
+}
+
+#[test]
+fn sensor_noise_stays_below_the_threshold() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames: Vec<_> = (0..5).map(|seed| noise(WIDTH, HEIGHT, seed, 8)).collect();
+    run(&mut detector, &frames, &MotionOptions::default());
+
+    assert_eq!(active_pixels(detector.persistence()), 0);
+}
+
+#[test]
+fn moving_square_is_detected_where_it_moves() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames: Vec<_> = (0..2)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    run(&mut detector, &frames, &MotionOptions::default());
+
+    let persistence = detector.persistence();
+    assert!(active_pixels(persistence) > 0);
+
+    // The changed pixels lie between the square's old and new position
+    let (first_x, square_y, side) = square_position(WIDTH, HEIGHT, 0);
+    let (second_x, _, _) = square_position(WIDTH, HEIGHT, 1);
+    let (x, y) = centroid(persistence, WIDTH).unwrap();
+    assert!((first_x as f32..(second_x + side) as f32).contains(&x));
+    assert!((square_y as f32..(square_y + side) as f32).contains(&y));
+}
+
+#[test]
+fn grayscale_output_shows_the_trails() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames: Vec<_> = (0..4)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let output = run(&mut detector, &frames, &MotionOptions::default());
+
+    for (pixel, &trail) in output.chunks_exact(4).zip(detector.persistence()) {
+        let level = trail.min(255.0) as u8;
+        assert_eq!(pixel, [level, level, level, 255]);
+    }
+}
+
+#[test]
+fn trails_decay_once_the_scene_stops() {
+    let mut options = MotionOptions::default();
+    options.decay_rate = 0.8;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let moving: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    run(&mut detector, &moving, &options);
+    let before = total_intensity(detector.persistence());
+    assert!(before > 0.0);
+
+    // Repeating the last frame: nothing moves, the trails only fade
+    let still = vec![moving[2].clone(); 3];
+    run(&mut detector, &still, &options);
+    let after = total_intensity(detector.persistence());
+    assert!((after / before - 0.8f64.powi(3)).abs() < 0.01);
+}
+
+#[test]
+fn same_frames_give_the_same_output() {
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options.speed = 2.0;
+    let mut first = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let mut second = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+
+    assert_eq!(
+        run(&mut first, &frames, &options),
+        run(&mut second, &frames, &options)
+    );
+}
+
+#[test]
+fn output_hashes_match_for_the_same_run() {
+    assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+    assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+
+    let frames: Vec<_> = (0..4)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options.speed = 2.0;
+    let hashes = |options: &MotionOptions| {
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        frames
+            .iter()
+            .map(|frame| {
+                detector.process_motion(frame, options).unwrap();
+                detector.hash_output()
+            })
+            .collect::<Vec<_>>()
+    };
+    let first = hashes(&options);
+    assert_eq!(first, hashes(&options));
+    options.speed = 3.0;
+    assert_ne!(first, hashes(&options));
+}
+
+#[test]
+fn copied_phase_keeps_wave_detectors_in_step() {
+    let mut options = MotionOptions::default();
+    options
+        .set_option("move_type", OptionValue::Text("wave"))
+        .unwrap();
+    options.delta_time_ms = 1000.0 / 60.0;
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut leader = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    assert!(leader.is_first_frame());
+    assert_eq!(leader.last_options().map(|o| o.decay_rate), None);
+    run(&mut leader, &frames, &options);
+    assert!(!leader.is_first_frame());
+    assert!(leader.phase() > 0.0);
+    assert!((leader.effective_decay_rate() - options.decay_rate).abs() < 1e-6);
+    assert!(leader.high_quality_radius() <= leader.medium_quality_radius());
+
+    // A detector started later catches up by taking over the phase
+    let mut follower = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut follower, &frames[2..], &options);
+    follower.set_phase(leader.phase());
+    run(&mut leader, &frames[2..], &options);
+    run(&mut follower, &frames[2..], &options);
+    assert_eq!(follower.phase(), leader.phase());
+}
+
+#[test]
+fn direction_output_colors_rightward_motion_red() {
+    let mut options = MotionOptions::default();
+    options.output_mode = OutputMode::Direction;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames: Vec<_> = (0..2)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let output = run(&mut detector, &frames, &options);
+
+    // Corners of the square read partly diagonal, but the trails are red overall
+    let mut totals = [0u32; 3];
+    for (pixel, &trail) in output.chunks_exact(4).zip(detector.persistence()) {
+        if trail >= 1.0 {
+            for channel in 0..3 {
+                totals[channel] += pixel[channel] as u32;
+            }
+        }
+    }
+    assert!(totals[0] > 0);
+    assert!(totals[0] > 2 * totals[1] && totals[0] > 2 * totals[2]);
+}
+
+#[test]
+fn scene_cuts_are_flagged_and_kept_out_of_the_trails() {
+    let mut options = MotionOptions::default();
+    options.scene_change_threshold = 0.5;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let before_cut = vec![gradient(WIDTH, HEIGHT, 0); 2];
+    run(&mut detector, &before_cut, &options);
+    assert_eq!(detector.last_frame_flags(), 0);
+
+    // Shifting the ramp by half the frame changes nearly every pixel at once
+    let cut = gradient(WIDTH, HEIGHT, WIDTH / 2);
+    run(&mut detector, std::slice::from_ref(&cut), &options);
+    assert_eq!(detector.last_frame_flags(), FRAME_SCENE_CHANGE);
+    assert_eq!(active_pixels(detector.persistence()), 0);
+
+    // The new scene is the reference from then on
+    run(&mut detector, &[cut], &options);
+    assert_eq!(detector.last_frame_flags(), 0);
+    assert_eq!(active_pixels(detector.persistence()), 0);
+}
+
+#[test]
+fn motion_is_not_a_scene_change() {
+    let mut options = MotionOptions::default();
+    options.scene_change_threshold = 0.5;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames: Vec<_> = (0..2)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    run(&mut detector, &frames, &options);
+
+    assert_eq!(detector.last_frame_flags(), 0);
+    assert!(active_pixels(detector.persistence()) > 0);
+}
+
+#[test]
+fn exposure_changes_are_compensated() {
+    let bright = gradient(WIDTH, HEIGHT, 0);
+    // The same scene at 60% exposure
+    let dim: Vec<u8> = bright
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let [r, g, b, a] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            [r, g, b]
+                .map(|value| (value as f32 * 0.6) as u8)
+                .into_iter()
+                .chain([a])
+        })
+        .collect();
+    let frames = [bright, dim];
+
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &MotionOptions::default());
+    assert!(active_pixels(detector.persistence()) > 0);
+
+    let mut options = MotionOptions::default();
+    options.exposure_compensation = true;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &options);
+    assert_eq!(active_pixels(detector.persistence()), 0);
+}
+
+#[test]
+fn motion_spawns_particles_that_are_drawn_and_die_out() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_particles(1000, 4, 1.0, false);
+    let frames: Vec<_> = (0..2)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    run(&mut detector, &frames, &MotionOptions::default());
+    assert!(detector.particle_count() > 0);
+
+    let mut canvas = vec![0; detector.output_len()];
+    detector.render_particles(&mut canvas).unwrap();
+    assert!(canvas.chunks_exact(4).any(|pixel| pixel[..3] != [0, 0, 0]));
+
+    let still = vec![frames[1].clone(); 4];
+    run(&mut detector, &still, &MotionOptions::default());
+    assert_eq!(detector.particle_count(), 0);
+}
+
+#[test]
+fn overlay_shows_the_frame_where_nothing_moves() {
+    let mut options = MotionOptions::default();
+    options.composite_mode = CompositeMode::OverlayAdd;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames = vec![gradient(WIDTH, HEIGHT, 0); 3];
+    let output = run(&mut detector, &frames, &options);
+
+    assert_eq!(output, frames[0]);
+}
+
+#[test]
+fn timeline_animates_the_options_per_frame() {
+    let keyframe = |time_ms: f32, option: &str, value: KeyframeValue| Keyframe {
+        time_ms,
+        option: option.to_string(),
+        value,
+        easing: Easing::Linear,
+    };
+    let keyframes = vec![
+        keyframe(0.0, "threshold", KeyframeValue::Number(10.0)),
+        keyframe(100.0, "threshold", KeyframeValue::Number(50.0)),
+        keyframe(50.0, "output_mode", KeyframeValue::Text("tint".to_string())),
+    ];
+    let mut options = MotionOptions::default();
+    options.delta_time_ms = 25.0;
+    let frame = moving_square(WIDTH, HEIGHT, 0);
+    let animate = |looping: bool| {
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        detector.set_timeline(keyframes.clone(), looping).unwrap();
+        (0..6)
+            .map(|_| {
+                detector.process_motion(&frame, &options).unwrap();
+                let animated = detector.last_options().unwrap();
+                (animated.threshold, animated.output_mode)
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let held = animate(false);
+    assert_eq!(
+        held.iter()
+            .map(|&(threshold, _)| threshold)
+            .collect::<Vec<_>>(),
+        [20.0, 30.0, 40.0, 50.0, 50.0, 50.0]
+    );
+    assert_eq!(held[0].1, OutputMode::Grayscale);
+    assert!(held[1..].iter().all(|&(_, mode)| mode == OutputMode::Tint));
+
+    // A looping timeline starts over after its last keyframe
+    let looped = animate(true);
+    assert_eq!(looped[3].0, 10.0);
+    assert_eq!(looped[4].0, 20.0);
+}
+
+#[test]
+fn config_json_round_trips_every_option() {
+    let mut options = MotionOptions::default();
+    for (key, value) in [
+        ("move_type", OptionValue::Text("spiral")),
+        ("threshold", OptionValue::Number(12.5)),
+        ("output_mode", OptionValue::Text("tint")),
+        ("composite_mode", OptionValue::Text("alpha_blend(0.4)")),
+        ("persistence_mode", OptionValue::Text("lerp(0.25)")),
+        ("flip_horizontal", OptionValue::Bool(true)),
+        ("temporal_filter", OptionValue::Text("median")),
+        ("erode", OptionValue::Number(2.0)),
+        // Past the 24 bits an f32 holds exactly
+        ("seed", OptionValue::Number(4_000_000_001.0)),
+    ] {
+        options.set_option(key, value).unwrap();
+    }
+    let config = options.get_config_json();
+
+    let mut restored = MotionOptions::default();
+    restored.apply_config_json(&config).unwrap();
+    assert_eq!(restored.get_config_json(), config);
+    assert_eq!(restored.seed, 4_000_000_001);
+    for seed in [-1.0, 0.5, 4_294_967_296.0, f64::NAN] {
+        assert!(restored
+            .set_option("seed", OptionValue::Number(seed))
+            .is_err());
+    }
+
+    // Every option of the schema is saved
+    let saved: serde_json::Value = serde_json::from_str(&config).unwrap();
+    let schema: serde_json::Value =
+        serde_json::from_str(&motion_detection::describe_parameters()).unwrap();
+    for parameter in schema["parameters"].as_array().unwrap() {
+        let name = parameter["name"].as_str().unwrap();
+        assert!(saved.get(name).is_some(), "{} is not saved", name);
+    }
+
+    // A partial config leaves the other options alone
+    restored
+        .apply_config_json(r#"{"threshold": 40, "move_type": null}"#)
+        .unwrap();
+    assert_eq!(restored.threshold, 40.0);
+    assert!(restored.move_type.is_none());
+    assert_eq!(restored.output_mode, OutputMode::Tint);
+
+    // A flip edited in a saved config must not be undone by the saved transform
+    let edited = config.replace(r#""flip_horizontal":true"#, r#""flip_horizontal":false"#);
+    assert_ne!(edited, config);
+    let before = restored.get_config_json();
+    assert!(restored.try_apply_config_json(&edited).is_err());
+    assert_eq!(restored.get_config_json(), before);
+    assert!(restored.flip_horizontal);
+}
+
+#[test]
+fn timestamped_frames_drop_out_of_order_arrivals() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let options = MotionOptions::default();
+    let mut output = vec![0; detector.output_len()];
+    let mut process = |detector: &mut MotionDetector, timestamp_us: f64, index: u32| {
+        let frame = moving_square(WIDTH, HEIGHT, index);
+        detector
+            .process_frame_at(timestamp_us, &frame, &mut output, &options)
+            .unwrap()
+    };
+    assert!(process(&mut detector, 0.0, 0));
+    assert!(process(&mut detector, 33_333.0, 1));
+    assert!(process(&mut detector, 66_666.0, 2));
+    let trails = detector.persistence().to_vec();
+
+    // A late and a repeated frame change nothing
+    assert!(!process(&mut detector, 50_000.0, 3));
+    assert!(!process(&mut detector, 66_666.0, 4));
+    assert_eq!(detector.persistence(), &trails[..]);
+    assert_eq!(detector.dropped_frames(), 2);
+
+    assert!(process(&mut detector, 100_000.0, 3));
+    assert!((detector.effective_fps() - 30.0).abs() < 0.1);
+    assert!((detector.stream_time_ms() - 100.0).abs() < 0.01);
+
+    detector.reset_all_state();
+    assert_eq!(detector.dropped_frames(), 0);
+    assert!(process(&mut detector, 0.0, 0));
+}
+
+#[test]
+fn batch_matches_frame_by_frame_processing() {
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let options = MotionOptions::default();
+
+    let mut single = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let mut energies = Vec::new();
+    for frame in &frames {
+        single.process_motion(frame, &options).unwrap();
+        energies.push(single.get_motion_stats().energy);
+    }
+
+    let mut batched = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let stats = batched
+        .process_batch(&frames.concat(), frames.len() as u32, &options, true)
+        .unwrap();
+    assert_eq!(batched.hash_output(), single.hash_output());
+    assert_eq!(
+        stats.iter().map(|stats| stats.energy).collect::<Vec<_>>(),
+        energies
+    );
+
+    // Without stats only the final composite is kept
+    let mut quiet = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let stats = quiet
+        .process_batch(&frames.concat(), frames.len() as u32, &options, false)
+        .unwrap();
+    assert!(stats.is_empty());
+    assert_eq!(quiet.hash_output(), single.hash_output());
+}
+
+#[test]
+fn self_feedback_suppression_ignores_the_projected_trails() {
+    // The camera sees the scene lit by what was projected the frame before, upside down, so
+    // trails of a box flickering at the top come back at the bottom
+    let flip = [1.0, 0.0, 0.0, 0.0, -1.0, 1.0, 0.0, 0.0, 1.0];
+    let bottom_trails = |suppress: bool, projected: bool| {
+        let mut options = MotionOptions::default();
+        options.suppress_self_feedback = suppress;
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        detector.set_feedback_homography(&flip).unwrap();
+        let mut output = vec![0; detector.output_len()];
+        for index in 0..8 {
+            let mut camera = vec![0u8; output.len()];
+            for (pixel, rgb) in camera.chunks_exact_mut(4).enumerate() {
+                let (x, y) = (pixel % WIDTH as usize, pixel / WIDTH as usize);
+                if index < 4 && index % 2 == 1 && (24..40).contains(&x) && (4..12).contains(&y) {
+                    rgb[..3].fill(255);
+                }
+                if projected {
+                    let seen = ((HEIGHT as usize - 1 - y) * WIDTH as usize + x) * 4;
+                    for channel in 0..3 {
+                        rgb[channel] = rgb[channel].saturating_add(output[seen + channel]);
+                    }
+                }
+            }
+            detector.process(&camera, &mut output, &options).unwrap();
+        }
+        let bottom = WIDTH as usize * HEIGHT as usize / 2..;
+        active_pixels(&detector.persistence()[bottom])
+    };
+    assert_eq!(bottom_trails(false, false), 0);
+    assert!(bottom_trails(false, true) > 0);
+    assert_eq!(bottom_trails(true, true), 0);
+}
+
+#[test]
+fn ignore_color_skips_motion_in_the_key_color() {
+    // A box flickering between two shades of green next to one flickering black and white
+    let frame = |index: usize| {
+        let mut frame = vec![0; (WIDTH * HEIGHT * 4) as usize];
+        for y in 16..32 {
+            for x in 14..50 {
+                let color = match (x < 30, index % 2) {
+                    (true, 0) => [40, 255, 40],
+                    (true, _) => [0, 140, 0],
+                    (false, 0) => [255, 255, 255],
+                    (false, _) => [0, 0, 0],
+                };
+                let index = (y * WIDTH as usize + x) * 4;
+                frame[index..index + 3].copy_from_slice(&color);
+                frame[index + 3] = 255;
+            }
+        }
+        frame
+    };
+    let frames: Vec<_> = (0..4).map(frame).collect();
+    let options = MotionOptions::default();
+    let active_in = |detector: &MotionDetector, columns: std::ops::Range<usize>| {
+        let persistence = detector.persistence();
+        (16..32)
+            .flat_map(|y| columns.clone().map(move |x| y * WIDTH as usize + x))
+            .filter(|&index| persistence[index] > 0.0)
+            .count()
+    };
+
+    let mut plain = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut plain, &frames, &options);
+    assert!(active_in(&plain, 14..30) > 0);
+
+    let mut keyed = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    keyed.set_ignore_color(0, 255, 0, 120.0);
+    run(&mut keyed, &frames, &options);
+    assert_eq!(active_in(&keyed, 14..30), 0);
+    assert_eq!(active_in(&keyed, 34..50), active_in(&plain, 34..50));
+
+    keyed.clear_ignore_color();
+    run(&mut keyed, &frames, &options);
+    assert!(active_in(&keyed, 14..30) > 0);
+}
+
+#[test]
+fn silhouette_fills_the_moving_outline_and_hides_the_frame() {
+    // The outline of a box flickers while its inside stays still, like the edges of a body
+    // that moves as a whole
+    let outline = |brightness: u8| {
+        let mut frame = gradient(WIDTH, HEIGHT, 0);
+        for y in 10..30 {
+            for x in 20..40 {
+                if !(12..28).contains(&y) || !(22..38).contains(&x) {
+                    let index = (y * WIDTH as usize + x) * 4;
+                    frame[index..index + 3].fill(brightness);
+                }
+            }
+        }
+        frame
+    };
+    let frames: Vec<_> = (0..4).map(|index| outline([0, 255][index % 2])).collect();
+    let mut options = MotionOptions::default();
+    options
+        .set_option("output_mode", OptionValue::Text("silhouette"))
+        .unwrap();
+    options.tint_color = 0x20c040;
+    options.composite_mode = CompositeMode::OverlayAdd;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let output = run(&mut detector, &frames, &options);
+
+    let filled = |x: usize, y: usize| {
+        let index = (y * WIDTH as usize + x) * 4;
+        match output[index..index + 3] {
+            [0x20, 0xc0, 0x40] => true,
+            [0, 0, 0] => false,
+            _ => panic!("neither silhouette nor background at {}, {}", x, y),
+        }
+    };
+    for y in 0..HEIGHT as usize {
+        for x in 0..WIDTH as usize {
+            let inside = (10..30).contains(&y) && (20..40).contains(&x);
+            assert_eq!(filled(x, y), inside, "at {}, {}", x, y);
+        }
+    }
+
+    // Smoothing lets a silhouette build up over a few frames
+    options.silhouette_smoothing = 0.8;
+    let mut smoothed = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let first = run(&mut smoothed, &frames[..2], &options);
+    assert!(first.chunks_exact(4).all(|pixel| pixel[..3] == [0, 0, 0]));
+    let later = run(&mut smoothed, &frames, &options);
+    assert_eq!(later, output);
+}
+
+#[test]
+fn fluid_dye_swirls_on_after_the_motion_and_fades() {
+    let moving: Vec<_> = (0..8)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options
+        .set_option("output_mode", OptionValue::Text("fluid"))
+        .unwrap();
+    options.tint_color = 0xff0000;
+    options.fluid_dissipation = 0.9;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let output = run(&mut detector, &moving, &options);
+
+    let dye = |output: &[u8]| -> u64 {
+        assert!(output.chunks_exact(4).all(|pixel| pixel[1..3] == [0, 0]));
+        output.chunks_exact(4).map(|pixel| pixel[0] as u64).sum()
+    };
+    let stirred = dye(&output);
+    assert!(stirred > 0);
+
+    // The dye stays in the picture once the square stops, and fades frame by frame
+    let still = vec![moving[7].clone(); 2];
+    let after = dye(&run(&mut detector, &still, &options));
+    assert!(after > 0);
+    assert!(after < stirred);
+    let later = dye(&run(&mut detector, &still, &options));
+    assert!(later < after);
+
+    // Other output modes drop the fluid
+    options.output_mode = OutputMode::Tint;
+    run(&mut detector, &still, &options);
+    assert!(detector
+        .memory_breakdown()
+        .iter()
+        .any(|(name, bytes)| *name == "fluid" && *bytes == 0));
+}
+
+#[test]
+fn debug_info_reports_clamped_options_rejected_frames_and_stage_timings() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_log_level(LogLevel::Off);
+    assert_eq!(detector.log_level(), LogLevel::Off);
+    let mut options = MotionOptions::default();
+    options.fluid_force = 50.0;
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut output = run(&mut detector, &frames, &options);
+    assert!(detector
+        .process(&frames[0][4..], &mut output, &options)
+        .is_err());
+
+    let info: serde_json::Value = serde_json::from_str(&detector.debug_info()).unwrap();
+    assert_eq!(info["log_level"], "off");
+    assert_eq!(info["frame"], 4);
+    assert_eq!(
+        info["clamped_options"],
+        serde_json::json!([{ "option": "fluid_force", "value": 50.0, "clamped_to": 10.0 }])
+    );
+    // The range is reported once for the unchanged options, the short frame on its own
+    let levels: Vec<_> = info["messages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|message| message["level"].as_str().unwrap())
+        .collect();
+    assert_eq!(levels, ["warn", "error"]);
+
+    // The stages of the last analysed frame, in pipeline order
+    run(&mut detector, &frames, &options);
+    let info: serde_json::Value = serde_json::from_str(&detector.debug_info()).unwrap();
+    let stages: Vec<_> = info["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stage| stage["stage"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        stages,
+        [
+            "move",
+            "decode",
+            "diff",
+            "detect",
+            "analyse",
+            "composite",
+            "output"
+        ]
+    );
+    assert!(info["total_ms"].as_f64().unwrap() >= 0.0);
+}
+
+#[test]
+fn detection_kernel_averages_away_single_pixel_flicker() {
+    // A lone pixel flickering next to a moving square
+    let frames: Vec<_> = (0..4)
+        .map(|index| {
+            let mut frame = moving_square(WIDTH, HEIGHT, index);
+            let pixel = (10 * WIDTH as usize + 20) * 4;
+            frame[pixel..pixel + 3].fill([0, 255][index as usize % 2]);
+            frame
+        })
+        .collect();
+    let mut options = MotionOptions::default();
+    options.move_type = None;
+    let flicker = |detector: &MotionDetector| detector.persistence()[10 * WIDTH as usize + 20];
+
+    let mut plain = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut plain, &frames, &options);
+    assert!(flicker(&plain) > 0.0);
+
+    let mut boxed = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    boxed.set_detection_kernel(&[1.0 / 9.0; 9], 3).unwrap();
+    run(&mut boxed, &frames, &options);
+    assert_eq!(flicker(&boxed), 0.0);
+    assert!(active_pixels(boxed.persistence()) > 0);
+
+    boxed.clear_detection_kernel();
+    run(&mut boxed, &frames, &options);
+    assert!(flicker(&boxed) > 0.0);
+}
+
+#[test]
+fn output_viewport_crops_the_frame_between_black_bars() {
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options.delta_time_ms = 20.0;
+    let mut whole = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let full = run(&mut whole, &frames, &options);
+
+    // A square region at its own scale in a wider output: bars of 8 pixels either side
+    let mut cropped = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    cropped.set_output_viewport(16, 8, 32, 32, 48, 32).unwrap();
+    assert_eq!((cropped.output_width(), cropped.output_height()), (48, 32));
+    assert_eq!(cropped.output_len(), 48 * 32 * 4);
+    let output = run(&mut cropped, &frames, &options);
+    assert_eq!(output.len(), 48 * 32 * 4);
+    for y in 0..32 {
+        for x in 0..48 {
+            let pixel = &output[(y * 48 + x) * 4..(y * 48 + x + 1) * 4];
+            if (8..40).contains(&x) {
+                let source = ((y + 8) * WIDTH as usize + x + 8) * 4;
+                assert_eq!(pixel, &full[source..source + 4], "at {}, {}", x, y);
+            } else {
+                assert_eq!(pixel, [0, 0, 0, 255], "at {}, {}", x, y);
+            }
+        }
+    }
+    assert_eq!(
+        active_pixels(cropped.persistence()),
+        active_pixels(whole.persistence())
+    );
+
+    cropped.clear_output_viewport();
+    assert_eq!(cropped.output_len(), full.len());
+}
+
+#[test]
+fn selftest_report_times_the_stages_and_reproduces_its_checksums() {
+    let report = MotionDetector::run_selftest_report(WIDTH, HEIGHT, 4).unwrap();
+    assert_eq!(report["frames"], 4);
+    let stages: Vec<_> = report["stages_ms"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stage| stage["stage"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        stages,
+        [
+            "move",
+            "decode",
+            "diff",
+            "detect",
+            "analyse",
+            "composite",
+            "output"
+        ]
+    );
+
+    // Timings differ from run to run, the output doesn't
+    let again = MotionDetector::run_selftest_report(WIDTH, HEIGHT, 4).unwrap();
+    for checksum in ["last_output_checksum", "outputs_checksum"] {
+        assert_eq!(report[checksum].as_str().unwrap().len(), 16);
+        assert_eq!(report[checksum], again[checksum]);
+    }
+    let longer = MotionDetector::run_selftest_report(WIDTH, HEIGHT, 5).unwrap();
+    assert_ne!(report["outputs_checksum"], longer["outputs_checksum"]);
+    assert!(MotionDetector::run_selftest_report(0, HEIGHT, 4).is_err());
+}
+
+#[test]
+fn alpha_carries_freshness_or_confidence_while_the_colors_stay() {
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options.decay_rate = 0.8;
+    let opaque = run(
+        &mut MotionDetector::try_new(WIDTH, HEIGHT).unwrap(),
+        &frames,
+        &options,
+    );
+    let alphas = |output: &[u8]| {
+        output
+            .chunks_exact(4)
+            .map(|pixel| pixel[3])
+            .collect::<Vec<_>>()
+    };
+    assert!(alphas(&opaque).iter().all(|&alpha| alpha == 255));
+
+    options
+        .set_option("alpha_mode", OptionValue::Text("freshness"))
+        .unwrap();
+    let fresh = run(
+        &mut MotionDetector::try_new(WIDTH, HEIGHT).unwrap(),
+        &frames,
+        &options,
+    );
+    let rgb = |output: &[u8]| {
+        output
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(rgb(&fresh), rgb(&opaque));
+    // Brighter trails are fresher, and where there is no trail there is no alpha
+    let levels: Vec<_> = opaque.chunks_exact(4).map(|pixel| pixel[0]).collect();
+    let mut by_level: Vec<_> = levels.iter().zip(alphas(&fresh)).collect();
+    by_level.sort();
+    assert!(by_level.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+    assert!(by_level
+        .iter()
+        .all(|&(&level, alpha)| (level == 0) == (alpha == 0)));
+    assert!(by_level.last().unwrap().1 > 200);
+
+    // Confidence only marks this frame's motion, not the older trails
+    options.alpha_mode = AlphaMode::Confidence;
+    let confident = run(
+        &mut MotionDetector::try_new(WIDTH, HEIGHT).unwrap(),
+        &frames,
+        &options,
+    );
+    assert_eq!(rgb(&confident), rgb(&opaque));
+    let confidence = alphas(&confident);
+    let marked = confidence.iter().filter(|&&alpha| alpha > 0).count();
+    let trails = levels.iter().filter(|&&level| level > 0).count();
+    assert!(marked > 0 && marked < trails, "{} of {}", marked, trails);
+}
+
+#[test]
+fn composite_modes_lighten_or_fade_the_frame_with_the_trails() {
+    let frames: Vec<_> = (0..2)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut plain = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let trails = run(&mut plain, &frames, &MotionOptions::default());
+
+    let mut options = MotionOptions::default();
+    options
+        .set_option("composite_mode", OptionValue::Text("alpha_blend(0.25)"))
+        .unwrap();
+    assert_eq!(options.composite_mode, CompositeMode::AlphaBlend);
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let blended = run(&mut detector, &frames, &options);
+    for ((blended, trail), frame) in blended.iter().zip(&trails).zip(&frames[1]) {
+        let expected = *frame as f32 + (*trail as f32 - *frame as f32) * 0.25;
+        assert!((*blended as f32 - expected).abs() <= 1.0);
+    }
+
+    options.composite_mode = CompositeMode::OverlayScreen;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let screened = run(&mut detector, &frames, &options);
+    assert!(screened
+        .iter()
+        .zip(&frames[1])
+        .all(|(out, frame)| out >= frame));
+    assert!(screened
+        .iter()
+        .zip(&frames[1])
+        .any(|(out, frame)| out > frame));
+}
+
+#[test]
+fn level_curve_shapes_the_trail_output() {
+    let frames: Vec<_> = (0..4)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut plain = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let linear = run(&mut plain, &frames, &MotionOptions::default());
+
+    let mut options = MotionOptions::default();
+    options.gamma = 2.0;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let lifted = run(&mut detector, &frames, &options);
+    assert!(lifted
+        .iter()
+        .zip(&linear)
+        .all(|(lifted, linear)| lifted >= linear));
+    assert!(lifted
+        .iter()
+        .zip(&linear)
+        .any(|(lifted, linear)| lifted > linear));
+
+    let mut options = MotionOptions::default();
+    options.posterize_levels = 2;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let posterized = run(&mut detector, &frames, &options);
+    assert!(posterized.iter().all(|&value| value == 0 || value == 255));
+}
+
+#[test]
+fn output_vignette_darkens_the_edges_without_changing_detection() {
+    let frames: Vec<_> = (0..4)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut plain = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let trails = run(&mut plain, &frames, &MotionOptions::default());
+
+    let mut options = MotionOptions::default();
+    options.output_vignette_strength = 1.0;
+    options.output_vignette_radius = 0.0;
+    options.output_vignette_softness = 1.0;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let vignetted = run(&mut detector, &frames, &options);
+
+    assert_eq!(detector.persistence(), plain.persistence());
+    assert!(vignetted
+        .iter()
+        .zip(&trails)
+        .all(|(out, trail)| out <= trail));
+    // The square starts at the left edge, where the vignette takes most of the light
+    let (_, square_y, side) = square_position(WIDTH, HEIGHT, 0);
+    let pixel = ((square_y + side / 2) * WIDTH as usize + 1) * 4;
+    assert!(trails[pixel] > 0);
+    assert!(vignetted[pixel] < trails[pixel] / 2);
+}
+
+#[test]
+fn echo_adds_delayed_copies_of_the_trails() {
+    let mut options = MotionOptions::default();
+    options.decay_rate = 0.1;
+    let frames: Vec<_> = (0..8)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut plain = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let trails = run(&mut plain, &frames, &options);
+
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_echo(&[4], &[1.0]).unwrap();
+    let echoed = run(&mut detector, &frames, &options);
+    assert_eq!(detector.persistence(), plain.persistence());
+
+    // The trailing edge of the square 4 frames ago shows up again where the fast decay
+    // already cleared it
+    let (echo_x, square_y, side) = square_position(WIDTH, HEIGHT, 3);
+    let pixel = ((square_y + side / 2) * WIDTH as usize + echo_x - 2) * 4;
+    assert_eq!(trails[pixel], 0);
+    assert!(echoed[pixel] > 0);
+    assert!(echoed
+        .iter()
+        .zip(&trails)
+        .all(|(echoed, plain)| echoed >= plain));
+
+    detector.set_echo(&[], &[]).unwrap();
+    let still = vec![frames[7].clone(); 1];
+    let output = run(&mut detector, &still, &options);
+    assert_eq!(output[pixel], 0);
+}
+
+#[test]
+fn bgra_and_rgb_input_match_rgba() {
+    // Red squares, so a wrong channel order changes the luma
+    let rgba: Vec<Vec<u8>> = (0..3)
+        .map(|index| {
+            let mut frame = moving_square(WIDTH, HEIGHT, index);
+            for pixel in frame.chunks_exact_mut(4) {
+                pixel[1] /= 4;
+                pixel[2] /= 4;
+            }
+            frame
+        })
+        .collect();
+    let bgra: Vec<Vec<u8>> = rgba
+        .iter()
+        .map(|frame| {
+            frame
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[2], pixel[1], pixel[0], pixel[3]])
+                .collect()
+        })
+        .collect();
+    let rgb: Vec<Vec<u8>> = rgba
+        .iter()
+        .map(|frame| {
+            frame
+                .chunks_exact(4)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+                .collect()
+        })
+        .collect();
+    let mut reference = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let expected = run(&mut reference, &rgba, &MotionOptions::default());
+
+    for (format, frames) in [("bgra", &bgra), ("rgb", &rgb)] {
+        let mut options = MotionOptions::default();
+        options
+            .set_option("input_format", OptionValue::Text(format))
+            .unwrap();
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        assert_eq!(run(&mut detector, frames, &options), expected);
+        assert_eq!(detector.persistence(), reference.persistence());
+    }
+}
+
+#[test]
+fn padded_rows_match_packed_frames() {
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    // Rows padded to 16 bytes more than the pixels need, padding filled with junk
+    let row_len = WIDTH as usize * 4;
+    let stride = row_len + 16;
+    let padded: Vec<Vec<u8>> = frames
+        .iter()
+        .map(|frame| {
+            frame
+                .chunks_exact(row_len)
+                .flat_map(|row| row.iter().copied().chain([0xab; 16]))
+                .collect()
+        })
+        .collect();
+
+    for flip_horizontal in [false, true] {
+        let mut options = MotionOptions::default();
+        options.flip_horizontal = flip_horizontal;
+        let mut packed = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        let expected = run(&mut packed, &frames, &options);
+
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        let mut output = vec![0; detector.output_len()];
+        for frame in &padded {
+            detector
+                .process_motion_with_cache(frame, &mut output, &options, Some(stride as u32))
+                .unwrap();
+        }
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn input_transform_mirrors_while_decoding() {
+    let frames: Vec<_> = (0..4)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let (width, height) = (WIDTH as usize, HEIGHT as usize);
+    for (name, mirror_x, mirror_y) in [
+        ("mirror_x", true, false),
+        ("mirror_y", false, true),
+        ("rotate_180", true, true),
+    ] {
+        let mut options = MotionOptions::default();
+        options.move_type = None;
+        let mirrored: Vec<Vec<u8>> = frames
+            .iter()
+            .map(|frame| {
+                (0..width * height)
+                    .flat_map(|pixel| {
+                        let (x, y) = (pixel % width, pixel / width);
+                        let x = if mirror_x { width - 1 - x } else { x };
+                        let y = if mirror_y { height - 1 - y } else { y };
+                        let index = (y * width + x) * 4;
+                        frame[index..index + 4].to_vec()
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut by_hand = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        run(&mut by_hand, &mirrored, &options);
+
+        options
+            .set_option("input_transform", OptionValue::Text(name))
+            .unwrap();
+        assert_eq!(options.input_transform().name(), name);
+        let mut transformed = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        run(&mut transformed, &frames, &options);
+        assert_eq!(transformed.persistence(), by_hand.persistence(), "{}", name);
+    }
+}
+
+#[test]
+fn detectors_of_a_context_share_their_tables() {
+    let context = DetectorContext::new();
+    let mut first = MotionDetector::with_context(WIDTH, HEIGHT, &context).unwrap();
+    let second = MotionDetector::with_context(WIDTH, HEIGHT, &context).unwrap();
+    let mut alone = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    assert_eq!(context.shared_table_count(), 1);
+    assert!(first.memory_bytes() < alone.memory_bytes());
+
+    // Shared tables behave like the detector's own
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options
+        .set_option("move_type", OptionValue::Text("spiral"))
+        .unwrap();
+    assert_eq!(
+        run(&mut first, &frames, &options),
+        run(&mut alone, &frames, &options)
+    );
+
+    // A detector that changes size gets tables of its own; dropped tables are freed
+    first.try_resize(WIDTH / 2, HEIGHT / 2).unwrap();
+    assert_eq!(context.shared_table_count(), 2);
+    drop(second);
+    assert_eq!(context.shared_table_count(), 1);
+}
+
+#[test]
+fn orientation_takes_any_angle() {
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options.move_type = Some(MoveType::Direction);
+    options.speed = 3.0;
+    let render = |degrees: u32| {
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        detector.set_orientation(degrees);
+        run(&mut detector, &frames, &options)
+    };
+    // u32::MAX is 255 degrees past a whole number of turns
+    assert_eq!(render(u32::MAX), render(270));
+    assert_eq!(render(450), render(90));
+    assert_ne!(render(90), render(0));
+}
+
+#[test]
+fn recording_length_is_capped() {
+    // Any length is taken without reserving room for it up front
+    let mut detector = MotionDetector::try_new(8, 8).unwrap();
+    detector.record_frames(u32::MAX, RecordingSource::Persistence);
+    let frame = vec![0; 8 * 8 * 4];
+    let frames = vec![frame; 601];
+    run(&mut detector, &frames, &MotionOptions::default());
+    assert_eq!(detector.recorded_frames(), 600);
+    assert_eq!(detector.drain_recording().len(), 600 * 64);
+}
+
+#[test]
+fn memory_limit_is_checked_before_allocating() {
+    let estimate = MotionDetector::estimate_memory_bytes(WIDTH, HEIGHT);
+    let mut detector =
+        MotionDetector::try_new_with_memory_limit(WIDTH, HEIGHT, estimate as u64).unwrap();
+    let frames: Vec<_> = (0..2)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    for frame in &frames {
+        detector
+            .process_motion(frame, &MotionOptions::default())
+            .unwrap();
+    }
+    assert_eq!(detector.memory_usage_bytes(), estimate);
+
+    assert!(MotionDetector::try_new_with_memory_limit(WIDTH, HEIGHT, estimate as u64 - 1).is_err());
+    assert!(MotionDetector::try_new_with_memory_limit(4096, 4096, 64 << 20).is_err());
+}
+
+#[test]
+fn grid_mode_samples_a_coarse_grid_and_interpolates_between() {
+    let frames: Vec<_> = (0..4)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &MotionOptions::default());
+    assert!(detector.get_motion_grid().is_empty());
+
+    let mut options = MotionOptions::default();
+    options.grid_mode = 4;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let output = run(&mut detector, &frames, &options);
+    let grid = detector.get_motion_grid();
+    assert_eq!(detector.motion_grid_width(), WIDTH / 4);
+    assert_eq!(detector.motion_grid_height(), HEIGHT / 4);
+    assert_eq!(grid.len(), (WIDTH / 4 * HEIGHT / 4) as usize);
+    assert!(active_pixels(&grid) > 0);
+    // Each sample is the trail level at the top-left pixel of its block
+    let persistence = detector.persistence();
+    for (index, &level) in grid.iter().enumerate() {
+        let (x, y) = (index % 16 * 4, index / 16 * 4);
+        assert_eq!(level, persistence[y * WIDTH as usize + x]);
+    }
+    // The display blends between samples instead of repeating them over each block
+    let gray = |x: usize, y: usize| output[(y * WIDTH as usize + x) * 4];
+    assert!((0..HEIGHT as usize - 4)
+        .any(|y| { (0..WIDTH as usize).any(|x| gray(x, y) != gray(x - x % 4, y - y % 4)) }));
+}
+
+#[test]
+fn zones_report_enter_active_and_exit() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_zones(vec![
+        Zone::rect("left", 0.0, 0.0, 0.5, 1.0),
+        Zone::polygon("right", vec![(0.6, 0.1), (0.95, 0.1), (0.95, 0.9)]),
+    ]);
+    let mut frames: Vec<_> = (0..4)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    frames.extend(std::iter::repeat_n(frames[3].clone(), 2));
+    let mut kinds = Vec::new();
+    for frame in &frames {
+        detector
+            .process_motion(frame, &MotionOptions::default())
+            .unwrap();
+        for event in detector.poll_zone_events() {
+            // The square never leaves the left half
+            assert_eq!(event.name(), "left");
+            kinds.push(event.kind);
+        }
+    }
+    assert_eq!(
+        kinds,
+        [
+            ZoneEventKind::Enter,
+            ZoneEventKind::Active,
+            ZoneEventKind::Active,
+            ZoneEventKind::Exit,
+        ]
+    );
+    assert!(detector.poll_zone_events().is_empty());
+}
+
+#[test]
+fn motion_trigger_debounces_started_and_stopped() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_motion_trigger(0.5, 40.0, 80.0, DebounceUnit::Milliseconds);
+    let mut options = MotionOptions::default();
+    options.delta_time_ms = 40.0;
+    // Still, moving for four frames, then still again
+    let mut frames = vec![moving_square(WIDTH, HEIGHT, 0); 2];
+    frames.extend((1..5).map(|index| moving_square(WIDTH, HEIGHT, index)));
+    frames.extend(std::iter::repeat_n(frames[5].clone(), 4));
+    let mut events = Vec::new();
+    for frame in &frames {
+        detector.process_motion(frame, &options).unwrap();
+        events.extend(detector.poll_events());
+        if events.len() == 1 {
+            assert!(detector.is_motion_active());
+        }
+    }
+    assert_eq!(detector.stream_time_ms(), 400.0);
+
+    let summary: Vec<_> = events
+        .iter()
+        .map(|event| (event.kind, event.timestamp_ms, event.confirmed_ms))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (MotionEventKind::MotionStarted, 120.0, 160.0),
+            (MotionEventKind::MotionStopped, 280.0, 360.0),
+        ]
+    );
+    assert!(events[0].active_percent >= 0.5 && events[1].active_percent < 0.5);
+    assert!(!detector.is_motion_active());
+}
+
+#[test]
+fn temporal_median_drops_single_frame_flashes() {
+    let still = gradient(WIDTH, HEIGHT, 0);
+    let mut flash = still.clone();
+    for y in 10..20 {
+        for x in 10..20 {
+            let index = (y * WIDTH as usize + x) * 4;
+            flash[index..index + 3].fill(255);
+        }
+    }
+    let frames = [&still, &still, &still, &flash, &still, &still];
+    let most_active = |options: &MotionOptions| {
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        frames
+            .iter()
+            .map(|frame| {
+                detector.process_motion(frame, options).unwrap();
+                active_pixels(detector.persistence())
+            })
+            .max()
+            .unwrap()
+    };
+    let mut options = MotionOptions::default();
+    assert!(most_active(&options) >= 100);
+    options
+        .set_option("temporal_filter", OptionValue::Text("median"))
+        .unwrap();
+    assert_eq!(most_active(&options), 0);
+
+    // Lasting changes still come through, a frame later
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    for frame in [&still, &still, &flash, &flash, &flash] {
+        detector.process_motion(frame, &options).unwrap();
+    }
+    assert!(active_pixels(detector.persistence()) >= 100);
+}
+
+#[test]
+fn lens_model_slows_radial_movement_towards_the_edges() {
+    let (width, height) = (200, 150);
+    let still = gradient(width, height, 0);
+    let mut options = MotionOptions::default();
+    options.move_type = Some(MoveType::Radial);
+    options.speed = 2.0;
+    options.decay_rate = 1.0;
+    // Where a dot 70 pixels right of the center ends up after a few frames
+    let dot_x = |detector: &mut MotionDetector| {
+        detector.process_motion(&still, &options).unwrap();
+        let mut trails = vec![0.0; (width * height) as usize];
+        trails[75 * width as usize + 170] = 255.0;
+        detector.import_persistence(&trails).unwrap();
+        for _ in 0..5 {
+            detector.process_motion(&still, &options).unwrap();
+        }
+        centroid(detector.persistence(), width).unwrap().0
+    };
+
+    let mut detector = MotionDetector::try_new(width, height).unwrap();
+    let ideal = dot_x(&mut detector);
+    assert!((ideal - 180.0).abs() <= 1.0);
+    // A barrel-distorted picture squeezes the edges, so the same true speed covers fewer
+    // picture pixels there
+    let mut detector = MotionDetector::try_new(width, height).unwrap();
+    detector.set_lens_model(0.5, 0.0, 0.5, 0.5);
+    let fisheye = dot_x(&mut detector);
+    assert!(fisheye < ideal - 2.0 && fisheye > 170.0);
+
+    detector.clear_lens_model();
+    detector.reset_all_state();
+    assert_eq!(dot_x(&mut detector), ideal);
+}
+
+#[test]
+fn low_memory_packs_the_polar_tables() {
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options.move_type = Some(MoveType::Spiral);
+    let mut full = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut full, &frames, &options);
+    let mut packed = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    packed.set_low_memory(true);
+    run(&mut packed, &frames, &options);
+
+    // 12 bytes per pixel of polar tables become 4, and the trails and frame cache 8 bytes
+    // of the 12 with the movement scratch (see `low_memory_packs_the_state_between_frames`)
+    let pixels = (WIDTH * HEIGHT) as usize;
+    assert_eq!(full.memory_bytes() - packed.memory_bytes(), pixels * 16);
+    // Rounding moves the odd sample by a pixel, but the trails end up in the same place
+    let (full_x, full_y) = centroid(full.persistence(), WIDTH).unwrap();
+    let (packed_x, packed_y) = centroid(packed.persistence(), WIDTH).unwrap();
+    assert!((full_x - packed_x).abs() < 0.5 && (full_y - packed_y).abs() < 0.5);
+    assert!(
+        (total_intensity(full.persistence()) - total_intensity(packed.persistence())).abs()
+            < total_intensity(full.persistence()) * 0.01
+    );
+}
+
+#[test]
+fn low_memory_packs_the_state_between_frames() {
+    let bytes_of = |detector: &MotionDetector, field: &str| -> usize {
+        detector
+            .memory_breakdown()
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map_or(0, |(_, bytes)| *bytes)
+    };
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let options = MotionOptions::default();
+    let mut full = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut full, &frames[..3], &options);
+    let mut packed = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    packed.set_low_memory(true);
+    run(&mut packed, &frames[..3], &options);
+
+    // Trails and previous frame take 2 rather than 4 bytes per pixel, the scratch none
+    let pixels = (WIDTH * HEIGHT) as usize;
+    assert_eq!(full.memory_bytes() - packed.memory_bytes(), pixels * 8);
+    assert_eq!(bytes_of(&packed, "persistence_buffer"), 0);
+    assert_eq!(bytes_of(&packed, "packed_persistence"), pixels * 2);
+    assert_eq!(bytes_of(&packed, "previous_gray"), 0);
+    assert_eq!(bytes_of(&packed, "packed_gray"), pixels * 2);
+    assert_eq!(bytes_of(&packed, "temp_buffer"), 0);
+
+    // Reading the trails unpacks a copy until the next frame packs them again
+    let close = |packed: &MotionDetector, full: &MotionDetector| {
+        packed
+            .persistence()
+            .iter()
+            .zip(full.persistence())
+            .all(|(packed, full)| (packed - full).abs() <= 1.0)
+    };
+    assert!(close(&packed, &full));
+    assert_eq!(bytes_of(&packed, "unpacked_persistence"), pixels * 4);
+    run(&mut full, &frames[3..], &options);
+    run(&mut packed, &frames[3..], &options);
+    assert_eq!(full.memory_bytes() - packed.memory_bytes(), pixels * 8);
+    assert!(active_pixels(packed.persistence()) > 0);
+    assert!(close(&packed, &full));
+
+    // Turning the mode off unpacks the state for good
+    packed.set_low_memory(false);
+    assert_eq!(bytes_of(&packed, "packed_persistence"), 0);
+    assert_eq!(bytes_of(&packed, "persistence_buffer"), pixels * 4);
+}
+
+#[test]
+fn polar_tables_are_built_on_first_use_and_can_be_freed() {
+    let polar_bytes = |detector: &MotionDetector| -> usize {
+        detector
+            .memory_breakdown()
+            .iter()
+            .filter(|(name, _)| name.starts_with("polar_"))
+            .map(|(_, bytes)| bytes)
+            .sum()
+    };
+    let frames: Vec<_> = (0..2)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut options = MotionOptions::default();
+    options.move_type = Some(MoveType::Direction);
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &options);
+    assert_eq!(polar_bytes(&detector), 0);
+
+    options.move_type = Some(MoveType::Spiral);
+    run(&mut detector, &frames, &options);
+    let pixels = (WIDTH * HEIGHT) as usize;
+    assert_eq!(polar_bytes(&detector), pixels * 12);
+
+    // Freed tables come back when a mode needs them again
+    detector.free_unused_luts();
+    assert_eq!(polar_bytes(&detector), 0);
+    run(&mut detector, &frames, &options);
+    assert_eq!(polar_bytes(&detector), pixels * 12);
+}
+
+#[test]
+fn comparison_shows_the_difference_next_to_the_trails() {
+    let frames: Vec<_> = (0..3)
+        .map(|index| gradient(WIDTH, HEIGHT, index * 16))
+        .collect();
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let output = run(&mut detector, &frames, &MotionOptions::default());
+    let mut comparison = vec![0; detector.output_len()];
+    detector
+        .render_comparison(&mut comparison, ComparisonLayout::SideBySide)
+        .unwrap();
+
+    let half = WIDTH as usize / 2 * 4;
+    let rows = || {
+        output
+            .chunks_exact(WIDTH as usize * 4)
+            .zip(comparison.chunks_exact(WIDTH as usize * 4))
+    };
+    // Pixels past the threshold show up red on the left, the trails as usual on the right
+    assert!(rows().any(|(_, compared)| compared[..half]
+        .chunks_exact(4)
+        .any(|pixel| pixel[0] >= 96 && pixel[1] == 0)));
+    assert!(rows().all(|(processed, compared)| processed[half..] == compared[half..]));
+    assert!(rows().any(|(processed, _)| processed[half..]
+        .chunks_exact(4)
+        .any(|pixel| pixel[..3] != [0, 0, 0])));
+}
+
+#[test]
+fn otsu_threshold_rises_above_the_noise() {
+    let frames: Vec<_> = (0..40).map(|seed| noise(WIDTH, HEIGHT, seed, 12)).collect();
+    let mut options = MotionOptions::default();
+    options.threshold = 4.0;
+    options.decay_rate = 0.0;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &options);
+    let histogram = detector.get_diff_histogram();
+    assert_eq!(histogram.len(), 256);
+    assert_eq!(histogram.iter().sum::<u32>(), WIDTH * HEIGHT);
+    assert_eq!(detector.auto_threshold(), None);
+    let manual = active_pixels(detector.persistence());
+
+    options
+        .set_option("auto_threshold", OptionValue::Text("otsu"))
+        .unwrap();
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &options);
+    let threshold = detector.auto_threshold().unwrap();
+    assert!(threshold > 8.0, "{}", threshold);
+    assert!(active_pixels(detector.persistence()) < manual / 2);
+}
+
+#[test]
+fn short_buffers_are_rejected() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frame = moving_square(WIDTH, HEIGHT, 0);
+    let mut output = vec![0; detector.output_len()];
+    let options = MotionOptions::default();
+
+    assert!(detector
+        .process(&frame[..frame.len() - 4], &mut output, &options)
+        .is_err());
+    assert!(detector
+        .process(&frame, &mut output[1..], &options)
+        .is_err());
+}
+
+#[test]
+fn indexed_and_cell_outputs_follow_the_trails() {
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &MotionOptions::default());
+    let persistence = detector.persistence();
+
+    let indexed = detector.render_indexed(4);
+    assert_eq!(indexed.len(), persistence.len());
+    assert!(indexed.iter().all(|&index| index < 4));
+    assert!(indexed
+        .iter()
+        .zip(persistence)
+        .all(|(&index, &level)| index == (level.clamp(0.0, 255.0) / 64.0) as u8));
+    assert!(indexed.contains(&3));
+
+    // One cell per pixel reproduces the levels, a single cell averages them all
+    let pixels = detector.render_cells(WIDTH, HEIGHT);
+    assert!(pixels
+        .iter()
+        .zip(persistence)
+        .all(|(&cell, &level)| cell == level.clamp(0.0, 255.0) as u8));
+    let mean = total_intensity(persistence) / persistence.len() as f64;
+    let single = detector.render_cells(1, 1);
+    assert!(single.len() == 1 && (single[0] as f64 - mean.floor()).abs() <= 1.0);
+    let cells = detector.render_cells(8, 6);
+    assert_eq!(cells.len(), 48);
+    assert!(cells.iter().any(|&cell| cell > 0) && cells.contains(&0));
+}
+
+#[test]
+fn motion_projections_sum_the_trails_per_column_and_row() {
+    let frames: Vec<_> = (0..6)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &MotionOptions::default());
+    let projections = detector.get_motion_projections();
+    let (columns, rows) = (projections.columns(), projections.rows());
+    assert_eq!(
+        (columns.len(), rows.len()),
+        (WIDTH as usize, HEIGHT as usize)
+    );
+
+    let total = total_intensity(detector.persistence());
+    for sums in [&columns, &rows] {
+        let sum: f64 = sums.iter().map(|&value| value as f64).sum();
+        assert!((sum - total).abs() < total * 1e-4);
+    }
+    // Only the rows the square sweeps along and the columns it passed carry trails
+    let (square_x, square_y, square) = square_position(WIDTH, HEIGHT, 5);
+    assert!(rows[..square_y.saturating_sub(2)]
+        .iter()
+        .all(|&sum| sum == 0.0));
+    assert!(rows[square_y + square / 2] > 0.0);
+    assert!(columns[square_x + square + 2..]
+        .iter()
+        .all(|&sum| sum == 0.0));
+}
+
+#[test]
+fn global_motion_finds_a_camera_pan() {
+    let frames = [
+        texture(WIDTH, HEIGHT, 7, 0, 0),
+        texture(WIDTH, HEIGHT, 7, 3, -2),
+    ];
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &MotionOptions::default());
+    let motion = detector.estimate_global_motion(8, true);
+    assert!(
+        (motion.dx - 3.0).abs() < 0.3 && (motion.dy + 2.0).abs() < 0.3,
+        "{:?}",
+        motion
+    );
+    assert!(
+        motion.rotation.abs() < 0.01 && motion.confidence > 0.5,
+        "{:?}",
+        motion
+    );
+
+    // Unrelated frames don't line up anywhere
+    let frames = [noise(WIDTH, HEIGHT, 1, 60), noise(WIDTH, HEIGHT, 2, 60)];
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &MotionOptions::default());
+    assert!(detector.estimate_global_motion(8, false).confidence < 0.2);
+}
+
+#[test]
+fn stabilize_cancels_a_camera_pan() {
+    let frames: Vec<_> = (0..4)
+        .map(|index| texture(WIDTH, HEIGHT, 7, index * 2, -index))
+        .collect();
+    let mut options = MotionOptions::default();
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &options);
+    let shaky = active_pixels(detector.persistence());
+
+    options.stabilize = true;
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(&mut detector, &frames, &options);
+    let stabilized = active_pixels(detector.persistence());
+    assert!(
+        stabilized * 10 < shaky,
+        "{} pixels lit with stabilization, {} without",
+        stabilized,
+        shaky
+    );
+}
+
+#[test]
+fn persistence_modes_rank_by_how_much_they_build_up() {
+    let frames: Vec<_> = (0..8)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let glow = |mode| {
+        let mut options = MotionOptions::default();
+        options.persistence_mode = mode;
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        run(&mut detector, &frames, &options);
+        total_intensity(detector.persistence())
+    };
+    let (max, additive, lerp, screen) = (
+        glow(PersistenceMode::Max),
+        glow(PersistenceMode::Additive),
+        glow(PersistenceMode::Lerp),
+        glow(PersistenceMode::Screen),
+    );
+    assert!(additive > screen && screen > max && max > lerp && lerp > 0.0);
+
+    let mut options = MotionOptions::default();
+    options
+        .set_option("persistence_mode", OptionValue::Text("lerp(0.25)"))
+        .unwrap();
+    assert_eq!(options.persistence_mode, PersistenceMode::Lerp);
+    assert_eq!(options.persistence_alpha, 0.25);
+}
+
+#[test]
+fn decay_map_fades_regions_at_their_own_rate() {
+    // The square sweeps across the frame, then the scene holds still
+    let mut frames: Vec<_> = (0..20)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    frames.extend(std::iter::repeat_n(frames[19].clone(), 3));
+    let width = WIDTH as usize;
+    let half = width / 2;
+    let rates: Vec<f32> = (0..(WIDTH * HEIGHT) as usize)
+        .map(|pixel_index| if pixel_index % width < half { 1.0 } else { 0.0 })
+        .collect();
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_decay_map(&rates).unwrap();
+    run(&mut detector, &frames, &MotionOptions::default());
+
+    let (kept, faded): (Vec<_>, Vec<_>) = detector
+        .persistence()
+        .iter()
+        .enumerate()
+        .partition(|(pixel_index, _)| pixel_index % width < half);
+    assert!(kept.iter().any(|(_, &level)| level > 0.0));
+    assert!(faded.iter().all(|(_, &level)| level == 0.0));
+
+    // A NaN rate is rejected and the map in use stays
+    let mut broken = rates.clone();
+    broken[5] = f32::NAN;
+    assert!(detector.try_set_decay_map(&broken).is_err());
+    run(&mut detector, &frames[..1], &MotionOptions::default());
+    assert!(detector.persistence().iter().all(|level| level.is_finite()));
+}
+
+#[test]
+fn frame_capture_keeps_the_frames_before_motion_starts() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_frame_capture(1.0, 2, 16);
+    let mut options = MotionOptions::default();
+    options.delta_time_ms = 40.0;
+    // Still for four frames, then moving
+    let mut frames = vec![moving_square(WIDTH, HEIGHT, 0); 4];
+    frames.extend((1..6).map(|index| moving_square(WIDTH, HEIGHT, index)));
+    run(&mut detector, &frames, &options);
+
+    // Ongoing motion is captured once, with the two still frames before it
+    let captures = detector.take_captures();
+    let summary: Vec<_> = captures
+        .iter()
+        .map(|frame| (frame.timestamp_ms, frame.pre_trigger))
+        .collect();
+    assert_eq!(summary, [(120.0, true), (160.0, true), (200.0, false)]);
+    assert!(captures[2].energy > 1.0 && captures[0].energy <= 1.0);
+    let trigger = &captures[2];
+    assert_eq!((trigger.width, trigger.height), (WIDTH, HEIGHT));
+    assert_eq!(trigger.pixels(), frames[4]);
+    assert_eq!(detector.pending_captures(), 0);
+
+    // Motion stops and starts again: a new capture
+    let still = frames[8].clone();
+    run(
+        &mut detector,
+        &[still.clone(), still, moving_square(WIDTH, HEIGHT, 0)],
+        &options,
+    );
+    assert_eq!(detector.pending_captures(), 3);
+    detector.clear_frame_capture();
+    assert!(detector.take_captures().is_empty());
+}
+
+#[test]
+fn dwell_heats_up_with_lasting_motion_and_cools_down_after() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_dwell_colors(&[0xffffff, 0x000000]).unwrap();
+    let mut options = MotionOptions::default();
+    options
+        .set_option("output_mode", OptionValue::Text("dwell"))
+        .unwrap();
+    options.move_type = None;
+    options.delta_time_ms = 100.0;
+    options.dwell_max_ms = 1000.0;
+    options.dwell_cooling = 2.0;
+
+    // Five frames of motion, then the scene holds still
+    let frames: Vec<_> = (0..6)
+        .map(|index| gradient(WIDTH, HEIGHT, index * 8))
+        .collect();
+    let output = run(&mut detector, &frames, &options);
+    let dwell = detector.dwell_times();
+    assert_eq!(dwell.len(), (WIDTH * HEIGHT) as usize);
+    let hottest = (0..dwell.len())
+        .max_by(|&a, &b| dwell[a].total_cmp(&dwell[b]))
+        .unwrap();
+    assert_eq!(dwell[hottest], 500.0);
+    // Halfway along the white to black ramp
+    assert_eq!(output[hottest * 4..hottest * 4 + 4], [127, 127, 127, 255]);
+    let cold = dwell.iter().position(|&ms| ms == 0.0).unwrap();
+    assert_eq!(output[cold * 4..cold * 4 + 4], [0, 0, 0, 255]);
+
+    // Cooling twice as fast as it heated
+    run(
+        &mut detector,
+        &[frames[5].clone(), frames[5].clone()],
+        &options,
+    );
+    assert_eq!(detector.dwell_times()[hottest], 100.0);
+
+    options.output_mode = OutputMode::Grayscale;
+    run(&mut detector, &frames[..1], &options);
+    assert!(detector.dwell_times().is_empty());
+}
+
+#[test]
+fn dispose_frees_the_buffers_and_refuses_frames() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    run(&mut detector, &frames, &MotionOptions::default());
+    let before = detector.memory_bytes();
+
+    detector.dispose();
+    assert!(detector.is_disposed());
+    assert!(detector.memory_bytes() * 100 < before);
+    let mut output = vec![0; (WIDTH * HEIGHT * 4) as usize];
+    let refused = detector.process(&frames[0], &mut output, &MotionOptions::default());
+    assert_eq!(refused, Err("detector has been disposed".to_string()));
+    // Neither resizing nor the zero-copy pointers bring the buffers back
+    let disposed = Err("detector has been disposed".to_string());
+    assert_eq!(detector.try_resize(WIDTH, HEIGHT), disposed);
+    assert!(detector.try_input_ptr().is_err());
+    assert!(detector.try_output_ptr().is_err());
+    detector.set_roi(0, 0, WIDTH, HEIGHT);
+    detector.reset_all_state();
+    assert!(detector.is_disposed());
+    assert!(detector.memory_bytes() * 100 < before);
+}
+
+#[test]
+fn diffuse_skips_a_zero_width_roi() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    detector.set_roi(4, 4, 0, 8);
+    let mut options = MotionOptions::default();
+    options.move_type = Some(MoveType::Diffuse);
+    options.diffuse_iterations = 2;
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    run(&mut detector, &frames, &options);
+    assert_eq!(active_pixels(detector.persistence()), 0);
+}
+
+// Frames without `delta_time_ms` count as one 60 fps frame, the first as none
+#[cfg(feature = "deterministic")]
+#[test]
+fn deterministic_clock_starts_at_zero() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    let frames = vec![gradient(WIDTH, HEIGHT, 0); 3];
+    run(&mut detector, &frames[..1], &MotionOptions::default());
+    assert_eq!(detector.stream_time_ms(), 0.0);
+    run(&mut detector, &frames[1..], &MotionOptions::default());
+    assert!((detector.stream_time_ms() - 2000.0 / 60.0).abs() < 1e-3);
+
+    detector.reset_all_state();
+    run(&mut detector, &frames[..1], &MotionOptions::default());
+    assert_eq!(detector.stream_time_ms(), 0.0);
+}
+
+#[test]
+fn options_written_out_of_range_are_clamped() {
+    type Setter = fn(&mut MotionOptions, f64);
+    let moving: Vec<_> = (0..12)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let render = |set: Setter, value: f64| {
+        let mut options = MotionOptions::default();
+        set(&mut options, value);
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        run(&mut detector, &moving, &options)
+    };
+    // Option, how a JS property write sets it, a value out of range and the one it must
+    // behave like
+    let rows: [(&str, Setter, f64, f64); 11] = [
+        (
+            "process_every_n",
+            |options, value| options.process_every_n = value as u32,
+            u32::MAX as f64,
+            8.0,
+        ),
+        (
+            "process_every_n",
+            |options, value| options.process_every_n = value as u32,
+            0.0,
+            1.0,
+        ),
+        (
+            "processing_scale",
+            |options, value| options.processing_scale = value as f32,
+            0.0,
+            0.125,
+        ),
+        (
+            "processing_scale",
+            |options, value| options.processing_scale = value as f32,
+            4.0,
+            1.0,
+        ),
+        (
+            "processing_scale",
+            |options, value| options.processing_scale = value as f32,
+            f64::NAN,
+            1.0,
+        ),
+        (
+            "erode",
+            |options, value| {
+                options.erode = value as u32;
+                options.dilate = 1;
+            },
+            u32::MAX as f64,
+            8.0,
+        ),
+        (
+            "dilate",
+            |options, value| options.dilate = value as u32,
+            u32::MAX as f64,
+            8.0,
+        ),
+        (
+            "diffusion",
+            |options, value| options.diffusion = value as f32,
+            1e30,
+            8.0,
+        ),
+        (
+            "diffusion",
+            |options, value| options.diffusion = value as f32,
+            f64::INFINITY,
+            0.0,
+        ),
+        (
+            "fluid_cell_size",
+            |options, value| {
+                options.output_mode = OutputMode::Fluid;
+                options.fluid_cell_size = value as u32;
+            },
+            0.0,
+            2.0,
+        ),
+        (
+            "fluid_cell_size",
+            |options, value| {
+                options.output_mode = OutputMode::Fluid;
+                options.fluid_cell_size = value as u32;
+            },
+            u32::MAX as f64,
+            32.0,
+        ),
+    ];
+    for (name, set, extreme, cap) in rows {
+        assert_eq!(
+            render(set, extreme),
+            render(set, cap),
+            "{} = {}",
+            name,
+            extreme
+        );
+        // `set_option` clamps finite values the same way
+        if extreme.is_finite() {
+            let mut clamped = MotionOptions::default();
+            clamped
+                .set_option(name, OptionValue::Number(extreme))
+                .unwrap();
+            let mut capped = MotionOptions::default();
+            capped.set_option(name, OptionValue::Number(cap)).unwrap();
+            assert_eq!(clamped, capped, "{} = {}", name, extreme);
+        }
+    }
+
+    // Values that can't be clamped are rejected and leave the options alone
+    for name in [
+        "process_every_n",
+        "processing_scale",
+        "erode",
+        "dilate",
+        "diffusion",
+    ] {
+        let mut options = MotionOptions::default();
+        for value in [f64::NAN, f64::INFINITY] {
+            assert!(options
+                .set_option(name, OptionValue::Number(value))
+                .is_err());
+        }
+        assert_eq!(options, MotionOptions::default());
+    }
+
+    // NaN and huge fluid forces don't stop the frame from rendering
+    let force: Setter = |options, value| {
+        options.output_mode = OutputMode::Fluid;
+        options.fluid_force = value as f32;
+    };
+    for value in [f64::NAN, f32::MAX as f64] {
+        assert_eq!(render(force, value).len(), (WIDTH * HEIGHT * 4) as usize);
+    }
+}
+
+#[test]
+fn grid_mode_rejects_undefined_spacings() {
+    let mut options = MotionOptions::default();
+    options
+        .set_option("grid_mode", OptionValue::Number(4.0))
+        .unwrap();
+    for step in [2.5, -1.0, 17.0, 1e9, f64::NAN, f64::INFINITY] {
+        assert!(options
+            .set_option("grid_mode", OptionValue::Number(step))
+            .is_err());
+    }
+    assert_eq!(options.grid_mode, 4);
+
+    // Written directly, spacings past the sparsest one sample like it
+    let frames: Vec<_> = (0..3)
+        .map(|index| moving_square(WIDTH, HEIGHT, index))
+        .collect();
+    let render = |step: u32| {
+        let mut options = MotionOptions::default();
+        options.grid_mode = step;
+        let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+        run(&mut detector, &frames, &options)
+    };
+    assert_eq!(render(u32::MAX), render(16));
+}
+
+#[test]
+fn constructor_rejects_unusable_resolutions() {
+    assert!(MotionDetector::try_new(WIDTH, HEIGHT).is_ok());
+    assert!(MotionDetector::try_new(0, HEIGHT).is_err());
+    assert!(MotionDetector::try_new(WIDTH, 0).is_err());
+    assert!(MotionDetector::try_new(65536, 65537).is_err());
+}
+
+#[test]
+fn resize_rejects_unusable_resolutions() {
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    assert!(detector.try_resize(0, 0).is_err());
+    assert!(detector.try_resize(65536, 65537).is_err());
+    // A rejected size leaves the detector as it was
+    assert_eq!(detector.processing_size(), (WIDTH, HEIGHT));
+    detector.try_resize(WIDTH / 2, HEIGHT / 2).unwrap();
+    assert_eq!(detector.processing_size(), (WIDTH / 2, HEIGHT / 2));
+
+    let estimate = MotionDetector::estimate_memory_bytes(WIDTH, HEIGHT);
+    let mut limited =
+        MotionDetector::try_new_with_memory_limit(WIDTH, HEIGHT, estimate as u64).unwrap();
+    assert!(limited.try_resize(WIDTH * 2, HEIGHT).is_err());
+    limited.try_resize(WIDTH / 2, HEIGHT).unwrap();
+}
+
+#[test]
+fn rejected_frames_leave_the_input_format_alone() {
+    let format_options = |format: &str| {
+        let mut options = MotionOptions::default();
+        options
+            .set_option("input_format", OptionValue::Text(format))
+            .unwrap();
+        options
+    };
+    let rgb_frame = vec![0; (WIDTH * HEIGHT * 3) as usize];
+    let mut detector = MotionDetector::try_new(WIDTH, HEIGHT).unwrap();
+    run(
+        &mut detector,
+        std::slice::from_ref(&rgb_frame),
+        &format_options("rgb"),
+    );
+    assert_eq!(detector.buffer_len(), rgb_frame.len());
+    // An RGB frame is too short for RGBA and is refused before the switch
+    let mut output = vec![0; detector.output_len()];
+    assert!(detector
+        .process(&rgb_frame, &mut output, &format_options("rgba"))
+        .is_err());
+    assert_eq!(detector.buffer_len(), rgb_frame.len());
+}